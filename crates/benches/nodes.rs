@@ -221,46 +221,98 @@ fn bench_delay_quality(c: &mut Criterion) {
     group.finish();
 }
 
-// Removing pipe idea now for oversampling
-
-// fn bench_oversampler(c: &mut Criterion) {
-//     let config = Config {
-//         block_size: 4096,
-//         channels: 2,
-//         sample_rate: 44_100,
-//         rt_capacity: 0,
-//     };
-
-//     let ports = PortBuilder::default().audio_in(2).audio_out(2).build();
-
-//     let (mut app, _) = LegatoBuilder::new(config, ports).build_dsl(&String::from(
-//         r#"
-//             audio {
-//                 sweep { range: [40.0, 48000.0], duration: 5000.0, chans: 2 } | oversample2X()
-//             }
-
-//             { sweep }
-//         "#,
-//     )).expect("graph should build");
-
-//     c.bench_function("Basic oversampler", |b| {
-//         let ai: &[Box<[f32]>] = &[
-//             vec![0.0; config.block_size].into(),
-//             vec![0.0; config.block_size].into(),
-//         ];
-
-//         let mut inputs: [Option<&[f32]>; MAX_INPUTS] = [None; MAX_INPUTS];
-
-//         for (i, x) in ai.iter().enumerate() {
-//             inputs[i] = Some(&x)
-//         }
-
-//         b.iter(|| {
-//             let out = app.next_block();
-//             black_box(out);
-//         });
-//     });
-// }
+/// A long one-to-one chain, where every input has a single source and is read in place,
+/// against the same number of filters all summed into one node.
+fn bench_executor_inputs(c: &mut Criterion) {
+    const FILTERS: usize = 32;
+
+    let config = Config {
+        block_size: 4096,
+        channels: 1,
+        sample_rate: 48_000,
+        rt_capacity: 0,
+    };
+
+    let build = |edges: &dyn Fn(usize) -> String| {
+        let ports = PortBuilder::default().audio_out(1).build();
+        let filters: String = (0..FILTERS)
+            .map(|i| format!("onepole: f{i} {{ cutoff: 8000.0, chans: 1 }},\n"))
+            .collect();
+        let edges: String = (0..FILTERS).map(edges).collect();
+        let graph = format!(
+            r#"
+                audio {{
+                    saw: osc {{ freq: 110.0, chans: 1 }},
+                    {filters}
+                    onepole: out {{ cutoff: 18000.0, chans: 1 }}
+                }}
+
+                {edges}
+
+                {{ out }}
+            "#
+        );
+        let (app, _) = LegatoBuilder::new(config, ports)
+            .build_dsl(&graph)
+            .expect("graph should build");
+        app
+    };
+
+    let mut group = c.benchmark_group("Executor inputs");
+
+    let mut chain = build(&|i| match i {
+        0 => format!("osc >> f0[0]\nf{} >> out[0]\n", FILTERS - 1),
+        i => format!("f{} >> f{i}[0]\n", i - 1),
+    });
+    group.bench_function("direct chain", |b| {
+        b.iter(|| {
+            let out = chain.next_block();
+            black_box(out);
+        });
+    });
+
+    let mut fan_in = build(&|i| format!("osc >> f{i}[0]\nf{i} >> out[0]\n"));
+    group.bench_function("summed fan-in", |b| {
+        b.iter(|| {
+            let out = fan_in.next_block();
+            black_box(out);
+        });
+    });
+
+    group.finish();
+}
+
+fn bench_oversampler(c: &mut Criterion) {
+    let config = Config {
+        block_size: 4096,
+        channels: 2,
+        sample_rate: 44_100,
+        rt_capacity: 0,
+    };
+
+    let ports = PortBuilder::default().audio_in(2).audio_out(2).build();
+
+    let (mut app, _) = LegatoBuilder::new(config, ports)
+        .build_dsl(
+            r#"
+                oversample(4) {
+                    audio {
+                        sweep { range: [40.0, 48000.0], duration: 5000.0, chans: 2 }
+                    }
+                }
+
+                { sweep }
+            "#,
+        )
+        .expect("graph should build");
+
+    c.bench_function("Basic oversampler", |b| {
+        b.iter(|| {
+            let out = app.next_block();
+            black_box(out);
+        });
+    });
+}
 
 fn bench_kitchen_sink(c: &mut Criterion) {
     let config = Config {
@@ -737,7 +789,9 @@ criterion_group!(
     bench_fir,
     bench_stereo_delay,
     bench_delay_quality,
+    bench_executor_inputs,
     bench_svf,
+    bench_oversampler,
     bench_kitchen_sink,
     bench_plate_rust_vs_kernel
 );
@@ -1,6 +1,7 @@
 use legato::{
     builder::{LegatoBuilder, Unconfigured},
     config::Config,
+    dsl::module::MemoryLoader,
     input::DeviceSelection,
     interface::{AudioInterface, InputSpec},
     kernel::EXAMPLE_PLATE_KERNEL_PATCH,
@@ -23,12 +24,14 @@ fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
 /// You can see the example patch below, as well as a custom node
 /// implementation in nodes/plate.rs
 fn main() {
-    let graph = format!(
-        "{}\n{}",
-        EXAMPLE_PLATE_KERNEL_PATCH,
-        r#"
+    // The plate kernel is imported, as if it were a `plate.legato` file next to this one
+    let library = MemoryLoader::new().with_file("plate.legato", EXAMPLE_PLATE_KERNEL_PATCH);
+
+    let graph = r#"
+        import "plate.legato"
+
         patches {
-            plate: verb { predelay: 32.0, decay: 0.8, damping: 0.3, wet: 0.8, dry: 0.2 }
+            plate::plate: verb { predelay: 32.0, decay: 0.8, damping: 0.3, wet: 0.8, dry: 0.2 }
         }
 
         audio {
@@ -39,8 +42,7 @@ fn main() {
         external >> mono_fan_out >> verb[0..2]
 
         { verb }
-    "#,
-    );
+    "#;
 
     let config = Config {
         sample_rate: env_or("LEGATO_SAMPLE_RATE", 44_100),
@@ -55,7 +57,8 @@ fn main() {
 
     let (app, _) = LegatoBuilder::<Unconfigured>::new(config, ports)
         .register_audio_input("one", consumer, 1, config.block_size)
-        .build_dsl(&graph)
+        .set_module_loader(library)
+        .build_dsl(graph)
         .expect("graph should build");
 
     #[cfg(target_os = "macos")]
@@ -0,0 +1,65 @@
+//! Formats `.legato` files in place, keeping their comments.
+//!
+//! `legato-fmt a.legato b.legato` rewrites both files. With `--check`, nothing is written,
+//! and the files that are not formatted are listed instead. Without files, stdin is
+//! formatted to stdout.
+
+use std::{
+    io::{Read, Write},
+    process::ExitCode,
+};
+
+use legato::dsl::print::format;
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let check = args.iter().any(|a| a == "--check");
+    let files: Vec<&String> = args.iter().filter(|a| *a != "--check").collect();
+
+    if files.is_empty() {
+        let mut src = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut src) {
+            eprintln!("could not read stdin: {e}");
+            return ExitCode::FAILURE;
+        }
+        return match format(&src) {
+            Ok(formatted) => {
+                std::io::stdout().write_all(formatted.as_bytes()).unwrap();
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{e:?}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut status = ExitCode::SUCCESS;
+    for file in files {
+        let formatted = std::fs::read_to_string(file)
+            .map_err(|e| format!("could not read: {e}"))
+            .and_then(|src| {
+                let formatted = format(&src).map_err(|e| format!("{e:?}"))?;
+                Ok((src, formatted))
+            });
+
+        match formatted {
+            Ok((src, formatted)) if src == formatted => {}
+            Ok(_) if check => {
+                println!("{file}");
+                status = ExitCode::FAILURE;
+            }
+            Ok((_, formatted)) => {
+                if let Err(e) = std::fs::write(file, formatted) {
+                    eprintln!("{file}: could not write: {e}");
+                    status = ExitCode::FAILURE;
+                }
+            }
+            Err(e) => {
+                eprintln!("{file}: {e}");
+                status = ExitCode::FAILURE;
+            }
+        }
+    }
+    status
+}
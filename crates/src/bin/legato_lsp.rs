@@ -0,0 +1,8 @@
+//! The Legato language server, over stdio.
+//!
+//! Build with `cargo build --features lsp --bin legato-lsp`, and point your editor's LSP
+//! client at the binary for `.legato` files.
+
+fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    legato::lsp::LanguageServer::new().run_stdio()
+}
@@ -3,29 +3,41 @@ use slotmap::SlotMap;
 use crate::{
     LegatoApp, LegatoFrontend,
     config::Config,
-    context::AudioContext,
+    context::{AudioContext, Rate, at_rate},
     dsl::{
-        ir::{DSLParams, NodeId, Port},
-        parse::legato_parser,
+        generate::unfilled,
+        ir::{ASTPipe, DSLParams, IRNodeKind, NodeId, Port, SourceSpan, Span},
+        module::{FileLoader, ModuleLoader, resolve_imports},
+        parse::{legato_parser, print_validation_error},
         pipeline::Pipeline,
     },
-    graph::{Connection, ConnectionEntry},
+    edit::GraphMirror,
+    executor::MAIN_OUTPUT,
+    graph::{Connection, ConnectionEntry, GraphError},
     midi::{MidiRuntimeFrontend, MidiStore},
     node::LegatoNode,
-    nodes::audio::mixer::{MonoFanOut, TrackMixer},
+    nodes::audio::{
+        mixer::{MonoFanOut, TrackMixer},
+        resample::Resample,
+    },
+    pipes::{OVERSAMPLE, Pipe, PipeRegistry, oversample_factor, pipe_registry_factory},
+    pool::WorkerPool,
     ports::{PortKind, Ports},
+    profile::{PROFILE_QUEUE_CAPACITY, ProfileReader, Profiler},
     registry::{
         NodeRegistry, audio_registry_factory, control_registry_factory, midi_registry_factory,
     },
+    reload::{AudioInputLayout, GraphSwap, PreparedReload, ReloadContext},
     resources::{
-        AudioInputKey, DelayLineKey, ExternalBufferKey, ResourceBuilder, Resources,
+        AudioInputKey, DelayLineKey, ExternalBufferKey, ResourceBuilder, ResourceFrontend,
+        Resources,
         arena::RuntimeArena,
         params::{ParamKey, ParamMeta, ParamStore},
     },
     runtime::{NodeKey, Runtime, RuntimeFrontend},
     spec::NodeSpec,
 };
-use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+use std::{collections::HashMap, marker::PhantomData, path::PathBuf, sync::Arc};
 
 /// ValidationError covers logical issues
 /// when lowering from the AST to the IR.
@@ -48,12 +60,83 @@ pub enum ValidationError {
     UnsupportedInKernel(String),
     /// A node has more audio ports on input/output side than [`crate::executor::MAX_ARITY`].
     ArityExceeded(String),
+    /// Connected nodes run at different rates, with no resampler between them.
+    RateMismatch(String),
     /// Two declarations resolve to the same alias, so one would shadow the other.
     DuplicateAlias(String),
+    /// Two outputs share a name, or an auxiliary output is named like the sink's `main`.
+    DuplicateOutput(String),
     /// Two node selections cannot be paired: neither is single and they differ.
     SelectionArity(String),
     /// A graph pass could not expand the program as written.
     Expansion(String),
+    /// A param expression names an unknown template or function, or does arithmetic on
+    /// something that is not a number. The span points into the DSL source.
+    InvalidExpression(String, Span),
+    /// An `import` could not be loaded or imports itself, or a `use` or `ns::name` names
+    /// nothing. The message names the file it happened in.
+    Import(String),
+    /// Connections loop back on themselves outside of a kernel.
+    Cycle(String),
+    /// A step of the pipeline could not be written out, see
+    /// [`crate::dsl::pipeline::Pipeline::dump_json`].
+    PipelineDump(String),
+    /// The error was made in the declaration or connection at the span, in the DSL source.
+    Spanned(Box<ValidationError>, Span),
+}
+
+impl ValidationError {
+    /// Point the error at `span`, unless it already points somewhere.
+    pub fn at(self, span: &SourceSpan) -> Self {
+        match (&span.0, self.span()) {
+            (Some(span), None) => Self::Spanned(Box::new(self), span.clone()),
+            _ => self,
+        }
+    }
+
+    /// Where in the DSL source the error was made, if known.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Self::InvalidExpression(_, span) | Self::Spanned(_, span) => Some(span.clone()),
+            _ => None,
+        }
+    }
+
+    /// The error without its span.
+    pub fn unspanned(&self) -> &Self {
+        match self {
+            Self::Spanned(err, _) => err.unspanned(),
+            err => err,
+        }
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParseError(msg)
+            | Self::NodeNotFound(msg)
+            | Self::NamespaceNotFound(msg)
+            | Self::InvalidParameter(msg)
+            | Self::MissingRequiredParameters(msg)
+            | Self::MissingRequiredParameter(msg)
+            | Self::ResourceNotFound(msg)
+            | Self::PipeNotFound(msg)
+            | Self::NotKernelCapable(msg)
+            | Self::UnsupportedInKernel(msg)
+            | Self::ArityExceeded(msg)
+            | Self::RateMismatch(msg)
+            | Self::DuplicateAlias(msg)
+            | Self::DuplicateOutput(msg)
+            | Self::SelectionArity(msg)
+            | Self::Expansion(msg)
+            | Self::InvalidExpression(msg, _)
+            | Self::Import(msg)
+            | Self::Cycle(msg)
+            | Self::PipelineDump(msg) => f.write_str(msg),
+            Self::Spanned(err, _) => err.fmt(f),
+        }
+    }
 }
 
 // Typestates for the builder
@@ -109,12 +192,17 @@ impl<S> LegatoBuilder<S> {
         LegatoBuilder {
             runtime: self.runtime,
             namespaces: self.namespaces,
+            pipes: self.pipes,
             working_name_lookup: self.working_name_lookup,
+            node_spans: self.node_spans,
             delay_name_to_key: self.delay_name_to_key,
             resource_builder: self.resource_builder,
             external_buffer_to_key: self.external_buffer_to_key,
             last_selection: self.last_selection,
             midi_runtime_frontend: self.midi_runtime_frontend,
+            profile_window: self.profile_window,
+            module_loader: self.module_loader,
+            pipeline_dump: self.pipeline_dump,
             _state: PhantomData,
         }
     }
@@ -124,8 +212,12 @@ pub struct LegatoBuilder<State> {
     runtime: Runtime,
     // String to registries of node spec (including factory fn) lookup
     namespaces: HashMap<String, NodeRegistry>,
+    // Pipes that can follow a declaration, by name
+    pipes: PipeRegistry,
     // Lookup from string to NodeKey
     working_name_lookup: HashMap<String, NodeKey>,
+    // The declaration each DSL node came from, for errors found once the graph is sealed
+    node_spans: HashMap<NodeKey, SourceSpan>,
     // Resources being built. These can be pased to node factories
     resource_builder: ResourceBuilder,
     // Name to key maps
@@ -135,6 +227,12 @@ pub struct LegatoBuilder<State> {
     last_selection: Option<SelectionKind>,
     // The midi runtime that can be added to the runtime
     midi_runtime_frontend: Option<MidiRuntimeFrontend>,
+    // Blocks per profiling window, when profiling is on
+    profile_window: Option<usize>,
+    // Where the DSL's imports are loaded from
+    module_loader: Arc<dyn ModuleLoader>,
+    // Where the DSL pipeline's JSON dumps are written, if anywhere
+    pipeline_dump: Option<PathBuf>,
     _state: PhantomData<State>,
 }
 
@@ -180,9 +278,14 @@ impl LegatoBuilder<Unconfigured> {
             external_buffer_to_key: HashMap::new(),
             delay_name_to_key: HashMap::new(),
             namespaces,
+            pipes: pipe_registry_factory(),
             working_name_lookup: HashMap::new(),
+            node_spans: HashMap::new(),
             last_selection: None,
             midi_runtime_frontend: None,
+            profile_window: None,
+            module_loader: Arc::new(FileLoader::default()),
+            pipeline_dump: None,
             _state: std::marker::PhantomData,
         }
     }
@@ -193,6 +296,55 @@ impl LegatoBuilder<Configured> {
         let can_build = self.into_state::<DslBuilding>();
         can_build._build_dsl(graph)
     }
+
+    /// Build `graph` for an app that is already running, with the namespaces and
+    /// audio inputs it was originally built with.
+    ///
+    /// Audio inputs are registered as silent placeholders of the same shape, and
+    /// only receive the live consumers when the runtime swaps the graph in.
+    pub(crate) fn build_dsl_reload(
+        mut self,
+        graph: &str,
+        context: &ReloadContext,
+    ) -> Result<PreparedReload, ValidationError> {
+        self.namespaces = context.namespaces.clone();
+        self.module_loader = context.module_loader.clone();
+        self.pipeline_dump = context.pipeline_dump.clone();
+        self.runtime.set_worker_pool(context.worker_pool.clone());
+        self.runtime.set_control_rate(context.control_rate);
+
+        for input in &context.audio_inputs {
+            let (_, placeholder) = rtrb::RingBuffer::new(1);
+            self.resource_builder.register_audio_input(
+                &input.name,
+                placeholder,
+                input.chans,
+                input.block_size,
+            );
+        }
+
+        let mut can_build = self.into_state::<DslBuilding>();
+        can_build._lower_dsl(graph)?;
+
+        let sealed = can_build.seal()?;
+        let (executor, resources) = sealed.runtime.into_parts();
+
+        Ok(PreparedReload {
+            swap: GraphSwap::new(executor, resources),
+            resources_frontend: sealed.resources_frontend,
+            node_registry: sealed.node_registry,
+            reload_context: sealed.reload_context,
+        })
+    }
+}
+
+/// A graph that has been validated, given its resources and prepared, but not yet
+/// split into a [`LegatoApp`]/[`LegatoFrontend`] pair.
+struct SealedGraph {
+    runtime: Runtime,
+    resources_frontend: ResourceFrontend,
+    node_registry: HashMap<String, NodeKey>,
+    reload_context: ReloadContext,
 }
 
 impl<S> LegatoBuilder<S>
@@ -212,6 +364,13 @@ where
         }
         self
     }
+    /// Register a pipe, so declarations can be followed by `| name` or `| name(value)`.
+    ///
+    /// `oversample` is read by the builder itself, so a pipe registered under that name never runs.
+    pub fn register_pipe(mut self, name: &'static str, pipe: impl Pipe + 'static) -> Self {
+        self.pipes.declare_pipe(name, pipe);
+        self
+    }
     /// Register an AudioInput
     pub fn register_audio_input(
         mut self,
@@ -224,6 +383,18 @@ where
             .register_audio_input(name, consumer, chans, block_size);
         self
     }
+    /// Load the files the DSL imports through `loader`, rather than from disk relative to the working directory.
+    pub fn set_module_loader(mut self, loader: impl ModuleLoader + 'static) -> Self {
+        self.module_loader = Arc::new(loader);
+        self
+    }
+    /// Dump the DSL's graph as JSON into `dir` before and after each pass of the pipeline,
+    /// see [`Pipeline::dump_json`]. Hot reloads dump into the same directory, and a dump that
+    /// cannot be written fails the build with [`ValidationError::PipelineDump`].
+    pub fn set_pipeline_dump(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.pipeline_dump = Some(dir.into());
+        self
+    }
 }
 
 impl<S> LegatoBuilder<S>
@@ -234,38 +405,56 @@ where
         self.midi_runtime_frontend = Some(rt);
         self
     }
+    /// Spread independent branches of the graph over `pool`, rather than running every node on the audio thread.
+    pub fn set_worker_pool(mut self, pool: Arc<WorkerPool>) -> Self {
+        self.runtime.set_worker_pool(Some(pool));
+        self
+    }
+    /// Run control-kind nodes like `lfo`, `signal` and `map` once every `decimation` samples,
+    /// rather than every sample. See [`crate::executor::Executor::set_control_rate`].
+    ///
+    /// `block_size / decimation` has to be a whole multiple of the SIMD width, which is up to 16 samples.
+    pub fn set_control_rate(mut self, decimation: usize) -> Self {
+        self.runtime.set_control_rate(decimation);
+        self
+    }
+    /// Time every node and block, and send the min, average and max over each `window` blocks
+    /// to the frontend. See [`LegatoFrontend::profile`].
+    pub fn enable_profiling(mut self, window: usize) -> Self {
+        self.profile_window = Some(window);
+        self
+    }
 }
 
 impl<S> LegatoBuilder<S>
 where
     S: CanAddNode,
 {
-    /// This pattern is used because we sometimes execute this in a non-owned context
+    /// This pattern is used because we sometimes execute this in a non-owned context.
+    /// The node is built against the config it will see when it runs at `rate`.
     fn _add_node_ref_self(
         &mut self,
         namespace: &String,
         node_kind: &String,
         alias: &String,
         params: &DSLParams,
-    ) {
-        let ns = self
-            .namespaces
-            .get(namespace)
-            .unwrap_or_else(|| panic!("Could not find namespace {}", namespace));
+        rate: Rate,
+    ) -> Result<(), ValidationError> {
+        let ns = self.namespaces.get(namespace).ok_or_else(|| {
+            ValidationError::NamespaceNotFound(format!("Could not find namespace {}", namespace))
+        })?;
 
         let mut resource_builder_view = ResourceBuilderView {
-            config: &self.runtime.get_config(),
+            config: &at_rate(rate, || self.runtime.get_config()),
             resource_builder: &mut self.resource_builder,
             external_buffer_keys: &mut self.external_buffer_to_key,
             delay_keys: &mut self.delay_name_to_key,
             instance_alias: alias,
         };
 
-        let node = ns
-            .get_node(&mut resource_builder_view, node_kind, params)
-            .unwrap_or_else(|_| panic!("Could not find node {}", node_kind));
+        let node = ns.get_node(&mut resource_builder_view, node_kind, params)?;
 
-        let legato_node = LegatoNode::new(alias.into(), node_kind.into(), node);
+        let legato_node = LegatoNode::new(alias.into(), node_kind.into(), node).with_rate(rate);
 
         let key = self.runtime.add_node(legato_node);
 
@@ -273,6 +462,7 @@ where
 
         // Set the last node_ref_added
         self.last_selection = Some(SelectionKind::Single(key));
+        Ok(())
     }
     pub fn add_node(
         mut self,
@@ -280,9 +470,9 @@ where
         node_kind: &String,
         alias: &String,
         params: &DSLParams,
-    ) -> LegatoBuilder<ContainsNodes> {
-        self._add_node_ref_self(namespace, node_kind, alias, params);
-        self.into_state()
+    ) -> Result<LegatoBuilder<ContainsNodes>, ValidationError> {
+        self._add_node_ref_self(namespace, node_kind, alias, params, Rate::GRAPH)?;
+        Ok(self.into_state())
     }
 
     /// Skip the ceremony with namespaces, specs, etc. and just add a LegatoNode. This still requires an alias for connections and debugging
@@ -302,112 +492,39 @@ where
     S: CanConnect,
 {
     /// This pattern is used because we sometimes execute this in a non-owned context
-    fn _connect_ref_self(&mut self, connection: AddConnectionProps) {
-        let source_indicies: Vec<usize> = match connection.source_kind {
-            Port::None => {
-                let ports = self.runtime.get_node_ports(&connection.source);
-                ports.audio_out.iter().enumerate().map(|(i, _)| i).collect()
-            }
-            Port::Index(port) => vec![port],
-            Port::Named(ref port) => {
-                let ports = self.runtime.get_node_ports(&connection.source);
-                let index = ports
-                    .audio_out
-                    .iter()
-                    .find(|x| x.name == port)
-                    .unwrap_or_else(|| panic!("Could not find index for named port {}", port))
-                    .index;
-
-                vec![index]
-            }
-            Port::Slice(start, end) => {
-                if end < start {
-                    panic!("End slice cannot be less than start!");
-                }
-
-                (start..end).collect::<Vec<_>>()
-            }
-            Port::Stride { start, end, stride } => {
-                if end < start {
-                    panic!("End slice cannot be less than start!");
-                }
-
-                (start..end).step_by(stride).collect()
-            }
-        };
+    fn _connect_ref_self(&mut self, connection: AddConnectionProps) -> Result<(), ValidationError> {
+        let source_indicies =
+            self.port_indices(&connection.source, &connection.source_kind, PortDir::Out)?;
 
         // A node's outputs are single-kind, so the resolved source ports fix the
         // kind a portless sink should auto-map onto.
-        let source_kind = source_indicies
-            .first()
-            .and_then(|&i| {
-                self.runtime
-                    .get_node_ports(&connection.source)
-                    .audio_out
-                    .get(i)
-                    .map(|p| p.kind)
-            })
-            .unwrap_or(PortKind::Audio);
+        let source_kind = self.source_kind(&connection.source, &source_indicies);
 
         let sink_indicies: Vec<usize> = match connection.sink_kind {
             Port::None => {
                 let ports = self.runtime.get_node_ports(&connection.sink);
-                let matched: Vec<usize> = ports
-                    .audio_in
-                    .iter()
-                    .filter(|p| p.kind == source_kind)
-                    .map(|p| p.index)
-                    .collect();
+                let matched = self.matched_audio_in(&connection.sink, source_kind);
 
                 // A bare `>>` never crosses kinds: if the sink exposes no port of
                 // the source's kind, the target must be named explicitly.
                 if matched.is_empty() && !ports.audio_in.is_empty() {
-                    let (alias, kind) = self
-                        .runtime
-                        .get_node(&connection.sink)
-                        .map(|n| (n.name.clone(), n.node_kind.clone()))
-                        .unwrap_or_else(|| ("<unknown>".into(), "<unknown>".into()));
-                    panic!(
+                    let (alias, kind) = self.describe(&connection.sink);
+                    return Err(ValidationError::SelectionArity(format!(
                         "Bare `>>` from a {source_kind:?} source has no matching input on \
                          node '{alias}' ({kind}): name the target port explicitly"
-                    );
+                    )));
                 }
                 matched
             }
-            Port::Index(port) => vec![port],
-            Port::Named(ref port) => {
-                let ports = self.runtime.get_node_ports(&connection.sink);
-                let index = ports
-                    .audio_in
-                    .iter()
-                    .find(|x| x.name == port)
-                    .unwrap_or_else(|| panic!("Could not find index for named port {}", port))
-                    .index;
-
-                vec![index]
-            }
-            Port::Slice(start, end) => {
-                if end < start {
-                    panic!("End slice cannot be less than start!");
-                }
-
-                (start..end).collect::<Vec<_>>()
-            }
-            Port::Stride { start, end, stride } => {
-                if end < start {
-                    panic!("End slice cannot be less than start!");
-                }
-
-                (start..end).step_by(stride).collect()
-            }
+            ref port => self.port_indices(&connection.sink, port, PortDir::In)?,
         };
 
         // Guard against port indices that exceed the instantiated node's port
         // counts (e.g. reading `mixer[2..4]` from a node with only 2 outputs).
         // Without this the bad index slips through to the audio thread and
         // panics mid-process; here it fails loudly at build time instead.
-        self.assert_ports_in_range(&connection.source, &source_indicies, PortDir::Out);
-        self.assert_ports_in_range(&connection.sink, &sink_indicies, PortDir::In);
+        self.check_ports_in_range(&connection.source, &source_indicies, PortDir::Out)?;
+        self.check_ports_in_range(&connection.sink, &sink_indicies, PortDir::In)?;
 
         let source_arity = source_indicies.len();
         let sink_arity = sink_indicies.len();
@@ -420,7 +537,7 @@ where
                 sink_indicies[0],
             ),
             (1, n) if n >= 1 => {
-                self.assert_audio_fan(&connection.sink, &sink_indicies, PortDir::In);
+                self.check_audio_fan(&connection.sink, &sink_indicies, PortDir::In)?;
                 one_to_n(
                     &mut self.runtime,
                     connection,
@@ -429,7 +546,7 @@ where
                 )
             }
             (n, 1) if n >= 1 => {
-                self.assert_audio_fan(&connection.source, &source_indicies, PortDir::Out);
+                self.check_audio_fan(&connection.source, &source_indicies, PortDir::Out)?;
                 n_to_one(
                     &mut self.runtime,
                     connection,
@@ -444,52 +561,103 @@ where
                 &sink_indicies,
             ),
             (n, m) => {
-                let src = self.runtime.get_node(&connection.source);
-                let sink = self.runtime.get_node(&connection.sink);
-
-                unimplemented!(
-                    "Cannot match request arity {}:{} for nodes {:?}:{:?}",
-                    n,
-                    m,
-                    src,
-                    sink
-                )
+                let (src, src_kind) = self.describe(&connection.source);
+                let (sink, sink_kind) = self.describe(&connection.sink);
+
+                Err(ValidationError::SelectionArity(format!(
+                    "Cannot match request arity {n}:{m} for nodes '{src}' ({src_kind}) and \
+                     '{sink}' ({sink_kind})"
+                )))
             }
         }
     }
-    pub fn connect(mut self, connection: AddConnectionProps) -> LegatoBuilder<Connected> {
-        self._connect_ref_self(connection);
-        self.into_state()
+    pub fn connect(
+        mut self,
+        connection: AddConnectionProps,
+    ) -> Result<LegatoBuilder<Connected>, ValidationError> {
+        self._connect_ref_self(connection)?;
+        Ok(self.into_state())
+    }
+
+    /// The alias and node type of `key`, for error messages.
+    fn describe(&self, key: &NodeKey) -> (String, String) {
+        self.runtime
+            .get_node(key)
+            .map(|n| (n.name.clone(), n.node_kind.clone()))
+            .unwrap_or_else(|| ("<unknown>".into(), "<unknown>".into()))
+    }
+
+    /// The port indices `port` names on one side of `key`. Every output for a bare
+    /// source; a bare sink is resolved against the source's kind by the caller.
+    fn port_indices(
+        &self,
+        key: &NodeKey,
+        port: &Port,
+        dir: PortDir,
+    ) -> Result<Vec<usize>, ValidationError> {
+        let ports = self.runtime.get_node_ports(key);
+        let list = match dir {
+            PortDir::In => &ports.audio_in,
+            PortDir::Out => &ports.audio_out,
+        };
+        Ok(match port {
+            Port::None => (0..list.len()).collect(),
+            Port::Index(i) => vec![*i],
+            Port::Named(name) => {
+                let port = list.iter().find(|x| x.name == name).ok_or_else(|| {
+                    let (alias, kind) = self.describe(key);
+                    ValidationError::NodeNotFound(format!(
+                        "node '{alias}' ({kind}) has no {} port named '{name}'",
+                        dir.side()
+                    ))
+                })?;
+                vec![port.index]
+            }
+            Port::Slice(start, end) | Port::Stride { start, end, .. } if end < start => {
+                return Err(ValidationError::SelectionArity(format!(
+                    "port range [{start}..{end}] ends before it starts"
+                )));
+            }
+            Port::Slice(start, end) => (*start..*end).collect(),
+            Port::Stride { start, end, stride } => {
+                (*start..*end).step_by((*stride).max(1)).collect()
+            }
+            Port::Template(text) => return Err(unfilled(text)),
+        })
     }
 
-    /// Panic with a descriptive message if any resolved port index falls outside
-    /// the node's instantiated port range for the given direction.
-    fn assert_ports_in_range(&self, key: &NodeKey, indices: &[usize], dir: PortDir) {
+    /// Reject any resolved port index that falls outside the node's instantiated
+    /// port range for the given direction.
+    fn check_ports_in_range(
+        &self,
+        key: &NodeKey,
+        indices: &[usize],
+        dir: PortDir,
+    ) -> Result<(), ValidationError> {
         let ports = self.runtime.get_node_ports(key);
         let available = match dir {
             PortDir::In => ports.audio_in.len(),
             PortDir::Out => ports.audio_out.len(),
         };
         if let Some(&bad) = indices.iter().find(|&&i| i >= available) {
-            let (alias, kind) = self
-                .runtime
-                .get_node(key)
-                .map(|n| (n.name.clone(), n.node_kind.clone()))
-                .unwrap_or_else(|| ("<unknown>".into(), "<unknown>".into()));
-            panic!(
+            let (alias, kind) = self.describe(key);
+            return Err(ValidationError::SelectionArity(format!(
                 "Connection {dir:?} port index {bad} is out of range for node '{alias}' ({kind}): \
                  it has {available} audio {} port(s) (valid indices 0..{available})",
-                match dir {
-                    PortDir::In => "input",
-                    PortDir::Out => "output",
-                },
-            );
+                dir.side(),
+            )));
         }
+        Ok(())
     }
 
-    /// Panic if an implicit fan (broadcast or mix) would touch control ports:
+    /// Reject an implicit fan (broadcast or mix) that would touch control ports:
     /// those insert audio-only DSP nodes, so control targets must be named.
-    fn assert_audio_fan(&self, key: &NodeKey, indices: &[usize], dir: PortDir) {
+    fn check_audio_fan(
+        &self,
+        key: &NodeKey,
+        indices: &[usize],
+        dir: PortDir,
+    ) -> Result<(), ValidationError> {
         let ports = self.runtime.get_node_ports(key);
         let list = match dir {
             PortDir::In => &ports.audio_in,
@@ -502,38 +670,16 @@ where
             .map(|p| p.name)
             .collect();
         if !control.is_empty() {
-            let (alias, kind) = self
-                .runtime
-                .get_node(key)
-                .map(|n| (n.name.clone(), n.node_kind.clone()))
-                .unwrap_or_else(|| ("<unknown>".into(), "<unknown>".into()));
-            panic!(
+            let (alias, kind) = self.describe(key);
+            return Err(ValidationError::SelectionArity(format!(
                 "Implicit fan would touch control port(s) [{}] on node '{alias}' ({kind}): \
                  control connections cannot broadcast or mix; name the target explicitly \
                  (e.g. {alias}.{})",
                 control.join(", "),
                 control[0],
-            );
-        }
-    }
-
-    /// The audio-out port indices a source spec resolves to on its node.
-    fn source_out_indices(&self, key: &NodeKey, port: &Port) -> Vec<usize> {
-        let ports = self.runtime.get_node_ports(key);
-        match port {
-            Port::None => ports.audio_out.iter().enumerate().map(|(i, _)| i).collect(),
-            Port::Index(i) => vec![*i],
-            Port::Named(name) => vec![
-                ports
-                    .audio_out
-                    .iter()
-                    .find(|p| p.name == name)
-                    .unwrap_or_else(|| panic!("Could not find index for named port {name}"))
-                    .index,
-            ],
-            Port::Slice(start, end) => (*start..*end).collect(),
-            Port::Stride { start, end, stride } => (*start..*end).step_by(*stride).collect(),
+            )));
         }
+        Ok(())
     }
 
     /// The audio-in port indices on `sink` whose kind matches `kind`.
@@ -569,6 +715,15 @@ enum PortDir {
     Out,
 }
 
+impl PortDir {
+    fn side(self) -> &'static str {
+        match self {
+            PortDir::In => "input",
+            PortDir::Out => "output",
+        }
+    }
+}
+
 impl<S> LegatoBuilder<S>
 where
     S: CanSetSink,
@@ -587,13 +742,101 @@ where
         self.try_build().expect("Could not build Legato app")
     }
 
+    /// Add a named output besides the sink, e.g. a cue bus or a dry send for recording.
+    /// See [`LegatoApp::output`].
+    pub fn add_output(mut self, name: &str, key: NodeKey) -> Result<Self, ValidationError> {
+        self.add_output_ref_self(name, key)?;
+        Ok(self)
+    }
+
+    fn add_output_ref_self(&mut self, name: &str, key: NodeKey) -> Result<(), ValidationError> {
+        let taken = name == MAIN_OUTPUT
+            || self
+                .runtime
+                .get_executor()
+                .outputs()
+                .iter()
+                .any(|(n, _)| n == name);
+        if taken {
+            return Err(ValidationError::DuplicateOutput(name.into()));
+        }
+
+        self.runtime
+            .add_output(name, key)
+            .map_err(|_| ValidationError::NodeNotFound(format!("output '{name}' has no node")))
+    }
+
     pub fn try_build(mut self) -> Result<(LegatoApp, LegatoFrontend), ValidationError> {
+        let midi_runtime_frontend = self.midi_runtime_frontend.take();
+        let profile_window = self.profile_window;
+
+        let sealed = self.seal()?;
+        let mut runtime = sealed.runtime;
+
+        if let Some(fe) = midi_runtime_frontend {
+            let ctx = runtime.get_context_mut();
+            ctx.set_midi_store(MidiStore::new(256));
+            ctx.set_midi_runtime_frontend(fe);
+        }
+
+        let (producer, consumer) = rtrb::RingBuffer::new(512);
+
+        // Sized like the message queue: every retired graph arrived as one message
+        let (retired_producer, retired_consumer) = rtrb::RingBuffer::new(512);
+        runtime.set_retired_graph_sender(retired_producer);
+
+        let profile_reader = profile_window.map(|window| {
+            let (producer, consumer) = rtrb::RingBuffer::new(PROFILE_QUEUE_CAPACITY);
+            runtime.set_profiler(Profiler::new(producer, window, runtime.get_config()));
+            ProfileReader::new(consumer)
+        });
+
+        let mirror = GraphMirror::new(runtime.get_executor(), runtime.get_config().block_size);
+        let leaked_graphs = runtime.leaked_graph_counter();
+
+        let app = LegatoApp::new(runtime, consumer);
+
+        let rt_frontend = RuntimeFrontend::new(sealed.resources_frontend);
+
+        let frontend = LegatoFrontend::new(rt_frontend, producer, sealed.node_registry)
+            .with_reload(
+                sealed.reload_context,
+                mirror,
+                retired_consumer,
+                leaked_graphs,
+            )
+            .with_profiler(profile_reader);
+
+        Ok((app, frontend))
+    }
+
+    /// Validate the graph, build the real resources and allocate the executor's buffers.
+    fn seal(mut self) -> Result<SealedGraph, ValidationError> {
         let mut runtime = self.runtime;
 
+        resample_boundaries(
+            &mut runtime,
+            &mut self.working_name_lookup,
+            &self.node_spans,
+        )?;
         runtime.validate_arity()?;
+        runtime.validate_control_rate()?;
+        runtime.validate_rates(&self.node_spans)?;
 
         let cfg = runtime.get_config();
 
+        let audio_inputs = self
+            .resource_builder
+            .audio_input_layouts()
+            .into_iter()
+            .map(|(name, key, chans, block_size)| AudioInputLayout {
+                name,
+                key,
+                chans,
+                block_size,
+            })
+            .collect();
+
         // IMPORTANT: Swap out the dummy resources for the actual ones
         let (resources_frontend, resources) = self
             .resource_builder
@@ -604,21 +847,25 @@ where
         // Allocate all of the audio buffers needed at runtime
         runtime.prepare();
 
-        if let Some(fe) = self.midi_runtime_frontend.take() {
-            let ctx = runtime.get_context_mut();
-            ctx.set_midi_store(MidiStore::new(256));
-            ctx.set_midi_runtime_frontend(fe);
-        }
-
-        let (producer, consumer) = rtrb::RingBuffer::new(512);
-
-        let app = LegatoApp::new(runtime, consumer);
-
-        let rt_frontend = RuntimeFrontend::new(resources_frontend);
-
-        let frontend = LegatoFrontend::new(rt_frontend, producer, self.working_name_lookup);
+        let reload_context = ReloadContext {
+            config: cfg,
+            ports: runtime.get_ports().clone(),
+            namespaces: self.namespaces,
+            delay_name_to_key: self.delay_name_to_key,
+            external_buffer_to_key: self.external_buffer_to_key,
+            audio_inputs,
+            worker_pool: runtime.get_executor().worker_pool().cloned(),
+            control_rate: runtime.get_executor().control_rate(),
+            module_loader: self.module_loader,
+            pipeline_dump: self.pipeline_dump,
+        };
 
-        Ok((app, frontend))
+        Ok(SealedGraph {
+            runtime,
+            resources_frontend,
+            node_registry: self.working_name_lookup,
+            reload_context,
+        })
     }
 }
 
@@ -631,14 +878,14 @@ impl LegatoBuilder<DslBuilding> {
         &mut self,
         ir: &crate::dsl::ir::IRGraph,
         node: &crate::dsl::ir::IRNode,
-    ) {
-        let ir_macro = ir
-            .macro_registry
-            .get(&node.node_type)
-            .unwrap_or_else(|| panic!("Kernel '{}' not found in registry", node.node_type));
+        rate: Rate,
+    ) -> Result<(), ValidationError> {
+        let ir_macro = ir.macro_registry.get(&node.node_type).ok_or_else(|| {
+            ValidationError::NodeNotFound(format!("Kernel '{}' not found", node.node_type))
+        })?;
 
         let mut resource_builder_view = ResourceBuilderView {
-            config: &self.runtime.get_config(),
+            config: &at_rate(rate, || self.runtime.get_config()),
             resource_builder: &mut self.resource_builder,
             external_buffer_keys: &mut self.external_buffer_to_key,
             delay_keys: &mut self.delay_name_to_key,
@@ -646,23 +893,71 @@ impl LegatoBuilder<DslBuilding> {
         };
 
         let kernel_graph =
-            crate::kernel::lower_kernel(ir_macro, &node.params, &mut resource_builder_view)
-                .unwrap_or_else(|e| panic!("Could not build kernel '{}': {:?}", node.alias, e));
+            crate::kernel::lower_kernel(ir_macro, &node.params, &mut resource_builder_view)?;
 
         let legato_node = LegatoNode::new(
             node.alias.clone(),
             node.node_type.clone(),
             Box::new(crate::persample::PerSample::new(kernel_graph)),
-        );
+        )
+        .with_rate(rate);
 
         let key = self.runtime.add_node(legato_node);
         self.working_name_lookup.insert(node.alias.clone(), key);
         self.last_selection = Some(SelectionKind::Single(key));
+        Ok(())
+    }
+
+    /// The rate `node` runs at: that of the patch it is in, or of the kernel it instances,
+    /// times its oversampling.
+    fn node_rate(&self, ir: &crate::dsl::ir::IRGraph, node: &crate::dsl::ir::IRNode) -> Rate {
+        let kernel = ir
+            .macro_registry
+            .get(&node.node_type)
+            .filter(|_| node.kind == IRNodeKind::KernelRef);
+        match kernel.and_then(|kernel| kernel.rate) {
+            Some(rate) => rate,
+            None => node.rate.unwrap_or(Rate::GRAPH) * Rate::times(node.oversample as usize),
+        }
+    }
+
+    /// Run `chain` over `selection`, leaving whatever the last pipe selected as the last selection.
+    /// `| oversample(n)` was already read when the nodes were built, so it is skipped.
+    fn _pipe_ref_self(
+        &mut self,
+        selection: SelectionKind,
+        chain: &[ASTPipe],
+    ) -> Result<(), ValidationError> {
+        let mut view =
+            SelectionView::new(&mut self.runtime, &mut self.working_name_lookup, selection);
+        for pipe in chain.iter().filter(|pipe| pipe.name != OVERSAMPLE) {
+            self.pipes
+                .get_pipe(&pipe.name)?
+                .pipe(&mut view, pipe.params.as_ref())?;
+        }
+        self.last_selection = Some(view.get_selection_owned());
+        Ok(())
     }
 
     fn _build_dsl(mut self, content: &str) -> Result<(LegatoApp, LegatoFrontend), ValidationError> {
-        let ast = legato_parser(content)?;
-        let ir = Pipeline::default().run_from_ast(ast)?;
+        self._lower_dsl(content)?;
+
+        self.try_build()
+    }
+
+    /// Parse `content`, run the pipeline and add the resulting nodes, edges and sink to the runtime.
+    fn _lower_dsl(&mut self, content: &str) -> Result<(), ValidationError> {
+        self.lower_source(content)
+            .inspect_err(|err| print_validation_error("main", content, err))
+    }
+
+    fn lower_source(&mut self, content: &str) -> Result<(), ValidationError> {
+        let ast = resolve_imports(legato_parser(content)?, &*self.module_loader)?;
+        let mut pipeline = Pipeline::default();
+        if let Some(dir) = &self.pipeline_dump {
+            pipeline = pipeline.dump_json(dir);
+        }
+        let ir = pipeline.run_from_ast(ast)?;
 
         // Sanity check: every node must be a leaf before the builder runs.
         debug_assert!(
@@ -673,25 +968,56 @@ impl LegatoBuilder<DslBuilding> {
         // Map each IRNode (by NodeId) to a runtime NodeKey as we add nodes.
         let mut ir_to_runtime: HashMap<NodeId, NodeKey> = HashMap::new();
 
-        for node_id in ir.topological_sort() {
+        // `| oversample(n)` is read up front, so the nodes are built for the faster rate
+        // the same way as inside an `oversample(n) { }` block
+        let mut piped_rates: HashMap<NodeId, Rate> = HashMap::new();
+        for pipe in &ir.pipes {
+            for oversample in pipe.chain.iter().filter(|p| p.name == OVERSAMPLE) {
+                let factor =
+                    oversample_factor(oversample.params.as_ref()).map_err(|e| e.at(&pipe.span))?;
+                for id in &pipe.nodes {
+                    let rate = piped_rates.entry(*id).or_insert(Rate::GRAPH);
+                    *rate = *rate * Rate::times(factor);
+                }
+            }
+        }
+
+        for node_id in ir.try_topological_sort()? {
             let node = ir.get_node(node_id).unwrap().clone();
+            let piped = piped_rates.get(&node_id).copied().unwrap_or(Rate::GRAPH);
+            let rate = self.node_rate(&ir, &node) * piped;
 
-            if node.kind == crate::dsl::ir::IRNodeKind::KernelRef {
-                self._add_kernel_ref_self(&ir, &node);
+            let added = if node.kind == IRNodeKind::KernelRef {
+                self._add_kernel_ref_self(&ir, &node, rate)
             } else {
                 let dsl_params = DSLParams::new(&node.params);
 
                 // _add_node_ref_self populates working_name_lookup (needed by
                 // pipes) and sets last_selection.
-                self._add_node_ref_self(&node.namespace, &node.node_type, &node.alias, &dsl_params);
-            }
+                self._add_node_ref_self(
+                    &node.namespace,
+                    &node.node_type,
+                    &node.alias,
+                    &dsl_params,
+                    rate,
+                )
+            };
+            added.map_err(|e| e.at(&node.span))?;
 
-            let runtime_key = *self
-                .working_name_lookup
-                .get(&node.alias)
-                .expect("alias must be in lookup immediately after adding the node");
+            let key = self.working_name_lookup[&node.alias];
+            self.node_spans.insert(key, node.span.clone());
+            ir_to_runtime.insert(node_id, key);
+        }
 
-            ir_to_runtime.insert(node_id, runtime_key);
+        // Pipes see every node, but nothing is wired yet, so they may still change ports
+        for pipe in &ir.pipes {
+            let keys: Vec<NodeKey> = pipe.nodes.iter().map(|id| ir_to_runtime[id]).collect();
+            let selection = match keys.as_slice() {
+                [key] => SelectionKind::Single(*key),
+                _ => SelectionKind::Multiple(keys),
+            };
+            self._pipe_ref_self(selection, &pipe.chain)
+                .map_err(|e| e.at(&pipe.span))?;
         }
 
         // Wire edges using the NodeId -> NodeKey map (no string lookups).
@@ -711,7 +1037,9 @@ impl LegatoBuilder<DslBuilding> {
             }
             let source = ir_to_runtime[&edge.source];
             let sink = ir_to_runtime[&edge.sink];
-            let out_indices = self.source_out_indices(&source, &edge.source_port);
+            let out_indices = self
+                .port_indices(&source, &edge.source_port, PortDir::Out)
+                .map_err(|e| e.at(&edge.span))?;
             let kind = self.source_kind(&source, &out_indices);
             let matched = self.matched_audio_in(&sink, kind);
             if out_indices.len() < matched.len() {
@@ -726,7 +1054,9 @@ impl LegatoBuilder<DslBuilding> {
             let source = ir_to_runtime[&edge.source];
             let sink = ir_to_runtime[&edge.sink];
 
-            let out_indices = self.source_out_indices(&source, &edge.source_port);
+            let out_indices = self
+                .port_indices(&source, &edge.source_port, PortDir::Out)
+                .map_err(|e| e.at(&edge.span))?;
             let kind = self.source_kind(&source, &out_indices);
             let matched = self.matched_audio_in(&sink, kind);
 
@@ -740,7 +1070,9 @@ impl LegatoBuilder<DslBuilding> {
                     source_kind: edge.source_port.clone(),
                     sink,
                     sink_kind: edge.sink_port.clone(),
-                });
+                    feedback: edge.feedback,
+                })
+                .map_err(|e| e.at(&edge.span))?;
                 continue;
             }
 
@@ -748,16 +1080,13 @@ impl LegatoBuilder<DslBuilding> {
             let cursor = zip_cursor.entry(sink).or_insert(0);
 
             if *cursor + out_indices.len() > matched.len() {
-                let (alias, node_kind) = self
-                    .runtime
-                    .get_node(&sink)
-                    .map(|n| (n.name.clone(), n.node_kind.clone()))
-                    .unwrap_or_else(|| ("<unknown>".into(), "<unknown>".into()));
+                let (alias, node_kind) = self.describe(&sink);
                 return Err(ValidationError::SelectionArity(format!(
                     "bare `>>` fan into '{alias}' ({node_kind}) overruns its {} matching input(s): \
                      name the ports explicitly (e.g. `>> {alias}[0..N]`) to fan or mix deliberately",
                     matched.len(),
-                )));
+                ))
+                .at(&edge.span));
             }
 
             for &out in &out_indices {
@@ -768,18 +1097,28 @@ impl LegatoBuilder<DslBuilding> {
                     source_kind: Port::Index(out),
                     sink,
                     sink_kind: Port::Index(sink_index),
-                });
+                    feedback: edge.feedback,
+                })
+                .map_err(|e| e.at(&edge.span))?;
             }
         }
 
-        let sink_id = ir
-            .sink
-            .expect("IRGraph has no sink — check the DSL for a `sink:` declaration");
+        let sink_id = ir.sink.ok_or_else(|| {
+            ValidationError::NodeNotFound(
+                "The graph has no output: end the file with `{ alias }` to name one".into(),
+            )
+        })?;
         self.runtime
             .set_sink_key(ir_to_runtime[&sink_id])
-            .expect("Could not set sink");
+            .map_err(|_| {
+                ValidationError::NodeNotFound("Could not set the graph's output".into())
+            })?;
 
-        Ok(self.build())
+        for (name, id) in &ir.outputs {
+            self.add_output_ref_self(name, ir_to_runtime[id])?;
+        }
+
+        Ok(())
     }
 }
 
@@ -795,6 +1134,16 @@ pub enum SelectionKind {
     Multiple(Vec<NodeKey>),
 }
 
+impl SelectionKind {
+    /// The selected nodes, in the order they were declared or inserted.
+    pub fn keys(&self) -> &[NodeKey] {
+        match self {
+            Self::Single(key) => std::slice::from_ref(key),
+            Self::Multiple(keys) => keys,
+        }
+    }
+}
+
 /// Selections are passed between pipes, and set after inserting nodes.
 ///
 /// They expose a small view of operations on the runtime, so that pipes can
@@ -974,6 +1323,8 @@ pub struct AddConnectionProps {
     pub source_kind: Port,
     pub sink: NodeKey,
     pub sink_kind: Port,
+    /// Read the previous block of the source, as `~>` does, so the connection may close a cycle.
+    pub feedback: bool,
 }
 
 pub enum AddConnectionKind {
@@ -984,24 +1335,179 @@ pub enum AddConnectionKind {
 
 // Utility functions for handling only audio connections for now
 
+/// The runtime graph only rejects an edge for a cycle or a missing node; the DSL
+/// catches most cycles up front, but hand-written connections can still close one.
+fn edge_error(err: GraphError) -> ValidationError {
+    match err {
+        GraphError::CycleDetected => {
+            ValidationError::Cycle("Connection would close a cycle in the graph".into())
+        }
+        other => ValidationError::NodeNotFound(format!("Could not add edge: {other:?}")),
+    }
+}
+
+/// Add an edge into `props.sink`, delayed by a block if `props` asks for feedback. Any mixer
+/// in front of the sink is wired up normally, so only the edge into the sink is delayed.
+fn sink_edge(
+    runtime: &mut Runtime,
+    props: &AddConnectionProps,
+    connection: Connection,
+) -> Result<(), ValidationError> {
+    match props.feedback {
+        true => runtime.add_feedback_edge(connection),
+        false => runtime.add_edge(connection),
+    }
+    .map_err(edge_error)?;
+    Ok(())
+}
+
+/// Mixers run at the rate of the sink they feed, so only their input may need resampling.
+fn sink_rate(runtime: &Runtime, props: &AddConnectionProps) -> Rate {
+    runtime
+        .get_node(&props.sink)
+        .map_or(Rate::GRAPH, LegatoNode::rate)
+}
+
+/// Put a [`Resample`] on every connection between nodes at different rates, and in front
+/// of the sink and named outputs where they do not run at the graph's rate.
+///
+/// A resampler converts every output of one node to one rate, so the nodes at that rate
+/// reading from it share it. Resamplers themselves read across rates, and are left alone.
+/// Each is registered in `names` as `source@rate`, so a reload carries its filter memory.
+fn resample_boundaries(
+    runtime: &mut Runtime,
+    names: &mut HashMap<String, NodeKey>,
+    spans: &HashMap<NodeKey, SourceSpan>,
+) -> Result<(), ValidationError> {
+    let graph = &runtime.get_executor().graph;
+    let rate = |key: NodeKey| graph.get_node(key).map_or(Rate::GRAPH, LegatoNode::rate);
+
+    let mut crossing = vec![];
+    for key in graph.keys() {
+        if graph.get_node(key).unwrap().get_node().resamples() {
+            continue;
+        }
+        let incoming = graph
+            .incoming_connections(key)
+            .into_iter()
+            .flatten()
+            .map(|con| (*con, false));
+        let feedback = graph
+            .feedback_connections()
+            .iter()
+            .filter(|con| con.sink.node_key == key)
+            .map(|con| (*con, true));
+        crossing.extend(
+            incoming
+                .chain(feedback)
+                .filter(|(con, _)| rate(con.source.node_key) != rate(key)),
+        );
+    }
+
+    let mut resamplers = HashMap::new();
+    for (con, feedback) in crossing {
+        let to = runtime.get_node(&con.sink.node_key).unwrap().rate();
+        let resampler = resampler(runtime, &mut resamplers, names, con.source.node_key, to)
+            .map_err(|e| match spans.get(&con.source.node_key) {
+                Some(span) => e.at(span),
+                None => e,
+            })?;
+        let rerouted = Connection {
+            source: ConnectionEntry {
+                node_key: resampler,
+                port_index: con.source.port_index,
+            },
+            sink: con.sink,
+        };
+        match feedback {
+            true => runtime
+                .remove_feedback_edge(con)
+                .and_then(|_| runtime.add_feedback_edge(rerouted)),
+            false => runtime
+                .remove_edge(con)
+                .and_then(|_| runtime.add_edge(rerouted)),
+        }
+        .map_err(edge_error)?;
+    }
+
+    if let Some(sink) = *runtime.get_executor().sink() {
+        let resampled = resampler(runtime, &mut resamplers, names, sink, Rate::GRAPH)?;
+        runtime.set_sink_key(resampled).map_err(edge_error)?;
+    }
+    for (name, key) in runtime.get_executor().outputs().to_vec() {
+        let resampled = resampler(runtime, &mut resamplers, names, key, Rate::GRAPH)?;
+        runtime.add_output(&name, resampled).map_err(edge_error)?;
+    }
+    Ok(())
+}
+
+/// `source`'s outputs at `to`, through a shared resampler. `source` itself if it already
+/// runs at `to`.
+fn resampler(
+    runtime: &mut Runtime,
+    resamplers: &mut HashMap<(NodeKey, Rate), NodeKey>,
+    names: &mut HashMap<String, NodeKey>,
+    source: NodeKey,
+    to: Rate,
+) -> Result<NodeKey, ValidationError> {
+    let node = runtime.get_node(&source).unwrap();
+    let from = node.rate();
+    if from == to {
+        return Ok(source);
+    }
+    if let Some(key) = resamplers.get(&(source, to)) {
+        return Ok(*key);
+    }
+
+    let chans = node.get_node().ports().audio_out.len();
+    let input_len = from.scale(runtime.get_config().block_size);
+    let resample = Resample::new(chans, to.relative_to(from), input_len).ok_or_else(|| {
+        ValidationError::RateMismatch(format!(
+            "'{}' at {from} feeds nodes at {to}, which is not a power of two away",
+            node.name
+        ))
+    })?;
+    let name = format!("{}@{to}", node.name);
+
+    let key = runtime.add_node(
+        LegatoNode::new(name.clone(), "resample".into(), Box::new(resample)).with_rate(to),
+    );
+    names.insert(name, key);
+    for port_index in 0..chans {
+        runtime
+            .add_edge(Connection {
+                source: ConnectionEntry {
+                    node_key: source,
+                    port_index,
+                },
+                sink: ConnectionEntry {
+                    node_key: key,
+                    port_index,
+                },
+            })
+            .map_err(edge_error)?;
+    }
+    resamplers.insert((source, to), key);
+    Ok(key)
+}
+
 fn one_to_one(
     runtime: &mut Runtime,
     props: AddConnectionProps,
     source_index: usize,
     sink_index: usize,
-) {
-    runtime
-        .add_edge(Connection {
-            source: ConnectionEntry {
-                node_key: props.source,
-                port_index: source_index,
-            },
-            sink: ConnectionEntry {
-                node_key: props.sink,
-                port_index: sink_index,
-            },
-        })
-        .expect("Could not add edge");
+) -> Result<(), ValidationError> {
+    let connection = Connection {
+        source: ConnectionEntry {
+            node_key: props.source,
+            port_index: source_index,
+        },
+        sink: ConnectionEntry {
+            node_key: props.sink,
+            port_index: sink_index,
+        },
+    };
+    sink_edge(runtime, &props, connection)
 }
 
 fn one_to_n(
@@ -1009,15 +1515,18 @@ fn one_to_n(
     props: AddConnectionProps,
     source_index: usize,
     sink_indicies: &[usize],
-) {
+) -> Result<(), ValidationError> {
     let n = sink_indicies.len();
 
     // Fanout mixer going from 1 -> n
-    let mixer = runtime.add_node(LegatoNode::new(
-        format!("MonoFanOut{:?}{:?}", props.source, props.sink),
-        "MonoFanOut".into(),
-        Box::new(MonoFanOut::new(n)),
-    ));
+    let mixer = runtime.add_node(
+        LegatoNode::new(
+            format!("MonoFanOut{:?}{:?}", props.source, props.sink),
+            "MonoFanOut".into(),
+            Box::new(MonoFanOut::new(n)),
+        )
+        .with_rate(sink_rate(runtime, &props)),
+    );
 
     // Wire mono to mixer
     runtime
@@ -1031,24 +1540,24 @@ fn one_to_n(
                 port_index: 0,
             },
         })
-        .expect("Could not add edge");
+        .map_err(edge_error)?;
 
     // Wire fanout connection to each sink. We add this node in order to change the gain when fanning out
 
     for sink_index in sink_indicies.iter() {
-        runtime
-            .add_edge(Connection {
-                source: ConnectionEntry {
-                    node_key: mixer,
-                    port_index: 0,
-                },
-                sink: ConnectionEntry {
-                    node_key: props.sink,
-                    port_index: *sink_index,
-                },
-            })
-            .expect("Could not add edge");
+        let connection = Connection {
+            source: ConnectionEntry {
+                node_key: mixer,
+                port_index: 0,
+            },
+            sink: ConnectionEntry {
+                node_key: props.sink,
+                port_index: *sink_index,
+            },
+        };
+        sink_edge(runtime, &props, connection)?;
     }
+    Ok(())
 }
 
 fn n_to_one(
@@ -1056,15 +1565,18 @@ fn n_to_one(
     props: AddConnectionProps,
     source_indicies: &[usize],
     sink_index: usize,
-) {
+) -> Result<(), ValidationError> {
     let n = source_indicies.len();
 
     // Make mixer with n mono tracks
-    let mixer = runtime.add_node(LegatoNode::new(
-        format!("TrackMixer{:?}{:?}", props.source, props.sink),
-        "TrackMixer".into(),
-        Box::new(TrackMixer::new(1, n, vec![1.0 / f32::sqrt(n as f32); n])),
-    ));
+    let mixer = runtime.add_node(
+        LegatoNode::new(
+            format!("TrackMixer{:?}{:?}", props.source, props.sink),
+            "TrackMixer".into(),
+            Box::new(TrackMixer::new(1, n, vec![1.0 / f32::sqrt(n as f32); n])),
+        )
+        .with_rate(sink_rate(runtime, &props)),
+    );
 
     // Build connections into track mixer
     for (i, source_index) in source_indicies.iter().enumerate() {
@@ -1079,22 +1591,21 @@ fn n_to_one(
                     port_index: i,
                 },
             })
-            .expect("Could not add edge");
+            .map_err(edge_error)?;
     }
 
     // Wire track mixer to sink index
-    runtime
-        .add_edge(Connection {
-            source: ConnectionEntry {
-                node_key: mixer,
-                port_index: 0,
-            },
-            sink: ConnectionEntry {
-                node_key: props.sink,
-                port_index: sink_index,
-            },
-        })
-        .expect("Could not add edge");
+    let connection = Connection {
+        source: ConnectionEntry {
+            node_key: mixer,
+            port_index: 0,
+        },
+        sink: ConnectionEntry {
+            node_key: props.sink,
+            port_index: sink_index,
+        },
+    };
+    sink_edge(runtime, &props, connection)
 }
 
 fn n_to_n(
@@ -1102,23 +1613,20 @@ fn n_to_n(
     props: AddConnectionProps,
     source_indicies: &[usize],
     sink_indicies: &[usize],
-) {
+) -> Result<(), ValidationError> {
     assert!(source_indicies.len() == sink_indicies.len());
-    source_indicies
-        .iter()
-        .zip(sink_indicies)
-        .for_each(|(source, sink)| {
-            runtime
-                .add_edge(Connection {
-                    source: ConnectionEntry {
-                        node_key: props.source,
-                        port_index: *source,
-                    },
-                    sink: ConnectionEntry {
-                        node_key: props.sink,
-                        port_index: *sink,
-                    },
-                })
-                .expect("Could not add edge");
-        });
+    for (source, sink) in source_indicies.iter().zip(sink_indicies) {
+        let connection = Connection {
+            source: ConnectionEntry {
+                node_key: props.source,
+                port_index: *source,
+            },
+            sink: ConnectionEntry {
+                node_key: props.sink,
+                port_index: *sink,
+            },
+        };
+        sink_edge(runtime, &props, connection)?;
+    }
+    Ok(())
 }
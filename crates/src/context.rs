@@ -1,4 +1,4 @@
-use std::time::Instant;
+use std::{cell::Cell, fmt, ops::Mul, time::Instant};
 
 use slotmap::new_key_type;
 
@@ -36,11 +36,6 @@ impl AudioContext {
             block_start: Instant::now(),
         }
     }
-    /// For a time being, this is a quick hack inside oversampling. I would recommend not using, as it does not reflex internal state!!!
-    pub fn set_sample_rate(&mut self, sr: usize) {
-        self.config.sample_rate = sr;
-    }
-
     pub(crate) fn update_midi(&mut self) {
         self.clear_midi();
 
@@ -57,12 +52,21 @@ impl AudioContext {
         self.midi_runtime_frontend = Some(runtime);
     }
 
-    /// For a time being, this is a quick hack inside oversampling. I would recommend not using, as it does not reflex internal state!!!
-    pub fn set_block_size(&mut self, block_size: usize) {
-        self.config.block_size = block_size;
+    /// The config as the running node sees it: the block size and sample rate are scaled to
+    /// the node's [`Rate`], so an oversampled node sees more samples per block, and a faster
+    /// sample rate, than the graph itself.
+    #[inline(always)]
+    pub fn get_config(&self) -> Config {
+        let rate = NODE_RATE.with(Cell::get);
+        Config {
+            sample_rate: rate.scale(self.config.sample_rate),
+            block_size: rate.scale(self.config.block_size),
+            ..self.config
+        }
     }
 
-    pub fn get_config(&self) -> Config {
+    /// The graph's own config, whatever rate the running node is at.
+    pub fn graph_config(&self) -> Config {
         self.config
     }
 
@@ -78,17 +82,21 @@ impl AudioContext {
         &mut self.resources
     }
 
+    pub fn into_resources(self) -> Resources {
+        self.resources
+    }
+
     pub fn get_param(&self, key: &ParamKey) -> Result<f32, ParamError> {
         self.resources.get_param(key)
     }
 
     pub fn sample_rate_f32(&self) -> f32 {
-        self.config.sample_rate as f32
+        self.sample_rate() as f32
     }
 
     #[inline(always)]
     pub fn sample_rate(&self) -> usize {
-        self.config.sample_rate
+        NODE_RATE.with(Cell::get).scale(self.config.sample_rate)
     }
 
     // Add a midi store to the runtime.
@@ -141,3 +149,110 @@ impl AudioContext {
         }
     }
 }
+
+thread_local! {
+    // The rate of the node running on this thread, see `at_rate`
+    static NODE_RATE: Cell<Rate> = const { Cell::new(Rate::GRAPH) };
+}
+
+/// Run `f` with [`AudioContext::get_config`] scaled to `rate` on this thread.
+///
+/// The executor wraps each node's `process` in this, so nodes running alongside each other on
+/// the worker pool each see their own rate, and the shared config is never written.
+#[inline(always)]
+pub(crate) fn at_rate<R>(rate: Rate, f: impl FnOnce() -> R) -> R {
+    let outer = NODE_RATE.with(|r| r.replace(rate));
+    let out = f();
+    NODE_RATE.with(|r| r.set(outer));
+    out
+}
+
+/// How many times the graph's rate a node may run at, whether it got there by `oversample(n)`,
+/// a patch's own rate, or both. Every step up is another halfband stage on the way back down.
+pub const MAX_OVERSAMPLE: usize = 8;
+
+/// A node's sample rate, as the fraction `up / down` of the graph's.
+///
+/// Oversampled nodes run at [`Rate::times`] the graph's rate, and control-rate nodes at
+/// [`Rate::divided`] by the decimation. Always kept in lowest terms.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rate {
+    up: usize,
+    down: usize,
+}
+
+impl Rate {
+    /// The graph's own rate.
+    pub const GRAPH: Rate = Rate { up: 1, down: 1 };
+
+    /// `up / down` times the graph's rate. A zero on either side is taken as 1.
+    pub fn new(up: usize, down: usize) -> Self {
+        let (up, down) = (up.max(1), down.max(1));
+        let gcd = gcd(up, down);
+        Self {
+            up: up / gcd,
+            down: down / gcd,
+        }
+    }
+
+    /// `factor` times the graph's rate.
+    pub fn times(factor: usize) -> Self {
+        Self::new(factor, 1)
+    }
+
+    /// The graph's rate divided by `factor`.
+    pub fn divided(factor: usize) -> Self {
+        Self::new(1, factor)
+    }
+
+    pub fn up(self) -> usize {
+        self.up
+    }
+
+    pub fn down(self) -> usize {
+        self.down
+    }
+
+    /// `n` samples at the graph's rate, at this one instead. Rounded down.
+    #[inline(always)]
+    pub fn scale(self, n: usize) -> usize {
+        n * self.up / self.down
+    }
+
+    /// How many times faster `self` is than `other`, e.g. `x4` from the graph to a 4x node.
+    pub fn relative_to(self, other: Rate) -> Rate {
+        Rate::new(self.up * other.down, self.down * other.up)
+    }
+}
+
+impl Default for Rate {
+    fn default() -> Self {
+        Self::GRAPH
+    }
+}
+
+impl Mul for Rate {
+    type Output = Rate;
+
+    fn mul(self, rhs: Rate) -> Rate {
+        Rate::new(self.up * rhs.up, self.down * rhs.down)
+    }
+}
+
+/// `x4` for four times the graph's rate, `/2` for half of it, and `x3/2` otherwise.
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.up, self.down) {
+            (up, 1) => write!(f, "x{up}"),
+            (1, down) => write!(f, "/{down}"),
+            (up, down) => write!(f, "x{up}/{down}"),
+        }
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    match b {
+        0 => a,
+        b => gcd(b, a % b),
+    }
+}
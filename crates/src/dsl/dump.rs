@@ -0,0 +1,478 @@
+//! Dumping an [`IRGraph`] as Graphviz DOT or JSON, to look at or diff what a pass did.
+//!
+//! Both dumps list nodes and edges in the graph's own order, which only changes when a pass
+//! changes the graph, so the dumps taken around a pass diff cleanly. See
+//! [`crate::dsl::pipeline::Pipeline::dump_json`].
+
+use std::fmt::Write;
+
+use crate::dsl::{
+    ir::*,
+    print::{print_endpoint, print_macro, print_pipes, print_value},
+};
+
+impl IRGraph {
+    /// The graph as Graphviz DOT, e.g. for `dot -Tsvg`.
+    ///
+    /// Nodes are labelled with their alias, type, count, sample rate and oversampling, and
+    /// edges with the selectors and ports at each end. `~>` edges are dashed, and the sink has a double border.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph ir {\n    rankdir=LR;\n    node [shape=box];\n");
+
+        for node in self.nodes() {
+            let mut label = format!("{}\n{}::{}", node.alias, node.namespace, node.node_type);
+            match node.kind {
+                IRNodeKind::Leaf => {}
+                IRNodeKind::MacroRef => label.push_str(" (patch)"),
+                IRNodeKind::KernelRef => label.push_str(" (kernel)"),
+            }
+            if node.count != 1 {
+                write!(label, " * {}", node.count).unwrap();
+            }
+            if let Some(rate) = node.rate {
+                write!(label, " @ {rate}").unwrap();
+            }
+            if node.oversample > 1 {
+                write!(label, " @ x{}", node.oversample).unwrap();
+            }
+            let sink = if self.sink == Some(node.id) {
+                ", peripheries=2"
+            } else {
+                ""
+            };
+            writeln!(out, "    n{} [label={label:?}{sink}];", node.id.index()).unwrap();
+        }
+
+        for edge in self.edges() {
+            let label = format!(
+                "{} → {}",
+                endpoint("", &edge.source_selector, &edge.source_port),
+                endpoint("", &edge.sink_selector, &edge.sink_port)
+            );
+            let style = if edge.feedback { ", style=dashed" } else { "" };
+            writeln!(
+                out,
+                "    n{} -> n{} [label={:?}{style}];",
+                edge.source.index(),
+                edge.sink.index(),
+                label.trim()
+            )
+            .unwrap();
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// The graph as JSON, one node or edge per line.
+    ///
+    /// Edges name their nodes by alias rather than id, as passes that rebuild the graph
+    /// hand out new ids to the same nodes. Params are kept as JSON where they can be, and
+    /// templates and expressions as their DSL text. Patches and kernels are listed with
+    /// their bodies, templates as the DSL they were written as, and pipes with the aliases
+    /// of the nodes they run on.
+    pub fn to_json(&self) -> String {
+        self.json("") + "\n"
+    }
+
+    /// [`Self::to_json`] without the trailing newline, nested `indent` deep.
+    fn json(&self, indent: &str) -> String {
+        let alias = |id: NodeId| self.get_node(id).map_or("?", |node| node.alias.as_str());
+
+        let nodes: Vec<String> = self
+            .nodes()
+            .map(|node| {
+                let kind = match node.kind {
+                    IRNodeKind::Leaf => "leaf",
+                    IRNodeKind::MacroRef => "patch",
+                    IRNodeKind::KernelRef => "kernel",
+                };
+                format!(
+                    r#"{{"id": {}, "alias": {}, "kind": "{kind}", "namespace": {}, "type": {}, "count": {}, "params": {}}}"#,
+                    node.id.index(),
+                    json_string(&node.alias),
+                    json_string(&node.namespace),
+                    json_string(&node.node_type),
+                    node.count,
+                    json_object(&node.params)
+                )
+            })
+            .collect();
+
+        let edges: Vec<String> = self
+            .edges()
+            .iter()
+            .map(|edge| {
+                format!(
+                    r#"{{"source": {}, "sink": {}, "feedback": {}}}"#,
+                    json_string(&endpoint(
+                        alias(edge.source),
+                        &edge.source_selector,
+                        &edge.source_port
+                    )),
+                    json_string(&endpoint(
+                        alias(edge.sink),
+                        &edge.sink_selector,
+                        &edge.sink_port
+                    )),
+                    edge.feedback
+                )
+            })
+            .collect();
+
+        let sink = self.sink.map_or("null".into(), |id| json_string(alias(id)));
+        let outputs: Vec<String> = self
+            .outputs
+            .iter()
+            .map(|(name, id)| format!("{}: {}", json_string(name), json_string(alias(*id))))
+            .collect();
+
+        let pipes: Vec<String> = self
+            .pipes
+            .iter()
+            .map(|pipe| {
+                let nodes: Vec<String> = pipe
+                    .nodes
+                    .iter()
+                    .map(|id| json_string(alias(*id)))
+                    .collect();
+                format!(
+                    r#"{{"nodes": [{}], "chain": {}}}"#,
+                    nodes.join(", "),
+                    json_string(&print_pipes(&pipe.chain))
+                )
+            })
+            .collect();
+
+        // The registries are hash maps, so sort them to keep the dumps diffable
+        let inner = format!("{indent}    ");
+        let mut macros: Vec<&IRMacro> = self.macro_registry.values().collect();
+        macros.sort_by(|a, b| a.name.cmp(&b.name));
+        let macros: Vec<String> = macros
+            .into_iter()
+            .map(|mac| format!("{}: {}", json_string(&mac.name), mac.json(&inner)))
+            .collect();
+
+        let mut templates: Vec<&AstMacro> = self.templates.values().collect();
+        templates.sort_by(|a, b| a.name.cmp(&b.name));
+        let templates: Vec<String> = templates
+            .into_iter()
+            .map(|mac| {
+                format!(
+                    "{}: {}",
+                    json_string(&mac.name),
+                    json_string(&print_macro(mac))
+                )
+            })
+            .collect();
+
+        format!(
+            "{{\n{indent}  \"nodes\": {},\n{indent}  \"edges\": {},\n{indent}  \"sink\": {sink},\n{indent}  \"outputs\": {{{}}},\n{indent}  \"pipes\": {},\n{indent}  \"macros\": {},\n{indent}  \"templates\": {}\n{indent}}}",
+            json_lines(&nodes, indent, '[', ']'),
+            json_lines(&edges, indent, '[', ']'),
+            outputs.join(", "),
+            json_lines(&pipes, indent, '[', ']'),
+            json_lines(&macros, indent, '{', '}'),
+            json_lines(&templates, indent, '{', '}'),
+        )
+    }
+}
+
+impl IRMacro {
+    /// The patch or kernel as JSON, with its ports as the endpoints in its body they lead
+    /// to, and the body as a graph of its own.
+    fn json(&self, indent: &str) -> String {
+        let alias = |id: NodeId| {
+            self.body
+                .get_node(id)
+                .map_or("?", |node| node.alias.as_str())
+        };
+        let kind = match self.kind {
+            MacroKind::Patch => "patch",
+            MacroKind::Kernel => "kernel",
+        };
+        let params = self
+            .default_params
+            .as_ref()
+            .map_or("null".into(), json_object);
+        let rate = self
+            .rate
+            .map_or("null".into(), |rate| json_string(&rate.to_string()));
+
+        let inputs: Vec<String> = self
+            .virtual_input_map
+            .iter()
+            .map(|(name, targets)| {
+                let targets: Vec<String> = targets
+                    .iter()
+                    .map(|(id, selector, port)| json_string(&endpoint(alias(*id), selector, port)))
+                    .collect();
+                format!("{}: [{}]", json_string(name), targets.join(", "))
+            })
+            .collect();
+        let outputs: Vec<String> = self
+            .virtual_output_map
+            .iter()
+            .map(|(name, (id, selector, port))| {
+                format!(
+                    "{}: {}",
+                    json_string(name),
+                    json_string(&endpoint(alias(*id), selector, port))
+                )
+            })
+            .collect();
+
+        let inner = format!("{indent}  ");
+        format!(
+            "{{\n{inner}\"kind\": \"{kind}\",\n{inner}\"params\": {params},\n{inner}\"rate\": {rate},\n{inner}\"inputs\": {{{}}},\n{inner}\"outputs\": {{{}}},\n{inner}\"sink\": {},\n{inner}\"body\": {}\n{indent}}}",
+            inputs.join(", "),
+            outputs.join(", "),
+            json_string(alias(self.sink)),
+            self.body.json(&inner),
+        )
+    }
+}
+
+/// An end of an edge as it would be written in a connection, e.g. `voice(*).gate`.
+fn endpoint(node: &str, selector: &NodeSelector, port: &Port) -> String {
+    print_endpoint(&Endpoint {
+        node: node.into(),
+        node_selector: selector.clone(),
+        port: port.clone(),
+    })
+}
+
+/// A JSON array or object with one item per line, in a value nested `indent` deep.
+fn json_lines(items: &[String], indent: &str, open: char, close: char) -> String {
+    if items.is_empty() {
+        return format!("{open}{close}");
+    }
+    let separator = format!(",\n{indent}    ");
+    format!(
+        "{open}\n{indent}    {}\n{indent}  {close}",
+        items.join(&separator)
+    )
+}
+
+fn json_object(object: &Object) -> String {
+    let fields: Vec<String> = object
+        .iter()
+        .map(|(k, v)| format!("{}: {}", json_string(k), json_value(v)))
+        .collect();
+    format!("{{{}}}", fields.join(", "))
+}
+
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::U32(x) => x.to_string(),
+        Value::I32(x) => x.to_string(),
+        Value::F32(x) if x.is_finite() => print_value(value),
+        Value::Bool(x) => x.to_string(),
+        Value::String(x) => json_string(x),
+        Value::Array(xs) => {
+            let xs: Vec<String> = xs.iter().map(json_value).collect();
+            format!("[{}]", xs.join(", "))
+        }
+        Value::Object(object) => json_object(object),
+        Value::F32(_) | Value::Ident(_) | Value::Template(_) | Value::Expr(_) => {
+            json_string(&print_value(value))
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        builder::ValidationError,
+        dsl::{parse::legato_parser, pipeline::Pipeline},
+    };
+
+    fn lowered(src: &str) -> IRGraph {
+        Pipeline::new()
+            .run_from_ast(legato_parser(src).unwrap())
+            .unwrap()
+    }
+
+    const SRC: &str = r#"
+        audio {
+            sine * 2 { freq: 440.0, name: "a \"b\"" },
+            add: acc { val: $x + 1.0 }
+        }
+        sine(*) >> acc[0]
+        acc ~> acc[1]
+        { acc }
+    "#;
+
+    #[test]
+    fn json_lists_nodes_and_edges_by_alias() {
+        let json = lowered(SRC).to_json();
+
+        assert_eq!(
+            json,
+            r#"{
+  "nodes": [
+    {"id": 0, "alias": "sine", "kind": "leaf", "namespace": "audio", "type": "sine", "count": 2, "params": {"freq": 440.0, "name": "a \"b\""}},
+    {"id": 1, "alias": "acc", "kind": "leaf", "namespace": "audio", "type": "add", "count": 1, "params": {"val": "$x + 1.0"}}
+  ],
+  "edges": [
+    {"source": "sine(*)", "sink": "acc[0]", "feedback": false},
+    {"source": "acc", "sink": "acc[1]", "feedback": true}
+  ],
+  "sink": "acc",
+  "outputs": {},
+  "pipes": [],
+  "macros": {},
+  "templates": {}
+}
+"#
+        );
+    }
+
+    #[test]
+    fn json_lists_macros_templates_and_pipes() {
+        let json = lowered(
+            r#"
+            patch gain(level = 0.5) @ /2 {
+                in x
+                audio { mult: amp { val: $level } }
+                x >> amp[0]
+                { amp }
+            }
+            patch wide() {
+                oversample(2) {
+                    audio { sine }
+                }
+                { sine }
+            }
+            audio { saw | stereo }
+            patches { gain, wide }
+            saw >> gain.x
+            { gain }
+        "#,
+        )
+        .to_json();
+
+        assert!(
+            json.contains(r#"{"nodes": ["saw"], "chain": "stereo"}"#),
+            "{json}"
+        );
+        assert!(
+            json.contains(
+                r#"
+    "gain": {
+      "kind": "patch",
+      "params": {"level": 0.5},
+      "rate": "/2",
+      "inputs": {"x": ["amp[0]"]},
+      "outputs": {},
+      "sink": "amp",
+      "body": {
+        "nodes": [
+          {"id": 0, "alias": "amp", "kind": "leaf", "namespace": "audio", "type": "mult", "count": 1, "params": {"val": "$level"}}
+        ],"#
+            ),
+            "{json}"
+        );
+        assert!(
+            json.contains(r#""wide": "patch wide() {\n    oversample(2) {"#),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn dot_labels_nodes_and_edges() {
+        let dot = lowered(SRC).to_dot();
+
+        assert!(dot.starts_with("digraph ir {"), "{dot}");
+        assert!(
+            dot.contains(r#"n0 [label="sine\naudio::sine * 2"];"#),
+            "{dot}"
+        );
+        assert!(
+            dot.contains(r#"n1 [label="acc\naudio::add", peripheries=2];"#),
+            "{dot}"
+        );
+        assert!(dot.contains(r#"n0 -> n1 [label="(*) → [0]"];"#), "{dot}");
+        assert!(
+            dot.contains(r#"n1 -> n1 [label="→ [1]", style=dashed];"#),
+            "{dot}"
+        );
+    }
+
+    #[test]
+    fn pipeline_dumps_every_pass() {
+        let dir = std::env::temp_dir().join(format!("legato-dump-{}", std::process::id()));
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let log = seen.clone();
+        Pipeline::default()
+            .inspect(move |name, _| log.borrow_mut().push(name.to_string()))
+            .dump_json(&dir)
+            .run_from_ast(legato_parser(&SRC.replace("$x + 1.0", "1.0")).unwrap())
+            .unwrap();
+        let mut names: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        let last = std::fs::read_to_string(dir.join("4-ResolvePass.json")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            *seen.borrow(),
+            [
+                "input",
+                "GenerativePass",
+                "MacroExpansionPass",
+                "SpawnKNodesPass",
+                "ResolvePass"
+            ]
+        );
+        assert_eq!(
+            names,
+            [
+                "0-input.json",
+                "1-GenerativePass.json",
+                "2-MacroExpansionPass.json",
+                "3-SpawnKNodesPass.json",
+                "4-ResolvePass.json"
+            ]
+        );
+        assert!(last.contains(r#""alias": "sine.1""#), "{last}");
+    }
+
+    #[test]
+    fn unwritable_dumps_fail_the_run() {
+        // A file where the directory should be
+        let file = std::env::temp_dir().join(format!("legato-dump-file-{}", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let err = Pipeline::default()
+            .dump_json(file.join("passes"))
+            .run_from_ast(legato_parser(SRC).unwrap())
+            .unwrap_err();
+        std::fs::remove_file(&file).unwrap();
+
+        assert!(
+            matches!(&err, ValidationError::PipelineDump(msg) if msg.contains("0-input.json")),
+            "{err:?}"
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! Evaluation of param expressions such as `$base * 2.0` or `clamp($q, 0.5, 8.0)`.
+//!
+//! Integers stay integers as long as every operand is one, so `$voices - 1` is still a
+//! `U32` that count-like params accept. Mixing in an `F32` makes the result an `F32`.
+
+use crate::builder::ValidationError;
+use crate::dsl::ir::{BinaryOp, Expr, ExprKind, Object, Span, Value};
+
+type Function = fn(f32) -> f32;
+
+/// Single-argument functions, which always return an `F32`.
+const FUNCTIONS: &[(&str, Function)] = &[
+    ("floor", f32::floor),
+    ("ceil", f32::ceil),
+    ("round", f32::round),
+    ("sqrt", f32::sqrt),
+    ("exp", f32::exp),
+    ("ln", f32::ln),
+    ("log2", f32::log2),
+    ("log10", f32::log10),
+    ("sin", f32::sin),
+    ("cos", f32::cos),
+    ("tan", f32::tan),
+    // Decibels to linear gain
+    ("db", |x| 10f32.powf(x / 20.0)),
+    // MIDI note number to Hz
+    ("mtof", |x| 440.0 * 2f32.powf((x - 69.0) / 12.0)),
+];
+
+#[derive(Clone, Copy, Debug)]
+enum Num {
+    // Whether the integer was unsigned, so unsigned arithmetic can give back a `U32`
+    Int(i64, bool),
+    Float(f32),
+}
+
+impl Num {
+    fn of(value: &Value, span: &Span) -> Result<Self, ValidationError> {
+        match value {
+            Value::U32(x) => Ok(Num::Int(*x as i64, true)),
+            Value::I32(x) => Ok(Num::Int(*x as i64, false)),
+            Value::F32(x) => Ok(Num::Float(*x)),
+            other => Err(invalid(format!("expected a number, found {other:?}"), span)),
+        }
+    }
+
+    fn as_f32(self) -> f32 {
+        match self {
+            Num::Int(x, _) => x as f32,
+            Num::Float(x) => x,
+        }
+    }
+
+    fn into_value(self, span: &Span) -> Result<Value, ValidationError> {
+        match self {
+            Num::Float(x) if x.is_finite() => Ok(Value::F32(x)),
+            Num::Float(x) => Err(invalid(format!("evaluates to {x}"), span)),
+            Num::Int(x, true) if x >= 0 && x <= u32::MAX as i64 => Ok(Value::U32(x as u32)),
+            Num::Int(x, _) => i32::try_from(x)
+                .map(Value::I32)
+                .map_err(|_| invalid(format!("{x} is out of range"), span)),
+        }
+    }
+}
+
+/// Evaluate `expr`, looking up its templates in `scope`.
+pub fn eval(expr: &Expr, scope: &Object) -> Result<Value, ValidationError> {
+    eval_num(expr, scope)?.into_value(&expr.span)
+}
+
+fn eval_num(expr: &Expr, scope: &Object) -> Result<Num, ValidationError> {
+    let span = &expr.span;
+    match &expr.kind {
+        ExprKind::Number(value) => Num::of(value, span),
+        ExprKind::Template(name) => {
+            let value = scope
+                .get(name.trim_start_matches('$'))
+                .ok_or_else(|| invalid(format!("unknown param `{name}`"), span))?;
+            Num::of(value, span)
+        }
+        ExprKind::Neg(inner) => Ok(match eval_num(inner, scope)? {
+            Num::Int(x, _) => Num::Int(x.checked_neg().ok_or_else(|| overflow(span))?, false),
+            Num::Float(x) => Num::Float(-x),
+        }),
+        ExprKind::Binary(op, lhs, rhs) => {
+            binary(*op, eval_num(lhs, scope)?, eval_num(rhs, scope)?, span)
+        }
+        ExprKind::Call(name, args) => {
+            let args = args
+                .iter()
+                .map(|arg| eval_num(arg, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            call(name, &args, span)
+        }
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Num, rhs: Num, span: &Span) -> Result<Num, ValidationError> {
+    let divides = matches!(op, BinaryOp::Div | BinaryOp::Rem);
+    if divides && rhs.as_f32() == 0.0 {
+        return Err(invalid("division by zero".into(), span));
+    }
+
+    Ok(match (lhs, rhs) {
+        (Num::Int(a, a_unsigned), Num::Int(b, b_unsigned)) => {
+            let x = match op {
+                BinaryOp::Add => a.checked_add(b),
+                BinaryOp::Sub => a.checked_sub(b),
+                BinaryOp::Mul => a.checked_mul(b),
+                BinaryOp::Div => a.checked_div(b),
+                BinaryOp::Rem => a.checked_rem(b),
+            };
+            Num::Int(x.ok_or_else(|| overflow(span))?, a_unsigned && b_unsigned)
+        }
+        _ => {
+            let (a, b) = (lhs.as_f32(), rhs.as_f32());
+            Num::Float(match op {
+                BinaryOp::Add => a + b,
+                BinaryOp::Sub => a - b,
+                BinaryOp::Mul => a * b,
+                BinaryOp::Div => a / b,
+                BinaryOp::Rem => a % b,
+            })
+        }
+    })
+}
+
+fn call(name: &str, args: &[Num], span: &Span) -> Result<Num, ValidationError> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(invalid(
+                format!("`{name}` takes {n} argument(s), found {}", args.len()),
+                span,
+            ))
+        }
+    };
+    // Keeps the winning argument as it was, so `min` of integers is an integer
+    let pick = |a: Num, b: Num, less: bool| {
+        if (a.as_f32() < b.as_f32()) == less {
+            a
+        } else {
+            b
+        }
+    };
+
+    match name {
+        "min" | "max" => {
+            if args.len() < 2 {
+                return Err(invalid(
+                    format!("`{name}` takes at least 2 arguments, found {}", args.len()),
+                    span,
+                ));
+            }
+            let less = name == "min";
+            Ok(args[1..].iter().fold(args[0], |a, b| pick(a, *b, less)))
+        }
+        "clamp" => {
+            arity(3)?;
+            Ok(pick(pick(args[0], args[2], true), args[1], false))
+        }
+        "pow" => {
+            arity(2)?;
+            Ok(Num::Float(args[0].as_f32().powf(args[1].as_f32())))
+        }
+        "abs" => {
+            arity(1)?;
+            Ok(match args[0] {
+                Num::Int(x, unsigned) => {
+                    Num::Int(x.checked_abs().ok_or_else(|| overflow(span))?, unsigned)
+                }
+                Num::Float(x) => Num::Float(x.abs()),
+            })
+        }
+        _ => {
+            let (_, f) = FUNCTIONS
+                .iter()
+                .find(|(n, _)| *n == name)
+                .ok_or_else(|| invalid(format!("unknown function `{name}`"), span))?;
+            arity(1)?;
+            Ok(Num::Float(f(args[0].as_f32())))
+        }
+    }
+}
+
+fn invalid(message: String, span: &Span) -> ValidationError {
+    ValidationError::InvalidExpression(message, span.clone())
+}
+
+fn overflow(span: &Span) -> ValidationError {
+    invalid("integer overflow".into(), span)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dsl::ir::Object;
+
+    fn num(value: impl Into<Value>) -> Expr {
+        Expr {
+            kind: ExprKind::Number(value.into()),
+            span: 0..0,
+        }
+    }
+
+    fn template(name: &str, span: Span) -> Expr {
+        Expr {
+            kind: ExprKind::Template(name.into()),
+            span,
+        }
+    }
+
+    fn bin(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr {
+            kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+            span: 0..0,
+        }
+    }
+
+    fn call(name: &str, args: Vec<Expr>) -> Expr {
+        Expr {
+            kind: ExprKind::Call(name.into(), args),
+            span: 0..0,
+        }
+    }
+
+    #[test]
+    fn integers_stay_integers() {
+        let scope = Object::from([("voices".to_string(), Value::U32(4))]);
+        let minus = |rhs: u32| bin(BinaryOp::Sub, template("$voices", 0..7), num(rhs));
+
+        assert_eq!(eval(&minus(1), &scope), Ok(Value::U32(3)));
+        assert_eq!(eval(&minus(5), &scope), Ok(Value::I32(-1)));
+        assert_eq!(
+            eval(&bin(BinaryOp::Div, num(7u32), num(2u32)), &scope),
+            Ok(Value::U32(3))
+        );
+        assert_eq!(
+            eval(&bin(BinaryOp::Div, num(7u32), num(2.0)), &scope),
+            Ok(Value::F32(3.5))
+        );
+    }
+
+    #[test]
+    fn functions() {
+        let scope = Object::new();
+
+        assert_eq!(
+            eval(
+                &call("clamp", vec![num(12u32), num(0u32), num(10u32)]),
+                &scope
+            ),
+            Ok(Value::U32(10))
+        );
+        assert_eq!(
+            eval(&call("max", vec![num(1u32), num(2.5), num(-3)]), &scope),
+            Ok(Value::F32(2.5))
+        );
+        assert_eq!(
+            eval(&call("pow", vec![num(2u32), num(3u32)]), &scope),
+            Ok(Value::F32(8.0))
+        );
+        assert_eq!(
+            eval(&call("mtof", vec![num(69u32)]), &scope),
+            Ok(Value::F32(440.0))
+        );
+    }
+
+    #[test]
+    fn errors_carry_the_offending_span() {
+        let scope = Object::from([("name".to_string(), Value::String("lead".into()))]);
+
+        let unknown = bin(BinaryOp::Mul, template("$missing", 3..11), num(2u32));
+        assert!(matches!(
+            eval(&unknown, &scope),
+            Err(ValidationError::InvalidExpression(_, span)) if span == (3..11)
+        ));
+
+        let mismatch = bin(BinaryOp::Add, template("$name", 5..10), num(1u32));
+        assert!(matches!(
+            eval(&mismatch, &scope),
+            Err(ValidationError::InvalidExpression(_, span)) if span == (5..10)
+        ));
+
+        assert!(eval(&call("nope", vec![num(1u32)]), &scope).is_err());
+        assert!(eval(&call("pow", vec![num(1u32)]), &scope).is_err());
+        assert!(eval(&bin(BinaryOp::Div, num(1.0), num(0u32)), &scope).is_err());
+    }
+
+    #[test]
+    fn integer_overflow_is_an_error() {
+        let scope = Object::new();
+        let big = Expr {
+            span: 2..9,
+            ..bin(BinaryOp::Mul, num(65_536u32), num(65_536u32))
+        };
+        let bigger = bin(BinaryOp::Mul, big.clone(), big);
+
+        assert!(matches!(
+            eval(&bigger, &scope),
+            Err(ValidationError::InvalidExpression(msg, span)) if msg.contains("overflow") && span == (0..0)
+        ));
+    }
+}
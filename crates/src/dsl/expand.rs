@@ -1,5 +1,7 @@
 use crate::builder::ValidationError;
 use crate::dsl::{
+    eval::eval,
+    generate::unfilled,
     ir::*,
     pipeline::GraphPass,
     resolve::{port_for_instance, source_ports},
@@ -36,6 +38,24 @@ fn selection_of(graph: &IRGraph, node: NodeId, sel: &NodeSelector) -> Vec<usize>
     sel.selected_indices(count as usize)
 }
 
+/// Where an expanded instance's outputs come from: its sink, or the node behind one of its
+/// `out` declarations.
+struct Outputs {
+    sink: NodeId,
+    named: IndexMap<String, (NodeId, NodeSelector, Port)>,
+}
+
+impl Outputs {
+    /// The node, selector and port that reading `port` off the instance reads. A name the
+    /// patch declares with `out` is looked up, anything else is a port of the sink.
+    fn line(&self, port: &Port) -> (NodeId, NodeSelector, Port) {
+        match port {
+            Port::Named(name) if self.named.contains_key(name) => self.named[name].clone(),
+            _ => (self.sink, NodeSelector::Single, port.clone()),
+        }
+    }
+}
+
 /// This pass expands all [`IRMacros`] into the interior nodes,
 /// wires the new interior connections, then handles connections
 /// in and out to the macro instance.
@@ -63,16 +83,26 @@ impl GraphPass for MacroExpansionPass {
             }
             depth += 1;
         }
+
+        // Expressions outside of any patch, which have no params to refer to
+        let ids: Vec<NodeId> = graph.nodes().map(|n| n.id).collect();
+        for id in ids {
+            if let Some(node) = graph.get_node_mut(id) {
+                substitute_templates(&mut node.params, &Object::new())?;
+            }
+        }
+        for pipe in &mut graph.pipes {
+            substitute_pipes(&mut pipe.chain, &Object::new()).map_err(|e| e.at(&pipe.span))?;
+        }
         Ok(graph)
     }
 }
 
 impl MacroExpansionPass {
     fn expand_macro(&self, graph: &mut IRGraph, node_id: NodeId) -> Result<(), ValidationError> {
-        let node = graph
-            .get_node(node_id)
-            .expect("macro node vanished between listing and expanding")
-            .clone();
+        let Some(node) = graph.get_node(node_id).cloned() else {
+            return Ok(());
+        };
 
         let ir_macro = graph
             .macro_registry
@@ -89,6 +119,9 @@ impl MacroExpansionPass {
         for (k, v) in &node.params {
             resolved_params.insert(k.clone(), v.clone());
         }
+        // Nested instantiations were evaluated when their parent was cloned, so anything
+        // left here is written with literals
+        substitute_templates(&mut resolved_params, &Object::new())?;
 
         let incoming: Vec<IREdge> = graph.incoming_edges(node_id).cloned().collect();
         let outgoing: Vec<IREdge> = graph.outgoing_edges(node_id).cloned().collect();
@@ -96,6 +129,7 @@ impl MacroExpansionPass {
 
         // Expand n=count instances, each with a distinct alias prefix.
         let mut new_sinks: Vec<NodeId> = Vec::with_capacity(node.count as usize);
+        let mut new_outputs: Vec<Outputs> = Vec::with_capacity(node.count as usize);
 
         for i in 0..node.count as usize {
             let instance_alias = if node.count == 1 {
@@ -104,9 +138,21 @@ impl MacroExpansionPass {
                 format!("{}.{}", node.alias, i)
             };
 
-            let id_map = self.clone_body_into(graph, &ir_macro, &instance_alias, &resolved_params);
+            let id_map = self
+                .clone_body_into(graph, &ir_macro, &instance_alias, &resolved_params, &node)
+                .map_err(|e| e.at(&node.span))?;
             let new_sink = id_map[&ir_macro.sink];
             new_sinks.push(new_sink);
+            new_outputs.push(Outputs {
+                sink: new_sink,
+                named: ir_macro
+                    .virtual_output_map
+                    .iter()
+                    .map(|(name, (id, sel, port))| {
+                        (name.clone(), (id_map[id], sel.clone(), port.clone()))
+                    })
+                    .collect(),
+            });
 
             let remapped_virtual: IndexMap<String, Vec<(NodeId, NodeSelector, Port)>> = ir_macro
                 .virtual_input_map
@@ -139,19 +185,26 @@ impl MacroExpansionPass {
                     edge.source_port.clone()
                 };
 
-                // Pair the source selection against this instance's position.
-                let sources = selection_of(graph, edge.source, &edge.source_selector);
-                let resolved_source_selector =
-                    pair_selector(&sources, pos, picked.len(), &edge.source_selector).ok_or_else(
-                        || {
-                            ValidationError::SelectionArity(format!(
-                                "cannot match selection arity {}:{} into patch '{}'",
-                                sources.len(),
-                                picked.len(),
-                                node.node_type
-                            ))
-                        },
-                    )?;
+                // A patch wired into itself, e.g. `verb ~> verb`, has each instance read itself
+                let (source, resolved_source_selector, resolved_source_port) =
+                    if edge.source == node_id {
+                        new_outputs[i].line(&edge.source_port)
+                    } else {
+                        // Pair the source selection against this instance's position.
+                        let sources = selection_of(graph, edge.source, &edge.source_selector);
+                        let selector =
+                            pair_selector(&sources, pos, picked.len(), &edge.source_selector)
+                                .ok_or_else(|| {
+                                    ValidationError::SelectionArity(format!(
+                                        "cannot match selection arity {}:{} into patch '{}'",
+                                        sources.len(),
+                                        picked.len(),
+                                        node.node_type
+                                    ))
+                                    .at(&edge.span)
+                                })?;
+                        (edge.source, selector, resolved_source_port)
+                    };
 
                 let targets: Vec<(NodeId, NodeSelector, Port)> = match &edge.sink_port {
                     Port::Named(name) => remapped_virtual.get(name).cloned().unwrap_or_else(|| {
@@ -172,25 +225,35 @@ impl MacroExpansionPass {
                             "slice and stride ports are not supported on the virtual ports of \
                              patch '{}'",
                             node.node_type
-                        )));
+                        ))
+                        .at(&edge.span));
                     }
+                    Port::Template(text) => return Err(unfilled(text).at(&edge.span)),
                 };
                 for (target_id, target_selector, target_port) in targets {
-                    graph.connect_multi(
-                        edge.source,
-                        resolved_source_selector.clone(),
-                        resolved_source_port.clone(),
-                        target_id,
-                        target_selector,
-                        target_port,
-                    );
+                    graph
+                        .connect_multi(
+                            source,
+                            resolved_source_selector.clone(),
+                            resolved_source_port.clone(),
+                            target_id,
+                            target_selector,
+                            target_port,
+                        )
+                        .inherit(edge);
                 }
             }
         }
 
-        // Rewire outgoing edges from the last instance
-        for edge in &outgoing {
-            let srcs = edge.source_selector.select(&new_sinks).to_vec();
+        // Rewire outgoing edges from the last instance. Edges back into the patch were
+        // wired per instance above.
+        for edge in outgoing.iter().filter(|e| e.sink != node_id) {
+            let srcs: Vec<_> = edge
+                .source_selector
+                .select(&new_outputs)
+                .iter()
+                .map(|outputs| outputs.line(&edge.source_port))
+                .collect();
             let multi_src = srcs.len() > 1;
             // Splitting one edge per source instance would otherwise let each
             // half broadcast independently, giving a cross product.
@@ -200,32 +263,36 @@ impl MacroExpansionPass {
             // sink's port slice. Instances x source-ports must equal the slice
             // width exactly, so there is one flatten axis and no cross-product.
             if let (true, 1, Port::Slice(start, end)) = (multi_src, sinks.len(), &edge.sink_port) {
-                let width = end - start;
-                let ports = source_ports(&edge.source_port);
-                if srcs.len() * ports.len() != width {
+                // Every instance reads the same port of its own copy of the body
+                let ports = source_ports(&srcs[0].2);
+                if end.checked_sub(*start) != Some(srcs.len() * ports.len()) {
                     return Err(ValidationError::SelectionArity(format!(
-                        "source lines ({} instances x {} ports) must equal sink port slice width ({width}) out of patch '{}'",
+                        "source lines ({} instances x {} ports) must equal sink port slice width ({}) out of patch '{}'",
                         srcs.len(),
                         ports.len(),
+                        end.saturating_sub(*start),
                         node.node_type,
-                    )));
+                    ))
+                    .at(&edge.span));
                 }
-                for (i, &src) in srcs.iter().enumerate() {
+                for (i, (src, selector, _)) in srcs.iter().enumerate() {
                     for (offset, source_port) in ports.iter().enumerate() {
-                        graph.connect_multi(
-                            src,
-                            NodeSelector::Single,
-                            source_port.clone(),
-                            edge.sink,
-                            NodeSelector::Single,
-                            Port::Index(start + i * ports.len() + offset),
-                        );
+                        graph
+                            .connect_multi(
+                                *src,
+                                selector.clone(),
+                                source_port.clone(),
+                                edge.sink,
+                                NodeSelector::Single,
+                                Port::Index(start + i * ports.len() + offset),
+                            )
+                            .inherit(edge);
                     }
                 }
                 continue;
             }
 
-            for (i, &src) in srcs.iter().enumerate() {
+            for (i, (src, selector, port)) in srcs.iter().enumerate() {
                 let resolved_sink_selector =
                     pair_selector(&sinks, i, srcs.len(), &edge.sink_selector).ok_or_else(|| {
                         ValidationError::SelectionArity(format!(
@@ -234,6 +301,7 @@ impl MacroExpansionPass {
                             sinks.len(),
                             node.node_type
                         ))
+                        .at(&edge.span)
                     })?;
 
                 // Distribute a strided/sliced sink port one index per source
@@ -245,20 +313,29 @@ impl MacroExpansionPass {
                 } else {
                     edge.sink_port.clone()
                 };
-                graph.connect_multi(
-                    src,
-                    NodeSelector::Single,
-                    edge.source_port.clone(),
-                    edge.sink,
-                    resolved_sink_selector,
-                    resolved_sink_port,
-                );
+                graph
+                    .connect_multi(
+                        *src,
+                        selector.clone(),
+                        port.clone(),
+                        edge.sink,
+                        resolved_sink_selector,
+                        resolved_sink_port,
+                    )
+                    .inherit(edge);
             }
         }
 
         if graph.sink == Some(node_id) {
             graph.sink = new_sinks.last().copied();
         }
+        for (_, output) in graph.outputs.iter_mut() {
+            if *output == node_id
+                && let Some(last) = new_sinks.last()
+            {
+                *output = *last;
+            }
+        }
         if graph.source == Some(node_id) {
             graph.source = new_sinks.first().copied();
         }
@@ -272,7 +349,9 @@ impl MacroExpansionPass {
         ir_macro: &IRMacro,
         instance_alias: &str,
         resolved_params: &Object,
-    ) -> HashMap<NodeId, NodeId> {
+        instance: &IRNode,
+    ) -> Result<HashMap<NodeId, NodeId>, ValidationError> {
+        let instance_span = &instance.span;
         let mut id_map: HashMap<NodeId, NodeId> = HashMap::new();
 
         // A this point, everything should be a leaf!
@@ -280,7 +359,7 @@ impl MacroExpansionPass {
             let fqn = format!("{}.{}", instance_alias, node.alias);
 
             let mut params = node.params.clone();
-            substitute_templates(&mut params, resolved_params);
+            substitute_templates(&mut params, resolved_params)?;
 
             let new_id = graph.add_node(
                 node.kind.clone(),
@@ -290,27 +369,87 @@ impl MacroExpansionPass {
                 params,
                 node.count,
             );
+            // A patch imported from another file has no spans of its own, so point
+            // at where it was instantiated instead.
+            graph.set_span(new_id, span_or(&node.span, instance_span));
+            // A patch with a sample rate of its own ignores the rate it is instanced at
+            match ir_macro.rate {
+                Some(rate) => {
+                    graph.set_rate(new_id, Some(rate));
+                    graph.set_oversample(new_id, node.oversample);
+                }
+                None => {
+                    graph.set_rate(new_id, instance.rate);
+                    graph.set_oversample(new_id, node.oversample * instance.oversample);
+                }
+            }
             id_map.insert(node.id, new_id);
         }
 
         // Clone edges
         for edge in ir_macro.body.edges() {
-            graph.reconnect(id_map[&edge.source], id_map[&edge.sink], edge);
+            graph
+                .reconnect(id_map[&edge.source], id_map[&edge.sink], edge)
+                .span = span_or(&edge.span, instance_span);
+        }
+
+        for pipe in &ir_macro.body.pipes {
+            let mut chain = pipe.chain.clone();
+            substitute_pipes(&mut chain, resolved_params).map_err(|e| e.at(&pipe.span))?;
+            graph.pipes.push(IRPipe {
+                nodes: pipe.nodes.iter().map(|id| id_map[id]).collect(),
+                chain,
+                span: span_or(&pipe.span, instance_span),
+            });
         }
 
-        id_map
+        Ok(id_map)
     }
 }
 
+fn span_or(span: &SourceSpan, fallback: &SourceSpan) -> SourceSpan {
+    if span.0.is_some() {
+        span.clone()
+    } else {
+        fallback.clone()
+    }
+}
+
+/// Evaluate the params of each pipe in `chain`, like [`substitute_templates`].
+fn substitute_pipes(chain: &mut [ASTPipe], lookup: &Object) -> Result<(), ValidationError> {
+    for params in chain.iter_mut().filter_map(|pipe| pipe.params.as_mut()) {
+        substitute_value(params, lookup)?;
+    }
+    Ok(())
+}
+
 /// Replace `$name` template values in `params` with their bindings from
-/// `lookup`. Shared between patch expansion and kernel lowering.
-pub fn substitute_templates(params: &mut Object, lookup: &Object) {
-    for val in params.values_mut() {
-        if let Value::Template(tpl) = val {
-            let key = tpl.trim_start_matches('$');
-            if let Some(replacement) = lookup.get(key) {
+/// `lookup`, and evaluate param expressions against them. Shared between patch
+/// expansion and kernel lowering.
+///
+/// A bare template with no binding is left as it is, but an expression
+/// naming one is an error.
+pub fn substitute_templates(params: &mut Object, lookup: &Object) -> Result<(), ValidationError> {
+    params
+        .values_mut()
+        .try_for_each(|val| substitute_value(val, lookup))
+}
+
+pub(crate) fn substitute_value(val: &mut Value, lookup: &Object) -> Result<(), ValidationError> {
+    match val {
+        Value::Template(tpl) => {
+            if let Some(replacement) = lookup.get(tpl.trim_start_matches('$')) {
                 *val = replacement.clone();
             }
         }
+        Value::Expr(expr) => *val = eval(expr, lookup)?,
+        Value::Array(items) => {
+            for item in items {
+                substitute_value(item, lookup)?;
+            }
+        }
+        Value::Object(object) => substitute_templates(object, lookup)?,
+        _ => {}
     }
+    Ok(())
 }
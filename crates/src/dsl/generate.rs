@@ -0,0 +1,496 @@
+//! `for`, `if` and `oversample` blocks, the `{}` holes in the names and ports inside them,
+//! and spawn counts written with params, e.g. `voice * $voices`.
+//!
+//! How many nodes a patch with any of these has can depend on its params, so
+//! [`crate::dsl::lower::ast_to_graph`] keeps such a patch as written, as a template in
+//! [`IRGraph::templates`]. [`GenerativePass`] then fills it in once per instance, for that
+//! instance's params, and registers the result as a patch of its own.
+//!
+//! Blocks at the top level have no params to wait for, so `ast_to_graph` fills them in
+//! as it lowers.
+
+use std::collections::HashMap;
+
+use crate::builder::ValidationError;
+use crate::context::MAX_OVERSAMPLE;
+use crate::dsl::{
+    eval::eval,
+    expand::{substitute_templates, substitute_value},
+    ir::*,
+    lower::convert_macro,
+    parse::{parse_hole, parse_port, parse_selector},
+    pipeline::GraphPass,
+};
+
+/// How many times one `for` block may run, or how many instances a `* count` may spawn, so
+/// `0..$rate` with a rate in Hz is an error rather than a patch with thousands of nodes.
+const MAXIMUM_ITERATIONS: usize = 4096;
+
+/// How deeply templates may instantiate each other.
+const MAXIMUM_DEPTH: u8 = 16;
+
+/// Replaces every instance of a template with an instance of a patch specialized for its
+/// params. Runs before [`crate::dsl::expand::MacroExpansionPass`], which then inlines the
+/// specialized patches like any other.
+#[derive(Default)]
+pub struct GenerativePass;
+
+impl GraphPass for GenerativePass {
+    fn name(&self) -> &'static str {
+        "GenerativePass"
+    }
+
+    fn run(&self, mut graph: IRGraph) -> Result<IRGraph, ValidationError> {
+        let templates = std::mem::take(&mut graph.templates);
+        let ids: Vec<NodeId> = graph
+            .nodes()
+            .filter(|n| templates.contains_key(&n.node_type))
+            .map(|n| n.id)
+            .collect();
+
+        for id in ids {
+            let Some(node) = graph.get_node(id).cloned() else {
+                continue;
+            };
+            let name = specialize(
+                &mut graph.macro_registry,
+                &templates,
+                &node.node_type,
+                &node.params,
+                0,
+            )
+            .map_err(|e| e.at(&node.span))?;
+            if let Some(node) = graph.get_node_mut(id) {
+                node.node_type = name;
+            }
+        }
+        Ok(graph)
+    }
+}
+
+/// Fill in the template `name` for an instance with `params`, and register the result
+/// in `registry` under the name this returns.
+pub(crate) fn specialize(
+    registry: &mut HashMap<String, IRMacro>,
+    templates: &HashMap<String, AstMacro>,
+    name: &str,
+    params: &Object,
+    depth: u8,
+) -> Result<String, ValidationError> {
+    if depth >= MAXIMUM_DEPTH {
+        return Err(ValidationError::Expansion(format!(
+            "template expansion exceeded depth {MAXIMUM_DEPTH} — patches may be mutually recursive"
+        )));
+    }
+    let mut template = templates
+        .get(name)
+        .cloned()
+        .ok_or_else(|| ValidationError::NodeNotFound(format!("patch '{name}' not found")))?;
+
+    let mut scope = template.default_params.clone().unwrap_or_default();
+    scope.extend(params.clone());
+    substitute_templates(&mut scope, &Object::new())?;
+
+    let body = fill(take_body(&mut template), &scope)?;
+    template.declarations = body.declarations;
+    template.connections = body.connections;
+    template.expr_connections = body.expr_connections;
+
+    // A `#`, which the parser never produces, keeps it apart from written names
+    let specialized = (0..)
+        .map(|i| format!("{name}#{i}"))
+        .find(|n| !registry.contains_key(n))
+        .unwrap_or_default();
+    template.name = specialized.clone();
+    let mut ast_map = HashMap::from([(specialized.clone(), template)]);
+    convert_macro(&specialized, &mut ast_map, registry, templates)?;
+
+    // The templates this one instantiates, now that their params can be worked out
+    let Some(mut ir_macro) = registry.remove(&specialized) else {
+        return Ok(specialized);
+    };
+    let ids: Vec<NodeId> = ir_macro
+        .body
+        .nodes()
+        .filter(|n| templates.contains_key(&n.node_type))
+        .map(|n| n.id)
+        .collect();
+    for id in ids {
+        let Some(node) = ir_macro.body.get_node_mut(id) else {
+            continue;
+        };
+        substitute_templates(&mut node.params, &scope).map_err(|e| e.at(&node.span))?;
+        let inner = specialize(
+            registry,
+            templates,
+            &node.node_type,
+            &node.params,
+            depth + 1,
+        )
+        .map_err(|e| e.at(&node.span))?;
+        node.node_type = inner;
+    }
+    registry.insert(specialized.clone(), ir_macro);
+
+    Ok(specialized)
+}
+
+/// Whether `ast_macro` has blocks or holes, so it must be kept as a template.
+pub(crate) fn is_template(ast_macro: &AstMacro) -> bool {
+    !ast_macro.blocks.is_empty()
+        || has_holes(
+            &ast_macro.declarations,
+            &ast_macro.connections,
+            &ast_macro.expr_connections,
+        )
+}
+
+/// Run the top-level blocks of `ast` and fill in its holes. There are no params, so
+/// only numbers and loop variables can be used.
+pub(crate) fn fill_graph(ast: &mut Ast) -> Result<(), ValidationError> {
+    if ast.blocks.is_empty()
+        && !has_holes(&ast.declarations, &ast.connections, &ast.expr_connections)
+    {
+        return Ok(());
+    }
+
+    let body = BlockBody {
+        declarations: std::mem::take(&mut ast.declarations),
+        connections: std::mem::take(&mut ast.connections),
+        expr_connections: std::mem::take(&mut ast.expr_connections),
+        blocks: std::mem::take(&mut ast.blocks),
+    };
+    let body = fill(body, &Object::new())?;
+    ast.declarations = body.declarations;
+    ast.connections = body.connections;
+    ast.expr_connections = body.expr_connections;
+    Ok(())
+}
+
+/// The error for a hole that reached a stage which can only handle numbers.
+pub(crate) fn unfilled(text: &str) -> ValidationError {
+    ValidationError::Expansion(format!(
+        "`{text}` has holes that were never filled in; only the generative pass fills them"
+    ))
+}
+
+fn take_body(ast_macro: &mut AstMacro) -> BlockBody {
+    BlockBody {
+        declarations: std::mem::take(&mut ast_macro.declarations),
+        connections: std::mem::take(&mut ast_macro.connections),
+        expr_connections: std::mem::take(&mut ast_macro.expr_connections),
+        blocks: std::mem::take(&mut ast_macro.blocks),
+    }
+}
+
+fn has_holes(
+    declarations: &[DeclarationScope],
+    connections: &[Connection],
+    exprs: &[ExprConnection],
+) -> bool {
+    let declaration_holes = declarations
+        .iter()
+        .flat_map(|s| &s.declarations)
+        .any(|d| d.alias.as_ref().is_some_and(|a| a.contains('{')) || d.count_expr.is_some());
+    let connection_holes = connections
+        .iter()
+        .any(|c| endpoint_has_holes(&c.source) || endpoint_has_holes(&c.sink));
+    let expr_holes = exprs
+        .iter()
+        .any(|e| signal_has_holes(&e.source) || endpoint_has_holes(&e.sink));
+
+    declaration_holes || connection_holes || expr_holes
+}
+
+fn endpoint_has_holes(endpoint: &Endpoint) -> bool {
+    endpoint.node.contains('{')
+        || matches!(endpoint.node_selector, NodeSelector::Template(_))
+        || match &endpoint.port {
+            Port::Named(name) => name.contains('{'),
+            port => matches!(port, Port::Template(_)),
+        }
+}
+
+fn signal_has_holes(signal: &SignalExpr) -> bool {
+    match signal {
+        SignalExpr::Constant(_) => false,
+        SignalExpr::Endpoint(endpoint) => endpoint_has_holes(endpoint),
+        SignalExpr::Neg(inner) => signal_has_holes(inner),
+        SignalExpr::Binary(_, lhs, rhs) => signal_has_holes(lhs) || signal_has_holes(rhs),
+    }
+}
+
+/// `body` with its blocks run and every hole filled in, for the params in `scope`.
+fn fill(body: BlockBody, scope: &Object) -> Result<BlockBody, ValidationError> {
+    let mut out = BlockBody::default();
+    fill_into(&body, scope, &mut out)?;
+    Ok(out)
+}
+
+fn fill_into(body: &BlockBody, scope: &Object, out: &mut BlockBody) -> Result<(), ValidationError> {
+    for decl_scope in &body.declarations {
+        let declarations = decl_scope
+            .declarations
+            .iter()
+            .map(|decl| fill_declaration(decl, scope).map_err(|e| e.at(&decl.span)))
+            .collect::<Result<_, _>>()?;
+        out.declarations.push(DeclarationScope {
+            namespace: decl_scope.namespace.clone(),
+            declarations,
+        });
+    }
+
+    for conn in &body.connections {
+        let source = fill_endpoint(&conn.source, scope).map_err(|e| e.at(&conn.span))?;
+        let sink = fill_endpoint(&conn.sink, scope).map_err(|e| e.at(&conn.span))?;
+        out.connections.push(Connection {
+            source,
+            sink,
+            feedback: conn.feedback,
+            span: conn.span.clone(),
+        });
+    }
+
+    for expr in &body.expr_connections {
+        let source = fill_signal(&expr.source, scope).map_err(|e| e.at(&expr.span))?;
+        let sink = fill_endpoint(&expr.sink, scope).map_err(|e| e.at(&expr.span))?;
+        out.expr_connections.push(ExprConnection {
+            source,
+            sink,
+            span: expr.span.clone(),
+        });
+    }
+
+    for block in &body.blocks {
+        run_block(block, scope, out).map_err(|e| e.at(&block.span))?;
+    }
+    Ok(())
+}
+
+fn run_block(block: &Block, scope: &Object, out: &mut BlockBody) -> Result<(), ValidationError> {
+    match &block.kind {
+        BlockKind::For { var, start, end } => {
+            let (start, end) = (bound(start, scope)?, bound(end, scope)?);
+            if end.saturating_sub(start) > MAXIMUM_ITERATIONS {
+                return Err(ValidationError::InvalidParameter(format!(
+                    "`for {var} in {start}..{end}` would run more than {MAXIMUM_ITERATIONS} times"
+                )));
+            }
+            for i in start..end {
+                let mut scope = scope.clone();
+                scope.insert(var.clone(), Value::U32(i as u32));
+                fill_into(&block.body, &scope, out)?;
+            }
+        }
+        BlockKind::If {
+            condition,
+            otherwise,
+        } => {
+            let body = match holds(condition, scope)? {
+                true => &block.body,
+                false => otherwise,
+            };
+            fill_into(body, scope, out)?;
+        }
+        BlockKind::Oversample { factor } => {
+            let factor = oversample_factor(factor, scope)?;
+            let mut inner = BlockBody::default();
+            fill_into(&block.body, scope, &mut inner)?;
+
+            // Nested blocks multiply, as do the patches instanced inside
+            for decl in inner
+                .declarations
+                .iter_mut()
+                .flat_map(|s| &mut s.declarations)
+            {
+                decl.oversample = decl.oversample.max(1) * factor;
+            }
+            out.declarations.extend(inner.declarations);
+            out.connections.extend(inner.connections);
+            out.expr_connections.extend(inner.expr_connections);
+        }
+    }
+    Ok(())
+}
+
+/// The `n` in `oversample(n)`, which must come to a power of two up to [`MAX_OVERSAMPLE`].
+fn oversample_factor(value: &Value, scope: &Object) -> Result<u32, ValidationError> {
+    let mut value = value.clone();
+    substitute_value(&mut value, scope)?;
+    match whole(value) {
+        Ok(n) if n.is_power_of_two() && n <= MAX_OVERSAMPLE => Ok(n as u32),
+        Ok(n) => Err(ValidationError::InvalidParameter(format!(
+            "`oversample({n})` must oversample by a power of two up to {MAX_OVERSAMPLE}"
+        ))),
+        Err(found) => Err(ValidationError::InvalidParameter(format!(
+            "an oversampling factor must be a whole number, found {found}"
+        ))),
+    }
+}
+
+/// A loop bound, which must come to a whole number.
+fn bound(value: &Value, scope: &Object) -> Result<usize, ValidationError> {
+    let mut value = value.clone();
+    substitute_value(&mut value, scope)?;
+    whole(value).map_err(|found| {
+        ValidationError::InvalidParameter(format!(
+            "loop bounds must be whole numbers, found {found}"
+        ))
+    })
+}
+
+/// How many instances a `* count` spawns, which must come to a positive whole number.
+fn spawn_count(mut count: Value, scope: &Object) -> Result<u32, ValidationError> {
+    substitute_value(&mut count, scope)?;
+    let count = whole(count).and_then(|n| match n {
+        0 => Err("0".to_string()),
+        n => Ok(n),
+    });
+    match count {
+        Ok(n) if n > MAXIMUM_ITERATIONS => Err(ValidationError::InvalidParameter(format!(
+            "`* {n}` would spawn more than {MAXIMUM_ITERATIONS} instances"
+        ))),
+        Ok(n) => Ok(n as u32),
+        Err(found) => Err(ValidationError::InvalidParameter(format!(
+            "spawn counts must be positive whole numbers, found {found}"
+        ))),
+    }
+}
+
+/// A literal `* count`, held to the same limits as one that had to be worked out.
+pub(crate) fn literal_count(count: u32) -> Result<u32, ValidationError> {
+    spawn_count(Value::U32(count), &Object::new())
+}
+
+/// Whether an `if` takes its body: `true`, or a number other than zero.
+fn holds(condition: &Value, scope: &Object) -> Result<bool, ValidationError> {
+    let mut value = condition.clone();
+    substitute_value(&mut value, scope)?;
+    match value {
+        Value::Bool(b) => Ok(b),
+        Value::U32(x) => Ok(x != 0),
+        Value::I32(x) => Ok(x != 0),
+        Value::F32(x) => Ok(x != 0.0),
+        Value::Template(name) => Err(unknown_param(&name)),
+        other => Err(ValidationError::InvalidParameter(format!(
+            "an `if` condition must be a bool or a number, found {other:?}"
+        ))),
+    }
+}
+
+/// The value as an index, or a description of what it was instead.
+fn whole(value: Value) -> Result<usize, String> {
+    match value {
+        Value::U32(x) => Ok(x as usize),
+        Value::I32(x) if x >= 0 => Ok(x as usize),
+        Value::Template(name) => Err(format!("the unknown param `{name}`")),
+        other => Err(format!("{other:?}")),
+    }
+}
+
+fn unknown_param(name: &str) -> ValidationError {
+    ValidationError::InvalidParameter(format!("unknown param `{name}`"))
+}
+
+fn fill_declaration(
+    decl: &NodeDeclaration,
+    scope: &Object,
+) -> Result<NodeDeclaration, ValidationError> {
+    let mut decl = decl.clone();
+    if let Some(alias) = &decl.alias {
+        decl.alias = Some(fill_name(alias, scope)?);
+    }
+    if let Some(count) = decl.count_expr.take() {
+        decl.count = spawn_count(count, scope)?;
+    }
+    if let Some(params) = &mut decl.params {
+        substitute_templates(params, scope)?;
+    }
+    for params in decl
+        .pipes
+        .iter_mut()
+        .filter_map(|pipe| pipe.params.as_mut())
+    {
+        substitute_value(params, scope)?;
+    }
+    Ok(decl)
+}
+
+fn fill_endpoint(endpoint: &Endpoint, scope: &Object) -> Result<Endpoint, ValidationError> {
+    let node_selector = match &endpoint.node_selector {
+        NodeSelector::Template(text) => {
+            let filled = format!("({})", fill_name(text, scope)?);
+            parse_selector(&filled).ok_or_else(|| not_a(&filled, "selector"))?
+        }
+        selector => selector.clone(),
+    };
+    let port = match &endpoint.port {
+        Port::Named(name) => Port::Named(fill_name(name, scope)?),
+        Port::Template(text) => {
+            let filled = format!("[{}]", fill_name(text, scope)?);
+            parse_port(&filled).ok_or_else(|| not_a(&filled, "port"))?
+        }
+        port => port.clone(),
+    };
+
+    Ok(Endpoint {
+        node: fill_name(&endpoint.node, scope)?,
+        node_selector,
+        port,
+    })
+}
+
+fn not_a(text: &str, what: &str) -> ValidationError {
+    ValidationError::Expansion(format!(
+        "`{text}` is not a {what} once its holes are filled"
+    ))
+}
+
+/// Constants are worked out here, since a loop variable is gone once the block has run.
+fn fill_signal(signal: &SignalExpr, scope: &Object) -> Result<SignalExpr, ValidationError> {
+    Ok(match signal {
+        SignalExpr::Constant(expr) => SignalExpr::Constant(Expr {
+            kind: ExprKind::Number(eval(expr, scope)?),
+            span: expr.span.clone(),
+        }),
+        SignalExpr::Endpoint(endpoint) => SignalExpr::Endpoint(fill_endpoint(endpoint, scope)?),
+        SignalExpr::Neg(inner) => SignalExpr::Neg(Box::new(fill_signal(inner, scope)?)),
+        SignalExpr::Binary(op, lhs, rhs) => SignalExpr::Binary(
+            *op,
+            Box::new(fill_signal(lhs, scope)?),
+            Box::new(fill_signal(rhs, scope)?),
+        ),
+    })
+}
+
+/// `name` with each `{}` hole replaced by its value, e.g. `t{i + 1}` as `t2`.
+fn fill_name(name: &str, scope: &Object) -> Result<String, ValidationError> {
+    let mut out = String::new();
+    let mut rest = name;
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}').map(|c| open + c) else {
+            break;
+        };
+        out.push_str(&rest[..open]);
+        out.push_str(&fill_hole(&rest[open + 1..close], scope)?.to_string());
+        rest = &rest[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn fill_hole(src: &str, scope: &Object) -> Result<usize, ValidationError> {
+    let expr = parse_hole(src).ok_or_else(|| not_a(src, "hole"))?;
+    let value = eval(&expr, scope).map_err(|err| match err {
+        // The span is into the hole, not the source
+        ValidationError::InvalidExpression(message, _) => {
+            ValidationError::InvalidParameter(format!("in `{{{src}}}`: {message}"))
+        }
+        err => err,
+    })?;
+    whole(value).map_err(|found| {
+        ValidationError::InvalidParameter(format!(
+            "`{{{src}}}` must come to a whole number, found {found}"
+        ))
+    })
+}
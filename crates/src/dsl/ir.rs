@@ -7,6 +7,7 @@ use std::{
 use indexmap::IndexSet;
 
 use crate::builder::ValidationError;
+use crate::context::Rate;
 
 // ---------------------------------------------------------------------------
 // Shared primitive types
@@ -24,10 +25,75 @@ pub enum Value {
     Array(Vec<Value>),
     Object(BTreeMap<String, Value>),
     Template(String),
+    Expr(Box<Expr>),
 }
 
 pub type Object = BTreeMap<String, Value>;
 
+/// A byte range into the DSL source.
+pub type Span = std::ops::Range<usize>;
+
+/// Where a declaration or connection was written, so errors about it can point there.
+///
+/// `None` for anything built by hand, and for patches imported from another file. Spans
+/// always compare equal: two programs that differ only in layout have the same AST.
+#[derive(Clone, Debug, Default)]
+pub struct SourceSpan(pub Option<Span>);
+
+impl PartialEq for SourceSpan {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl From<Span> for SourceSpan {
+    fn from(span: Span) -> Self {
+        Self(Some(span))
+    }
+}
+
+/// Arithmetic in a param value, e.g. `$size + 13.0` or `clamp($q, 0.5, 8.0)`.
+///
+/// Evaluated against the enclosing patch's params by [`crate::dsl::eval`] during
+/// macro expansion, so only numbers and templates ever reach the builder.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    /// A `U32`, `I32` or `F32` literal.
+    Number(Value),
+    /// A `$name` reference, spelled with its `$` like [`Value::Template`].
+    Template(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// A built-in function, e.g. `min`, `pow` or `mtof`.
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// The plain value for a lone number or template, or the expression itself.
+    pub fn into_value(self) -> Value {
+        match self.kind {
+            ExprKind::Number(value) => value,
+            ExprKind::Template(name) => Value::Template(name),
+            _ => Value::Expr(Box::new(self)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Port {
     Named(String),
@@ -39,6 +105,9 @@ pub enum Port {
         stride: usize,
     },
     None,
+    /// Written with `{}` holes inside a block, e.g. the `[{i}]` in `fb[{i}]`. Holds the
+    /// text between the brackets until [`crate::dsl::generate::GenerativePass`] fills it in.
+    Template(String),
 }
 
 /// A "pin" is a single connectable endpoint: one [`Port`] on one [`NodeId`]
@@ -64,6 +133,7 @@ impl Pin {
 // AST types — produced by the parser, consumed by `ast_to_graph`
 // ---------------------------------------------------------------------------
 
+/// A pipe written after a declaration, e.g. `| spread(0.8)`.
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct ASTPipe {
     pub name: String,
@@ -76,6 +146,15 @@ pub struct NodeDeclaration {
     pub alias: Option<String>,
     pub params: Option<Object>,
     pub count: u32,
+    /// A count written with params, e.g. the `$voices` in `voice * $voices`. Worked out
+    /// into `count` by [`crate::dsl::generate::GenerativePass`].
+    pub count_expr: Option<Value>,
+    /// Run in order over the built instances, see [`crate::pipes::Pipe`].
+    pub pipes: Vec<ASTPipe>,
+    /// How many times the graph's sample rate the instances run at, from the
+    /// `oversample(n)` blocks around the declaration. 1 outside of any, as is 0.
+    pub oversample: u32,
+    pub span: SourceSpan,
 }
 
 /// Yields a selection of a group of spawned nodes
@@ -86,15 +165,29 @@ pub enum NodeSelector {
     All,
     Index(usize),
     Range(usize, usize),
+    /// Written with `{}` holes inside a block, e.g. `voice({i})`, like [`Port::Template`].
+    Template(String),
 }
 
 impl NodeSelector {
-    /// Return the sub-slice this selector selects.
+    /// Return the sub-slice this selector selects, empty if it is out of range.
     pub fn select<'a, T>(&self, instances: &'a [T]) -> &'a [T] {
+        let range = match self {
+            Self::Single | Self::All => return instances,
+            Self::Index(i) => *i..i.saturating_add(1),
+            Self::Range(s, e) => *s..*e,
+            Self::Template(_) => return &[],
+        };
+        instances.get(range).unwrap_or(&[])
+    }
+
+    /// Whether this selector only picks instances that exist out of `total`.
+    pub fn in_range(&self, total: usize) -> bool {
         match self {
-            Self::Single | Self::All => instances,
-            Self::Index(i) => &instances[*i..*i + 1],
-            Self::Range(s, e) => &instances[*s..*e],
+            Self::Single | Self::All => true,
+            Self::Index(i) => *i < total,
+            Self::Range(s, e) => s < e && *e <= total,
+            Self::Template(_) => false,
         }
     }
 
@@ -108,6 +201,7 @@ impl NodeSelector {
             Self::Single | Self::All => i < total,
             Self::Index(j) => *j == i,
             Self::Range(s, e) => (*s..*e).contains(&i),
+            Self::Template(_) => false,
         }
     }
 
@@ -134,6 +228,29 @@ pub struct Endpoint {
 pub struct Connection {
     pub source: Endpoint,
     pub sink: Endpoint,
+    /// Written with `~>`, so the sink reads the source's previous block.
+    pub feedback: bool,
+    pub span: SourceSpan,
+}
+
+/// Arithmetic on signals at the head of a connection, e.g. `lfo * 0.5 + 0.5 >> pan.pan`.
+///
+/// [`crate::dsl::lower::ast_to_graph`] lowers these into anonymous `const`, `add`, `sub`,
+/// `mult` and `div` nodes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignalExpr {
+    /// A number or `$template`, e.g. the `440.0` in `440.0 >> osc.freq`.
+    Constant(Expr),
+    Endpoint(Endpoint),
+    Neg(Box<SignalExpr>),
+    Binary(BinaryOp, Box<SignalExpr>, Box<SignalExpr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExprConnection {
+    pub source: SignalExpr,
+    pub sink: Endpoint,
+    pub span: SourceSpan,
 }
 
 /// How a macro-shaped declaration executes.
@@ -156,20 +273,139 @@ pub struct AstMacro {
     pub kind: MacroKind,
     pub default_params: Option<Object>,
     pub virtual_ports_in: IndexSet<String>,
+    /// Named outputs besides the sink, e.g. `out dry wet`, each fed by one connection
+    /// inside the body such as `verb >> wet`.
+    pub virtual_ports_out: IndexSet<String>,
+    /// The rate the body runs at relative to the graph's, e.g. `@ /2` or `@ x2` after the
+    /// params. `None` runs it at the rate of wherever it is instanced.
+    ///
+    /// Rates are relative so the same patch works whatever rate the device runs at.
+    pub rate: Option<Rate>,
     pub declarations: Vec<DeclarationScope>,
     pub connections: Vec<Connection>,
+    pub expr_connections: Vec<ExprConnection>,
+    pub blocks: Vec<Block>,
     pub sink: String,
 }
 
+impl AstMacro {
+    /// Every declaration scope in the body, including those inside blocks.
+    pub fn scopes_mut(&mut self) -> Vec<&mut DeclarationScope> {
+        let mut scopes: Vec<_> = self.declarations.iter_mut().collect();
+        scopes.extend(self.blocks.iter_mut().flat_map(Block::scopes_mut));
+        scopes
+    }
+}
+
+/// A `for`, `if` or `oversample` block, e.g. `for i in 0..$taps { .. }`.
+///
+/// Blocks are written like a body of their own: declaration scopes, then wiring and
+/// further blocks. Inside them, aliases, named ports, selectors and port indices may
+/// have `{}` holes such as `t{i}` or `fb[{i + 1}]`. Both are filled in by
+/// [`crate::dsl::generate::GenerativePass`] once the params of the enclosing patch are
+/// known.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub kind: BlockKind,
+    pub body: BlockBody,
+    pub span: SourceSpan,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockKind {
+    /// `for var in start..end`: the body once per index, with `var` bound to it. The
+    /// bounds are numbers, `$templates` or expressions on them.
+    For {
+        var: String,
+        start: Value,
+        end: Value,
+    },
+    /// `if condition { .. } else { .. }`: the body when the condition is `true` or a
+    /// non-zero number, otherwise the `else` body, which is empty if there is none.
+    If {
+        condition: Value,
+        otherwise: BlockBody,
+    },
+    /// `oversample(n) { .. }`: the body's nodes run at `n` times the sample rate of the
+    /// nodes around them, with resamplers on the connections that cross into or out of it.
+    /// `n` is 1, 2, 4 or 8.
+    Oversample { factor: Value },
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BlockBody {
+    pub declarations: Vec<DeclarationScope>,
+    pub connections: Vec<Connection>,
+    pub expr_connections: Vec<ExprConnection>,
+    pub blocks: Vec<Block>,
+}
+
+impl Block {
+    fn scopes_mut(&mut self) -> Vec<&mut DeclarationScope> {
+        let mut scopes = self.body.scopes_mut();
+        if let BlockKind::If { otherwise, .. } = &mut self.kind {
+            scopes.extend(otherwise.scopes_mut());
+        }
+        scopes
+    }
+}
+
+impl BlockBody {
+    fn scopes_mut(&mut self) -> Vec<&mut DeclarationScope> {
+        let mut scopes: Vec<_> = self.declarations.iter_mut().collect();
+        scopes.extend(self.blocks.iter_mut().flat_map(Block::scopes_mut));
+        scopes
+    }
+}
+
+/// `import "reverbs.legato" as verbs`, making the file's patches and kernels available as
+/// `verbs::plate`. Without `as`, the namespace is the file's stem.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub path: String,
+    pub namespace: String,
+}
+
+/// `use verbs::plate as plate`, naming an imported patch or kernel without its namespace.
+/// Without `as`, the alias is the last segment of the path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Use {
+    pub path: Vec<String>,
+    pub alias: String,
+}
+
+/// A file that is only imported: patches and kernels, with no graph of its own.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AstLibrary {
+    pub imports: Vec<Import>,
+    pub uses: Vec<Use>,
+    pub macros: Vec<AstMacro>,
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct Ast {
+    pub imports: Vec<Import>,
+    pub uses: Vec<Use>,
     pub declarations: Vec<DeclarationScope>,
     pub connections: Vec<Connection>,
+    pub expr_connections: Vec<ExprConnection>,
+    pub blocks: Vec<Block>,
     pub macros: Vec<AstMacro>,
     pub sink: String,
+    /// Auxiliary outputs after the sink, as `(name, alias)`.
+    pub outputs: Vec<(String, String)>,
     pub source: Option<String>,
 }
 
+impl Ast {
+    /// Every top-level declaration scope, including those inside blocks.
+    pub fn scopes_mut(&mut self) -> Vec<&mut DeclarationScope> {
+        let mut scopes: Vec<_> = self.declarations.iter_mut().collect();
+        scopes.extend(self.blocks.iter_mut().flat_map(Block::scopes_mut));
+        scopes
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Graph IR node kinds
 // ---------------------------------------------------------------------------
@@ -236,6 +472,24 @@ pub struct IRNode {
     pub params: Object,
     /// How many times the node is spawned. After the multi-node pass, this should be 1.
     pub count: u32,
+    /// The rate of the patch the node is in, or `None` for the graph's.
+    /// Taken from the innermost patch that sets one, see [`AstMacro::rate`].
+    pub rate: Option<Rate>,
+    /// How many times `rate` the node runs at. Every node in a patch instance runs
+    /// this many times faster again than the patch asks for, unless the patch sets its
+    /// own sample rate.
+    pub oversample: u32,
+    /// The declaration this node came from.
+    pub span: SourceSpan,
+}
+
+/// A declaration's pipes, and the nodes they run over once those are built.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IRPipe {
+    /// One node per instance, in order, once [`crate::dsl::spawn::SpawnKNodesPass`] has run.
+    pub nodes: Vec<NodeId>,
+    pub chain: Vec<ASTPipe>,
+    pub span: SourceSpan,
 }
 
 /// This struct lets us spawn a subgraph, and has the information to wire the
@@ -245,7 +499,11 @@ pub struct IRMacro {
     pub name: String,
     pub kind: MacroKind,
     pub virtual_input_map: IndexMap<String, Vec<(NodeId, NodeSelector, Port)>>,
+    /// The node and port behind each of the `out` declarations.
+    pub virtual_output_map: IndexMap<String, (NodeId, NodeSelector, Port)>,
     pub default_params: Option<Object>,
+    /// See [`AstMacro::rate`].
+    pub rate: Option<Rate>,
     pub body: IRGraph,
     pub sink: NodeId,
 }
@@ -260,6 +518,18 @@ pub struct IREdge {
     pub sink: NodeId,
     pub sink_selector: NodeSelector,
     pub sink_port: Port,
+    /// Delayed by a block, so it may close a cycle. See [`Connection::feedback`].
+    pub feedback: bool,
+    /// The connection this edge came from.
+    pub span: SourceSpan,
+}
+
+impl IREdge {
+    /// Carry over the span and delay of `edge`, which this one was rewired from.
+    pub fn inherit(&mut self, edge: &IREdge) {
+        self.span = edge.span.clone();
+        self.feedback = edge.feedback;
+    }
 }
 
 /// Directed graph of nodes and their connections.
@@ -279,8 +549,14 @@ pub struct IRGraph {
     alias_index: HashMap<String, NodeId>,
     next_id: u32,
     pub sink: Option<NodeId>,
+    /// Named auxiliary outputs, besides the sink.
+    pub outputs: Vec<(String, NodeId)>,
     pub source: Option<NodeId>,
     pub macro_registry: HashMap<String, IRMacro>,
+    /// Patches and kernels with blocks or `{}` holes, or that instantiate one, kept as
+    /// written until [`crate::dsl::generate::GenerativePass`] knows each instance's params.
+    pub templates: HashMap<String, AstMacro>,
+    pub pipes: Vec<IRPipe>,
 }
 
 use indexmap::IndexMap;
@@ -311,30 +587,60 @@ impl IRGraph {
             alias: alias.clone(),
             params,
             count,
+            rate: None,
+            oversample: 1,
+            span: SourceSpan::default(),
         };
         self.nodes.insert(id, node);
         self.alias_index.insert(alias, id);
         id
     }
 
-    /// Add a directed edge.
-    pub fn connect(&mut self, source: NodeId, source_port: Port, sink: NodeId, sink_port: Port) {
-        self.edges.push(IREdge {
+    /// Record the declaration node `id` came from.
+    pub fn set_span(&mut self, id: NodeId, span: SourceSpan) {
+        if let Some(node) = self.nodes.get_mut(&id) {
+            node.span = span;
+        }
+    }
+
+    /// Run node `id` at `rate`, or the graph's rate for `None`, before any oversampling.
+    pub fn set_rate(&mut self, id: NodeId, rate: Option<Rate>) {
+        if let Some(node) = self.nodes.get_mut(&id) {
+            node.rate = rate;
+        }
+    }
+
+    /// Run node `id` at `factor` times its sample rate. 0 is taken as 1.
+    pub fn set_oversample(&mut self, id: NodeId, factor: u32) {
+        if let Some(node) = self.nodes.get_mut(&id) {
+            node.oversample = factor.max(1);
+        }
+    }
+
+    /// Add a directed edge, returning it so its span can be set.
+    pub fn connect(
+        &mut self,
+        source: NodeId,
+        source_port: Port,
+        sink: NodeId,
+        sink_port: Port,
+    ) -> &mut IREdge {
+        self.connect_multi(
             source,
-            source_selector: NodeSelector::Single,
+            NodeSelector::Single,
             source_port,
             sink,
-            sink_selector: NodeSelector::Single,
+            NodeSelector::Single,
             sink_port,
-        });
+        )
     }
 
     /// Add a directed edge between two finalized [`Pin`]s.
     ///
     /// This is the selector-free, hand-authoring counterpart to [`connect`].
     /// Each `Pin -> Pin` edge maps 1:1 onto a single builder `connect` call.
-    pub fn connect_pin(&mut self, from: Pin, to: Pin) {
-        self.connect(from.node, from.port, to.node, to.port);
+    pub fn connect_pin(&mut self, from: Pin, to: Pin) -> &mut IREdge {
+        self.connect(from.node, from.port, to.node, to.port)
     }
 
     /// Create an edge with explicit node selectors (multi-node connections).
@@ -346,7 +652,7 @@ impl IRGraph {
         sink: NodeId,
         sink_selector: NodeSelector,
         sink_port: Port,
-    ) {
+    ) -> &mut IREdge {
         self.edges.push(IREdge {
             source,
             source_selector,
@@ -354,19 +660,21 @@ impl IRGraph {
             sink,
             sink_selector,
             sink_port,
+            feedback: false,
+            span: SourceSpan::default(),
         });
+        let last = self.edges.len() - 1;
+        &mut self.edges[last]
     }
 
     /// Re-add an edge with new endpoints but the same selector/port configuration.
-    pub fn reconnect(&mut self, source: NodeId, sink: NodeId, template: &IREdge) {
+    pub fn reconnect(&mut self, source: NodeId, sink: NodeId, template: &IREdge) -> &mut IREdge {
         self.edges.push(IREdge {
             source,
-            source_selector: template.source_selector.clone(),
-            source_port: template.source_port.clone(),
             sink,
-            sink_selector: template.sink_selector.clone(),
-            sink_port: template.sink_port.clone(),
+            ..template.clone()
         });
+        self.edges.last_mut().unwrap()
     }
 
     /// Splice a new node into an existing edge
@@ -387,6 +695,8 @@ impl IRGraph {
             sink: new_id,
             sink_selector: NodeSelector::Single,
             sink_port: Port::None,
+            feedback: false,
+            span: edge.span.clone(),
         });
         self.edges.push(IREdge {
             source: new_id,
@@ -395,6 +705,8 @@ impl IRGraph {
             sink: edge.sink,
             sink_selector: NodeSelector::Single,
             sink_port: edge.sink_port,
+            feedback: edge.feedback,
+            span: edge.span,
         });
         new_id
     }
@@ -521,9 +833,18 @@ impl IRGraph {
     ///
     /// This will panic on a cycle.
     pub fn topological_sort(&self) -> Vec<NodeId> {
+        self.try_topological_sort()
+            .expect("IRGraph contains a cycle — topological sort is undefined")
+    }
+
+    /// Like [`Self::topological_sort`], but a cycle is returned as an error that points
+    /// at one of the connections closing it. Feedback edges read the previous block, so
+    /// they are left out.
+    pub fn try_topological_sort(&self) -> Result<Vec<NodeId>, ValidationError> {
         let mut in_degree: HashMap<NodeId, usize> = self.nodes.keys().map(|&k| (k, 0)).collect();
+        let edges = || self.edges.iter().filter(|e| !e.feedback);
 
-        for edge in &self.edges {
+        for edge in edges() {
             *in_degree.entry(edge.sink).or_insert(0) += 1;
         }
 
@@ -537,9 +858,7 @@ impl IRGraph {
         let mut sorted = Vec::with_capacity(self.nodes.len());
         while let Some(id) = queue.pop_front() {
             sorted.push(id);
-            let mut next: Vec<NodeId> = self
-                .edges
-                .iter()
+            let mut next: Vec<NodeId> = edges()
                 .filter(|e| e.source == id)
                 .filter_map(|e| {
                     let deg = in_degree.get_mut(&e.sink)?;
@@ -551,12 +870,26 @@ impl IRGraph {
             queue.extend(next);
         }
 
-        assert_eq!(
-            sorted.len(),
-            self.nodes.len(),
-            "IRGraph contains a cycle — topological sort is undefined"
-        );
-        sorted
+        if sorted.len() == self.nodes.len() {
+            return Ok(sorted);
+        }
+
+        // Every node left over is on a cycle or downstream of one
+        let closing = edges()
+            .filter(|e| in_degree.get(&e.source).is_some_and(|d| *d > 0))
+            .find(|e| in_degree.get(&e.sink).is_some_and(|d| *d > 0));
+        let name = |id| self.get_node(id).map_or("?", |n| n.alias.as_str());
+        let err = match closing {
+            Some(edge) => ValidationError::Cycle(format!(
+                "'{}' >> '{}' closes a cycle; use `~>` to feed back a block late, or a kernel \
+                 for feedback within the block",
+                name(edge.source),
+                name(edge.sink)
+            ))
+            .at(&edge.span),
+            None => ValidationError::Cycle("the graph contains a cycle".into()),
+        };
+        Err(err)
     }
 }
 
@@ -588,10 +921,11 @@ impl fmt::Display for IRGraph {
         for edge in &self.edges {
             let src = id_to_alias.get(&edge.source).unwrap_or(&"?");
             let snk = id_to_alias.get(&edge.sink).unwrap_or(&"?");
+            let arrow = if edge.feedback { "~>" } else { "->" };
             writeln!(
                 f,
-                "  {} {:?} -> {} {:?}",
-                src, edge.source_port, snk, edge.sink_port
+                "  {} {:?} {} {} {:?}",
+                src, edge.source_port, arrow, snk, edge.sink_port
             )?;
         }
         Ok(())
@@ -606,106 +940,105 @@ impl<'a> DSLParams<'a> {
         Self(obj)
     }
 
-    pub fn get_f32(&self, key: &str) -> Option<f32> {
-        match self.0.get(key) {
-            Some(Value::F32(x)) => Some(*x),
-            Some(Value::I32(x)) => Some(*x as f32),
-            Some(Value::U32(x)) => Some(*x as f32),
-            Some(x) => panic!("Expected F32 param, found {:?}", x),
-            _ => None,
-        }
+    /// The param a getter finds for `key`, or an error if it is missing, e.g.
+    /// `p.require("freq", DSLParams::get_f32)?`.
+    pub fn require<T>(
+        &self,
+        key: &str,
+        get: impl Fn(&Self, &str) -> Result<Option<T>, ValidationError>,
+    ) -> Result<T, ValidationError> {
+        get(self, key)?.ok_or_else(|| {
+            ValidationError::MissingRequiredParameter(format!("Missing required parameter {key}"))
+        })
+    }
+
+    pub fn get_f32(&self, key: &str) -> Result<Option<f32>, ValidationError> {
+        self.0
+            .get(key)
+            .map(|x| as_f32(x).ok_or_else(|| wrong_type(key, "a number", x)))
+            .transpose()
     }
 
     // TODO: More units
-    pub fn get_duration_ms(&self, key: &str) -> Option<Duration> {
-        match self.0.get(key) {
-            Some(Value::F32(ms)) => Some(Duration::from_secs_f32(ms / 1000.0)),
-            Some(Value::I32(ms)) => Some(Duration::from_millis(*ms as u64)),
-            Some(Value::U32(ms)) => Some(Duration::from_millis(*ms as u64)),
-            Some(x) => panic!("Expected F32 or I32 param for ms, found {:?}", x),
-            _ => None,
-        }
+    pub fn get_duration_ms(&self, key: &str) -> Result<Option<Duration>, ValidationError> {
+        self.0
+            .get(key)
+            .map(|x| {
+                as_duration_ms(x)
+                    .ok_or_else(|| wrong_type(key, "a positive number of milliseconds", x))
+            })
+            .transpose()
     }
 
-    pub fn get_u32(&self, key: &str) -> Option<u32> {
+    pub fn get_u32(&self, key: &str) -> Result<Option<u32>, ValidationError> {
         match self.0.get(key) {
-            Some(Value::U32(s)) => Some(*s),
-            Some(x) => panic!("Expected U32 param, found {:?}", x),
-            _ => None,
+            Some(Value::U32(s)) => Ok(Some(*s)),
+            Some(x) => Err(wrong_type(key, "a whole number", x)),
+            _ => Ok(None),
         }
     }
 
-    pub fn get_usize(&self, key: &str) -> Option<usize> {
-        self.get_u32(key).map(|i| i as usize)
+    pub fn get_usize(&self, key: &str) -> Result<Option<usize>, ValidationError> {
+        Ok(self.get_u32(key)?.map(|i| i as usize))
     }
 
-    pub fn get_str(&self, key: &str) -> Option<String> {
+    pub fn get_str(&self, key: &str) -> Result<Option<String>, ValidationError> {
         match self.0.get(key) {
-            Some(Value::String(s)) => Some(s.clone()),
-            Some(Value::Ident(i)) => Some(i.clone()),
-            Some(x) => panic!("Expected str param, found {:?}", x),
-            _ => None,
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            Some(Value::Ident(i)) => Ok(Some(i.clone())),
+            Some(x) => Err(wrong_type(key, "a string", x)),
+            _ => Ok(None),
         }
     }
 
-    pub fn get_bool(&self, key: &str) -> Option<bool> {
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, ValidationError> {
         match self.0.get(key) {
-            Some(Value::Bool(b)) => Some(*b),
-            Some(x) => panic!("Expected bool param, found {:?}", x),
-            _ => None,
+            Some(Value::Bool(b)) => Ok(Some(*b)),
+            Some(x) => Err(wrong_type(key, "a bool", x)),
+            _ => Ok(None),
         }
     }
 
-    pub fn get_object(&self, key: &str) -> Option<Object> {
+    pub fn get_object(&self, key: &str) -> Result<Option<Object>, ValidationError> {
         match self.0.get(key) {
-            Some(Value::Object(o)) => Some(o.clone()),
-            Some(x) => panic!("Expected object param, found {:?}", x),
-            _ => None,
+            Some(Value::Object(o)) => Ok(Some(o.clone())),
+            Some(x) => Err(wrong_type(key, "an object", x)),
+            _ => Ok(None),
         }
     }
 
-    pub fn get_array(&self, key: &str) -> Option<Vec<Value>> {
+    pub fn get_array(&self, key: &str) -> Result<Option<Vec<Value>>, ValidationError> {
         match self.0.get(key) {
-            Some(Value::Array(v)) => Some(v.clone()),
-            Some(x) => panic!("Expected array param, found {:?}", x),
-            _ => None,
+            Some(Value::Array(v)) => Ok(Some(v.clone())),
+            Some(x) => Err(wrong_type(key, "an array", x)),
+            _ => Ok(None),
         }
     }
 
-    pub fn get_array_f32(&self, key: &str) -> Option<Vec<f32>> {
-        let arr = match self.0.get(key) {
-            Some(Value::Array(v)) => Some(v.clone()),
-            Some(x) => panic!("Expected array param, found {:?}", x),
-            _ => None,
-        }?;
-        Some(
-            arr.into_iter()
-                .map(|x| match x {
-                    Value::F32(x) => x,
-                    Value::I32(x) => x as f32,
-                    Value::U32(x) => x as f32,
-                    _ => panic!("Unexpected value in f32 array {:?}", x),
-                })
-                .collect(),
-        )
+    pub fn get_array_f32(&self, key: &str) -> Result<Option<Vec<f32>>, ValidationError> {
+        self.get_array(key)?
+            .map(|arr| {
+                arr.iter()
+                    .map(|x| as_f32(x).ok_or_else(|| wrong_type(key, "an array of numbers", x)))
+                    .collect()
+            })
+            .transpose()
     }
 
-    pub fn get_array_duration_ms(&self, key: &str) -> Option<Vec<Duration>> {
-        let arr = match self.0.get(key) {
-            Some(Value::Array(v)) => v.clone(),
-            Some(x) => panic!("Expected array param, found {:?}", x),
-            _ => return None,
-        };
-        Some(
-            arr.into_iter()
-                .map(|x| match x {
-                    Value::F32(x) => Duration::from_secs_f32(x / 1000.0),
-                    Value::I32(x) => Duration::from_millis(x as u64),
-                    Value::U32(x) => Duration::from_millis(x as u64),
-                    _ => panic!("Unexpected value in duration array {:?}", x),
-                })
-                .collect(),
-        )
+    pub fn get_array_duration_ms(
+        &self,
+        key: &str,
+    ) -> Result<Option<Vec<Duration>>, ValidationError> {
+        self.get_array(key)?
+            .map(|arr| {
+                arr.iter()
+                    .map(|x| {
+                        as_duration_ms(x)
+                            .ok_or_else(|| wrong_type(key, "an array of positive milliseconds", x))
+                    })
+                    .collect()
+            })
+            .transpose()
     }
 
     pub fn validate(&self, allowed: &BTreeSet<String>) -> Result<(), ValidationError> {
@@ -733,6 +1066,28 @@ impl<'a> DSLParams<'a> {
     }
 }
 
+fn as_f32(value: &Value) -> Option<f32> {
+    match value {
+        Value::F32(x) => Some(*x),
+        Value::I32(x) => Some(*x as f32),
+        Value::U32(x) => Some(*x as f32),
+        _ => None,
+    }
+}
+
+fn as_duration_ms(value: &Value) -> Option<Duration> {
+    match value {
+        Value::F32(ms) => Duration::try_from_secs_f32(ms / 1000.0).ok(),
+        Value::I32(ms) => u64::try_from(*ms).ok().map(Duration::from_millis),
+        Value::U32(ms) => Some(Duration::from_millis(*ms as u64)),
+        _ => None,
+    }
+}
+
+fn wrong_type(key: &str, expected: &str, found: &Value) -> ValidationError {
+    ValidationError::InvalidParameter(format!("`{key}` should be {expected}, found {found:?}"))
+}
+
 impl From<f32> for Value {
     fn from(v: f32) -> Self {
         Value::F32(v)
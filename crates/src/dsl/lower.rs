@@ -1,7 +1,10 @@
 use indexmap::IndexMap;
 
 use crate::builder::ValidationError;
-use crate::dsl::ir::*;
+use crate::dsl::{
+    generate::{fill_graph, is_template, literal_count},
+    ir::*,
+};
 use std::collections::HashMap;
 
 /// Aliases key the alias index, so a repeat would shadow the earlier declaration.
@@ -12,26 +15,222 @@ fn duplicate_alias(alias: &str, scope: Option<&str>) -> ValidationError {
     ))
 }
 
-/// Convert the ASTMacro to the IRMacro
-fn convert_macro(
+/// The pipes after `decl`, to run over the node it was lowered to.
+fn lower_pipes(
+    decl: &NodeDeclaration,
+    kind: &IRNodeKind,
+    id: NodeId,
+) -> Result<Option<IRPipe>, ValidationError> {
+    if decl.pipes.is_empty() {
+        return Ok(None);
+    }
+    // A patch is gone by the time pipes run, and its nodes have pipes of their own
+    if *kind == IRNodeKind::MacroRef {
+        return Err(ValidationError::InvalidParameter(format!(
+            "'{}' is a patch, so it cannot be piped; pipe the nodes inside it instead",
+            decl.node_type
+        ))
+        .at(&decl.span));
+    }
+    Ok(Some(IRPipe {
+        nodes: vec![id],
+        chain: decl.pipes.clone(),
+        span: decl.span.clone(),
+    }))
+}
+
+/// The namespace of the nodes that connection arithmetic lowers to.
+const EXPR_NAMESPACE: &str = "audio";
+
+/// A lowered operand: a constant still to be placed, or a signal to connect.
+enum Operand {
+    Constant(Expr),
+    Signal(Endpoint),
+}
+
+/// Lowers [`ExprConnection`]s into anonymous nodes, and the plain connections between them.
+///
+/// Their aliases contain a `#`, which the parser never produces, so they cannot collide
+/// with declared ones.
+#[derive(Default)]
+struct ExprLowering {
+    declarations: Vec<NodeDeclaration>,
+    connections: Vec<Connection>,
+    // The connection being lowered, which everything it adds points back to
+    span: SourceSpan,
+}
+
+impl ExprLowering {
+    /// Lower `exprs`, adding the nodes they need as a new declaration scope.
+    fn lower_into(
+        exprs: Vec<ExprConnection>,
+        declarations: &mut Vec<DeclarationScope>,
+        connections: &mut Vec<Connection>,
+    ) -> Result<(), ValidationError> {
+        if exprs.is_empty() {
+            return Ok(());
+        }
+
+        let mut lowering = Self::default();
+        for expr in exprs {
+            lowering.span = expr.span;
+            let source = lowering
+                .lower(expr.source)
+                .map_err(|e| e.at(&lowering.span))?;
+            let source = lowering.signal(source);
+            lowering.connect(source, expr.sink);
+        }
+
+        declarations.push(DeclarationScope {
+            namespace: EXPR_NAMESPACE.to_string(),
+            declarations: lowering.declarations,
+        });
+        connections.extend(lowering.connections);
+        Ok(())
+    }
+
+    fn lower(&mut self, expr: SignalExpr) -> Result<Operand, ValidationError> {
+        Ok(match expr {
+            SignalExpr::Constant(expr) => Operand::Constant(expr),
+            SignalExpr::Endpoint(endpoint) => Operand::Signal(endpoint),
+            SignalExpr::Neg(inner) => match self.lower(*inner)? {
+                Operand::Constant(expr) => Operand::Constant(Expr {
+                    span: expr.span.clone(),
+                    kind: ExprKind::Neg(Box::new(expr)),
+                }),
+                Operand::Signal(signal) => {
+                    let node = self.node("mult", (-1.0f32).into());
+                    self.connect(signal, port(&node, Port::Index(0)));
+                    Operand::Signal(port(&node, Port::None))
+                }
+            },
+            SignalExpr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.lower(*lhs)?, self.lower(*rhs)?);
+                self.binary(op, lhs, rhs)?
+            }
+        })
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        lhs: Operand,
+        rhs: Operand,
+    ) -> Result<Operand, ValidationError> {
+        if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (&lhs, &rhs) {
+            return Ok(Operand::Constant(Expr {
+                span: lhs.span.start..rhs.span.end,
+                kind: ExprKind::Binary(op, Box::new(lhs.clone()), Box::new(rhs.clone())),
+            }));
+        }
+
+        let (node_type, identity) = match op {
+            BinaryOp::Add => ("add", 0.0f32),
+            BinaryOp::Sub => ("sub", 0.0),
+            BinaryOp::Mul => ("mult", 1.0),
+            BinaryOp::Div => ("div", 1.0),
+            BinaryOp::Rem => {
+                return Err(ValidationError::InvalidParameter(
+                    "`%` is only supported between constants, not signals".into(),
+                ));
+            }
+        };
+        let commutes = matches!(op, BinaryOp::Add | BinaryOp::Mul);
+
+        // The node's input, and what it is combined with: a constant `val`, or a signal
+        // into the `val` port
+        let (input, val) = match (lhs, rhs) {
+            (Operand::Constant(val), Operand::Signal(signal)) if commutes => {
+                (signal, Operand::Constant(val))
+            }
+            (lhs, rhs) => (self.signal(lhs), rhs),
+        };
+
+        let node = match val {
+            Operand::Constant(val) => self.node(node_type, val.into_value()),
+            Operand::Signal(val) => {
+                let node = self.node(node_type, identity.into());
+                self.connect(val, port(&node, Port::Named("val".into())));
+                node
+            }
+        };
+        self.connect(input, port(&node, Port::Index(0)));
+        Ok(Operand::Signal(port(&node, Port::None)))
+    }
+
+    /// The operand as a signal, placing a `const` node for a constant.
+    fn signal(&mut self, operand: Operand) -> Endpoint {
+        match operand {
+            Operand::Signal(endpoint) => endpoint,
+            Operand::Constant(expr) => {
+                let node = self.node("const", expr.into_value());
+                port(&node, Port::None)
+            }
+        }
+    }
+
+    /// Declare an anonymous node with `val` set, returning its alias.
+    fn node(&mut self, node_type: &str, val: Value) -> String {
+        let alias = format!("{node_type}#{}", self.declarations.len());
+        self.declarations.push(NodeDeclaration {
+            node_type: node_type.to_string(),
+            alias: Some(alias.clone()),
+            params: Some(Object::from([("val".to_string(), val)])),
+            count: 1,
+            count_expr: None,
+            pipes: vec![],
+            oversample: 1,
+            span: self.span.clone(),
+        });
+        alias
+    }
+
+    fn connect(&mut self, source: Endpoint, sink: Endpoint) {
+        self.connections.push(Connection {
+            source,
+            sink,
+            feedback: false,
+            span: self.span.clone(),
+        });
+    }
+}
+
+fn port(node: &str, port: Port) -> Endpoint {
+    Endpoint {
+        node: node.to_string(),
+        node_selector: NodeSelector::Single,
+        port,
+    }
+}
+
+/// Convert the ASTMacro to the IRMacro. Instances of `templates` are left for
+/// [`crate::dsl::generate::GenerativePass`].
+pub(crate) fn convert_macro(
     name: &str,
     ast_map: &mut HashMap<String, AstMacro>,
     converted: &mut HashMap<String, IRMacro>,
+    templates: &HashMap<String, AstMacro>,
 ) -> Result<(), ValidationError> {
     // Check to avoid redundant macro conversion
     if converted.contains_key(name) {
         return Ok(());
     }
 
-    let ast_macro = ast_map
+    let mut ast_macro = ast_map
         .remove(name)
-        .unwrap_or_else(|| panic!("Macro '{}' not found", name));
+        .ok_or_else(|| ValidationError::NodeNotFound(format!("patch '{name}' not found")))?;
+
+    ExprLowering::lower_into(
+        std::mem::take(&mut ast_macro.expr_connections),
+        &mut ast_macro.declarations,
+        &mut ast_macro.connections,
+    )?;
 
     // Resolve dependencies first
     for scope in &ast_macro.declarations {
         for decl in &scope.declarations {
             if ast_map.contains_key(&decl.node_type) {
-                convert_macro(&decl.node_type, ast_map, converted)?;
+                convert_macro(&decl.node_type, ast_map, converted, templates)?;
             }
         }
     }
@@ -45,48 +244,73 @@ fn convert_macro(
             let alias = decl.alias.clone().unwrap_or_else(|| decl.node_type.clone());
 
             // Classify by what the node type names.
-            let kind = classify_node_type(&decl.node_type, converted);
+            let kind = classify_node_type(&decl.node_type, converted, templates);
 
             // Kernel bodies are lowered whole into a single per-sample node,
             // so they can only contain kernel-capable leaves.
             if ast_macro.kind == MacroKind::Kernel && kind != IRNodeKind::Leaf {
-                panic!(
+                return Err(ValidationError::UnsupportedInKernel(format!(
                     "kernel '{}' declares '{}' ({}), but kernels may only contain leaf nodes",
                     name, alias, decl.node_type
-                );
+                ))
+                .at(&decl.span));
+            }
+            if ast_macro.kind == MacroKind::Kernel && !decl.pipes.is_empty() {
+                return Err(ValidationError::UnsupportedInKernel(format!(
+                    "kernel '{}' pipes '{}', but pipes run on built nodes; pipe the kernel instead",
+                    name, alias
+                ))
+                .at(&decl.span));
             }
 
             if local_alias_to_id.contains_key(&alias) {
-                return Err(duplicate_alias(&alias, Some(name)));
+                return Err(duplicate_alias(&alias, Some(name)).at(&decl.span));
             }
 
             let id = body.add_node(
-                kind,
+                kind.clone(),
                 scope.namespace.clone(),
                 decl.node_type.clone(),
                 alias.clone(),
                 decl.params.clone().unwrap_or_default(),
-                decl.count,
+                literal_count(decl.count).map_err(|e| e.at(&decl.span))?,
             );
+            body.set_span(id, decl.span.clone());
+            body.set_oversample(id, decl.oversample);
+            body.pipes.extend(lower_pipes(decl, &kind, id)?);
             local_alias_to_id.insert(alias, id);
         }
     }
 
     for conn in &ast_macro.connections {
-        // This is handled below
-        if ast_macro.virtual_ports_in.contains(&conn.source.node) {
-            continue;
+        if conn.feedback && ast_macro.kind == MacroKind::Kernel {
+            return Err(ValidationError::UnsupportedInKernel(format!(
+                "kernel '{name}' uses `~>`, but kernels run per sample and may feed back with `>>`"
+            ))
+            .at(&conn.span));
+        }
+        if conn.feedback && ast_macro.virtual_ports_in.contains(&conn.source.node) {
+            return Err(ValidationError::Cycle(format!(
+                "'{}' is an input of patch '{name}', so it cannot feed back; use `~>` where the \
+                 patch is connected instead",
+                conn.source.node
+            ))
+            .at(&conn.span));
         }
 
-        let src = *local_alias_to_id
-            .get(&conn.source.node)
-            .unwrap_or_else(|| panic!("Could not find src node for alias {}", conn.source.node));
+        // These are handled below
+        if ast_macro.virtual_ports_in.contains(&conn.source.node)
+            || ast_macro.virtual_ports_out.contains(&conn.sink.node)
+        {
+            continue;
+        }
 
-        let snk = *local_alias_to_id
-            .get(&conn.sink.node)
-            .unwrap_or_else(|| panic!("Could not find sink node for alias {}", conn.sink.node));
+        let src = endpoint_node(&body, &local_alias_to_id, &conn.source, Some(name))
+            .map_err(|e| e.at(&conn.span))?;
+        let snk = endpoint_node(&body, &local_alias_to_id, &conn.sink, Some(name))
+            .map_err(|e| e.at(&conn.span))?;
 
-        body.connect_multi(
+        let edge = body.connect_multi(
             src,
             conn.source.node_selector.clone(),
             conn.source.port.clone(),
@@ -94,6 +318,8 @@ fn convert_macro(
             conn.sink.node_selector.clone(),
             conn.sink.port.clone(),
         );
+        edge.span = conn.span.clone();
+        edge.feedback = conn.feedback;
     }
 
     let mut virtual_input_map: IndexMap<String, Vec<(NodeId, NodeSelector, Port)>> =
@@ -103,14 +329,54 @@ fn convert_macro(
         .iter()
         .filter(|c| ast_macro.virtual_ports_in.contains(&c.source.node))
     {
-        let target_id = local_alias_to_id[&c.sink.node];
+        let target_id = endpoint_node(&body, &local_alias_to_id, &c.sink, Some(name))
+            .map_err(|e| e.at(&c.span))?;
         virtual_input_map
             .entry(c.source.node.clone())
             .or_default()
             .push((target_id, c.sink.node_selector.clone(), c.sink.port.clone()));
     }
 
-    let sink_id = local_alias_to_id[&ast_macro.sink];
+    let mut virtual_output_map: IndexMap<String, (NodeId, NodeSelector, Port)> = IndexMap::new();
+    for out in &ast_macro.virtual_ports_out {
+        let mut feeding = ast_macro.connections.iter().filter(|c| &c.sink.node == out);
+        let Some(c) = feeding.next() else {
+            return Err(ValidationError::Expansion(format!(
+                "'{out}' is an output of patch '{name}', but nothing is connected to it"
+            )));
+        };
+        if let Some(again) = feeding.next() {
+            return Err(ValidationError::Expansion(format!(
+                "'{out}' is an output of patch '{name}' and is already connected; mix the \
+                 signals inside the patch first"
+            ))
+            .at(&again.span));
+        }
+        if c.feedback || c.sink.node_selector != NodeSelector::Single || c.sink.port != Port::None {
+            return Err(ValidationError::Expansion(format!(
+                "'{out}' is an output of patch '{name}', so connect to it with a plain `>> {out}`"
+            ))
+            .at(&c.span));
+        }
+
+        let source_id = endpoint_node(&body, &local_alias_to_id, &c.source, Some(name))
+            .map_err(|e| e.at(&c.span))?;
+        virtual_output_map.insert(
+            out.clone(),
+            (
+                source_id,
+                c.source.node_selector.clone(),
+                c.source.port.clone(),
+            ),
+        );
+    }
+
+    let sink_id = *local_alias_to_id.get(&ast_macro.sink).ok_or_else(|| {
+        ValidationError::NodeNotFound(format!(
+            "patch '{name}' outputs '{}', which is not declared",
+            ast_macro.sink
+        ))
+    })?;
     body.sink = Some(sink_id);
 
     converted.insert(
@@ -119,7 +385,9 @@ fn convert_macro(
             name: name.to_string(),
             kind: ast_macro.kind,
             default_params: ast_macro.default_params,
+            rate: ast_macro.rate,
             virtual_input_map,
+            virtual_output_map,
             body,
             sink: sink_id,
         },
@@ -127,12 +395,45 @@ fn convert_macro(
     Ok(())
 }
 
+/// The node `endpoint` names, provided its selector only picks instances that exist.
+fn endpoint_node(
+    graph: &IRGraph,
+    aliases: &HashMap<String, NodeId>,
+    endpoint: &Endpoint,
+    scope: Option<&str>,
+) -> Result<NodeId, ValidationError> {
+    let id = *aliases.get(&endpoint.node).ok_or_else(|| {
+        let scope = scope.map_or_else(|| "top level".to_string(), |s| format!("patch '{s}'"));
+        ValidationError::NodeNotFound(format!(
+            "'{}' is connected at {scope}, but not declared there",
+            endpoint.node
+        ))
+    })?;
+
+    let count = graph.get_node(id).map_or(1, |n| n.count);
+    if !endpoint.node_selector.in_range(count as usize) {
+        return Err(ValidationError::SelectionArity(format!(
+            "{:?} selects past the {count} instance(s) of '{}'",
+            endpoint.node_selector, endpoint.node
+        )));
+    }
+    Ok(id)
+}
+
 /// Leaf, MacroRef or KernelRef, depending on what `node_type` names in the
-/// (partially built) macro registry.
-fn classify_node_type(node_type: &str, registry: &HashMap<String, IRMacro>) -> IRNodeKind {
-    match registry.get(node_type) {
-        Some(m) if m.kind == MacroKind::Kernel => IRNodeKind::KernelRef,
-        Some(_) => IRNodeKind::MacroRef,
+/// (partially built) macro registry or the templates.
+fn classify_node_type(
+    node_type: &str,
+    registry: &HashMap<String, IRMacro>,
+    templates: &HashMap<String, AstMacro>,
+) -> IRNodeKind {
+    let kind = registry
+        .get(node_type)
+        .map(|m| m.kind)
+        .or_else(|| templates.get(node_type).map(|m| m.kind));
+    match kind {
+        Some(MacroKind::Kernel) => IRNodeKind::KernelRef,
+        Some(MacroKind::Patch) => IRNodeKind::MacroRef,
         None => IRNodeKind::Leaf,
     }
 }
@@ -140,21 +441,46 @@ fn classify_node_type(node_type: &str, registry: &HashMap<String, IRMacro>) -> I
 /// We have a bit of an easier Ast shape that the actual IR, since patches
 /// are not-quite recursive. Here, we convert macros to a type that can recurse,
 /// this makes various graph transformations easier.
-pub fn ast_to_graph(ast: Ast) -> Result<IRGraph, ValidationError> {
+pub fn ast_to_graph(mut ast: Ast) -> Result<IRGraph, ValidationError> {
     let mut graph = IRGraph::new();
 
+    fill_graph(&mut ast)?;
+    ExprLowering::lower_into(
+        std::mem::take(&mut ast.expr_connections),
+        &mut ast.declarations,
+        &mut ast.connections,
+    )?;
+
     let mut macro_ast_map: HashMap<String, AstMacro> = ast
         .macros
         .into_iter()
         .map(|m| (m.name.clone(), m))
         .collect();
 
+    // Templates, and the patches that instantiate them, are kept as written
+    let mut templates: HashMap<String, AstMacro> = HashMap::new();
+    loop {
+        let names: Vec<String> = macro_ast_map
+            .values()
+            .filter(|m| is_template(m) || instantiates(m, &templates))
+            .map(|m| m.name.clone())
+            .collect();
+        if names.is_empty() {
+            break;
+        }
+        for name in names {
+            if let Some(m) = macro_ast_map.remove(&name) {
+                templates.insert(name, m);
+            }
+        }
+    }
+
     let mut converted: HashMap<String, IRMacro> = HashMap::new();
 
     // Process each macro, recursing into dependencies first
     let names: Vec<String> = macro_ast_map.keys().cloned().collect();
     for name in names {
-        convert_macro(&name, &mut macro_ast_map, &mut converted)?;
+        convert_macro(&name, &mut macro_ast_map, &mut converted, &templates)?;
     }
 
     graph.macro_registry = converted;
@@ -166,20 +492,23 @@ pub fn ast_to_graph(ast: Ast) -> Result<IRGraph, ValidationError> {
         for decl in &scope.declarations {
             let alias = decl.alias.clone().unwrap_or_else(|| decl.node_type.clone());
 
-            let kind = classify_node_type(&decl.node_type, &graph.macro_registry);
+            let kind = classify_node_type(&decl.node_type, &graph.macro_registry, &templates);
 
             if alias_to_id.contains_key(&alias) {
-                return Err(duplicate_alias(&alias, None));
+                return Err(duplicate_alias(&alias, None).at(&decl.span));
             }
 
             let id = graph.add_node(
-                kind,
+                kind.clone(),
                 scope.namespace.clone(),
                 decl.node_type.clone(),
                 alias.clone(),
                 decl.params.clone().unwrap_or_default(),
-                decl.count,
+                literal_count(decl.count).map_err(|e| e.at(&decl.span))?,
             );
+            graph.set_span(id, decl.span.clone());
+            graph.set_oversample(id, decl.oversample);
+            graph.pipes.extend(lower_pipes(decl, &kind, id)?);
             alias_to_id.insert(alias, id);
         }
     }
@@ -187,13 +516,11 @@ pub fn ast_to_graph(ast: Ast) -> Result<IRGraph, ValidationError> {
     // Preserve connections verbatim.  Virtual ports are not resolved here;
     // they pass through as `Port::Named` and are handled by MacroExpansionPass.
     for conn in &ast.connections {
-        let src = *alias_to_id
-            .get(&conn.source.node)
-            .unwrap_or_else(|| panic!("ast_to_graph: source '{}' not found", conn.source.node));
-        let snk = *alias_to_id
-            .get(&conn.sink.node)
-            .unwrap_or_else(|| panic!("ast_to_graph: sink '{}' not found", conn.sink.node));
-        graph.connect_multi(
+        let src = endpoint_node(&graph, &alias_to_id, &conn.source, None)
+            .map_err(|e| e.at(&conn.span))?;
+        let snk =
+            endpoint_node(&graph, &alias_to_id, &conn.sink, None).map_err(|e| e.at(&conn.span))?;
+        let edge = graph.connect_multi(
             src,
             conn.source.node_selector.clone(),
             conn.source.port.clone(),
@@ -201,17 +528,44 @@ pub fn ast_to_graph(ast: Ast) -> Result<IRGraph, ValidationError> {
             conn.sink.node_selector.clone(),
             conn.sink.port.clone(),
         );
+        edge.span = conn.span.clone();
+        edge.feedback = conn.feedback;
     }
 
-    graph.sink = alias_to_id.get(&ast.sink).copied();
+    graph.sink = Some(*alias_to_id.get(&ast.sink).ok_or_else(|| {
+        ValidationError::NodeNotFound(format!(
+            "the graph outputs '{}', which is not declared",
+            ast.sink
+        ))
+    })?);
+    graph.outputs = ast
+        .outputs
+        .iter()
+        .map(|(name, alias)| match alias_to_id.get(alias) {
+            Some(id) => Ok((name.clone(), *id)),
+            None => Err(ValidationError::NodeNotFound(format!(
+                "output '{name}' names '{alias}', which is not declared"
+            ))),
+        })
+        .collect::<Result<_, _>>()?;
     graph.source = ast
         .source
         .as_ref()
         .and_then(|s| alias_to_id.get(s).copied());
+    graph.templates = templates;
 
     Ok(graph)
 }
 
+/// Whether `ast_macro` declares an instance of one of `templates`.
+fn instantiates(ast_macro: &AstMacro, templates: &HashMap<String, AstMacro>) -> bool {
+    ast_macro
+        .declarations
+        .iter()
+        .flat_map(|s| &s.declarations)
+        .any(|d| templates.contains_key(&d.node_type))
+}
+
 #[macro_export]
 macro_rules! object {
     () => { ::std::collections::BTreeMap::new() };
@@ -274,6 +628,8 @@ mod spawn_tests {
                 node_selector: snk_sel,
                 port: snk_port,
             },
+            feedback: false,
+            span: Default::default(),
         }
     }
 
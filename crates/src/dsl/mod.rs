@@ -1,7 +1,12 @@
+pub mod dump;
+pub mod eval;
 pub mod expand;
+pub mod generate;
 pub mod ir;
 pub mod lower;
+pub mod module;
 pub mod parse;
 pub mod pipeline;
+pub mod print;
 pub mod resolve;
 pub mod spawn;
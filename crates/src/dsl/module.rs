@@ -0,0 +1,457 @@
+//! `import` and `use`, for sharing patches and kernels between `.legato` files.
+//!
+//! Imports are resolved on the AST, before [`ast_to_graph`](crate::dsl::lower::ast_to_graph).
+//! Every imported patch is renamed after the namespaces it was imported through, e.g.
+//! `verbs::plate`, and every reference to it is rewritten to match. The rest of the pipeline
+//! then sees one file, in which two libraries can each have their own `voice`.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builder::ValidationError,
+    dsl::{
+        ir::{
+            Ast, AstLibrary, AstMacro, Block, BlockKind, DeclarationScope, Import, SourceSpan, Use,
+        },
+        parse::library_parser,
+    },
+};
+
+/// Where the files named by `import` come from.
+pub trait ModuleLoader: Send + Sync {
+    /// The id of the file `path` names when imported from the file `from`, or from the
+    /// source being built if `None`. Imports that resolve to the same id are the same file.
+    fn resolve(&self, path: &str, from: Option<&str>) -> String;
+
+    /// The source of the file `id`.
+    fn load(&self, id: &str) -> io::Result<String>;
+}
+
+/// Loads imports from disk, relative to the importing file, or to `root` for the source
+/// being built. This is the default, rooted at the working directory.
+#[derive(Clone, Debug)]
+pub struct FileLoader {
+    root: PathBuf,
+}
+
+impl FileLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Default for FileLoader {
+    fn default() -> Self {
+        Self::new(".")
+    }
+}
+
+impl ModuleLoader for FileLoader {
+    fn resolve(&self, path: &str, from: Option<&str>) -> String {
+        let dir = match from {
+            Some(file) => Path::new(file).parent().unwrap_or(Path::new("")),
+            None => self.root.as_path(),
+        };
+        let path = dir.join(path);
+
+        path.canonicalize()
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    fn load(&self, id: &str) -> io::Result<String> {
+        std::fs::read_to_string(id)
+    }
+}
+
+/// Loads imports from sources held in memory, e.g. ones embedded with `include_str!`.
+///
+/// Paths are looked up exactly as written, wherever they are imported from.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLoader {
+    files: HashMap<String, String>,
+}
+
+impl MemoryLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(mut self, path: impl Into<String>, src: impl Into<String>) -> Self {
+        self.files.insert(path.into(), src.into());
+        self
+    }
+}
+
+impl ModuleLoader for MemoryLoader {
+    fn resolve(&self, path: &str, _from: Option<&str>) -> String {
+        path.to_string()
+    }
+
+    fn load(&self, id: &str) -> io::Result<String> {
+        self.files
+            .get(id)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such file"))
+    }
+}
+
+/// Load everything `ast` imports, and point every patch reference at the patch it names.
+///
+/// Imported patches are added to `ast.macros` under their namespaced names.
+pub fn resolve_imports(mut ast: Ast, loader: &dyn ModuleLoader) -> Result<Ast, ValidationError> {
+    let mut resolver = Resolver {
+        loader,
+        stack: vec![],
+        macros: vec![],
+    };
+
+    let scope = resolver.scope(None, "", &ast.imports, &ast.uses, &ast.macros)?;
+    for ast_macro in &mut ast.macros {
+        rename(&scope, None, ast_macro.scopes_mut())?;
+    }
+    rename(&scope, None, ast.scopes_mut())?;
+
+    ast.macros.extend(resolver.macros);
+    Ok(ast)
+}
+
+/// The patches one file can name, by the name it uses for them.
+#[derive(Default)]
+struct Scope {
+    // Local or used name to the patch's namespaced name
+    macros: HashMap<String, String>,
+    imports: HashMap<String, Scope>,
+}
+
+impl Scope {
+    fn lookup(&self, path: &[String]) -> Option<&String> {
+        match path {
+            [name] => self.macros.get(name),
+            [namespace, rest @ ..] => self.imports.get(namespace)?.lookup(rest),
+            [] => None,
+        }
+    }
+}
+
+struct Resolver<'a> {
+    loader: &'a dyn ModuleLoader,
+    // The files being resolved, innermost last
+    stack: Vec<String>,
+    // Imported patches, already renamed
+    macros: Vec<AstMacro>,
+}
+
+impl Resolver<'_> {
+    /// Load the imports of `file`, naming their patches under `prefix`.
+    fn scope(
+        &mut self,
+        file: Option<&str>,
+        prefix: &str,
+        imports: &[Import],
+        uses: &[Use],
+        macros: &[AstMacro],
+    ) -> Result<Scope, ValidationError> {
+        let mut scope = Scope::default();
+
+        for import in imports {
+            let id = self.loader.resolve(&import.path, file);
+
+            if let Some(start) = self.stack.iter().position(|f| *f == id) {
+                let cycle: Vec<&str> = self.stack[start..]
+                    .iter()
+                    .chain([&id])
+                    .map(String::as_str)
+                    .collect();
+                return Err(import_error(
+                    file,
+                    format!("import cycle {}", cycle.join(" -> ")),
+                ));
+            }
+            if scope.imports.contains_key(&import.namespace) {
+                return Err(import_error(
+                    file,
+                    format!("two imports are named `{}`", import.namespace),
+                ));
+            }
+
+            let src = self.loader.load(&id).map_err(|e| {
+                import_error(file, format!("could not load '{}': {e}", import.path))
+            })?;
+            let library = library_parser(&id, &src)?;
+
+            self.stack.push(id.clone());
+            let inner = self.library(&id, &format!("{prefix}{}::", import.namespace), library)?;
+            self.stack.pop();
+
+            scope.imports.insert(import.namespace.clone(), inner);
+        }
+
+        for ast_macro in macros {
+            scope.macros.insert(
+                ast_macro.name.clone(),
+                format!("{prefix}{}", ast_macro.name),
+            );
+        }
+
+        for use_ in uses {
+            let path = use_.path.join("::");
+            let target = scope
+                .lookup(&use_.path)
+                .cloned()
+                .ok_or_else(|| import_error(file, format!("`use {path}` names nothing")))?;
+
+            if scope.macros.insert(use_.alias.clone(), target).is_some() {
+                return Err(import_error(
+                    file,
+                    format!("`use {path}` shadows `{}`", use_.alias),
+                ));
+            }
+        }
+
+        Ok(scope)
+    }
+
+    fn library(
+        &mut self,
+        file: &str,
+        prefix: &str,
+        library: AstLibrary,
+    ) -> Result<Scope, ValidationError> {
+        let scope = self.scope(
+            Some(file),
+            prefix,
+            &library.imports,
+            &library.uses,
+            &library.macros,
+        )?;
+
+        for mut ast_macro in library.macros {
+            rename(&scope, Some(file), ast_macro.scopes_mut())?;
+            forget_spans(&mut ast_macro);
+            ast_macro.name = format!("{prefix}{}", ast_macro.name);
+            self.macros.push(ast_macro);
+        }
+
+        Ok(scope)
+    }
+}
+
+/// Spans index into the file a patch was written in, so errors inside imported patches
+/// carry none rather than pointing at the wrong place in the main source.
+fn forget_spans(ast_macro: &mut AstMacro) {
+    for decl in ast_macro
+        .scopes_mut()
+        .into_iter()
+        .flat_map(|s| &mut s.declarations)
+    {
+        decl.span = SourceSpan::default();
+    }
+    for conn in &mut ast_macro.connections {
+        conn.span = SourceSpan::default();
+    }
+    for conn in &mut ast_macro.expr_connections {
+        conn.span = SourceSpan::default();
+    }
+    ast_macro.blocks.iter_mut().for_each(forget_block_spans);
+}
+
+fn forget_block_spans(block: &mut Block) {
+    block.span = SourceSpan::default();
+    let mut bodies = vec![&mut block.body];
+    if let BlockKind::If { otherwise, .. } = &mut block.kind {
+        bodies.push(otherwise);
+    }
+    for body in bodies {
+        for conn in &mut body.connections {
+            conn.span = SourceSpan::default();
+        }
+        for conn in &mut body.expr_connections {
+            conn.span = SourceSpan::default();
+        }
+        body.blocks.iter_mut().for_each(forget_block_spans);
+    }
+}
+
+/// Point every declaration of a patch at the patch's namespaced name.
+fn rename(
+    scope: &Scope,
+    file: Option<&str>,
+    scopes: Vec<&mut DeclarationScope>,
+) -> Result<(), ValidationError> {
+    for decl in scopes.into_iter().flat_map(|s| &mut s.declarations) {
+        let path: Vec<String> = decl.node_type.split("::").map(String::from).collect();
+
+        let Some(name) = scope.lookup(&path) else {
+            if path.len() > 1 {
+                return Err(import_error(
+                    file,
+                    format!("`{}` names nothing", decl.node_type),
+                ));
+            }
+            // A leaf node
+            continue;
+        };
+
+        // Without an alias, the node is still named as written, e.g. `plate` for `verbs::plate`
+        decl.alias
+            .get_or_insert_with(|| path[path.len() - 1].clone());
+        decl.node_type = name.clone();
+    }
+
+    Ok(())
+}
+
+fn import_error(file: Option<&str>, message: String) -> ValidationError {
+    ValidationError::Import(format!("{}: {message}", file.unwrap_or("main")))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dsl::parse::legato_parser;
+
+    fn resolve(src: &str, loader: &MemoryLoader) -> Result<Ast, ValidationError> {
+        resolve_imports(legato_parser(src).unwrap(), loader)
+    }
+
+    fn node_types(ast: &Ast) -> Vec<(String, Option<String>)> {
+        ast.declarations
+            .iter()
+            .flat_map(|s| &s.declarations)
+            .map(|d| (d.node_type.clone(), d.alias.clone()))
+            .collect()
+    }
+
+    const VOICE: &str = r#"
+        patch voice(freq = 220.0) {
+            audio { sine { freq: $freq } }
+            { sine }
+        }
+    "#;
+
+    #[test]
+    fn libraries_are_namespaced() {
+        let loader = MemoryLoader::new()
+            .with_file("lead.legato", VOICE)
+            .with_file("pads/bass.legato", VOICE);
+
+        let ast = resolve(
+            r#"
+            import "lead.legato"
+            import "pads/bass.legato" as low
+
+            patches {
+                lead::voice,
+                low::voice: bass { freq: 55.0 }
+            }
+
+            { bass }
+            "#,
+            &loader,
+        )
+        .unwrap();
+
+        assert_eq!(
+            node_types(&ast),
+            vec![
+                ("lead::voice".into(), Some("voice".into())),
+                ("low::voice".into(), Some("bass".into())),
+            ]
+        );
+
+        let mut names: Vec<_> = ast.macros.iter().map(|m| m.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["lead::voice", "low::voice"]);
+    }
+
+    #[test]
+    fn uses_and_nested_imports() {
+        let loader = MemoryLoader::new()
+            .with_file("osc.legato", VOICE)
+            .with_file(
+                "synth.legato",
+                r#"
+            import "osc.legato"
+            use osc::voice as osc_voice
+
+            patch lead() {
+                patches { osc_voice: a {}, osc::voice: b {} }
+                a >> b
+                { b }
+            }
+            "#,
+            );
+
+        let ast = resolve(
+            r#"
+            import "synth.legato"
+            use synth::lead
+
+            patches { lead }
+
+            { lead }
+            "#,
+            &loader,
+        )
+        .unwrap();
+
+        assert_eq!(
+            node_types(&ast),
+            vec![("synth::lead".into(), Some("lead".into()))]
+        );
+
+        let lead = ast.macros.iter().find(|m| m.name == "synth::lead").unwrap();
+        let inner: Vec<_> = lead.declarations[0]
+            .declarations
+            .iter()
+            .map(|d| d.node_type.as_str())
+            .collect();
+        assert_eq!(inner, ["synth::osc::voice", "synth::osc::voice"]);
+    }
+
+    #[test]
+    fn import_cycles_are_errors() {
+        let loader = MemoryLoader::new()
+            .with_file("a.legato", r#"import "b.legato""#)
+            .with_file("b.legato", r#"import "a.legato""#);
+
+        let err = resolve(r#"import "a.legato" audio { sine } { sine }"#, &loader);
+
+        assert_eq!(
+            err,
+            Err(ValidationError::Import(
+                "b.legato: import cycle a.legato -> b.legato -> a.legato".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn errors_name_the_file() {
+        let loader = MemoryLoader::new().with_file(
+            "lib.legato",
+            r#"
+            patch wrapper() {
+                patches { missing::voice }
+                { voice }
+            }
+            "#,
+        );
+
+        let missing = resolve(r#"import "nope.legato" audio { sine } { sine }"#, &loader);
+        assert!(matches!(missing, Err(ValidationError::Import(msg)) if msg.starts_with("main:")));
+
+        let unknown = resolve(r#"import "lib.legato" audio { sine } { sine }"#, &loader);
+        assert_eq!(
+            unknown,
+            Err(ValidationError::Import(
+                "lib.legato: `missing::voice` names nothing".into()
+            ))
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{builder::ValidationError, dsl::ir::*};
+use crate::{builder::ValidationError, context::Rate, dsl::ir::*};
 use ariadne::{Color, Label, Report, ReportKind, Source};
 use chumsky::{extra::Err, prelude::*};
 use std::collections::BTreeMap;
@@ -29,45 +29,7 @@ where
 
 fn value_parser<'a>() -> impl Parser<'a, &'a str, Value, Err<Rich<'a, char>>> {
     recursive(|value| {
-        let escape = just('\\').ignore_then(choice((
-            just('\\'),
-            just('/'),
-            just('"'),
-            just('n').to('\n'),
-            just('r').to('\r'),
-            just('t').to('\t'),
-        )));
-
-        let string_value = none_of("\\\"")
-            .or(escape)
-            .repeated()
-            .collect::<String>()
-            .delimited_by(just('"'), just('"'))
-            .map(Value::String);
-
-        let digits = text::digits(10);
-
-        let f32 = just('-')
-            .or_not()
-            .then(text::int(10))
-            .then(just('.').then(digits))
-            .to_slice()
-            .map(|s: &str| Value::F32(s.parse().unwrap()));
-
-        let i32 = just('-')
-            .then(digits)
-            .to_slice()
-            .map(|s: &str| Value::I32(s.parse().unwrap()))
-            .boxed();
-
-        let u32 = digits
-            .to_slice()
-            .map(|s: &str| Value::U32(s.parse().unwrap()));
-
-        let template = just('$')
-            .then(text::ascii::ident())
-            .to_slice()
-            .map(|s: &str| Value::Template(s.to_string()));
+        let string_value = string_parser().map(Value::String);
 
         let ident_raw = text::ascii::ident().map(ToString::to_string);
         let ident_value = ident_raw.map(|s| match s.as_str() {
@@ -108,31 +70,247 @@ fn value_parser<'a>() -> impl Parser<'a, &'a str, Value, Err<Rich<'a, char>>> {
             .map(Value::Array)
             .boxed();
 
-        choice((
-            f32,
-            i32,
-            u32,
-            string_value,
-            template,
-            object,
-            array,
-            ident_value,
+        choice((expr_parser(), string_value, object, array, ident_value))
+            .padded()
+            .boxed()
+    })
+}
+
+/// A double quoted string, with the usual escapes.
+fn string_parser<'a>() -> impl Parser<'a, &'a str, String, Err<Rich<'a, char>>> + Clone {
+    let escape = just('\\').ignore_then(choice((
+        just('\\'),
+        just('/'),
+        just('"'),
+        just('n').to('\n'),
+        just('r').to('\r'),
+        just('t').to('\t'),
+    )));
+
+    none_of("\\\"")
+        .or(escape)
+        .repeated()
+        .collect::<String>()
+        .delimited_by(just('"'), just('"'))
+}
+
+/// `1.5`, `-3` or `7`, as an `F32`, `I32` or `U32`.
+fn number_parser<'a>() -> impl Parser<'a, &'a str, Value, Err<Rich<'a, char>>> + Clone {
+    let digits = text::digits(10);
+
+    let f32 = just('-')
+        .or_not()
+        .then(text::int(10))
+        .then(just('.').then(digits))
+        .to_slice()
+        .map(|s: &str| Value::F32(s.parse().unwrap()));
+
+    let i32 = just('-')
+        .then(digits)
+        .to_slice()
+        .try_map(|s: &str, span| integer(s, span).map(Value::I32));
+
+    let u32 = digits
+        .to_slice()
+        .try_map(|s: &str, span| integer(s, span).map(Value::U32));
+
+    choice((f32, i32, u32))
+}
+
+/// An integer literal, which is an error rather than a wrap if it does not fit.
+fn integer<'a, T: std::str::FromStr>(s: &str, span: SimpleSpan) -> Result<T, Rich<'a, char>> {
+    s.parse()
+        .map_err(|_| Rich::custom(span, format!("{s} is out of range")))
+}
+
+/// A `u32` such as a port index.
+fn uint<'a>() -> impl Parser<'a, &'a str, u32, Err<Rich<'a, char>>> + Clone {
+    text::digits(10).to_slice().try_map(integer)
+}
+
+/// `$name`, keeping the `$`.
+fn template_parser<'a>() -> impl Parser<'a, &'a str, ExprKind, Err<Rich<'a, char>>> + Clone {
+    just('$')
+        .then(text::ascii::ident())
+        .to_slice()
+        .map(|s: &str| ExprKind::Template(s.to_string()))
+}
+
+/// One of the binary operators in `ops`, e.g. `"+-"`.
+fn binary_op<'a>(ops: &'a str) -> impl Parser<'a, &'a str, BinaryOp, Err<Rich<'a, char>>> + Clone {
+    one_of(ops).padded().map(|op| match op {
+        '+' => BinaryOp::Add,
+        '-' => BinaryOp::Sub,
+        '*' => BinaryOp::Mul,
+        '/' => BinaryOp::Div,
+        _ => BinaryOp::Rem,
+    })
+}
+
+/// Numbers, `$templates` and arithmetic on them, e.g. `$size + 13.0` or `max($q, 0.5)`.
+///
+/// A lone number or template comes back as its plain [`Value`]. Anything else is a
+/// [`Value::Expr`], evaluated once the enclosing patch's params are known.
+fn expr_parser<'a>() -> impl Parser<'a, &'a str, Value, Err<Rich<'a, char>>> {
+    expr_tree(false).map(Expr::into_value)
+}
+
+/// The [`Expr`] grammar. With `bare`, a name without its `$` is a template too, as loop
+/// variables are written in holes, e.g. `{i + 1}`.
+fn expr_tree<'a>(bare: bool) -> impl Parser<'a, &'a str, Expr, Err<Rich<'a, char>>> + Clone {
+    recursive(move |expr| {
+        // Nothing after the `)` is consumed, so spans end on it
+        let open = just('(').padded();
+        let close = text::whitespace().then(just(')'));
+
+        let call = text::ascii::ident()
+            .map(ToString::to_string)
+            .then(
+                expr.clone()
+                    .separated_by(just(',').padded())
+                    .collect::<Vec<Expr>>()
+                    .delimited_by(open, close),
+            )
+            .map(|(name, args)| ExprKind::Call(name, args));
+
+        let name = text::ascii::ident()
+            .filter(move |_| bare)
+            .map(|s: &str| ExprKind::Template(s.to_string()));
+
+        let atom = choice((
+            number_parser().map(ExprKind::Number),
+            template_parser(),
+            call,
+            name,
         ))
-        .padded()
-        .boxed()
+        .map_with(|kind, e| {
+            let span: SimpleSpan = e.span();
+            Expr {
+                kind,
+                span: span.into_range(),
+            }
+        })
+        .or(expr.delimited_by(open, close))
+        .boxed();
+
+        // Negative literals are numbers already, so only `-$x` and `-(..)` get here
+        let unary = recursive(|unary| {
+            atom.or(just('-').padded().ignore_then(unary).map_with(|inner, e| {
+                let span: SimpleSpan = e.span();
+                Expr {
+                    kind: ExprKind::Neg(Box::new(inner)),
+                    span: span.into_range(),
+                }
+            }))
+        });
+
+        let fold = |lhs: Expr, (op, rhs): (BinaryOp, Expr)| Expr {
+            span: lhs.span.start..rhs.span.end,
+            kind: ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)),
+        };
+
+        let product = unary
+            .clone()
+            .foldl(binary_op("*/%").then(unary).repeated(), fold)
+            .boxed();
+
+        product
+            .clone()
+            .foldl(binary_op("+-").then(product).repeated(), fold)
     })
 }
 
-fn node_declaration<'a>() -> impl Parser<'a, &'a str, NodeDeclaration, Err<Rich<'a, char>>> {
+/// `{expr}`, a hole in a name or port inside a block.
+fn hole<'a>() -> impl Parser<'a, &'a str, Expr, Err<Rich<'a, char>>> + Clone {
+    expr_tree(true).padded().delimited_by(just('{'), just('}'))
+}
+
+/// Whether a name goes on past a hole, as in `t{i}_{j}` or `t{i}[0]`.
+fn name_continues<'a>() -> impl Parser<'a, &'a str, char, Err<Rich<'a, char>>> + Clone {
+    any().filter(|c: &char| c.is_ascii_alphanumeric() || "_{[(.".contains(*c))
+}
+
+/// An alias or port name, e.g. `osc`, or `t{i}` inside a block.
+fn name_parser<'a>() -> impl Parser<'a, &'a str, String, Err<Rich<'a, char>>> + Clone {
+    text::ascii::ident()
+        .then(choice((text::ascii::ident().ignored(), hole().ignored())).repeated())
+        .to_slice()
+        .map(ToString::to_string)
+}
+
+/// The text between a selector's parentheses or a port's brackets when it has holes,
+/// e.g. `{i}..{i + 2}`.
+fn holed_text<'a>() -> impl Parser<'a, &'a str, String, Err<Rich<'a, char>>> + Clone {
+    choice((hole().ignored(), none_of("{}()[]\n").ignored()))
+        .repeated()
+        .to_slice()
+        .filter(|s: &&str| s.contains('{'))
+        .map(ToString::to_string)
+}
+
+/// `plate` or `verbs::plate`, a name that may be qualified by the imports it comes through.
+fn module_path<'a>() -> impl Parser<'a, &'a str, Vec<String>, Err<Rich<'a, char>>> + Clone {
+    text::ascii::ident()
+        .map(ToString::to_string)
+        .separated_by(just("::"))
+        .at_least(1)
+        .collect()
+}
+
+/// The `import` and `use` lines at the top of a file, in any order.
+fn header_parser<'a>() -> impl Parser<'a, &'a str, (Vec<Import>, Vec<Use>), Err<Rich<'a, char>>> {
     let ident = text::ascii::ident().map(ToString::to_string);
+    let keyword = |word| just(word).then(text::inline_whitespace().at_least(1));
+    let alias = text::inline_whitespace()
+        .at_least(1)
+        .ignore_then(keyword("as"))
+        .ignore_then(ident)
+        .or_not();
+
+    let import = keyword("import")
+        .ignore_then(string_parser())
+        .then(alias)
+        .map(|(path, namespace)| {
+            let namespace = namespace.unwrap_or_else(|| {
+                std::path::Path::new(&path)
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| path.clone())
+            });
+            Import { path, namespace }
+        });
 
-    let alias = just(':').padded().ignore_then(ident).or_not();
+    let use_ = keyword("use")
+        .ignore_then(module_path())
+        .then(alias)
+        .map(|(path, alias)| Use {
+            alias: alias.unwrap_or_else(|| path.last().cloned().unwrap_or_default()),
+            path,
+        });
 
-    let digits = text::digits(10);
+    extra_padded(import.map(Ok).or(use_.map(Err)))
+        .repeated()
+        .collect::<Vec<_>>()
+        .map(|lines| {
+            let mut imports = vec![];
+            let mut uses = vec![];
+            for line in lines {
+                match line {
+                    Ok(import) => imports.push(import),
+                    Err(use_) => uses.push(use_),
+                }
+            }
+            (imports, uses)
+        })
+}
 
-    let u32 = digits.to_slice().map(|s: &str| s.parse().unwrap());
+fn node_declaration<'a>() -> impl Parser<'a, &'a str, NodeDeclaration, Err<Rich<'a, char>>> {
+    let ident = text::ascii::ident().map(ToString::to_string);
+
+    let alias = just(':').padded().ignore_then(name_parser()).or_not();
 
-    let count = just("*").padded().ignore_then(u32).or_not();
+    // `* 4`, or `* $voices` and `* ($n + 1)` for a count worked out from params
+    let count = just("*").padded().ignore_then(expr_parser()).or_not();
 
     let obj_parser = ident
         .then_ignore(just(':').padded())
@@ -142,18 +320,44 @@ fn node_declaration<'a>() -> impl Parser<'a, &'a str, NodeDeclaration, Err<Rich<
         .collect::<BTreeMap<String, Value>>();
 
     let params = obj_parser
-        .delimited_by(just('{').padded(), just('}').padded())
+        .delimited_by(just('{').padded(), text::whitespace().then(just('}')))
         .or_not();
 
-    ident
+    // `| spread` or `| spread(0.8)`
+    let pipe = just('|')
+        .padded()
+        .ignore_then(text::ascii::ident().map(ToString::to_string))
+        .then(
+            value_parser()
+                .padded()
+                .delimited_by(just('('), just(')'))
+                .or_not(),
+        )
+        .map(|(name, params)| ASTPipe { name, params });
+
+    module_path()
+        .map(|path| path.join("::"))
         .then(alias)
         .then(count)
         .then(params)
-        .map(|(((node_type, alias), count), params)| NodeDeclaration {
-            node_type,
-            alias,
-            params,
-            count: count.unwrap_or(1),
+        .then(pipe.repeated().collect())
+        .map_with(|((((node_type, alias), count), params), pipes), e| {
+            let span: SimpleSpan = e.span();
+            let (count, count_expr) = match count {
+                None => (1, None),
+                Some(Value::U32(count)) => (count, None),
+                Some(expr) => (1, Some(expr)),
+            };
+            NodeDeclaration {
+                node_type,
+                alias,
+                params,
+                count,
+                count_expr,
+                pipes,
+                oversample: 1,
+                span: span.into_range().into(),
+            }
         })
 }
 
@@ -170,28 +374,25 @@ fn patch_parser<'a>() -> impl Parser<'a, &'a str, AstMacro, Err<Rich<'a, char>>>
         .delimited_by(just('(').padded(), just(')').padded())
         .or_not();
 
-    // Virtual ports: `in gate freq_in`
+    // Virtual ports: `in gate freq_in` and `out dry wet`
     let virtual_port_ident = ident.padded_by(text::inline_whitespace());
 
-    let virtual_ports = just("in")
-        .then_ignore(text::inline_whitespace().at_least(1))
-        .ignore_then(
-            virtual_port_ident
-                .repeated()
-                .at_least(1)
-                .collect::<Vec<String>>(),
-        );
-
-    // Interior connections is the same as the normal AST
-    let inner_connections = extra_padded(connection_parser())
-        .repeated()
-        .collect::<Vec<Vec<Connection>>>()
-        .map(|v| v.into_iter().flatten().collect::<Vec<Connection>>());
+    let virtual_ports = |keyword| {
+        just(keyword)
+            .then_ignore(text::inline_whitespace().at_least(1))
+            .ignore_then(
+                virtual_port_ident
+                    .repeated()
+                    .at_least(1)
+                    .collect::<Vec<String>>(),
+            )
+    };
 
-    let patch_body = extra_padded(virtual_ports)
+    // The interior is the same as the top level of the graph
+    let patch_body = extra_padded(virtual_ports("in"))
         .or_not()
-        .then(extra_padded(scope_parser()).repeated().collect::<Vec<_>>())
-        .then(inner_connections.or_not())
+        .then(extra_padded(virtual_ports("out")).or_not())
+        .then(body_parser())
         .then(extra_padded(scope_or_sink()))
         .delimited_by(extra_padded(just('{')), extra_padded(just('}')));
 
@@ -203,74 +404,128 @@ fn patch_parser<'a>() -> impl Parser<'a, &'a str, AstMacro, Err<Rich<'a, char>>>
     ))
     .then_ignore(text::whitespace().at_least(1));
 
+    // `@ /2` or `@ x2`, the rate the body runs at relative to the graph's. A bare number
+    // would be in Hz, which only works at some device rates, so it is pointed at the
+    // relative form instead.
+    let factor = |op| {
+        just(op).ignore_then(uint()).try_map(|n, span| {
+            if n.is_power_of_two() {
+                Ok(n as usize)
+            } else {
+                Err(Rich::custom(
+                    span,
+                    format!("rate factor {n} is not a power of two"),
+                ))
+            }
+        })
+    };
+    let relative = choice((
+        factor('x').map(Rate::times),
+        factor('/').map(Rate::divided),
+        uint().try_map(|hz, span| {
+            Err(Rich::custom(
+                span,
+                format!(
+                    "`@ {hz}` is in Hz, but rates are written relative to the graph's, \
+                     like `@ /2` or `@ x2`"
+                ),
+            ))
+        }),
+    ));
+    let rate = just('@').padded().ignore_then(relative).padded().or_not();
+
     keyword
         .then(ident)
         .then(extra_padded(default_params))
+        .then(rate)
         .then(patch_body)
         .map(
-            |(((kind, name), params), (((vports, decls), conns), sink))| AstMacro {
+            |((((kind, name), params), rate), (((ins, outs), body), sink))| AstMacro {
                 name,
                 kind,
                 default_params: params,
-                virtual_ports_in: vports.unwrap_or_default().into_iter().collect(),
-                declarations: decls,
-                connections: conns.unwrap_or_default(),
+                virtual_ports_in: ins.unwrap_or_default().into_iter().collect(),
+                virtual_ports_out: outs.unwrap_or_default().into_iter().collect(),
+                rate,
+                declarations: body.declarations,
+                connections: body.connections,
+                expr_connections: body.expr_connections,
+                blocks: body.blocks,
                 sink,
             },
         )
 }
 
-fn endpoint_parser<'a>() -> impl Parser<'a, &'a str, Endpoint, Err<Rich<'a, char>>> {
-    let ident = text::ascii::ident().map(ToString::to_string);
-    let uint = text::digits(10)
-        .to_slice()
-        .map(|s: &str| s.parse::<u32>().unwrap());
-
-    let selector = choice((
+/// `(0)`, `(1..3)` or `(*)` after a node, picking some of its spawned instances.
+fn selector_parser<'a>() -> impl Parser<'a, &'a str, NodeSelector, Err<Rich<'a, char>>> + Clone {
+    choice((
         // Single node selection
-        uint.delimited_by(just('('), just(')'))
+        uint()
+            .delimited_by(just('('), just(')'))
             .map(|x| NodeSelector::Index(x as usize)),
         // Range node selection
-        uint.then_ignore(just(".."))
-            .then(uint)
+        uint()
+            .then_ignore(just(".."))
+            .then(uint())
             .delimited_by(just('('), just(')'))
             .map(|(s, e)| NodeSelector::Range(s as usize, e as usize)),
         // Wildcard
         just("*")
             .delimited_by(just('('), just(')'))
             .map(|_| NodeSelector::All),
+        // voice({i}), filled in by the generative pass
+        holed_text()
+            .delimited_by(just('('), just(')'))
+            .map(NodeSelector::Template),
     ))
-    .or_not()
-    .map(|p| p.unwrap_or(NodeSelector::Single)); // TODO: Evaluate if this feels right
+}
 
-    let port = choice((
+/// `.name`, `[0]`, `[0..2]` or `[0:10:2]` after a node.
+fn port_parser<'a>() -> impl Parser<'a, &'a str, Port, Err<Rich<'a, char>>> + Clone {
+    choice((
         // node.mono
-        just('.').ignore_then(ident).map(Port::Named),
+        just('.').ignore_then(name_parser()).map(Port::Named),
         // port stride e.g [0:10:2]: this maps to [start:end:step].
         // NOTE: Unlike python we don't take implicit values, this is not good [::-1]!
-        uint.then_ignore(just(":"))
-            .then(uint)
+        uint()
             .then_ignore(just(":"))
-            .then(uint)
+            .then(uint())
+            .then_ignore(just(":"))
+            .then(uint())
             .delimited_by(just("["), just("]"))
-            .map(|((start, end), stride)| Port::Stride {
-                start: start as usize,
-                end: end as usize,
-                stride: stride as usize,
+            .try_map(|((start, end), stride), span| match stride {
+                0 => Err(Rich::custom(span, "a stride must be at least 1")),
+                _ => Ok(Port::Stride {
+                    start: start as usize,
+                    end: end as usize,
+                    stride: stride as usize,
+                }),
             }),
         // node[0..2]
-        uint.then_ignore(just(".."))
-            .then(uint)
+        uint()
+            .then_ignore(just(".."))
+            .then(uint())
             .delimited_by(just('['), just(']'))
             .map(|(s, e)| Port::Slice(s as usize, e as usize)),
         // node[0]
-        uint.delimited_by(just('['), just(']'))
+        uint()
+            .delimited_by(just('['), just(']'))
             .map(|x| Port::Index(x as usize)),
+        // node[{i}], filled in by the generative pass
+        holed_text()
+            .delimited_by(just('['), just(']'))
+            .map(Port::Template),
     ))
-    .or_not()
-    .map(|p| p.unwrap_or(Port::None));
+}
 
-    ident
+fn endpoint_parser<'a>() -> impl Parser<'a, &'a str, Endpoint, Err<Rich<'a, char>>> {
+    let selector = selector_parser()
+        .or_not()
+        .map(|p| p.unwrap_or(NodeSelector::Single)); // TODO: Evaluate if this feels right
+
+    let port = port_parser().or_not().map(|p| p.unwrap_or(Port::None));
+
+    name_parser()
         .then(selector)
         .then(port)
         .map(|((node, node_selector), port)| Endpoint {
@@ -280,26 +535,203 @@ fn endpoint_parser<'a>() -> impl Parser<'a, &'a str, Endpoint, Err<Rich<'a, char
         })
 }
 
-fn connection_parser<'a>() -> impl Parser<'a, &'a str, Vec<Connection>, Err<Rich<'a, char>>> {
-    endpoint_parser()
-        .separated_by(just(">>").padded())
-        .at_least(2)
-        .collect::<Vec<_>>()
-        .map(|endpoints| {
-            endpoints
-                .windows(2)
-                .map(|w| Connection {
-                    source: w[0].clone(),
-                    sink: w[1].clone(),
+/// The head of a connection: an endpoint, or arithmetic on endpoints, numbers and
+/// `$templates` such as `lfo * 0.5 + 0.5`.
+fn signal_expr_parser<'a>() -> impl Parser<'a, &'a str, SignalExpr, Err<Rich<'a, char>>> {
+    recursive(|signal| {
+        let constant = choice((number_parser().map(ExprKind::Number), template_parser())).map_with(
+            |kind, e| {
+                let span: SimpleSpan = e.span();
+                SignalExpr::Constant(Expr {
+                    kind,
+                    span: span.into_range(),
                 })
-                .collect()
+            },
+        );
+
+        let atom = choice((
+            constant,
+            endpoint_parser().map(SignalExpr::Endpoint),
+            signal.delimited_by(just('(').padded(), just(')').padded()),
+        ))
+        .boxed();
+
+        let unary = recursive(|unary| {
+            atom.or(just('-')
+                .padded()
+                .ignore_then(unary)
+                .map(|inner| SignalExpr::Neg(Box::new(inner))))
+        });
+
+        let fold = |lhs, (op, rhs)| SignalExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+
+        let product = unary
+            .clone()
+            .foldl(binary_op("*/").then(unary).repeated(), fold)
+            .boxed();
+
+        product
+            .clone()
+            .foldl(binary_op("+-").then(product).repeated(), fold)
+    })
+}
+
+/// One line of wiring, `a >> b >> c`, whose head may also be arithmetic, e.g.
+/// `lfo * 0.5 >> b`. A link written `~>` instead reads its source a block late.
+fn connection_parser<'a>()
+-> impl Parser<'a, &'a str, (Vec<Connection>, Option<ExprConnection>), Err<Rich<'a, char>>> {
+    // Each link spans from its source to its sink, e.g. `b >> c`
+    let chain = |endpoints: &[(Endpoint, Span, bool)]| -> Vec<Connection> {
+        endpoints
+            .windows(2)
+            .map(|w| Connection {
+                source: w[0].0.clone(),
+                sink: w[1].0.clone(),
+                feedback: w[1].2,
+                span: (w[0].1.start..w[1].1.end).into(),
+            })
+            .collect()
+    };
+
+    let arrow = choice((just(">>").to(false), just("~>").to(true))).padded();
+
+    signal_expr_parser()
+        .map_with(|head, e| {
+            let span: SimpleSpan = e.span();
+            (head, span.into_range())
+        })
+        .then(
+            arrow
+                .then(endpoint_parser().map_with(|sink, e| {
+                    let span: SimpleSpan = e.span();
+                    (sink, span.into_range())
+                }))
+                .map(|(feedback, (sink, span))| (sink, span, feedback))
+                .repeated()
+                .at_least(1)
+                .collect::<Vec<_>>(),
+        )
+        .try_map(move |((head, head_span), sinks), span| match head {
+            SignalExpr::Endpoint(source) => {
+                let endpoints: Vec<(Endpoint, Span, bool)> =
+                    std::iter::once((source, head_span, false))
+                        .chain(sinks)
+                        .collect();
+                Ok((chain(&endpoints), None))
+            }
+            _ if sinks[0].2 => Err(Rich::custom(
+                span,
+                "arithmetic cannot feed back with `~>`; connect it with `>>` instead",
+            )),
+            source => {
+                let expr = ExprConnection {
+                    source,
+                    sink: sinks[0].0.clone(),
+                    span: (head_span.start..sinks[0].1.end).into(),
+                };
+                Ok((chain(&sinks), Some(expr)))
+            }
         })
 }
 
-fn scope_parser<'a>() -> impl Parser<'a, &'a str, DeclarationScope, Err<Rich<'a, char>>> {
-    let ident = text::ascii::ident().map(ToString::to_string);
+/// One line of a body: a chain of wiring, or a block.
+enum Line {
+    Wiring(Vec<Connection>, Option<ExprConnection>),
+    Block(Block),
+}
 
-    ident
+/// Declaration scopes, then wiring and blocks: the body of a graph, patch or block.
+fn body_parser<'a>() -> impl Parser<'a, &'a str, BlockBody, Err<Rich<'a, char>>> {
+    recursive(|body| {
+        let line = choice((
+            block_parser(body).map(Line::Block),
+            connection_parser().map(|(chain, expr)| Line::Wiring(chain, expr)),
+        ))
+        .boxed();
+
+        extra_padded(scope_parser())
+            .repeated()
+            .collect::<Vec<_>>()
+            .then(extra_padded(line).repeated().collect::<Vec<_>>())
+            .map(|(declarations, lines)| {
+                let mut body = BlockBody {
+                    declarations,
+                    ..Default::default()
+                };
+                for line in lines {
+                    match line {
+                        Line::Wiring(chain, expr) => {
+                            body.connections.extend(chain);
+                            body.expr_connections.extend(expr);
+                        }
+                        Line::Block(block) => body.blocks.push(block),
+                    }
+                }
+                body
+            })
+            .boxed()
+    })
+}
+
+/// `for i in 0..$n { .. }`, `if $flag { .. }` with an optional `else { .. }`, or
+/// `oversample(4) { .. }`.
+fn block_parser<'a, B>(body: B) -> impl Parser<'a, &'a str, Block, Err<Rich<'a, char>>>
+where
+    B: Parser<'a, &'a str, BlockBody, Err<Rich<'a, char>>> + Clone + 'a,
+{
+    let keyword = |word| just(word).then(text::whitespace().at_least(1));
+
+    // Nothing after the `}` is consumed, so spans end on it
+    let braced = body
+        .delimited_by(extra_padded(just('{')), just('}'))
+        .boxed();
+
+    let for_block = keyword("for")
+        .ignore_then(text::ascii::ident().map(ToString::to_string))
+        .then_ignore(text::whitespace().at_least(1).then(keyword("in")))
+        .then(expr_parser().padded())
+        .then_ignore(just(".."))
+        .then(expr_parser().padded())
+        .then(braced.clone())
+        .map(|(((var, start), end), body)| (BlockKind::For { var, start, end }, body));
+
+    let if_block = keyword("if")
+        .ignore_then(value_parser())
+        .then(braced.clone())
+        .then(
+            extra_padded(just("else"))
+                .ignore_then(braced.clone())
+                .or_not(),
+        )
+        .map(|((condition, body), otherwise)| {
+            let otherwise = otherwise.unwrap_or_default();
+            (
+                BlockKind::If {
+                    condition,
+                    otherwise,
+                },
+                body,
+            )
+        });
+
+    let oversample_block = just("oversample")
+        .ignore_then(expr_parser().padded().delimited_by(just('('), just(')')))
+        .then(braced)
+        .map(|(factor, body)| (BlockKind::Oversample { factor }, body));
+
+    choice((for_block, if_block, oversample_block)).map_with(|(kind, body), e| {
+        let span: SimpleSpan = e.span();
+        Block {
+            kind,
+            body,
+            span: span.into_range().into(),
+        }
+    })
+}
+
+fn scope_parser<'a>() -> impl Parser<'a, &'a str, DeclarationScope, Err<Rich<'a, char>>> {
+    text::ascii::ident()
+        .map(ToString::to_string)
         .then_ignore(extra_padded(just('{')))
         .then(
             extra_padded(node_declaration())
@@ -307,7 +739,9 @@ fn scope_parser<'a>() -> impl Parser<'a, &'a str, DeclarationScope, Err<Rich<'a,
                 .allow_trailing()
                 .collect(),
         )
-        .then_ignore(extra_padded(just('}')))
+        // `t{i} >> out` in a block is wiring to a node, not a scope named `t`
+        .then_ignore(extra_padded(just('}').then(name_continues().not())))
+        .then_ignore(one_of(">*+%").not())
         .map(|(namespace, declarations)| DeclarationScope {
             namespace,
             declarations,
@@ -321,6 +755,32 @@ fn scope_or_sink<'a>() -> impl Parser<'a, &'a str, String, Err<Rich<'a, char>>>
         .delimited_by(just('{').padded(), just('}').padded())
 }
 
+/// The graph's outputs: `{ sink }`, optionally followed by named auxiliary outputs, e.g.
+/// `{ mix, cue: cue_bus, dry }`. An output without a name is named after its node.
+fn outputs_parser<'a>()
+-> impl Parser<'a, &'a str, (String, Vec<(String, String)>), Err<Rich<'a, char>>> {
+    let ident = text::ascii::ident().map(ToString::to_string);
+
+    let aux = ident
+        .then(just(':').padded().ignore_then(ident).or_not())
+        .map(|(name, alias)| match alias {
+            Some(alias) => (name, alias),
+            None => (name.clone(), name),
+        });
+
+    ident
+        .padded()
+        .then(
+            just(',')
+                .padded()
+                .ignore_then(aux.padded())
+                .repeated()
+                .collect::<Vec<_>>(),
+        )
+        .then_ignore(just(',').padded().or_not())
+        .delimited_by(just('{').padded(), just('}').padded())
+}
+
 /// The main entrypoint for the Legato parser.
 pub fn legato_parser_inner<'a>() -> impl Parser<'a, &'a str, Ast, Err<Rich<'a, char>>> {
     // Use the extra_padded helper here
@@ -330,53 +790,111 @@ pub fn legato_parser_inner<'a>() -> impl Parser<'a, &'a str, Ast, Err<Rich<'a, c
         .repeated()
         .collect::<Vec<AstMacro>>();
 
-    let declarations = extra_padded(scope_parser()).repeated().collect();
-
-    let connections = extra_padded(connection_parser())
-        .repeated()
-        .collect::<Vec<Vec<Connection>>>()
-        .map(|v| v.into_iter().flatten().collect::<Vec<Connection>>())
-        .or_not();
+    let sink = extra_padded(outputs_parser());
 
-    let sink = extra_padded(scope_or_sink());
-
-    source
+    header_parser()
+        .then(source)
         .then(patches)
-        .then(declarations)
-        .then(connections)
+        .then(body_parser())
         .then(sink)
         .map(
-            |((((source, macros), declarations), connections), sink)| Ast {
+            |(((((imports, uses), source), macros), body), (sink, outputs))| Ast {
+                imports,
+                uses,
                 source,
-                declarations,
-                connections: connections.unwrap_or_default(),
+                declarations: body.declarations,
+                connections: body.connections,
+                expr_connections: body.expr_connections,
+                blocks: body.blocks,
                 macros,
                 sink,
+                outputs,
             },
         )
         .then_ignore(extra_padded(end()))
 }
 
+/// The grammar of an imported file: `import`s, `use`s, then patches and kernels.
+pub fn library_parser_inner<'a>() -> impl Parser<'a, &'a str, AstLibrary, Err<Rich<'a, char>>> {
+    let patches = extra_padded(patch_parser())
+        .repeated()
+        .collect::<Vec<AstMacro>>();
+
+    header_parser()
+        .then(patches)
+        .map(|((imports, uses), macros)| AstLibrary {
+            imports,
+            uses,
+            macros,
+        })
+        .then_ignore(extra_padded(end()))
+}
+
 /// The Legato parser, using chumsky and ariande to handle errors.
 pub fn legato_parser(src: &str) -> Result<Ast, ValidationError> {
-    let (ast, errs) = legato_parser_inner().parse(src.trim()).into_output_errors();
+    let (ast, errs) = legato_parser_inner().parse(src).into_output_errors();
+    print_errors("main", src, errs);
+
+    ast.ok_or(ValidationError::ParseError(
+        "Could not parse source. Please check error report.".into(),
+    ))
+}
+
+/// Parse the imported file `path`, reporting errors against that file.
+pub fn library_parser(path: &str, src: &str) -> Result<AstLibrary, ValidationError> {
+    let (library, errs) = library_parser_inner().parse(src).into_output_errors();
+    print_errors(path, src, errs);
+
+    library.ok_or_else(|| {
+        ValidationError::ParseError(format!(
+            "Could not parse '{path}'. Please check error report."
+        ))
+    })
+}
+
+/// The expression in a `{}` hole, without its braces.
+pub(crate) fn parse_hole(src: &str) -> Option<Expr> {
+    expr_tree(true).padded().parse(src).into_result().ok()
+}
+
+/// A port written in brackets, e.g. `[3]` or `[0..2]`.
+pub(crate) fn parse_port(src: &str) -> Option<Port> {
+    port_parser().parse(src).into_result().ok()
+}
+
+/// A selector written in parentheses, e.g. `(3)` or `(0..2)`.
+pub(crate) fn parse_selector(src: &str) -> Option<NodeSelector> {
+    selector_parser().parse(src).into_result().ok()
+}
+
+fn print_errors(file: &str, src: &str, errs: Vec<Rich<char>>) {
     errs.into_iter().for_each(|e| {
-        Report::build(ReportKind::Error, ((), e.span().into_range()))
+        Report::build(ReportKind::Error, (file, e.span().into_range()))
             .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
             .with_message(e.to_string())
             .with_label(
-                Label::new(((), e.span().into_range()))
+                Label::new((file, e.span().into_range()))
                     .with_message(e.reason().to_string())
                     .with_color(Color::Red),
             )
             .finish()
-            .print(Source::from(&src))
+            .eprint((file, Source::from(src)))
             .unwrap()
     });
+}
 
-    ast.ok_or(ValidationError::ParseError(
-        "Could not parse source. Please check error report.".into(),
-    ))
+/// Report a build error against the line that caused it, when it points into `src`.
+pub(crate) fn print_validation_error(file: &str, src: &str, err: &ValidationError) {
+    let Some(span) = err.span().filter(|span| span.end <= src.len()) else {
+        return;
+    };
+    Report::build(ReportKind::Error, (file, span.clone()))
+        .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
+        .with_message(err.to_string())
+        .with_label(Label::new((file, span)).with_color(Color::Red))
+        .finish()
+        .eprint((file, Source::from(src)))
+        .unwrap()
 }
 
 #[cfg(test)]
@@ -519,12 +1037,105 @@ mod test {
     fn test_basic_connection() {
         let src = "osc >> gain";
         let parser = connection_parser();
-        let result = parser.parse(src).into_result().unwrap();
+        let (result, _) = parser.parse(src).into_result().unwrap();
 
         assert_eq!(result[0].source.node, "osc");
         assert_eq!(result[0].sink.node, "gain");
     }
 
+    #[test]
+    fn test_imports_and_uses() {
+        let src = r#"
+            import "lib/reverbs.legato"
+            use reverbs::plate
+            import "voices.legato" as lead
+            use lead::voice as lead_voice
+
+            patches { lead::voice: a {}, plate }
+
+            { a }
+        "#;
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+
+        assert_eq!(
+            ast.imports,
+            vec![
+                Import {
+                    path: "lib/reverbs.legato".into(),
+                    namespace: "reverbs".into(),
+                },
+                Import {
+                    path: "voices.legato".into(),
+                    namespace: "lead".into(),
+                },
+            ]
+        );
+        assert_eq!(
+            ast.uses,
+            vec![
+                Use {
+                    path: vec!["reverbs".into(), "plate".into()],
+                    alias: "plate".into(),
+                },
+                Use {
+                    path: vec!["lead".into(), "voice".into()],
+                    alias: "lead_voice".into(),
+                },
+            ]
+        );
+        assert_eq!(ast.declarations[0].declarations[0].node_type, "lead::voice");
+
+        // Libraries hold only patches, no graph
+        let library = library_parser_inner()
+            .parse(r#"import "osc.legato" patch p() { patches { osc::saw } { saw } }"#)
+            .into_result()
+            .unwrap();
+        assert_eq!(library.macros.len(), 1);
+        assert!(
+            library_parser_inner()
+                .parse("audio { sine } { sine }")
+                .into_result()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_expression_connection() {
+        let (chain, expr) = connection_parser()
+            .parse("lfo * 0.5 + $offset >> pan.pan >> out")
+            .into_result()
+            .unwrap();
+
+        // Only the head is arithmetic, the rest of the chain is plain
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].source.node, "pan");
+
+        let expr = expr.unwrap();
+        assert_eq!(expr.sink.port, Port::Named("pan".into()));
+        let SignalExpr::Binary(BinaryOp::Add, product, offset) = expr.source else {
+            panic!("expected a sum, got {:?}", expr.source);
+        };
+        assert!(matches!(*product, SignalExpr::Binary(BinaryOp::Mul, _, _)));
+        assert!(matches!(
+            *offset,
+            SignalExpr::Constant(Expr {
+                kind: ExprKind::Template(_),
+                ..
+            })
+        ));
+
+        let (_, expr) = connection_parser()
+            .parse("440.0 >> osc.freq")
+            .into_result()
+            .unwrap();
+        assert!(matches!(expr.unwrap().source, SignalExpr::Constant(_)));
+
+        // Parentheses around a lone endpoint are still a plain connection
+        let (chain, expr) = connection_parser().parse("(a) >> b").into_result().unwrap();
+        assert_eq!(chain.len(), 1);
+        assert!(expr.is_none());
+    }
+
     #[test]
     fn test_connections_in_ast() {
         let src = r#"
@@ -555,7 +1166,7 @@ mod test {
     fn test_connection_whitespace() {
         let src = "osc   >>   gain";
         let parser = connection_parser().padded();
-        let result = parser.parse(src).into_result().unwrap();
+        let (result, _) = parser.parse(src).into_result().unwrap();
         assert_eq!(result[0].source.node, "osc");
     }
 
@@ -592,7 +1203,7 @@ mod test {
     fn test_complex_ports() {
         let src = "audio_in.stereo >> looper[0..2] >> out[1]";
         let parser = connection_parser();
-        let result = parser.parse(src).into_result().unwrap();
+        let (result, _) = parser.parse(src).into_result().unwrap();
 
         assert_eq!(result.len(), 2);
 
@@ -607,7 +1218,7 @@ mod test {
     fn test_mixed_chain() {
         let src = "osc >> gain.input >> bus[0..2] >> master[1]";
         let parser = connection_parser();
-        let result = parser.parse(src).into_result().unwrap();
+        let (result, _) = parser.parse(src).into_result().unwrap();
 
         assert_eq!(result[0].source.port, Port::None);
         assert_eq!(result[0].sink.port, Port::Named("input".into()));
@@ -675,6 +1286,37 @@ mod test {
         assert_parse_equals_value("$attack_time", Value::Template("$attack_time".into()));
     }
 
+    #[test]
+    fn test_expression_value() {
+        let expr = |src: &str| match value_parser().parse(src).into_result() {
+            Ok(Value::Expr(expr)) => *expr,
+            other => panic!("expected an expression from {src:?}, got {other:?}"),
+        };
+
+        // `*` binds tighter than `+`, and spans cover the whole operation
+        let sum = expr("1 + $a * 2.0");
+        let ExprKind::Binary(BinaryOp::Add, lhs, rhs) = sum.kind else {
+            panic!("expected a sum, got {:?}", sum.kind);
+        };
+        assert_eq!(sum.span, 0..12);
+        assert_eq!(lhs.kind, ExprKind::Number(Value::U32(1)));
+        assert!(matches!(rhs.kind, ExprKind::Binary(BinaryOp::Mul, _, _)));
+        assert_eq!(rhs.span, 4..12);
+
+        let call = expr("clamp(-$q, 0.5, (8))");
+        let ExprKind::Call(name, args) = call.kind else {
+            panic!("expected a call, got {:?}", call.kind);
+        };
+        assert_eq!(name, "clamp");
+        assert!(matches!(args[0].kind, ExprKind::Neg(_)));
+        assert_eq!(args[2].kind, ExprKind::Number(Value::U32(8)));
+
+        // Plain values are still plain
+        assert_parse_equals_value("-5", Value::I32(-5));
+        assert_parse_equals_value("(5)", Value::U32(5));
+        assert_parse_equals_value("min", Value::Ident("min".into()));
+    }
+
     #[test]
     fn test_patch_minimal() {
         // just a scope and sink
@@ -700,6 +1342,47 @@ mod test {
         );
         assert!(m.virtual_ports_in.is_empty());
         assert_eq!(m.sink, "gain");
+        assert_eq!(m.rate, None);
+    }
+
+    #[test]
+    fn test_patch_rate() {
+        let src = r#"
+            patch lofi(bits = 8) @ /2 {
+                audio { sine }
+                { sine }
+            }
+            { lofi }
+        "#;
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+
+        assert_eq!(ast.macros[0].rate, Some(Rate::divided(2)));
+        assert!(ast.macros[0].default_params.is_some());
+    }
+
+    #[test]
+    fn test_patch_rate_errors() {
+        for (rate, reason) in [
+            ("x2", None),
+            ("x3", Some("rate factor 3 is not a power of two")),
+            ("24000", Some("`@ 24000` is in Hz")),
+        ] {
+            let src =
+                format!("patch lofi() @ {rate} {{\n audio {{ sine }}\n {{ sine }}\n}}\n{{ lofi }}");
+            let errs = legato_parser_inner()
+                .parse(&src)
+                .into_result()
+                .err()
+                .unwrap_or_default();
+
+            match reason {
+                Some(reason) => assert!(
+                    errs.iter().any(|e| e.reason().to_string().contains(reason)),
+                    "{errs:?}"
+                ),
+                None => assert!(errs.is_empty(), "{errs:?}"),
+            }
+        }
     }
 
     #[test]
@@ -884,4 +1567,173 @@ mod test {
 
         assert_eq!(ast.sink, "v1");
     }
+
+    #[test]
+    fn test_named_outputs() {
+        let src = r#"
+            audio {
+                sine: mix,
+                sine: cue_bus,
+                sine: dry
+            }
+
+            { mix, cue: cue_bus, dry, }
+        "#;
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+
+        assert_eq!(ast.sink, "mix");
+        assert_eq!(
+            ast.outputs,
+            vec![
+                ("cue".to_string(), "cue_bus".to_string()),
+                ("dry".to_string(), "dry".to_string())
+            ]
+        );
+
+        // The sink itself cannot be renamed
+        assert!(
+            legato_parser_inner()
+                .parse("audio { sine: a } { main: a }")
+                .into_result()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_for_and_if_blocks() {
+        let src = r#"
+            patch bank(n = 4, wide = true) {
+                audio { add: mix }
+
+                for i in 0..$n {
+                    audio { sine: t{i} }
+                    t{i} >> mix[{i}]
+
+                    if $wide {
+                        t{i}({i}) >> mix.in{i}
+                    } else {}
+                }
+
+                { mix }
+            }
+
+            patches { bank }
+            { bank }
+        "#;
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+        let mac = &ast.macros[0];
+
+        assert_eq!(mac.declarations.len(), 1);
+        assert_eq!(mac.blocks.len(), 1);
+
+        let block = &mac.blocks[0];
+        let BlockKind::For { var, start, .. } = &block.kind else {
+            panic!("{:?}", block.kind);
+        };
+        assert_eq!(var, "i");
+        assert_eq!(*start, Value::U32(0));
+        assert_eq!(
+            block.body.declarations[0].declarations[0].alias.as_deref(),
+            Some("t{i}")
+        );
+
+        let wire = &block.body.connections[0];
+        assert_eq!(wire.source.node, "t{i}");
+        assert_eq!(wire.sink.port, Port::Template("{i}".into()));
+
+        let inner = &block.body.blocks[0];
+        assert!(matches!(&inner.kind, BlockKind::If { .. }));
+        let wire = &inner.body.connections[0];
+        assert_eq!(
+            wire.source.node_selector,
+            NodeSelector::Template("{i}".into())
+        );
+        assert_eq!(wire.sink.port, Port::Named("in{i}".into()));
+    }
+
+    #[test]
+    fn test_scope_or_hole() {
+        // A name followed by wiring is a node, anything else is a scope
+        let ast = legato_parser_inner()
+            .parse("audio{t}\nfor i in 0..1 { audio{sine: s{i}}\nt{i}(0) >> s{i} }\n{ t }")
+            .into_result()
+            .unwrap();
+        assert_eq!(ast.declarations[0].namespace, "audio");
+
+        let body = &ast.blocks[0].body;
+        assert_eq!(
+            body.declarations[0].declarations[0].alias.as_deref(),
+            Some("s{i}")
+        );
+        assert_eq!(body.connections[0].source.node, "t{i}");
+    }
+
+    #[test]
+    fn test_declaration_pipes() {
+        let src = "audio { saw * 4 { chans: 1 } | detune(0.1) | stereo, sine } { saw }";
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+        let decls = &ast.declarations[0].declarations;
+
+        assert_eq!(decls.len(), 2);
+        assert_eq!(decls[0].count, 4);
+        assert_eq!(
+            decls[0].pipes,
+            vec![
+                ASTPipe {
+                    name: "detune".into(),
+                    params: Some(Value::F32(0.1)),
+                },
+                ASTPipe {
+                    name: "stereo".into(),
+                    params: None,
+                },
+            ]
+        );
+        assert!(decls[1].pipes.is_empty());
+    }
+
+    #[test]
+    fn test_declaration_count_exprs() {
+        let src = "audio { a * 2, b * $voices, c * ($n + 1) { chans: 1 } | stereo } { a }";
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+        let decls = &ast.declarations[0].declarations;
+
+        assert_eq!((decls[0].count, &decls[0].count_expr), (2, &None));
+        assert_eq!(decls[1].count_expr, Some(Value::Template("$voices".into())));
+        assert!(matches!(decls[2].count_expr, Some(Value::Expr(_))));
+        assert_eq!(decls[2].pipes.len(), 1);
+    }
+
+    #[test]
+    fn test_feedback_links() {
+        let src = "audio { a, b } a >> b ~> a >> b { b }";
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+        let feedback: Vec<_> = ast.connections.iter().map(|c| c.feedback).collect();
+        assert_eq!(feedback, [false, true, false]);
+
+        let src = "audio { a, b } a * 0.5 ~> b { b }";
+        assert!(legato_parser_inner().parse(src).into_result().is_err());
+    }
+
+    #[test]
+    fn test_virtual_ports_out() {
+        let src = r#"
+            patch voice() {
+                in gate
+                out dry wet
+                audio { sine, svf }
+                sine >> svf >> wet
+                sine >> dry
+                { svf }
+            }
+            { voice }
+        "#;
+        let ast = legato_parser_inner().parse(src).into_result().unwrap();
+        let m = &ast.macros[0];
+
+        assert_eq!(m.virtual_ports_in.len(), 1);
+        let outs: Vec<&str> = m.virtual_ports_out.iter().map(String::as_str).collect();
+        assert_eq!(outs, ["dry", "wet"]);
+        assert_eq!(m.connections.len(), 3);
+    }
 }
@@ -1,7 +1,9 @@
+use std::path::PathBuf;
+
 use crate::builder::ValidationError;
 use crate::dsl::{
-    expand::MacroExpansionPass, ir::*, lower::ast_to_graph, resolve::ResolvePass,
-    spawn::SpawnKNodesPass,
+    expand::MacroExpansionPass, generate::GenerativePass, ir::*, lower::ast_to_graph,
+    resolve::ResolvePass, spawn::SpawnKNodesPass,
 };
 
 /// A single, named transformation of an [`IRGraph`].
@@ -10,14 +12,24 @@ pub trait GraphPass {
     fn run(&self, graph: IRGraph) -> Result<IRGraph, ValidationError>;
 }
 
+/// Called with the graph before the first pass and after every pass, see [`Pipeline::inspect`].
+type Inspector = Box<dyn FnMut(&str, &IRGraph)>;
+
 /// An ordered sequence of [`GraphPass`]es applied to an [`IRGraph`].
 pub struct Pipeline {
     passes: Vec<Box<dyn GraphPass>>,
+    inspectors: Vec<Inspector>,
+    // Where to write each step, see `dump_json`
+    dump_dir: Option<PathBuf>,
 }
 
 impl Pipeline {
     pub fn new() -> Self {
-        Self { passes: vec![] }
+        Self {
+            passes: vec![],
+            inspectors: vec![],
+            dump_dir: None,
+        }
     }
 
     /// Append a pass to the end of the pipeline.
@@ -26,6 +38,23 @@ impl Pipeline {
         self
     }
 
+    /// Call `f` with the graph the passes start from, named `"input"`, and then with the
+    /// graph after each pass, named for the pass.
+    pub fn inspect(mut self, f: impl FnMut(&str, &IRGraph) + 'static) -> Self {
+        self.inspectors.push(Box::new(f));
+        self
+    }
+
+    /// Write [`IRGraph::to_json`] into `dir` at every step [`Self::inspect`] sees, as
+    /// `0-input.json`, `1-GenerativePass.json`, etc., so that e.g. diffing the last two
+    /// files shows what [`ResolvePass`] changed.
+    ///
+    /// A file that cannot be written stops the run with [`ValidationError::PipelineDump`].
+    pub fn dump_json(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dump_dir = Some(dir.into());
+        self
+    }
+
     /// Translate `ast` to a literal [`IRGraph`] (see [`ast_to_graph`]), then
     /// run all passes in order.
     pub fn run_from_ast(self, ast: Ast) -> Result<IRGraph, ValidationError> {
@@ -34,17 +63,46 @@ impl Pipeline {
     }
 
     /// Run all passes on an already-constructed graph.
-    pub fn run(self, graph: IRGraph) -> Result<IRGraph, ValidationError> {
-        self.passes
+    pub fn run(mut self, graph: IRGraph) -> Result<IRGraph, ValidationError> {
+        self.report(0, "input", &graph)?;
+        let passes = std::mem::take(&mut self.passes);
+        passes
             .into_iter()
-            .try_fold(graph, |g, pass| pass.run(g))
+            .enumerate()
+            .try_fold(graph, |g, (i, pass)| {
+                let g = pass.run(g)?;
+                self.report(i + 1, pass.name(), &g)?;
+                Ok(g)
+            })
+    }
+
+    /// Hand the graph after `step` to the inspectors, and dump it if asked to.
+    fn report(&mut self, step: usize, name: &str, graph: &IRGraph) -> Result<(), ValidationError> {
+        for inspect in &mut self.inspectors {
+            inspect(name, graph);
+        }
+
+        if let Some(dir) = &self.dump_dir {
+            let path = dir.join(format!("{step}-{name}.json"));
+            std::fs::create_dir_all(dir)
+                .and_then(|_| std::fs::write(&path, graph.to_json()))
+                .map_err(|err| {
+                    ValidationError::PipelineDump(format!(
+                        "could not dump {}: {err}",
+                        path.display()
+                    ))
+                })?;
+        }
+        Ok(())
     }
 }
 
 impl Default for Pipeline {
-    /// The default pipeline. This will eventually handle sample rates, spawning nodes N times, etc.
+    /// The default pipeline: blocks are filled in, patches inlined with the sample rates they
+    /// ask for, spawned nodes made N times, and the remaining selectors resolved.
     fn default() -> Self {
         Self::new()
+            .add_pass(GenerativePass)
             .add_pass(MacroExpansionPass)
             .add_pass(SpawnKNodesPass)
             .add_pass(ResolvePass)
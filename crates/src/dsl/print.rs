@@ -0,0 +1,835 @@
+//! Printing an [`Ast`] back to DSL source, and formatting source canonically.
+//!
+//! [`print`] emits the one canonical layout for an [`Ast`], which parses back to the same
+//! [`Ast`]. Use it to save a graph that was edited in code.
+//!
+//! [`format`] does the same for source text, and keeps its comments. The [`Ast`] has no
+//! comments, so they are put back afterwards: the tokens of the source are matched up
+//! with the tokens of the printed text, and each comment goes before the line holding the
+//! token it preceded, or after the line holding the token it trailed.
+
+use std::fmt::Write;
+
+use chumsky::Parser;
+
+use crate::{
+    builder::ValidationError,
+    dsl::{
+        ir::*,
+        parse::{legato_parser, legato_parser_inner, library_parser_inner},
+    },
+};
+
+const INDENT: &str = "    ";
+
+/// Print `ast` as canonical DSL source.
+pub fn print(ast: &Ast) -> String {
+    let mut sections = vec![header(&ast.imports, &ast.uses)];
+
+    if let Some(source) = &ast.source {
+        sections.push(format!("{{ {source} }}\n"));
+    }
+    sections.extend(ast.macros.iter().map(print_macro));
+    sections.extend(ast.declarations.iter().map(|s| print_scope(s, "")));
+    sections.push(print_connections(
+        &ast.connections,
+        &ast.expr_connections,
+        "",
+    ));
+    sections.extend(ast.blocks.iter().map(|b| print_block(b, "")));
+
+    let outputs =
+        std::iter::once(ast.sink.clone()).chain(ast.outputs.iter().map(|(name, alias)| {
+            if name == alias {
+                name.clone()
+            } else {
+                format!("{name}: {alias}")
+            }
+        }));
+    sections.push(format!(
+        "{{ {} }}\n",
+        outputs.collect::<Vec<_>>().join(", ")
+    ));
+
+    join_sections(sections)
+}
+
+/// Print an imported file's `library` as canonical DSL source.
+pub fn print_library(library: &AstLibrary) -> String {
+    let mut sections = vec![header(&library.imports, &library.uses)];
+    sections.extend(library.macros.iter().map(print_macro));
+    join_sections(sections)
+}
+
+/// Format the source of a graph or of an imported file, keeping its comments.
+pub fn format(src: &str) -> Result<String, ValidationError> {
+    let printed = match legato_parser_inner().parse(src).into_result() {
+        Ok(ast) => print(&ast),
+        Err(_) => match library_parser_inner().parse(src).into_result() {
+            Ok(library) => print_library(&library),
+            // Report against the grammar of a graph, the more common of the two
+            Err(_) => return Err(legato_parser(src).expect_err("the source did not parse")),
+        },
+    };
+
+    Ok(restore_comments(src, &printed))
+}
+
+/// Sections separated by blank lines, skipping empty ones.
+fn join_sections(sections: Vec<String>) -> String {
+    sections
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn header(imports: &[Import], uses: &[Use]) -> String {
+    let mut out = String::new();
+
+    for import in imports {
+        write!(out, "import {}", quote(&import.path)).unwrap();
+        if import.namespace != default_namespace(&import.path) {
+            write!(out, " as {}", import.namespace).unwrap();
+        }
+        out.push('\n');
+    }
+    for use_ in uses {
+        write!(out, "use {}", use_.path.join("::")).unwrap();
+        if use_.path.last() != Some(&use_.alias) {
+            write!(out, " as {}", use_.alias).unwrap();
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Mirrors the parser: the file's stem, or the whole path if it has none.
+fn default_namespace(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+pub(crate) fn print_macro(mac: &AstMacro) -> String {
+    let keyword = match mac.kind {
+        MacroKind::Patch => "patch",
+        MacroKind::Kernel => "kernel",
+    };
+    let defaults = mac.default_params.as_ref().map_or(String::new(), |params| {
+        let params: Vec<_> = params
+            .iter()
+            .map(|(k, v)| format!("{k} = {}", print_value(v)))
+            .collect();
+        format!("({})", params.join(", "))
+    });
+    let rate = mac.rate.map_or(String::new(), |rate| format!(" @ {rate}"));
+
+    let mut ports = String::new();
+    for (keyword, names) in [
+        ("in", &mac.virtual_ports_in),
+        ("out", &mac.virtual_ports_out),
+    ] {
+        if !names.is_empty() {
+            let names: Vec<&str> = names.iter().map(String::as_str).collect();
+            writeln!(ports, "{INDENT}{keyword} {}", names.join(" ")).unwrap();
+        }
+    }
+
+    let mut sections = vec![ports];
+    sections.extend(mac.declarations.iter().map(|s| print_scope(s, INDENT)));
+    sections.push(print_connections(
+        &mac.connections,
+        &mac.expr_connections,
+        INDENT,
+    ));
+    sections.extend(mac.blocks.iter().map(|b| print_block(b, INDENT)));
+    sections.push(format!("{INDENT}{{ {} }}\n", mac.sink));
+
+    format!(
+        "{keyword} {}{defaults}{rate} {{\n{}}}\n",
+        mac.name,
+        join_sections(sections)
+    )
+}
+
+fn print_block(block: &Block, indent: &str) -> String {
+    match &block.kind {
+        BlockKind::For { var, start, end } => format!(
+            "{indent}for {var} in {}..{} {}\n",
+            print_value(start),
+            print_value(end),
+            print_block_body(&block.body, indent)
+        ),
+        BlockKind::If {
+            condition,
+            otherwise,
+        } => {
+            let mut out = format!(
+                "{indent}if {} {}",
+                print_value(condition),
+                print_block_body(&block.body, indent)
+            );
+            if *otherwise != BlockBody::default() {
+                write!(out, " else {}", print_block_body(otherwise, indent)).unwrap();
+            }
+            out + "\n"
+        }
+        BlockKind::Oversample { factor } => format!(
+            "{indent}oversample({}) {}\n",
+            print_value(factor),
+            print_block_body(&block.body, indent)
+        ),
+    }
+}
+
+/// A block's body in braces, laid out like the body of a patch.
+fn print_block_body(body: &BlockBody, indent: &str) -> String {
+    let inner = format!("{indent}{INDENT}");
+    let mut sections: Vec<String> = body
+        .declarations
+        .iter()
+        .map(|s| print_scope(s, &inner))
+        .collect();
+    sections.push(print_connections(
+        &body.connections,
+        &body.expr_connections,
+        &inner,
+    ));
+    sections.extend(body.blocks.iter().map(|b| print_block(b, &inner)));
+
+    let sections = join_sections(sections);
+    if sections.is_empty() {
+        return "{}".into();
+    }
+    format!("{{\n{sections}{indent}}}")
+}
+
+fn print_scope(scope: &DeclarationScope, indent: &str) -> String {
+    let declarations: Vec<String> = scope
+        .declarations
+        .iter()
+        .map(|d| format!("{indent}{INDENT}{}", print_declaration(d)))
+        .collect();
+
+    if declarations.is_empty() {
+        return format!("{indent}{} {{}}\n", scope.namespace);
+    }
+    format!(
+        "{indent}{} {{\n{}\n{indent}}}\n",
+        scope.namespace,
+        declarations.join(",\n")
+    )
+}
+
+fn print_declaration(decl: &NodeDeclaration) -> String {
+    let mut out = decl.node_type.clone();
+    if let Some(alias) = &decl.alias {
+        write!(out, ": {alias}").unwrap();
+    }
+    match &decl.count_expr {
+        Some(count) => write!(out, " * {}", print_value(count)).unwrap(),
+        None if decl.count != 1 => write!(out, " * {}", decl.count).unwrap(),
+        None => {}
+    }
+    if let Some(params) = &decl.params {
+        write!(out, " {}", print_object(params)).unwrap();
+    }
+    if !decl.pipes.is_empty() {
+        write!(out, " | {}", print_pipes(&decl.pipes)).unwrap();
+    }
+    out
+}
+
+/// A chain of pipes as written after a declaration, e.g. `spread(0.8) | stereo`.
+pub(crate) fn print_pipes(pipes: &[ASTPipe]) -> String {
+    let pipes: Vec<String> = pipes
+        .iter()
+        .map(|pipe| match &pipe.params {
+            Some(params) => format!("{}({})", pipe.name, print_value(params)),
+            None => pipe.name.clone(),
+        })
+        .collect();
+    pipes.join(" | ")
+}
+
+/// One line per chain of connections, then one per connection with arithmetic.
+fn print_connections(connections: &[Connection], exprs: &[ExprConnection], indent: &str) -> String {
+    let mut out = String::new();
+
+    let mut rest = connections;
+    while let [first, ..] = rest {
+        // `a >> b` then `b >> c` is the chain `a >> b >> c`
+        let len = 1 + rest
+            .windows(2)
+            .take_while(|w| w[0].sink == w[1].source)
+            .count();
+        write!(out, "{indent}{}", print_endpoint(&first.source)).unwrap();
+        for connection in &rest[..len] {
+            let arrow = if connection.feedback { "~>" } else { ">>" };
+            write!(out, " {arrow} {}", print_endpoint(&connection.sink)).unwrap();
+        }
+        out.push('\n');
+        rest = &rest[len..];
+    }
+
+    for expr in exprs {
+        writeln!(
+            out,
+            "{indent}{} >> {}",
+            print_signal(&expr.source),
+            print_endpoint(&expr.sink)
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+pub(crate) fn print_endpoint(endpoint: &Endpoint) -> String {
+    let selector = match &endpoint.node_selector {
+        NodeSelector::Single => String::new(),
+        NodeSelector::All => "(*)".into(),
+        NodeSelector::Index(i) => format!("({i})"),
+        NodeSelector::Range(start, end) => format!("({start}..{end})"),
+        NodeSelector::Template(text) => format!("({text})"),
+    };
+    let port = match &endpoint.port {
+        Port::None => String::new(),
+        Port::Named(name) => format!(".{name}"),
+        Port::Index(i) => format!("[{i}]"),
+        Port::Slice(start, end) => format!("[{start}..{end}]"),
+        Port::Stride { start, end, stride } => format!("[{start}:{end}:{stride}]"),
+        Port::Template(text) => format!("[{text}]"),
+    };
+    format!("{}{selector}{port}", endpoint.node)
+}
+
+fn print_object(object: &Object) -> String {
+    if object.is_empty() {
+        return "{}".into();
+    }
+    let fields: Vec<_> = object
+        .iter()
+        .map(|(k, v)| format!("{k}: {}", print_value(v)))
+        .collect();
+    format!("{{ {} }}", fields.join(", "))
+}
+
+pub(crate) fn print_value(value: &Value) -> String {
+    match value {
+        Value::Null => "null".into(),
+        Value::U32(x) => x.to_string(),
+        Value::I32(x) => x.to_string(),
+        Value::F32(x) => print_f32(*x),
+        Value::Bool(x) => x.to_string(),
+        Value::Ident(x) => x.clone(),
+        Value::String(x) => quote(x),
+        Value::Array(xs) => {
+            let xs: Vec<_> = xs.iter().map(print_value).collect();
+            format!("[{}]", xs.join(", "))
+        }
+        Value::Object(object) => print_object(object),
+        // Templates keep their `$`
+        Value::Template(x) => x.clone(),
+        Value::Expr(expr) => print_expr(expr, 0),
+    }
+}
+
+/// The shortest text that parses back to `x`, always with a fraction so it stays an `F32`.
+fn print_f32(x: f32) -> String {
+    let text = x.to_string();
+    if text.contains('.') || !x.is_finite() {
+        text
+    } else {
+        format!("{text}.0")
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut out = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// How tightly an operator binds, so only the parentheses the parser needs are printed.
+fn precedence(op: BinaryOp) -> u8 {
+    match op {
+        BinaryOp::Add | BinaryOp::Sub => 1,
+        BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 2,
+    }
+}
+
+fn operator(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Add => "+",
+        BinaryOp::Sub => "-",
+        BinaryOp::Mul => "*",
+        BinaryOp::Div => "/",
+        BinaryOp::Rem => "%",
+    }
+}
+
+/// Print `expr`, in parentheses if it binds looser than `min`.
+fn print_expr(expr: &Expr, min: u8) -> String {
+    match &expr.kind {
+        ExprKind::Number(x) => print_value(x),
+        ExprKind::Template(x) => x.clone(),
+        ExprKind::Neg(inner) => format!("-{}", print_expr(inner, 3)),
+        ExprKind::Call(name, args) => {
+            let args: Vec<_> = args.iter().map(|a| print_expr(a, 0)).collect();
+            format!("{name}({})", args.join(", "))
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let p = precedence(*op);
+            // Left associative, so a right operand of the same precedence needs parentheses
+            let text = format!(
+                "{} {} {}",
+                print_expr(lhs, p),
+                operator(*op),
+                print_expr(rhs, p + 1)
+            );
+            parenthesize(text, p < min)
+        }
+    }
+}
+
+fn print_signal(signal: &SignalExpr) -> String {
+    print_signal_with(signal, 0)
+}
+
+fn print_signal_with(signal: &SignalExpr, min: u8) -> String {
+    match signal {
+        SignalExpr::Constant(expr) => print_expr(expr, 3),
+        SignalExpr::Endpoint(endpoint) => print_endpoint(endpoint),
+        SignalExpr::Neg(inner) => format!("-{}", print_signal_with(inner, 3)),
+        SignalExpr::Binary(op, lhs, rhs) => {
+            let p = precedence(*op);
+            let text = format!(
+                "{} {} {}",
+                print_signal_with(lhs, p),
+                operator(*op),
+                print_signal_with(rhs, p + 1)
+            );
+            parenthesize(text, p < min)
+        }
+    }
+}
+
+fn parenthesize(text: String, needed: bool) -> String {
+    if needed { format!("({text})") } else { text }
+}
+
+// ---------------------------------------------------------------------------
+// Comments
+// ---------------------------------------------------------------------------
+
+/// A token that is not a comment, for matching the source up with the printed text.
+#[derive(Debug)]
+struct Token<'a> {
+    text: &'a str,
+    line: usize,
+}
+
+impl Token<'_> {
+    /// Numbers are compared by value, since printing normalises `1.50` to `1.5`.
+    fn same(&self, other: &Token) -> bool {
+        let number = |t: &Token| {
+            t.text
+                .starts_with(|c: char| c.is_ascii_digit())
+                .then(|| t.text.parse::<f64>().ok())
+                .flatten()
+        };
+        match (number(self), number(other)) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.text == other.text,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Comment<'a> {
+    text: &'a str,
+    /// Whether code comes before it on its line.
+    trailing: bool,
+    /// The index of the first token after it.
+    next_token: usize,
+}
+
+/// Split `src` into tokens and comments, tracking the line each token is on.
+fn lex(src: &str) -> (Vec<Token<'_>>, Vec<Comment<'_>>) {
+    let bytes = src.as_bytes();
+    let (mut tokens, mut comments) = (vec![], vec![]);
+    let (mut i, mut line) = (0, 0);
+    let mut code_on_line = false;
+
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+        let rest = &src[i..];
+
+        if c == b'\n' {
+            line += 1;
+            code_on_line = false;
+            i += 1;
+            continue;
+        } else if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        } else if rest.starts_with("//") || rest.starts_with("/*") {
+            i = if rest.starts_with("//") {
+                rest.find('\n').map_or(bytes.len(), |e| i + e)
+            } else {
+                rest.find("*/").map_or(bytes.len(), |e| i + e + 2)
+            };
+            comments.push(Comment {
+                text: src[start..i].trim_end(),
+                trailing: code_on_line,
+                next_token: tokens.len(),
+            });
+            line += src[start..i].matches('\n').count();
+            continue;
+        } else if c == b'"' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+        } else if c.is_ascii_alphanumeric() || c == b'_' || c == b'$' {
+            i += 1;
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric()
+                    || bytes[i] == b'_'
+                    // The fraction of a number, but not a range's `..`
+                    || (bytes[i] == b'.'
+                        && c.is_ascii_digit()
+                        && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)))
+            {
+                i += 1;
+            }
+        } else {
+            i += [">>", "::", ".."]
+                .iter()
+                .find(|p| rest.starts_with(*p))
+                .map_or_else(
+                    || rest.chars().next().map_or(1, char::len_utf8),
+                    |p| p.len(),
+                );
+        }
+
+        tokens.push(Token {
+            text: &src[start..i],
+            line,
+        });
+        code_on_line = true;
+    }
+
+    (tokens, comments)
+}
+
+/// For each source token, the printed token it became, if it can be told.
+///
+/// Printing mostly keeps tokens in order, dropping some (`as` clauses that repeat the
+/// default) and adding others (the repeated node when a chain is split), so a greedy match
+/// that looks a little ahead finds nearly all of them.
+fn match_tokens(source: &[Token], printed: &[Token]) -> Vec<Option<usize>> {
+    const LOOKAHEAD: usize = 64;
+
+    let mut matched = vec![None; source.len()];
+    let (mut i, mut j) = (0, 0);
+
+    while i < source.len() && j < printed.len() {
+        if source[i].same(&printed[j]) {
+            matched[i] = Some(j);
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        // An inserted token, if the next two source tokens turn up a little later
+        let confirms = |k: usize| {
+            source[i].same(&printed[k])
+                && match (source.get(i + 1), printed.get(k + 1)) {
+                    (Some(a), Some(b)) => a.same(b),
+                    _ => true,
+                }
+        };
+        match (j + 1..(j + LOOKAHEAD).min(printed.len())).find(|&k| confirms(k)) {
+            Some(k) => j = k,
+            // Otherwise, a dropped one
+            None => i += 1,
+        }
+    }
+
+    matched
+}
+
+/// Put the comments of `src` into `printed`, its canonical form.
+fn restore_comments(src: &str, printed: &str) -> String {
+    let (source_tokens, comments) = lex(src);
+    if comments.is_empty() {
+        return printed.to_string();
+    }
+    let (printed_tokens, _) = lex(printed);
+    let matched = match_tokens(&source_tokens, &printed_tokens);
+
+    let lines: Vec<&str> = printed.lines().collect();
+    let mut before: Vec<Vec<&str>> = vec![vec![]; lines.len() + 1];
+    let mut after: Vec<Vec<&str>> = vec![vec![]; lines.len()];
+
+    for comment in &comments {
+        let line = match comment.trailing {
+            // After the line of the nearest matched token before it
+            true => (0..comment.next_token)
+                .rev()
+                .find_map(|t| matched[t])
+                .map(|p| printed_tokens[p].line),
+            false => None,
+        };
+        match line {
+            Some(line) => after[line].push(comment.text),
+            None => {
+                // Before the line of the nearest matched token after it
+                let line = (comment.next_token..source_tokens.len())
+                    .find_map(|t| matched[t])
+                    .map_or(lines.len(), |p| printed_tokens[p].line);
+                before[line].push(comment.text);
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for (n, line) in lines.iter().enumerate() {
+        let indent = &line[..line.len() - line.trim_start().len()];
+        for comment in &before[n] {
+            writeln!(out, "{indent}{comment}").unwrap();
+        }
+        out.push_str(line);
+        for comment in &after[n] {
+            write!(out, " {comment}").unwrap();
+        }
+        out.push('\n');
+    }
+    for comment in &before[lines.len()] {
+        writeln!(out, "{comment}").unwrap();
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dsl::parse::legato_parser;
+
+    const MESSY: &str = r#"
+import "verbs.legato"   as   verbs
+import "lib/voices.legato" as vox
+use verbs::plate as plate
+
+{mic}
+
+// A single voice
+patch voice ( freq=440.0,gain=0.5 ) {
+    in gate   freq_in
+    audio { sine{freq:$freq*2.0+1.0} , mult { val : clamp($gain, 0.0, 1.00) } }
+    sine>>mult[0]
+    { mult }
+}
+
+audio {
+    /* the only oscillator */
+    sine: osc * 2 { freq: 220.0, phase: -(0.5 - $p) },
+    svf { type: "low\"pass", gain: [1, -2, 3.0] },
+}
+patches { voice: lead {}, plate }
+
+osc(0)  >>svf[0..2] >> lead.gate // into the voice
+lfo * 0.5 + 0.25 >> svf.cutoff
+{ lead , cue : svf, osc }
+"#;
+
+    const CANONICAL: &str = r#"import "verbs.legato"
+import "lib/voices.legato" as vox
+use verbs::plate
+
+{ mic }
+
+// A single voice
+patch voice(freq = 440.0, gain = 0.5) {
+    in gate freq_in
+
+    audio {
+        sine { freq: $freq * 2.0 + 1.0 },
+        mult { val: clamp($gain, 0.0, 1.0) }
+    }
+
+    sine >> mult[0]
+
+    { mult }
+}
+
+audio {
+    /* the only oscillator */
+    sine: osc * 2 { freq: 220.0, phase: -(0.5 - $p) },
+    svf { gain: [1, -2, 3.0], type: "low\"pass" }
+}
+
+patches {
+    voice: lead {},
+    plate
+}
+
+osc(0) >> svf[0..2] >> lead.gate // into the voice
+lfo * 0.5 + 0.25 >> svf.cutoff
+
+{ lead, cue: svf, osc }
+"#;
+
+    #[test]
+    fn formats_canonically_and_keeps_comments() {
+        assert_eq!(format(MESSY).unwrap(), CANONICAL);
+        assert_eq!(format(CANONICAL).unwrap(), CANONICAL);
+    }
+
+    #[test]
+    fn printed_source_parses_back_to_the_same_ast() {
+        let ast = legato_parser(MESSY).unwrap();
+        let reparsed = legato_parser(&print(&ast)).unwrap();
+
+        // Expressions keep the spans of where they were written, so compare them as text
+        assert_eq!(print(&reparsed), print(&ast));
+        assert_eq!(reparsed.connections, ast.connections);
+        assert_eq!(
+            reparsed.macros[0].virtual_ports_in,
+            ast.macros[0].virtual_ports_in
+        );
+    }
+
+    #[test]
+    fn expressions_keep_their_grouping() {
+        let src = "audio { mult { val: $a - ($b - $c) * -$d / (2 % $e) } } { mult }";
+        let printed = print(&legato_parser(src).unwrap());
+
+        assert!(
+            printed.contains("val: $a - ($b - $c) * -$d / (2 % $e)"),
+            "{printed}"
+        );
+    }
+
+    #[test]
+    fn libraries_format_too() {
+        let src = "import \"a.legato\"\n\n\n  patch p(){ audio{sine} {sine} }";
+        assert_eq!(
+            format(src).unwrap(),
+            "import \"a.legato\"\n\npatch p() {\n    audio {\n        sine\n    }\n\n    { sine }\n}\n"
+        );
+    }
+
+    #[test]
+    fn floats_stay_floats() {
+        for x in [1.0f32, 0.1, -3.0, 1e20, 1.5e-9, 440.0] {
+            let printed = print_f32(x);
+            assert!(printed.contains('.'), "{printed}");
+            assert_eq!(printed.parse::<f32>().unwrap(), x);
+        }
+    }
+
+    #[test]
+    fn blocks_print_and_parse_back() {
+        let src = "patch p(n=2){audio{add:mix}\nfor i in 0..$n{audio{sine:t{i}}\nt{i}({i})>>mix[{i}]\nif $i{mix>>t{i}}else{}}\n{mix}}";
+        let printed = format(src).unwrap();
+
+        assert_eq!(
+            printed,
+            r#"patch p(n = 2) {
+    audio {
+        add: mix
+    }
+
+    for i in 0..$n {
+        audio {
+            sine: t{i}
+        }
+
+        t{i}({i}) >> mix[{i}]
+
+        if $i {
+            mix >> t{i}
+        }
+    }
+
+    { mix }
+}
+"#
+        );
+        assert_eq!(format(&printed).unwrap(), printed);
+    }
+
+    #[test]
+    fn oversample_blocks_print_and_parse_back() {
+        let src = "audio{saw}\noversample( $n*2 ){audio{svf}\nsaw>>svf}\n{svf}";
+        let printed = format(src).unwrap();
+
+        assert!(
+            printed.contains("oversample($n * 2) {\n    audio {\n        svf\n    }"),
+            "{printed}"
+        );
+        assert_eq!(format(&printed).unwrap(), printed);
+    }
+
+    #[test]
+    fn rates_follow_params() {
+        let src = "patch lofi(bits=8)@/2{audio{sine}\n{sine}}\npatches{lofi}\n{lofi}";
+        let printed = format(src).unwrap();
+
+        assert!(
+            printed.starts_with("patch lofi(bits = 8) @ /2 {\n"),
+            "{printed}"
+        );
+        assert_eq!(format(&printed).unwrap(), printed);
+    }
+
+    #[test]
+    fn pipes_follow_params() {
+        let src = "audio{saw*4{chans:1}|spread( 0.5 )|oversample} {saw}";
+        assert_eq!(
+            format(src).unwrap(),
+            "audio {\n    saw * 4 { chans: 1 } | spread(0.5) | oversample\n}\n\n{ saw }\n"
+        );
+    }
+
+    #[test]
+    fn counts_keep_their_params() {
+        let src = "patch p(n = 2) { audio{saw*($n*2){chans:1}} {saw} } patches{p} {p}";
+        assert!(format(src).unwrap().contains("saw * $n * 2 { chans: 1 }"));
+    }
+
+    #[test]
+    fn feedback_links_keep_their_arrow() {
+        let src = "audio{add:mix,delay}\nmix>>delay~>mix[1]\n{mix}";
+        assert!(format(src).unwrap().contains("mix >> delay ~> mix[1]"));
+    }
+
+    #[test]
+    fn virtual_ports_share_a_section() {
+        let src = "patch p(){in a\nout b c\naudio{sine}\nsine>>b\nsine>>c\n{sine}}\n{p}";
+        assert!(
+            format(src)
+                .unwrap()
+                .contains("    in a\n    out b c\n\n    audio {")
+        );
+    }
+}
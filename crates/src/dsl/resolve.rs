@@ -110,10 +110,12 @@ impl GraphPass for ResolvePass {
         // node-multiplicity sugar already consumed by expand/spawn, so they are
         // normalised to `Single` here to give a single, canonical post-resolve form.
         for edge in graph.take_edges() {
-            graph.connect_pin(
-                Pin::new(edge.source, edge.source_port),
-                Pin::new(edge.sink, edge.sink_port),
-            );
+            graph
+                .connect_pin(
+                    Pin::new(edge.source, edge.source_port.clone()),
+                    Pin::new(edge.sink, edge.sink_port.clone()),
+                )
+                .inherit(&edge);
         }
 
         Ok(graph)
@@ -49,11 +49,22 @@ impl SpawnKNodesPass {
                     node.params.clone(),
                     1,
                 );
+                graph.set_span(new_id, node.span.clone());
+                graph.set_rate(new_id, node.rate);
+                graph.set_oversample(new_id, node.oversample);
                 instances.push(new_id);
             }
             expansion.insert(*orig_id, instances);
         }
 
+        for pipe in &mut graph.pipes {
+            pipe.nodes = pipe
+                .nodes
+                .iter()
+                .flat_map(|id| expansion.get(id).cloned().unwrap_or_else(|| vec![*id]))
+                .collect();
+        }
+
         // ── Phase 2: expand edges that touch any multi-node ────────────────
         let snapshot: Vec<IREdge> = graph.edges().to_vec();
 
@@ -78,7 +89,7 @@ impl SpawnKNodesPass {
             let srcs = edge.source_selector.select(&src_pool).to_vec();
             let snks = edge.sink_selector.select(&snk_pool).to_vec();
 
-            Self::expand_edge(&mut graph, edge, &srcs, &snks)?;
+            Self::expand_edge(&mut graph, edge, &srcs, &snks).map_err(|e| e.at(&edge.span))?;
         }
 
         // ── Phase 3: remove originals (also removes their incident edges) ──
@@ -87,6 +98,13 @@ impl SpawnKNodesPass {
                 // Last instance is the natural graph output.
                 graph.sink = expansion[orig_id].last().copied();
             }
+            for (_, output) in graph.outputs.iter_mut() {
+                if *output == *orig_id
+                    && let Some(last) = expansion[orig_id].last()
+                {
+                    *output = *last;
+                }
+            }
             if graph.source == Some(*orig_id) {
                 graph.source = expansion[orig_id].first().copied();
             }
@@ -109,11 +127,15 @@ impl SpawnKNodesPass {
         // selects the concrete port, so this is its own zip rather than a plain
         // node-level broadcast.
         if let Port::Slice(start, end) = &edge.sink_port {
-            let width = end - start;
+            let width = end.checked_sub(*start).ok_or_else(|| {
+                ValidationError::SelectionArity(format!(
+                    "port slice [{start}..{end}] ends before it starts"
+                ))
+            })?;
             let ports = source_ports(&edge.source_port);
             // Exact zip: instances x source-ports must equal the slice width, so
             // there is a single flatten axis and no cross-product inference.
-            if srcs.len() * ports.len() != width {
+            if srcs.len() * ports.len() != width || snks.is_empty() {
                 return Err(ValidationError::SelectionArity(format!(
                     "source lines ({} instances x {} ports) must equal port slice width ({width})",
                     srcs.len(),
@@ -123,12 +145,14 @@ impl SpawnKNodesPass {
             // Flatten instance-major: instance i owns ports [i*len, i*len + len).
             for (i, &src) in srcs.iter().enumerate() {
                 for (offset, source_port) in ports.iter().enumerate() {
-                    graph.connect(
-                        src,
-                        source_port.clone(),
-                        snks[0],
-                        Port::Index(start + i * ports.len() + offset),
-                    );
+                    graph
+                        .connect(
+                            src,
+                            source_port.clone(),
+                            snks[0],
+                            Port::Index(start + i * ports.len() + offset),
+                        )
+                        .inherit(edge);
                 }
             }
             return Ok(());
@@ -146,19 +170,23 @@ impl SpawnKNodesPass {
             && matches!(edge.source_port, Port::Stride { .. } | Port::Slice(..))
         {
             for (i, &snk) in snks.iter().enumerate() {
-                graph.connect(
-                    srcs[0],
-                    port_for_instance(&edge.source_port, i),
-                    snk,
-                    edge.sink_port.clone(),
-                );
+                graph
+                    .connect(
+                        srcs[0],
+                        port_for_instance(&edge.source_port, i),
+                        snk,
+                        edge.sink_port.clone(),
+                    )
+                    .inherit(edge);
             }
             return Ok(());
         }
 
         // All other node-level multiplicity follows the shared broadcasting rule.
         let connect = |graph: &mut IRGraph, src: NodeId, snk: NodeId| {
-            graph.connect(src, edge.source_port.clone(), snk, edge.sink_port.clone());
+            graph
+                .connect(src, edge.source_port.clone(), snk, edge.sink_port.clone())
+                .inherit(edge);
         };
         match broadcast(srcs, snks).map_err(|e| ValidationError::SelectionArity(e.to_string()))? {
             Plan::Zip(pairs) => {
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt::Debug, sync::Arc};
+
+use crate::{
+    FrontendError,
+    executor::Executor,
+    graph::{AudioGraph, Connection, GraphError},
+    node::LegatoNode,
+    pool::WorkerPool,
+    runtime::NodeKey,
+};
+
+/// A structural change to the running graph, as requested by the frontend.
+pub(crate) enum Edit {
+    AddNode(LegatoNode),
+    RemoveNode(NodeKey),
+    AddEdge(Connection),
+    RemoveEdge(Connection),
+    ReplaceNode(NodeKey, LegatoNode),
+    /// Keep a node's outputs readable after the block, to be tapped.
+    PinOutputs(NodeKey),
+}
+
+/// An edited graph built and prepared off of the audio thread.
+///
+/// The runtime moves its live nodes into this graph so they keep running undisturbed, swaps it in
+/// at a block boundary, then sends the box back holding the outgoing graph to be dropped.
+pub struct GraphEdit {
+    pub(crate) executor: Executor,
+    // Nodes from before the edit, which are still stand-ins for the live ones
+    moved: Vec<NodeKey>,
+}
+
+impl GraphEdit {
+    /// Trade the stand-ins in this graph for the running nodes in `live`, along with what its
+    /// feedback edges and control-rate outputs kept from the last block. Called on the audio thread.
+    pub(crate) fn adopt_nodes(&mut self, live: &mut Executor) {
+        self.executor.carry_delays(live);
+
+        for key in &self.moved {
+            if let (Some(node), Some(running)) = (
+                self.executor.graph.get_node_mut(*key),
+                live.graph.get_node_mut(*key),
+            ) {
+                std::mem::swap(node, running);
+            }
+        }
+    }
+}
+
+impl Debug for GraphEdit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GraphEdit")
+            .field("graph", &self.executor.graph)
+            .field("moved_nodes", &self.moved.len())
+            .finish()
+    }
+}
+
+/// The frontend's copy of the running graph, which edits are worked out against.
+///
+/// Node keys match the running graph, but the nodes are clones from when they were added,
+/// so only their ports are ever looked at.
+#[derive(Clone)]
+pub(crate) struct GraphMirror {
+    graph: AudioGraph,
+    sink: NodeKey,
+    outputs: Vec<(String, NodeKey)>,
+    pinned: Vec<NodeKey>,
+    block_size: usize,
+    worker_pool: Option<Arc<WorkerPool>>,
+    // Carried into every rebuilt executor, like the worker pool
+    control_rate: usize,
+}
+
+impl GraphMirror {
+    pub(crate) fn new(executor: &Executor, block_size: usize) -> Self {
+        Self {
+            graph: executor.graph.clone(),
+            sink: executor.sink().expect("Sink node must be provided"),
+            outputs: executor.outputs().to_vec(),
+            pinned: executor.pinned().to_vec(),
+            block_size,
+            worker_pool: executor.worker_pool().cloned(),
+            control_rate: executor.control_rate(),
+        }
+    }
+
+    /// Whether `key`'s outputs are already kept readable after the block.
+    pub(crate) fn is_pinned(&self, key: NodeKey) -> bool {
+        self.is_output(key) || self.pinned.contains(&key)
+    }
+
+    fn is_output(&self, key: NodeKey) -> bool {
+        key == self.sink || self.outputs.iter().any(|(_, k)| *k == key)
+    }
+
+    pub(crate) fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    /// The number of audio outputs `key` has, if it exists.
+    pub(crate) fn outputs(&self, key: NodeKey) -> Option<usize> {
+        self.graph
+            .get_node(key)
+            .map(|n| n.get_node().ports().audio_out.len())
+    }
+
+    /// Apply `edit` to a copy of the mirror, and prepare an executor for the result.
+    ///
+    /// Returns the mirror as it will be once the runtime has applied the edit, along with
+    /// the key of the node that was added or replaced, if any.
+    pub(crate) fn prepare(
+        &self,
+        edit: Edit,
+    ) -> Result<(Self, Box<GraphEdit>, Option<NodeKey>), FrontendError> {
+        let mut graph = self.graph.clone();
+        let mut pinned = self.pinned.clone();
+
+        let fresh = match edit {
+            Edit::AddNode(node) => Some(graph.add_node(node)),
+            Edit::RemoveNode(key) => {
+                if self.is_output(key) {
+                    return Err(FrontendError::CannotRemoveSink());
+                }
+                graph
+                    .remove_node(key)
+                    .ok_or(FrontendError::InvalidEdit(GraphError::NodeDoesNotExist))?;
+                None
+            }
+            Edit::AddEdge(connection) => {
+                if !ports_in_range(&graph, &connection) {
+                    return Err(FrontendError::InvalidEdit(GraphError::BadConnection));
+                }
+                graph
+                    .add_edge(connection)
+                    .map_err(FrontendError::InvalidEdit)?;
+                None
+            }
+            Edit::RemoveEdge(connection) => {
+                graph
+                    .remove_edge(connection)
+                    .map_err(FrontendError::InvalidEdit)?;
+                None
+            }
+            Edit::ReplaceNode(key, node) => {
+                if !graph.exists(key) {
+                    return Err(FrontendError::InvalidEdit(GraphError::NodeDoesNotExist));
+                }
+                graph.replace(key, node);
+
+                // The replacement may have fewer ports than the edges into and out of it expect
+                let connections = graph
+                    .incoming_connections(key)
+                    .into_iter()
+                    .chain(graph.outgoing_connections(key))
+                    .flatten();
+                for connection in connections {
+                    if !ports_in_range(&graph, connection) {
+                        return Err(FrontendError::InvalidEdit(GraphError::BadConnection));
+                    }
+                }
+                Some(key)
+            }
+            Edit::PinOutputs(key) => {
+                if !graph.exists(key) {
+                    return Err(FrontendError::InvalidEdit(GraphError::NodeDoesNotExist));
+                }
+                if !pinned.contains(&key) {
+                    pinned.push(key);
+                }
+                None
+            }
+        };
+
+        pinned.retain(|key| graph.exists(*key));
+
+        let moved = graph
+            .keys()
+            .filter(|key| Some(*key) != fresh && self.graph.exists(*key))
+            .collect();
+
+        let mut executor = Executor::default();
+        executor.graph = graph.clone();
+        executor.set_worker_pool(self.worker_pool.clone());
+        executor.set_control_rate(self.control_rate);
+        executor
+            .validate_arity()
+            .map_err(FrontendError::InvalidGraph)?;
+        executor
+            .validate_control_rate(self.block_size)
+            .map_err(FrontendError::InvalidGraph)?;
+        executor
+            .validate_rates(self.block_size, &HashMap::new())
+            .map_err(FrontendError::InvalidGraph)?;
+        executor
+            .set_sink(self.sink)
+            .map_err(FrontendError::InvalidEdit)?;
+        for (name, key) in &self.outputs {
+            executor
+                .add_output(name, *key)
+                .map_err(FrontendError::InvalidEdit)?;
+        }
+        for key in &pinned {
+            executor
+                .pin_outputs(*key)
+                .map_err(FrontendError::InvalidEdit)?;
+        }
+        executor.prepare(self.block_size);
+
+        let mirror = Self {
+            graph,
+            sink: self.sink,
+            outputs: self.outputs.clone(),
+            pinned,
+            block_size: self.block_size,
+            worker_pool: self.worker_pool.clone(),
+            control_rate: self.control_rate,
+        };
+
+        Ok((mirror, Box::new(GraphEdit { executor, moved }), fresh))
+    }
+}
+
+fn ports_in_range(graph: &AudioGraph, connection: &Connection) -> bool {
+    let ports = |key: NodeKey| graph.get_node(key).map(|n| n.get_node().ports());
+
+    match (
+        ports(connection.source.node_key),
+        ports(connection.sink.node_key),
+    ) {
+        (Some(source), Some(sink)) => {
+            connection.source.port_index < source.audio_out.len()
+                && connection.sink.port_index < sink.audio_in.len()
+        }
+        _ => false,
+    }
+}
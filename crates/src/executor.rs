@@ -1,13 +1,28 @@
 use crate::{
     builder::ValidationError,
-    context::AudioContext,
+    context::{AudioContext, MAX_OVERSAMPLE, Rate, at_rate},
+    dsl::ir::SourceSpan,
     graph::{AudioGraph, GraphError},
+    node::{Interpolation, LegatoNode},
+    pool::{Job, WorkerPool},
+    ports::PortKind,
+    profile::{BlockProfile, ProfileEvent, Timing},
     runtime::NodeKey,
+    simd::LANES,
 };
 use slotmap::SecondaryMap;
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub const MAX_ARITY: usize = 32;
 
+/// The name the sink goes by among the named outputs.
+pub const MAIN_OUTPUT: &str = "main";
+
 /// For the time being, we just check if it has been prepared or not,
 /// but in the future we might pause, stop, etc.
 #[derive(Clone, Debug, PartialEq, Default)]
@@ -29,11 +44,67 @@ pub struct OutputView<'a> {
 #[derive(Clone, Debug, Default)]
 pub struct Executor {
     data: Box<[f32]>,
+    // One `MAX_ARITY` inputs long region per thread that can run a node, see `scratch_len`
     scratch: Box<[f32]>,
+    scratch_len: usize,
     pub graph: AudioGraph,
     node_offsets: SecondaryMap<NodeKey, usize>,
     sink_key: Option<NodeKey>,
+    // Named outputs besides the sink
+    outputs: Vec<(String, NodeKey)>,
+    // Nodes whose outputs stay readable after the block, like the sink's
+    pinned: Vec<NodeKey>,
     state: ExecutorState,
+    // Set to spread independent nodes over worker threads
+    pool: Option<Arc<WorkerPool>>,
+    // Nodes ordered by level, where a level only reads from earlier ones. See `schedule_levels`
+    schedule: Vec<Scheduled>,
+    levels: Vec<Range<usize>>,
+    // Refreshed every block, so worker threads can each reach their own node
+    node_ptrs: SecondaryMap<NodeKey, NodePtr>,
+    // Where each node reads its inputs and writes its outputs
+    plans: SecondaryMap<NodeKey, NodePlan>,
+    // Outputs copied aside at the end of the block, for feedback edges to read in the next
+    delayed: Vec<Delayed>,
+    // Samples per value for nodes at control rate, where 0 or 1 runs everything at audio rate
+    control_rate: usize,
+    // Which nodes run at control rate. See `mark_rates`
+    control: SecondaryMap<NodeKey, bool>,
+    // The rate each node runs at, its own or control rate
+    rates: SecondaryMap<NodeKey, Rate>,
+    // The last value of each control-rate output read at audio rate, from the last block and this one
+    history: Box<[f32]>,
+    buffer_stats: BufferStats,
+    // Set to time every node and block, see `crate::profile`
+    profiling: bool,
+    node_timings: SecondaryMap<NodeKey, Timing>,
+    block_timing: Timing,
+    overruns: usize,
+}
+
+/// The size of the executor's flat data buffer, with and without reusing output slots.
+///
+/// Both are in floats, so they scale with the block size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferStats {
+    /// What the buffer would be if every output port kept a slot of its own.
+    pub unshared_len: usize,
+    /// What the buffer is, with slots reused once all of their readers have run.
+    pub shared_len: usize,
+}
+
+/// A feedback edge's source output, and the slot it is kept in for the next block.
+#[derive(Clone, Copy, Debug)]
+struct Delayed {
+    source: usize,
+    slot: usize,
+    len: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Scheduled {
+    key: NodeKey,
+    shares_context: bool,
 }
 
 impl Executor {
@@ -52,6 +123,121 @@ impl Executor {
         &self.sink_key
     }
 
+    /// Add a named output besides the sink, read with [`Self::named_output`]. Adding a name
+    /// again points it at `key` instead.
+    pub fn add_output(&mut self, name: &str, key: NodeKey) -> Result<(), GraphError> {
+        if !self.graph.exists(key) {
+            return Err(GraphError::NodeDoesNotExist);
+        }
+        match self.outputs.iter_mut().find(|(n, _)| n == name) {
+            Some((_, k)) => *k = key,
+            None => self.outputs.push((name.into(), key)),
+        }
+        self.state = ExecutorState::Unprepared;
+        Ok(())
+    }
+
+    pub fn outputs(&self) -> &[(String, NodeKey)] {
+        &self.outputs
+    }
+
+    /// Keep `key`'s outputs in slots of their own, so they still hold this block's samples
+    /// once [`Self::process`] returns. Takes effect on the next [`Self::prepare`].
+    pub fn pin_outputs(&mut self, key: NodeKey) -> Result<(), GraphError> {
+        if !self.graph.exists(key) {
+            return Err(GraphError::NodeDoesNotExist);
+        }
+        if !self.pinned.contains(&key) {
+            self.pinned.push(key);
+            self.state = ExecutorState::Unprepared;
+        }
+        Ok(())
+    }
+
+    pub fn pinned(&self) -> &[NodeKey] {
+        &self.pinned
+    }
+
+    /// One of a pinned node's (or the sink's, or a named output's) outputs from the last block.
+    /// `None` for a node that does not run at the graph's rate.
+    pub fn node_output(&self, key: NodeKey, port: usize, block_size: usize) -> Option<&[f32]> {
+        let offset = *self.node_offsets.get(key)?;
+        let arity = self.graph.get_node(key)?.get_node().ports().audio_out.len();
+        let rate = *self.rates.get(key)?;
+
+        (port < arity && rate == Rate::GRAPH).then(|| {
+            let start = offset + port * block_size;
+            &self.data[start..start + block_size]
+        })
+    }
+
+    /// Run independent nodes on `pool`, or everything on the calling thread with `None`.
+    ///
+    /// Output is bit-identical either way. Takes effect on the next [`Self::prepare`].
+    pub fn set_worker_pool(&mut self, pool: Option<Arc<WorkerPool>>) {
+        self.pool = pool;
+        self.state = ExecutorState::Unprepared;
+    }
+
+    pub fn worker_pool(&self) -> Option<&Arc<WorkerPool>> {
+        self.pool.as_ref()
+    }
+
+    /// Run nodes whose outputs are all [`PortKind::Control`] once every `decimation` samples,
+    /// on blocks of `block_size / decimation`, with the context's block size and sample rate
+    /// scaled to match. The sample rate is rounded down.
+    ///
+    /// Such a node only runs at control rate if it is at the graph's rate, everything it reads
+    /// is at control rate too, and its outputs are neither resampled nor read after the block,
+    /// as the sink's are. Audio-rate nodes reading it see its outputs spread back over the
+    /// block, as the node's [`crate::node::Node::control_interpolation`] asks. Takes effect on
+    /// the next [`Self::prepare`].
+    pub fn set_control_rate(&mut self, decimation: usize) {
+        self.control_rate = decimation;
+        self.state = ExecutorState::Unprepared;
+    }
+
+    pub fn control_rate(&self) -> usize {
+        self.control_rate
+    }
+
+    /// Whether `key` ran at control rate in the last prepared graph.
+    pub fn is_control_rate(&self, key: NodeKey) -> bool {
+        self.control.get(key).copied().unwrap_or(false)
+    }
+
+    /// The rate `key` ran at in the last prepared graph, control rate included.
+    pub fn rate(&self, key: NodeKey) -> Option<Rate> {
+        self.rates.get(key).copied()
+    }
+
+    /// Time each node's `process` call, and the block as a whole.
+    pub(crate) fn set_profiling(&mut self, profiling: bool) {
+        self.profiling = profiling;
+    }
+
+    /// Blocks timed since the last [`Self::take_profile`].
+    pub(crate) fn profiled_blocks(&self) -> usize {
+        self.block_timing.count()
+    }
+
+    /// Hand every node's timings, then the block's, to `send`, and start a new window.
+    pub(crate) fn take_profile(&mut self, deadline: Duration, mut send: impl FnMut(ProfileEvent)) {
+        for (key, timing) in &self.node_timings {
+            if let Some(stats) = timing.take() {
+                send(ProfileEvent::Node(key, stats));
+            }
+        }
+
+        if let Some(time) = self.block_timing.take() {
+            send(ProfileEvent::Block(BlockProfile {
+                time,
+                deadline,
+                overruns: std::mem::take(&mut self.overruns),
+            }));
+        }
+    }
+
     /// Reject nodes wider than [`MAX_ARITY`].
     pub fn validate_arity(&self) -> Result<(), ValidationError> {
         for node in self.graph.nodes() {
@@ -74,35 +260,356 @@ impl Executor {
         Ok(())
     }
 
-    /// Prepare the flat buffer allocation for the graph, as well as the node offsets.
+    /// Reject a control rate that does not split the block into whole SIMD vectors, which
+    /// block based nodes expect.
+    pub fn validate_control_rate(&self, block_size: usize) -> Result<(), ValidationError> {
+        let rate = self.control_rate;
+        if rate > 1
+            && !(block_size.is_multiple_of(rate) && (block_size / rate).is_multiple_of(LANES))
+        {
+            return Err(ValidationError::InvalidParameter(format!(
+                "a control rate of {rate} does not split blocks of {block_size} samples into \
+                 a multiple of {LANES} samples"
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Reject nodes at a rate that does not split the block into whole SIMD vectors, and
+    /// connections between nodes at different rates with no resampler between them.
     ///
-    /// NOTE: This is not realtime safe!
-    pub fn prepare(&mut self, block_size: usize) {
-        // Allocate flat buffer
-        let num_ports = self.graph.total_ports();
-        let buffer_size = num_ports * block_size;
+    /// A resampler reads at the rate of its sources, so those must all be at one rate.
+    pub fn validate_rates(
+        &self,
+        block_size: usize,
+        spans: &HashMap<NodeKey, SourceSpan>,
+    ) -> Result<(), ValidationError> {
+        let feedback = self.graph.feedback_connections();
+        let blame = |key: NodeKey, err: ValidationError| match spans.get(&key) {
+            Some(span) => err.at(span),
+            None => err,
+        };
 
-        self.data = vec![0.0; buffer_size].into();
+        for key in self.graph.keys() {
+            let node = self.graph.get_node(key).unwrap();
+            let rate = node.rate();
+            let len = rate.scale(block_size);
 
-        // Scratch buffer that gets passed for node inputs
+            if rate.up() > MAX_OVERSAMPLE * rate.down() {
+                return Err(blame(
+                    key,
+                    ValidationError::InvalidParameter(format!(
+                        "node '{}' ({}) runs at {rate} the graph's rate, but nodes may run at \
+                         most x{MAX_OVERSAMPLE}",
+                        node.name, node.node_kind
+                    )),
+                ));
+            }
+            if len * rate.down() != block_size * rate.up() || !len.is_multiple_of(LANES) {
+                return Err(blame(
+                    key,
+                    ValidationError::InvalidParameter(format!(
+                        "node '{}' ({}) runs at {rate} the graph's rate, which does not split \
+                         blocks of {block_size} samples into a multiple of {LANES} samples",
+                        node.name, node.node_kind
+                    )),
+                ));
+            }
 
-        let scratch_len = block_size * MAX_ARITY;
+            let sources = self
+                .graph
+                .incoming_connections(key)
+                .into_iter()
+                .flatten()
+                .chain(feedback.iter().filter(|con| con.sink.node_key == key))
+                .filter_map(|con| self.graph.get_node(con.source.node_key));
 
-        self.scratch = vec![0.0; scratch_len].into();
+            let mut expected = (!node.get_node().resamples()).then_some(rate);
+            for source in sources {
+                let expected = *expected.get_or_insert(source.rate());
+                if source.rate() != expected {
+                    return Err(blame(
+                        key,
+                        ValidationError::RateMismatch(format!(
+                            "'{}' at {} feeds '{}' at {expected}, with nothing to resample \
+                             between them",
+                            source.name,
+                            source.rate(),
+                            node.name
+                        )),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
 
+    /// Take over what `live` kept from its last block for the next: the outputs delayed for
+    /// feedback edges, and the last values of control-rate outputs read at audio rate. Only
+    /// edges and outputs both graphs have are carried, the rest start over.
+    ///
+    /// Runs on the audio thread when an edit is swapped in, so it must not allocate.
+    pub(crate) fn carry_delays(&mut self, live: &Executor) {
+        let feedback = self.graph.feedback_connections();
+        for (con, delayed) in feedback.iter().zip(&self.delayed) {
+            let Some(index) = live.graph.feedback_connections().get_index_of(con) else {
+                continue;
+            };
+            let previous = live.delayed[index];
+            if previous.len == delayed.len {
+                let slot = previous.slot..previous.slot + previous.len;
+                self.data[delayed.slot..delayed.slot + delayed.len]
+                    .copy_from_slice(&live.data[slot]);
+            }
+        }
+
+        for (key, plan) in self.plans.iter() {
+            let Some(previous) = live.plans.get(key) else {
+                continue;
+            };
+            for &(port, history) in &plan.history {
+                if let Some(&(_, old)) = previous.history.iter().find(|(p, _)| *p == port) {
+                    self.history[history..history + 2].copy_from_slice(&live.history[old..old + 2]);
+                }
+            }
+        }
+    }
+
+    /// Prepare the flat buffer allocation for the graph, as well as the node offsets.
+    ///
+    /// NOTE: This is not realtime safe!
+    pub fn prepare(&mut self, block_size: usize) {
         // Now, we get all the keys from the topo sorted order, so we can give each node an offset into the flat buffer.
         let keys = self
             .graph
             .invalidate_topo_sort()
             .expect("Invalid graph topology found in prepare!");
 
-        let mut total_ports = 0_usize;
+        self.mark_rates(&keys);
+        self.schedule_levels(&keys);
 
-        self.node_offsets.clear();
+        self.node_timings = keys.iter().map(|key| (*key, Timing::default())).collect();
 
-        for key in keys {
-            self.node_offsets.insert(key, total_ports * block_size);
+        // Allocate flat buffer
+        let len = self.allocate_slots(&keys, block_size);
+        self.plan_inputs(block_size);
+
+        self.data = vec![0.0; len].into();
+
+        // Scratch buffer that gets passed for node inputs, one per thread, long enough for the
+        // longest inputs of any node
+        let threads = self.pool.as_ref().map_or(0, |pool| pool.threads()) + 1;
+        let longest = self.plans.values().map(|plan| plan.input_len);
+        self.scratch_len = longest.fold(block_size, usize::max) * MAX_ARITY;
+
+        self.scratch = vec![0.0; self.scratch_len * threads].into();
+
+        let ports: usize = keys
+            .iter()
+            .map(|&key| {
+                let arity = self
+                    .graph
+                    .get_node(key)
+                    .unwrap()
+                    .get_node()
+                    .ports()
+                    .audio_out
+                    .len();
+                arity * self.plans[key].len
+            })
+            .sum();
+        let delayed: usize = self.delayed.iter().map(|delayed| delayed.len).sum();
+        self.buffer_stats = BufferStats {
+            unshared_len: ports + delayed,
+            shared_len: len,
+        };
+
+        self.state = ExecutorState::Prepared;
+    }
+
+    /// Work out the rate every node runs at: its own, or control rate for the nodes that can,
+    /// see [`Self::set_control_rate`].
+    fn mark_rates(&mut self, topo_order: &[NodeKey]) {
+        self.control = topo_order.iter().map(|key| (*key, false)).collect();
+        self.rates = topo_order
+            .iter()
+            .map(|key| (*key, self.graph.get_node(*key).unwrap().rate()))
+            .collect();
+
+        if self.control_rate <= 1 {
+            return;
+        }
+
+        let feedback = self.graph.feedback_connections();
+        let read_after: Vec<NodeKey> = self
+            .sink_key
+            .iter()
+            .chain(self.outputs.iter().map(|(_, key)| key))
+            .chain(&self.pinned)
+            .chain(feedback.iter().map(|con| &con.source.node_key))
+            .copied()
+            .collect();
+        // A resampler reads its sources at their own rate, which must not change under it
+        let resampled: Vec<NodeKey> = topo_order
+            .iter()
+            .filter(|key| self.graph.get_node(**key).unwrap().get_node().resamples())
+            .flat_map(|key| self.graph.incoming_connections(*key).into_iter().flatten())
+            .map(|con| con.source.node_key)
+            .collect();
+
+        for key in topo_order.iter().copied() {
+            let legato_node = self.graph.get_node(key).unwrap();
+            let node = legato_node.get_node();
+            let outputs = &node.ports().audio_out;
+
+            let control = !outputs.is_empty()
+                && outputs.iter().all(|port| port.kind == PortKind::Control)
+                && legato_node.rate() == Rate::GRAPH
+                && !node.resamples()
+                // What they do through the context is timed against the graph's blocks
+                && !node.shares_context()
+                && !read_after.contains(&key)
+                && !resampled.contains(&key)
+                && !feedback.iter().any(|con| con.sink.node_key == key)
+                && self
+                    .graph
+                    .incoming_connections(key)
+                    .into_iter()
+                    .flatten()
+                    .all(|con| self.control[con.source.node_key]);
+
+            self.control[key] = control;
+            if control {
+                self.rates[key] = Rate::divided(self.control_rate);
+            }
+        }
+    }
+
+    /// Group nodes into levels for the worker pool: a node sits one level past the deepest node
+    /// it reads from, so everything within a level can run at once.
+    ///
+    /// Nodes that share the context are also chained one after another in topological order,
+    /// and run on their own after the rest of their level, which keeps their side effects in
+    /// the same order the serial executor has them in.
+    fn schedule_levels(&mut self, topo_order: &[NodeKey]) {
+        self.schedule.clear();
+        self.levels.clear();
+        self.node_ptrs.clear();
+
+        if self.pool.is_none() {
+            return;
+        }
 
+        let mut depth: SecondaryMap<NodeKey, usize> = SecondaryMap::new();
+        let mut last_shared: Option<usize> = None;
+        let mut entries = Vec::with_capacity(topo_order.len());
+
+        for key in topo_order.iter().copied() {
+            let mut level = self
+                .graph
+                .incoming_connections(key)
+                .into_iter()
+                .flatten()
+                .map(|con| depth[con.source.node_key] + 1)
+                .max()
+                .unwrap_or(0);
+
+            let node = self.graph.get_node(key).unwrap().get_node();
+            let shares_context = node.shares_context();
+
+            if shares_context {
+                level = level.max(last_shared.map_or(0, |l| l + 1));
+                last_shared = Some(level);
+            }
+
+            depth.insert(key, level);
+            entries.push((level, shares_context, key));
+
+            self.node_ptrs.insert(key, NodePtr(std::ptr::null_mut()));
+        }
+
+        // Stable, so each level keeps topological order, with the node sharing the context last
+        entries.sort_by_key(|(level, shares_context, _)| (*level, *shares_context));
+
+        let mut start = 0;
+        for (i, (level, shares_context, key)) in entries.iter().copied().enumerate() {
+            if i > 0 && level != entries[i - 1].0 {
+                self.levels.push(start..i);
+                start = i;
+            }
+            self.schedule.push(Scheduled {
+                key,
+                shares_context,
+            });
+        }
+        if !entries.is_empty() {
+            self.levels.push(start..entries.len());
+        }
+    }
+
+    /// Give every node a run of block sized slots in the data buffer for its outputs.
+    ///
+    /// Like a register allocator, a slot is handed out again once every node reading it has run,
+    /// so the buffer only needs to be as large as the outputs live at any one time. The sink's,
+    /// named outputs', pinned nodes' and feedback sources' outputs are read after the block, so
+    /// they are never reused. Each feedback edge then gets a slot past the rest, which holds its
+    /// source's output from the block before. Nodes at any other rate than the graph's, like
+    /// control rate, share slots of that rate's length after those. Returns the length of the
+    /// buffer.
+    fn allocate_slots(&mut self, topo_order: &[NodeKey], block_size: usize) -> usize {
+        // When each node runs. Nodes with the same time can run alongside each other
+        let mut time: SecondaryMap<NodeKey, usize> = SecondaryMap::new();
+        let mut order = Vec::with_capacity(topo_order.len());
+
+        if self.levels.is_empty() {
+            for (t, key) in topo_order.iter().copied().enumerate() {
+                time.insert(key, t);
+                order.push(key);
+            }
+        } else {
+            // A level's shared node runs after everything else in it
+            for (l, level) in self.levels.iter().enumerate() {
+                for node in &self.schedule[level.clone()] {
+                    time.insert(node.key, 2 * l + node.shares_context as usize);
+                    order.push(node.key);
+                }
+            }
+        }
+
+        // The last time each node's outputs are read, or written if nothing reads them
+        let mut last_use = time.clone();
+        for key in order.iter().copied() {
+            for con in self.graph.incoming_connections(key).into_iter().flatten() {
+                let source = &mut last_use[con.source.node_key];
+                *source = (*source).max(time[key]);
+            }
+        }
+        let outputs = self.outputs.iter().map(|(_, key)| key);
+        let feedback = self
+            .graph
+            .feedback_connections()
+            .iter()
+            .map(|con| &con.source.node_key);
+        for key in self
+            .sink_key
+            .iter()
+            .chain(outputs)
+            .chain(&self.pinned)
+            .chain(feedback)
+        {
+            if let Some(last) = last_use.get_mut(*key) {
+                *last = usize::MAX;
+            }
+        }
+
+        // The last use of each slot's current occupant, or `None` if it has never been handed
+        // out, for each rate in the order it is first seen
+        let mut pools: Vec<(Rate, Vec<Option<usize>>)> = vec![(Rate::GRAPH, Vec::new())];
+        let mut placed = Vec::with_capacity(order.len());
+
+        for key in order {
             let arity = self
                 .graph
                 .get_node(key)
@@ -112,112 +619,578 @@ impl Executor {
                 .audio_out
                 .len();
 
-            total_ports += arity;
+            let rate = self.rates[key];
+            let pool = match pools.iter().position(|(r, _)| *r == rate) {
+                Some(pool) => pool,
+                None => {
+                    pools.push((rate, Vec::new()));
+                    pools.len() - 1
+                }
+            };
+            let (start, reused) = first_fit(&mut pools[pool].1, arity, time[key], last_use[key]);
+            placed.push((key, pool, start, reused));
+        }
+
+        // The graph's rate first, then the delayed slots, then every other rate
+        let mut end = pools[0].1.len() * block_size;
+        let mut delayed = Vec::new();
+        for con in self.graph.feedback_connections() {
+            let len = self.rates[con.source.node_key].scale(block_size);
+            delayed.push((con, end, len));
+            end += len;
+        }
+        let mut starts = vec![0];
+        for (rate, slots) in &pools[1..] {
+            starts.push(end);
+            end += slots.len() * rate.scale(block_size);
         }
 
-        self.state = ExecutorState::Prepared;
+        self.node_offsets.clear();
+        self.plans.clear();
+
+        for (key, pool, start, reused) in placed {
+            let rate = pools[pool].0;
+            let len = rate.scale(block_size);
+            let start = starts[pool] + start * len;
+
+            self.node_offsets.insert(key, start);
+            self.plans.insert(
+                key,
+                NodePlan {
+                    start,
+                    reused,
+                    rate,
+                    len,
+                    ..Default::default()
+                },
+            );
+        }
+
+        self.delayed = delayed
+            .into_iter()
+            .map(|(con, slot, len)| Delayed {
+                source: self.node_offsets[con.source.node_key] + con.source.port_index * len,
+                slot,
+                len,
+            })
+            .collect();
+
+        end
     }
 
-    #[inline(always)]
-    pub fn process(&mut self, ctx: &mut AudioContext) -> OutputView<'_> {
-        assert!(self.state == ExecutorState::Prepared);
+    /// Work out where every input port reads from: its one source in place, or several sources
+    /// summed in scratch. Control-rate outputs read at audio rate are always summed, as they are
+    /// spread over the block on the way in.
+    ///
+    /// A node reads at its own rate, or a resampler at its sources'. Sources at any other rate
+    /// are left unread, see [`Self::validate_rates`].
+    fn plan_inputs(&mut self, block_size: usize) {
+        let keys: Vec<NodeKey> = self.plans.keys().collect();
 
-        let block_size = ctx.get_config().block_size;
+        // The control-rate output ports read at audio rate, each with a pair of history entries
+        let mut histories: Vec<(NodeKey, usize)> = Vec::new();
 
-        let (sorted_order, nodes, incoming) = self.graph.get_sort_order_nodes_and_runtime_info(); // TODO: I don't like this, feels like incorrect ownership
+        for key in keys.iter().copied() {
+            let incoming = self.graph.incoming_connections(key).into_iter().flatten();
+            let feedback = self.graph.feedback_connections().iter();
+            let first_source = incoming
+                .chain(feedback.filter(|con| con.sink.node_key == key))
+                .next()
+                .map(|con| con.source.node_key);
 
-        for node_key in sorted_order {
-            let ports = nodes[*node_key].get_node().ports();
+            let resamples = self.graph.get_node(key).unwrap().get_node().resamples();
+            self.plans[key].input_len = match (resamples, first_source) {
+                (true, Some(source)) => self.plans[source].len,
+                _ => self.plans[key].len,
+            };
+        }
 
-            let audio_inputs_size = ports.audio_in.len();
-            let audio_outputs_size = ports.audio_out.len();
+        for key in keys {
+            let input_len = self.plans[key].input_len;
 
-            // Fill the scratch buffer for the first N channels
-            self.scratch[..audio_inputs_size * block_size].fill(0.0);
+            for con in self.graph.incoming_connections(key).into_iter().flatten() {
+                let source = con.source.node_key;
+                let len = self.plans[source].len;
+                let offset = self.node_offsets[source] + con.source.port_index * len;
+                let port = con.sink.port_index;
 
-            let mut inputs: [Option<&[f32]>; MAX_ARITY] = [None; MAX_ARITY];
+                let read = if len == input_len {
+                    Read::Same
+                } else if self.control[source] && input_len == block_size {
+                    let output = (source, con.source.port_index);
+                    let index = histories
+                        .iter()
+                        .position(|h| *h == output)
+                        .unwrap_or_else(|| {
+                            histories.push(output);
+                            histories.len() - 1
+                        });
+                    let node = self.graph.get_node(source).unwrap().get_node();
+                    Read::Upsampled {
+                        history: 2 * index,
+                        interpolation: node.control_interpolation(),
+                        len,
+                    }
+                } else {
+                    continue;
+                };
 
-            let mut has_inputs: [bool; MAX_ARITY] = [false; MAX_ARITY];
+                let plan = &mut self.plans[key];
+                plan.inputs[port].add(offset);
+                if read != Read::Same {
+                    plan.inputs[port] = PortInput::Summed;
+                }
+                plan.summed.push(Source { port, offset, read });
+            }
+        }
 
-            let incoming = incoming
-                .get(*node_key)
-                .expect("Invalid connection in executor!");
+        let feedback = self.graph.feedback_connections().iter();
+        for (con, delayed) in feedback.zip(&self.delayed) {
+            let plan = &mut self.plans[con.sink.node_key];
+            if delayed.len != plan.input_len {
+                continue;
+            }
+            let port = con.sink.port_index;
+            plan.inputs[port].add(delayed.slot);
+            plan.summed.push(Source {
+                port,
+                offset: delayed.slot,
+                read: Read::Same,
+            });
+        }
 
-            for conn in incoming {
-                let base_offset = self
-                    .node_offsets
-                    .get(conn.source.node_key)
-                    .expect("Could not find offset for node!");
+        for (i, (key, port)) in histories.iter().enumerate() {
+            self.plans[*key].history.push((*port, 2 * i));
+        }
+        // Nothing came before the first block, which starts from its own first value instead
+        self.history = vec![f32::NAN; 2 * histories.len()].into();
 
-                let offset = (conn.source.port_index * block_size) + base_offset;
-                let end = offset + block_size;
+        for (_, plan) in self.plans.iter_mut() {
+            let inputs = plan.inputs;
+            plan.summed
+                .retain(|source| inputs[source.port] == PortInput::Summed);
+            plan.sums = !plan.summed.is_empty();
+        }
+    }
 
-                let buffer = &self.data[offset..end];
+    /// How much the data buffer shrank by reusing output slots.
+    pub fn buffer_stats(&self) -> BufferStats {
+        self.buffer_stats
+    }
 
-                has_inputs[conn.sink.port_index] = true;
+    /// Run the graph for one block, and return the sink's outputs.
+    #[inline(always)]
+    pub fn process(&mut self, ctx: &mut AudioContext) -> OutputView<'_> {
+        self.run(ctx);
+        self.output(ctx.get_config().block_size)
+    }
 
-                let scratch_start = conn.sink.port_index * block_size;
-                let scratch_end = scratch_start + block_size;
+    /// Run the graph for one block, leaving the sink's, named outputs' and pinned nodes' outputs
+    /// in place.
+    #[inline(always)]
+    pub fn run(&mut self, ctx: &mut AudioContext) {
+        assert!(self.state == ExecutorState::Prepared);
 
-                self.scratch[scratch_start..scratch_end]
-                    .iter_mut()
-                    .zip(buffer.iter())
-                    .for_each(|(dst, src)| *dst += src);
-            }
+        let started = self.profiling.then(Instant::now);
+        let timings = self.profiling.then_some(&self.node_timings);
+
+        let config = ctx.get_config();
+        let block_size = config.block_size;
+
+        let buffers = Buffers {
+            data: self.data.as_mut_ptr(),
+            scratch: self.scratch.as_mut_ptr(),
+            history: self.history.as_mut_ptr(),
+            scratch_len: self.scratch_len,
+        };
+
+        let (sorted_order, nodes, _) = self.graph.get_sort_order_nodes_and_runtime_info(); // TODO: I don't like this, feels like incorrect ownership
 
-            for i in 0..audio_inputs_size {
-                if has_inputs[i] {
-                    // Pass references to each slice to the inputs now
-                    let start = i * block_size;
-                    let end = start + block_size;
-                    inputs[i] = Some(&self.scratch[start..end]);
+        match &self.pool {
+            Some(pool) => {
+                for (key, node) in nodes.iter_mut() {
+                    if let Some(ptr) = self.node_ptrs.get_mut(key) {
+                        *ptr = NodePtr(node);
+                    }
                 }
-            }
 
-            let node = nodes
-                .get_mut(*node_key)
-                .expect("Could not find node at index {node_index:?}")
-                .get_node_mut();
+                let ctx = CtxPtr(ctx);
 
-            let node_start = *self.node_offsets.get(*node_key).unwrap();
+                for level in &self.levels {
+                    let level = &self.schedule[level.clone()];
+                    let parallel = level.iter().take_while(|n| !n.shares_context).count();
 
-            let mut active_outputs =
-                slice_node_ports_mut(&mut self.data, node_start, block_size, audio_outputs_size);
+                    let job = LevelJob {
+                        nodes: level,
+                        node_ptrs: &self.node_ptrs,
+                        plans: &self.plans,
+                        timings,
+                        buffers,
+                        ctx,
+                    };
 
-            node.process(
-                ctx,
-                &inputs[0..audio_inputs_size],
-                &mut active_outputs[0..audio_outputs_size],
-            );
+                    if parallel > 1 {
+                        pool.run(parallel, &job);
+                    } else {
+                        (0..parallel).for_each(|task| job.run(task, 0));
+                    }
+
+                    // Only the thread running the block is left, so nothing else sees the context
+                    (parallel..level.len()).for_each(|task| job.run(task, 0));
+                }
+            }
+            None => {
+                for node_key in sorted_order {
+                    let node = nodes
+                        .get_mut(*node_key)
+                        .expect("Could not find node at index {node_index:?}");
+
+                    let plan = &self.plans[*node_key];
+
+                    // SAFETY: Nodes run one at a time in topological order, so everything this
+                    // one reads has been written, and nothing else holds the buffers.
+                    timed(timings.map(|t| &t[*node_key]), || unsafe {
+                        process_node(ctx, node, buffers, plan, 0)
+                    });
+                }
+            }
+        }
+
+        // Everything has run, so feedback sources hold this block's output
+        for delayed in &self.delayed {
+            let source = delayed.source..delayed.source + delayed.len;
+            self.data.copy_within(source, delayed.slot);
         }
 
         ctx.set_instant();
 
-        let sink_key = self.sink_key.expect("Sink node must be provided");
+        if let Some(started) = started {
+            let elapsed = started.elapsed();
+            self.block_timing.record(elapsed);
 
-        let node_offset = self
-            .node_offsets
-            .get(sink_key)
-            .expect("Could not find sink");
+            let deadline = block_size as f64 / config.sample_rate as f64;
+            if elapsed.as_secs_f64() > deadline {
+                self.overruns += 1;
+            }
+        }
+    }
 
-        let node_arity = self
-            .graph
-            .get_node(sink_key)
+    /// The sink's outputs from the last block.
+    pub fn output(&self, block_size: usize) -> OutputView<'_> {
+        let sink_key = self.sink_key.expect("Sink node must be provided");
+
+        self.node_view(sink_key, block_size)
             .expect("Could not find sink")
-            .get_node()
-            .ports()
-            .audio_out
-            .len();
+    }
+
+    /// A named output's outputs from the last block.
+    pub fn named_output(&self, name: &str, block_size: usize) -> Option<OutputView<'_>> {
+        let (_, key) = self.outputs.iter().find(|(n, _)| n == name)?;
+
+        self.node_view(*key, block_size)
+    }
+
+    fn node_view(&self, key: NodeKey, block_size: usize) -> Option<OutputView<'_>> {
+        let node_offset = self.node_offsets.get(key)?;
+
+        let node_arity = self.graph.get_node(key)?.get_node().ports().audio_out.len();
 
         let final_outputs = slice_node_ports(&self.data, *node_offset, block_size, node_arity);
 
-        OutputView {
+        Some(OutputView {
             channels: final_outputs,
             chans: node_arity,
+        })
+    }
+}
+
+/// Raw views of the executor's buffers, so that nodes on different threads can each write their
+/// own outputs while reading what earlier levels wrote.
+#[derive(Clone, Copy)]
+struct Buffers {
+    data: *mut f32,
+    scratch: *mut f32,
+    history: *mut f32,
+    // Each worker's share of scratch
+    scratch_len: usize,
+}
+
+unsafe impl Send for Buffers {}
+unsafe impl Sync for Buffers {}
+
+/// Where a node reads its inputs from and writes its outputs to, worked out in `prepare`.
+#[derive(Clone, Debug, Default)]
+struct NodePlan {
+    start: usize,
+    // Another node used the output slots earlier in the block
+    reused: bool,
+    // The rate the node runs at, and the length of its outputs and inputs at that rate
+    rate: Rate,
+    len: usize,
+    input_len: usize,
+    inputs: [PortInput; MAX_ARITY],
+    // Everything summed into scratch, for the inputs that are `PortInput::Summed`
+    summed: Vec<Source>,
+    // Output ports read at audio rate, and the history entries that keep their last values
+    history: Vec<(usize, usize)>,
+    // Whether any input needs summing in scratch
+    sums: bool,
+}
+
+/// One source of an input port that is summed in scratch.
+#[derive(Clone, Copy, Debug)]
+struct Source {
+    port: usize,
+    offset: usize,
+    read: Read,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Read {
+    /// At the reading node's own rate.
+    Same,
+    /// A control-rate output `len` samples long, spread over an audio-rate block. The output's
+    /// last value from the block before is kept at `history`.
+    Upsampled {
+        history: usize,
+        interpolation: Interpolation,
+        len: usize,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum PortInput {
+    #[default]
+    Unconnected,
+    /// Exactly one source, read straight out of the data buffer at this offset.
+    Direct(usize),
+    /// Several sources, summed into scratch.
+    Summed,
+}
+
+impl PortInput {
+    /// Another source, at `offset` in the data buffer.
+    fn add(&mut self, offset: usize) {
+        *self = match *self {
+            PortInput::Unconnected => PortInput::Direct(offset),
+            _ => PortInput::Summed,
+        };
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct NodePtr(*mut LegatoNode);
+
+unsafe impl Send for NodePtr {}
+unsafe impl Sync for NodePtr {}
+
+#[derive(Clone, Copy)]
+struct CtxPtr(*mut AudioContext);
+
+unsafe impl Send for CtxPtr {}
+unsafe impl Sync for CtxPtr {}
+
+/// One level of the schedule, where task `n` runs the level's `n`th node.
+struct LevelJob<'a> {
+    nodes: &'a [Scheduled],
+    node_ptrs: &'a SecondaryMap<NodeKey, NodePtr>,
+    plans: &'a SecondaryMap<NodeKey, NodePlan>,
+    timings: Option<&'a SecondaryMap<NodeKey, Timing>>,
+    buffers: Buffers,
+    ctx: CtxPtr,
+}
+
+impl Job for LevelJob<'_> {
+    fn run(&self, task: usize, worker: usize) {
+        let key = self.nodes[task].key;
+
+        let plan = &self.plans[key];
+
+        // SAFETY: Each task is a different node, writing only its own outputs and this worker's
+        // scratch, and reading outputs of earlier levels, whose slots are not handed out again
+        // until their last reader is done. Only nodes that opted out of `shares_context`, and so
+        // never write through the context, run alongside another node.
+        let node = self.node_ptrs[key].0;
+        timed(self.timings.map(|t| &t[key]), || unsafe {
+            process_node(&mut *self.ctx.0, &mut *node, self.buffers, plan, worker);
+        });
+    }
+}
+
+#[inline(always)]
+fn timed(timing: Option<&Timing>, run: impl FnOnce()) {
+    match timing {
+        Some(timing) => {
+            let started = Instant::now();
+            run();
+            timing.record(started.elapsed());
         }
+        None => run(),
     }
 }
 
+/// Sum a node's incoming connections into `worker`'s scratch, then run it into its slot of `data`,
+/// at its own rate.
+///
+/// # Safety
+///
+/// Every node this one reads from must be done for the block, and nothing else may touch this
+/// node's outputs or `worker`'s scratch while it runs.
+#[inline(always)]
+unsafe fn process_node(
+    ctx: &mut AudioContext,
+    node: &mut LegatoNode,
+    buffers: Buffers,
+    plan: &NodePlan,
+    worker: usize,
+) {
+    let (len, input_len) = (plan.len, plan.input_len);
+
+    let (node, bypass) = node.split_mut();
+    let ports = node.ports();
+
+    let audio_inputs_size = ports.audio_in.len();
+    let audio_outputs_size = ports.audio_out.len();
+
+    let scratch_len = buffers.scratch_len;
+    let scratch = unsafe {
+        std::slice::from_raw_parts_mut(buffers.scratch.add(worker * scratch_len), scratch_len)
+    };
+
+    if plan.sums {
+        for (i, input) in plan.inputs[..audio_inputs_size].iter().enumerate() {
+            if *input == PortInput::Summed {
+                scratch[i * input_len..(i + 1) * input_len].fill(0.0);
+            }
+        }
+
+        for source in &plan.summed {
+            let dst = &mut scratch[source.port * input_len..(source.port + 1) * input_len];
+
+            match source.read {
+                Read::Same => {
+                    let buffer = unsafe {
+                        std::slice::from_raw_parts(buffers.data.add(source.offset), input_len)
+                    };
+
+                    dst.iter_mut()
+                        .zip(buffer.iter())
+                        .for_each(|(dst, src)| *dst += src);
+                }
+                Read::Upsampled {
+                    history,
+                    interpolation,
+                    len,
+                } => {
+                    let buffer =
+                        unsafe { std::slice::from_raw_parts(buffers.data.add(source.offset), len) };
+                    let previous = unsafe { *buffers.history.add(history) };
+
+                    upsample(buffer, previous, interpolation, dst);
+                }
+            }
+        }
+    }
+
+    let mut inputs: [Option<&[f32]>; MAX_ARITY] = [None; MAX_ARITY];
+
+    for (i, input) in plan.inputs[..audio_inputs_size].iter().enumerate() {
+        inputs[i] = match *input {
+            PortInput::Unconnected => None,
+            // A lone source is passed through as is, skipping the copy into scratch
+            PortInput::Direct(offset) => {
+                Some(unsafe { std::slice::from_raw_parts(buffers.data.add(offset), input_len) })
+            }
+            PortInput::Summed => Some(&scratch[i * input_len..(i + 1) * input_len]),
+        };
+    }
+
+    let outputs = unsafe {
+        std::slice::from_raw_parts_mut(buffers.data.add(plan.start), audio_outputs_size * len)
+    };
+
+    // Nodes may leave an output untouched, e.g. when nothing is connected to the matching
+    // input, which should read as silence rather than whatever last used the slot
+    if plan.reused {
+        outputs.fill(0.0);
+    }
+
+    let mut active_outputs = slice_node_ports_mut(outputs, 0, len, audio_outputs_size);
+
+    let inputs = &inputs[0..audio_inputs_size];
+    let outputs = &mut active_outputs[0..audio_outputs_size];
+
+    // A node that has faded out entirely is not run at all until it is brought back
+    let ran = bypass.runs_node();
+    if ran {
+        at_rate(plan.rate, || node.process(ctx, inputs, outputs));
+    }
+    if !bypass.is_idle() {
+        // A resampler's inputs are at another rate, so there is nothing to pass through
+        let dry = if input_len == len { inputs } else { &[] };
+        bypass.apply(dry, outputs, ran);
+    }
+
+    // Keep the last value of outputs read at audio rate, to ramp from in the next block
+    for &(port, history) in &plan.history {
+        unsafe {
+            *buffers.history.add(history) = *buffers.history.add(history + 1);
+            *buffers.history.add(history + 1) = outputs[port][len - 1];
+        }
+    }
+}
+
+/// Spread a control-rate block over `out`, adding to what is there. A linear ramp starts from
+/// `previous`, the last value of the block before, or the block's first value if that is NaN.
+#[inline(always)]
+fn upsample(control: &[f32], previous: f32, interpolation: Interpolation, out: &mut [f32]) {
+    let factor = out.len() / control.len();
+    let step = 1.0 / factor as f32;
+
+    let mut from = if previous.is_nan() {
+        control[0]
+    } else {
+        previous
+    };
+    for (&to, chunk) in control.iter().zip(out.chunks_exact_mut(factor)) {
+        match interpolation {
+            Interpolation::Linear => {
+                for (j, y) in chunk.iter_mut().enumerate() {
+                    *y += from + (to - from) * (j + 1) as f32 * step;
+                }
+            }
+            Interpolation::Step => chunk.iter_mut().for_each(|y| *y += to),
+        }
+        from = to;
+    }
+}
+
+/// The lowest run of `arity` slots that are free at `now`, taken until `until`, growing `slots`
+/// if need be. Also returns whether any of them were taken before.
+fn first_fit(
+    slots: &mut Vec<Option<usize>>,
+    arity: usize,
+    now: usize,
+    until: usize,
+) -> (usize, bool) {
+    let is_free = |slot: &Option<usize>| slot.is_none_or(|until| until < now);
+
+    let mut start = 0;
+    while !slots.iter().skip(start).take(arity).all(is_free) {
+        start += 1;
+    }
+
+    let end = start + arity;
+    if end > slots.len() {
+        slots.resize(end, None);
+    }
+
+    let reused = slots[start..end].iter().any(Option::is_some);
+    slots[start..end].fill(Some(until));
+
+    (start, reused)
+}
+
 #[inline(always)]
 fn slice_node_ports(
     buffer: &[f32],
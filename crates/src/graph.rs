@@ -4,7 +4,7 @@ use std::{collections::VecDeque, fmt::Debug};
 
 use crate::{node::LegatoNode, runtime::NodeKey};
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum GraphError {
     BadConnection,
     CycleDetected,
@@ -27,11 +27,15 @@ pub type EdgeMap = SecondaryMap<NodeKey, IndexSet<Connection>>;
 
 const INITIAL_INPUTS: usize = 8;
 /// A DAG for grabbing nodes and their dependencies via topological sort.
+///
+/// Feedback edges are kept apart from the rest, and read the previous block of their
+/// source, so they may close a cycle without the sort ever seeing them.
 #[derive(Clone, Default)]
 pub struct AudioGraph {
     nodes: SlotMap<NodeKey, LegatoNode>,
     incoming_edges: EdgeMap,
     outgoing_edges: EdgeMap,
+    feedback_edges: IndexSet<Connection>,
     // Pre-allocated work buffers for topo sort
     indegree: SecondaryMap<NodeKey, usize>,
     no_incoming_edges_queue: VecDeque<NodeKey>,
@@ -48,6 +52,7 @@ impl AudioGraph {
             indegree: SecondaryMap::with_capacity(capacity),
             no_incoming_edges_queue: VecDeque::with_capacity(capacity),
             topo_sorted: Vec::with_capacity(capacity),
+            feedback_edges: IndexSet::new(),
         }
     }
 
@@ -102,6 +107,69 @@ impl AudioGraph {
         self.nodes.values().collect()
     }
 
+    pub fn keys(&self) -> impl Iterator<Item = NodeKey> + '_ {
+        self.nodes.keys()
+    }
+
+    /// The graph as Graphviz DOT, e.g. for `dot -Tsvg`.
+    ///
+    /// Nodes are labelled with their name, kind, rate where it is not the graph's, and port
+    /// names, and edges with the port index at each end. Feedback edges are dashed.
+    pub fn to_dot(&self) -> String {
+        use std::fmt::Write;
+
+        let ids: SecondaryMap<NodeKey, usize> = self
+            .nodes
+            .keys()
+            .enumerate()
+            .map(|(i, key)| (key, i))
+            .collect();
+        let names = |ports: &[crate::ports::PortMeta]| {
+            ports.iter().map(|p| p.name).collect::<Vec<_>>().join(", ")
+        };
+
+        let mut out = String::from("digraph audio {\n    rankdir=LR;\n    node [shape=box];\n");
+        for (key, node) in &self.nodes {
+            let ports = node.get_node().ports();
+            let mut label = format!("{}\n{}", node.name, node.node_kind);
+            if node.rate() != crate::context::Rate::GRAPH {
+                write!(label, " @ {}", node.rate()).unwrap();
+            }
+            if !ports.audio_in.is_empty() {
+                write!(label, "\nin: {}", names(&ports.audio_in)).unwrap();
+            }
+            if !ports.audio_out.is_empty() {
+                write!(label, "\nout: {}", names(&ports.audio_out)).unwrap();
+            }
+            writeln!(out, "    n{} [label={label:?}];", ids[key]).unwrap();
+        }
+
+        let edges = self
+            .outgoing_edges
+            .values()
+            .flatten()
+            .map(|con| (con, ""))
+            .chain(
+                self.feedback_edges
+                    .iter()
+                    .map(|con| (con, ", style=dashed")),
+            );
+        for (con, style) in edges {
+            writeln!(
+                out,
+                "    n{} -> n{} [label=\"{} → {}\"{style}];",
+                ids[con.source.node_key],
+                ids[con.sink.node_key],
+                con.source.port_index,
+                con.sink.port_index
+            )
+            .unwrap();
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
     pub fn get_sort_order_nodes_and_runtime_info(
         &mut self,
     ) -> (&Vec<NodeKey>, &mut SlotMap<NodeKey, LegatoNode>, &EdgeMap) {
@@ -132,6 +200,9 @@ impl AudioGraph {
             }
         }
 
+        self.feedback_edges
+            .retain(|con| con.source.node_key != key && con.sink.node_key != key);
+
         self.indegree.remove(key);
         let node = self.nodes.remove(key);
 
@@ -163,6 +234,29 @@ impl AudioGraph {
         Ok(connection)
     }
 
+    /// Add an edge whose sink reads what its source wrote a block earlier. These may close
+    /// a cycle, since the topological sort ignores them.
+    pub fn add_feedback_edge(&mut self, connection: Connection) -> Result<Connection, GraphError> {
+        if !self.nodes.contains_key(connection.source.node_key)
+            || !self.nodes.contains_key(connection.sink.node_key)
+        {
+            return Err(GraphError::BadConnection);
+        }
+        self.feedback_edges.insert(connection);
+        Ok(connection)
+    }
+
+    pub fn remove_feedback_edge(&mut self, connection: Connection) -> Result<(), GraphError> {
+        match self.feedback_edges.shift_remove(&connection) {
+            true => Ok(()),
+            false => Err(GraphError::BadConnection),
+        }
+    }
+
+    pub fn feedback_connections(&self) -> &IndexSet<Connection> {
+        &self.feedback_edges
+    }
+
     pub fn replace(&mut self, key: NodeKey, node: LegatoNode) {
         if let Some(item) = self.nodes.get_mut(key) {
             *item = node;
@@ -287,6 +381,8 @@ impl Debug for AudioGraph {
             })
             .collect();
 
+        let feedback: Vec<String> = self.feedback_edges.iter().map(fmt_edge).collect();
+
         let topo_names: Vec<&str> = self.topo_sorted.iter().map(|&k| node_name(k)).collect();
 
         let node_names: Vec<&str> = self.nodes.values().map(|n| n.name.as_str()).collect();
@@ -294,6 +390,7 @@ impl Debug for AudioGraph {
         f.debug_struct("AudioGraph")
             .field("nodes", &node_names)
             .field("edges", &edges)
+            .field("feedback", &feedback)
             .field("topo_order", &topo_names)
             .finish()
     }
@@ -730,4 +827,87 @@ mod test {
 
         assert_eq!(res.unwrap_err(), GraphError::BadConnection);
     }
+
+    #[test]
+    fn feedback_edges_close_cycles() {
+        let mut graph = AudioGraph::with_capacity(2);
+
+        let a = graph.add_node(LegatoNode::new(
+            "a".into(),
+            "MonoExample".into(),
+            Box::new(MonoExample::default()),
+        ));
+        let b = graph.add_node(LegatoNode::new(
+            "b".into(),
+            "MonoExample".into(),
+            Box::new(MonoExample::default()),
+        ));
+
+        let forward = Connection {
+            source: ConnectionEntry {
+                node_key: a,
+                port_index: 0,
+            },
+            sink: ConnectionEntry {
+                node_key: b,
+                port_index: 0,
+            },
+        };
+        let back = Connection {
+            source: forward.sink,
+            sink: forward.source,
+        };
+        graph.add_edge(forward).unwrap();
+        graph.add_feedback_edge(back).unwrap();
+
+        assert_eq!(graph.invalidate_topo_sort(), Ok(vec![a, b]));
+        assert!(graph.incoming_connections(a).unwrap().is_empty());
+
+        graph.remove_node(b);
+        assert!(graph.feedback_connections().is_empty());
+    }
+
+    #[test]
+    fn dot_labels_ports_and_dashes_feedback() {
+        let mut graph = AudioGraph::with_capacity(2);
+        let node = |name: &str| {
+            LegatoNode::new(
+                name.into(),
+                "MonoExample".into(),
+                Box::new(MonoExample::default()),
+            )
+        };
+        let a = graph.add_node(node("a"));
+        let b = graph.add_node(node("b"));
+        let forward = Connection {
+            source: ConnectionEntry {
+                node_key: a,
+                port_index: 0,
+            },
+            sink: ConnectionEntry {
+                node_key: b,
+                port_index: 0,
+            },
+        };
+        graph.add_edge(forward).unwrap();
+        graph
+            .add_feedback_edge(Connection {
+                source: forward.sink,
+                sink: forward.source,
+            })
+            .unwrap();
+
+        assert_eq!(
+            graph.to_dot(),
+            r#"digraph audio {
+    rankdir=LR;
+    node [shape=box];
+    n0 [label="a\nMonoExample\nin: in\nout: out"];
+    n1 [label="b\nMonoExample\nin: in\nout: out"];
+    n0 -> n1 [label="0 → 0"];
+    n1 -> n0 [label="0 → 0", style=dashed];
+}
+"#
+        );
+    }
 }
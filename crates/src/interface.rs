@@ -184,7 +184,7 @@ fn write_block<T>(
     output: &mut [T],
     config: &StreamConfig,
     app: &mut LegatoApp,
-    visualization_producer: Option<&mut rtrb::Producer<f32>>, // TODO: Something more eloquent for per node outputs?
+    visualization_producer: Option<&mut rtrb::Producer<f32>>, // For other nodes' outputs, see `LegatoFrontend::tap`
 ) where
     T: SizedSample + FromSample<f64>,
 {
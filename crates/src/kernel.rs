@@ -7,6 +7,7 @@ use crate::{
     nodes::{
         audio::{
             allpass::Allpass,
+            constant::Constant,
             hadamard::HadamardMixer,
             householder::HouseholderMixer,
             noise::Noise,
@@ -24,7 +25,7 @@ use crate::{
     ports::Ports,
     resources::ResourceBuilder,
 };
-use std::collections::HashMap;
+use std::{any::Any, collections::HashMap};
 
 /// A finite enum of per-sample nodes.
 ///
@@ -47,6 +48,7 @@ pub enum KernelNode {
     Householder(HouseholderMixer),
     Hadamard(HadamardMixer),
     Pan(Pan),
+    Constant(Constant),
 }
 
 /// This macro lets us quickly write rules for all kernels
@@ -65,6 +67,7 @@ macro_rules! dispatch {
             KernelNode::Householder($inner) => $body,
             KernelNode::Hadamard($inner) => $body,
             KernelNode::Pan($inner) => $body,
+            KernelNode::Constant($inner) => $body,
         }
     };
 }
@@ -81,6 +84,15 @@ impl PerSampleNode for KernelNode {
     fn handle_msg(&mut self, msg: NodeMessage) {
         dispatch!(self, inner => PerSampleNode::handle_msg(inner, msg))
     }
+
+    /// `previous` is the [`KernelNode`] itself, so unwrap it before handing
+    /// the inner node on; a variant mismatch carries nothing.
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<KernelNode>() {
+            let prev: &dyn Any = dispatch!(prev, inner => inner);
+            dispatch!(self, inner => PerSampleNode::carry_state(inner, prev))
+        }
+    }
 }
 
 /// Build a node from kernels that process one sample at a time.
@@ -96,11 +108,11 @@ pub fn build_kernel_node(
     seed: u32,
 ) -> Result<KernelNode, ValidationError> {
     let op = |kind: ApplyOpKind, default: f32, chans: usize, p: &DSLParams| {
-        mult_node_factory(
-            p.get_f32("val").unwrap_or(default),
-            p.get_usize("chans").unwrap_or(chans),
+        Ok::<_, ValidationError>(mult_node_factory(
+            p.get_f32("val")?.unwrap_or(default),
+            p.get_usize("chans")?.unwrap_or(chans),
             kind,
-        )
+        ))
     };
 
     Ok(match node_type {
@@ -114,16 +126,17 @@ pub fn build_kernel_node(
         // An explicit `seed:` pins the stream; otherwise it comes from the
         // node's plan identity, so it is stable across builds but still
         // distinct per node and per kernel instantiation.
-        "noise" => KernelNode::Noise(Noise::with_seed(p.get_u32("seed").unwrap_or(seed))),
+        "noise" => KernelNode::Noise(Noise::with_seed(p.get_u32("seed")?.unwrap_or(seed))),
         "householder" => KernelNode::Householder(HouseholderMixer::from_params(rb, p)?),
         "hadamard" => KernelNode::Hadamard(HadamardMixer::from_params(rb, p)?),
         "pan" => KernelNode::Pan(Pan::from_params(rb, p)?),
+        "const" => KernelNode::Constant(Constant::from_params(p)?),
         // These match block rate defaults, perhaps we make a single source of truth in the future?
-        "mult" => KernelNode::Op(op(ApplyOpKind::Mult, 1.0, 1, p)),
-        "add" => KernelNode::Op(op(ApplyOpKind::Add, 0.0, 1, p)),
-        "sub" => KernelNode::Op(op(ApplyOpKind::Subtract, 0.0, 1, p)),
-        "div" => KernelNode::Op(op(ApplyOpKind::Div, 0.0, 1, p)),
-        "gain" => KernelNode::Op(op(ApplyOpKind::Gain, 1.0, 2, p)),
+        "mult" => KernelNode::Op(op(ApplyOpKind::Mult, 1.0, 1, p)?),
+        "add" => KernelNode::Op(op(ApplyOpKind::Add, 0.0, 1, p)?),
+        "sub" => KernelNode::Op(op(ApplyOpKind::Subtract, 0.0, 1, p)?),
+        "div" => KernelNode::Op(op(ApplyOpKind::Div, 0.0, 1, p)?),
+        "gain" => KernelNode::Op(op(ApplyOpKind::Gain, 1.0, 2, p)?),
         other => {
             return Err(ValidationError::NotKernelCapable(format!(
                 "node type '{other}' has no per-sample implementation"
@@ -239,6 +252,33 @@ impl PerSampleNode for KernelGraph {
             *out = self.values[slot as usize];
         }
     }
+
+    /// Interior nodes have no aliases of their own, so state only carries
+    /// when the interior has the same shape as before, matched by position.
+    fn carry_state(&mut self, previous: &dyn Any) {
+        let Some(prev) = previous.downcast_ref::<Self>() else {
+            return;
+        };
+
+        let same_shape = self.nodes.len() == prev.nodes.len()
+            && self.values.len() == prev.values.len()
+            && self
+                .nodes
+                .iter()
+                .zip(prev.nodes.iter())
+                .all(|(a, b)| std::mem::discriminant(a) == std::mem::discriminant(b));
+
+        if !same_shape {
+            return;
+        }
+
+        // The value table holds last sample's outputs, i.e. the z⁻¹ on feedback edges.
+        self.values.copy_from_slice(&prev.values);
+
+        for (node, prev_node) in self.nodes.iter_mut().zip(prev.nodes.iter()) {
+            node.carry_state(prev_node);
+        }
+    }
 }
 
 /// A [`PortOracle`] that answers by constructing the node and reading its
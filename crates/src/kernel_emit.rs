@@ -59,6 +59,7 @@ fn rust_type_for(node_type: &str) -> Option<(&'static str, &'static str)> {
         "householder" => ("Householder", "nodes::audio::householder::HouseholderMixer"),
         "hadamard" => ("Hadamard", "nodes::audio::hadamard::HadamardMixer"),
         "pan" => ("Pan", "nodes::audio::pan::Pan"),
+        "const" => ("Constant", "nodes::audio::constant::Constant"),
         // Every arithmetic node is one `ApplyOp` behind the scenes.
         "mult" | "add" | "sub" | "div" | "gain" => ("Op", "nodes::audio::ops::ApplyOp"),
         _ => return None,
@@ -138,12 +139,15 @@ fn value_literal(value: &crate::dsl::ir::Value, krate: &str) -> String {
             out.push_str("o }");
             format!("{krate}::dsl::ir::Value::Object({out})")
         }
-        // Templates are substituted during plan resolution, so one surviving
+        // Templates and expressions are resolved during plan resolution, so one surviving
         // here means resolution was skipped — emit something that fails loudly
         // rather than silently baking in a placeholder.
         Value::Template(t) => {
             format!("compile_error!(\"unsubstituted template ${t} reached codegen\")")
         }
+        Value::Expr(_) => {
+            "compile_error!(\"unevaluated param expression reached codegen\")".to_string()
+        }
     }
 }
 
@@ -238,7 +242,7 @@ pub fn generate_node(
     use crate::{
         builder::ValidationError,
         config::{BlockSize, Config},
-        dsl::{ir::Object, lower::ast_to_graph, parse::legato_parser},
+        dsl::{generate::specialize, ir::Object, lower::ast_to_graph, parse::legato_parser},
         kernel::ProbeOracle,
         kernel_plan::resolve_plan,
     };
@@ -248,13 +252,22 @@ pub fn generate_node(
     let program = format!("{source}\n audio {{ sine }} {{ sine }}");
     let ast = legato_parser(&program).map_err(|e| ValidationError::ParseError(format!("{e:?}")))?;
 
-    let definition = ast_to_graph(ast)?
-        .macro_registry
-        .get(kernel_name)
-        .ok_or_else(|| {
-            ValidationError::NodeNotFound(format!("no kernel named '{kernel_name}' in this file"))
-        })?
-        .clone();
+    // A kernel with blocks is filled in for its default params
+    let mut graph = ast_to_graph(ast)?;
+    let name = match graph.templates.contains_key(kernel_name) {
+        true => specialize(
+            &mut graph.macro_registry,
+            &graph.templates,
+            kernel_name,
+            &Object::new(),
+            0,
+        )?,
+        false => kernel_name.to_string(),
+    };
+    let mut definition = graph.macro_registry.remove(&name).ok_or_else(|| {
+        ValidationError::NodeNotFound(format!("no kernel named '{kernel_name}' in this file"))
+    })?;
+    definition.name = kernel_name.to_string();
 
     // Sample rate is a runtime property — the generated `new()` takes it from
     // the resource builder — so any rate works for resolving topology. Port
@@ -388,7 +401,7 @@ fn emit_node_definition(out: &mut String, plan: &KernelPlan, struct_name: &str,
     );
     let _ = writeln!(
         out,
-        "        let mut node = Self::new(rb)?;\n                 node.apply_params(params);\n                 Ok(Box::new({krate}::persample::PerSample::new(node)))"
+        "        let mut node = Self::new(rb)?;\n                 node.apply_params(params)?;\n                 Ok(Box::new({krate}::persample::PerSample::new(node)))"
     );
     let _ = writeln!(out, "    }}");
     let _ = writeln!(out, "}}");
@@ -605,7 +618,7 @@ fn emit_setters(out: &mut String, plan: &KernelPlan, krate: &str) {
     );
     let _ = writeln!(
         out,
-        "    pub fn apply_params(&mut self, params: &{krate}::dsl::ir::DSLParams) {{"
+        "    pub fn apply_params(\n                 &mut self,\n                 params: &{krate}::dsl::ir::DSLParams,\n             ) -> Result<(), {krate}::builder::ValidationError> {{"
     );
     if plan.params.is_empty() {
         // Emitted even with nothing to apply so `create` can call it
@@ -615,11 +628,12 @@ fn emit_setters(out: &mut String, plan: &KernelPlan, krate: &str) {
     for param in &plan.params {
         let _ = writeln!(
             out,
-            "        if let Some(value) = params.get_f32({:?}) {{\n                         self.set_{}(value);\n        }}",
+            "        if let Some(value) = params.get_f32({:?})? {{\n                         self.set_{}(value);\n        }}",
             param.name,
             sanitize(&param.name)
         );
     }
+    let _ = writeln!(out, "        Ok(())");
     let _ = writeln!(out, "    }}");
 }
 
@@ -749,6 +763,7 @@ mod tests {
             "householder",
             "hadamard",
             "pan",
+            "const",
             "mult",
             "add",
             "sub",
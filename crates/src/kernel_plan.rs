@@ -41,6 +41,7 @@ use crate::{
     builder::ValidationError,
     dsl::{
         expand::substitute_templates,
+        generate::unfilled,
         ir::{DSLParams, IRMacro, IRNodeKind, NodeId, NodeSelector, Object, Port, Value},
     },
     persample::MAX_FRAME_PORTS,
@@ -293,6 +294,7 @@ fn resolve_port(
         Port::Slice(..) | Port::Stride { .. } => Err(unsupported(format!(
             "port slices/strides on '{node_alias}'"
         ))),
+        Port::Template(text) => Err(unfilled(text)),
     }
 }
 
@@ -395,6 +397,7 @@ pub fn resolve_plan(
     for (k, v) in instance_params {
         resolved_params.insert(k.clone(), v.clone());
     }
+    substitute_templates(&mut resolved_params, &Object::new())?;
 
     // Pass 1: walk the body in declaration order, resolving each node's params
     // and asking the oracle for its port shape. Everything indexed by
@@ -422,7 +425,8 @@ pub fn resolve_plan(
 
         // Record where each `$template` lands *before* substitution erases it.
         // This provenance is what lets codegen emit a setter that reaches the
-        // right interior node param.
+        // right interior node param. Expressions such as `$rate * 2.0` are
+        // evaluated once below, so they stay at their build-time value.
         for (node_param, value) in &params {
             let Value::Template(template) = value else {
                 continue;
@@ -447,7 +451,7 @@ pub fn resolve_plan(
             ));
         }
 
-        substitute_templates(&mut params, &resolved_params);
+        substitute_templates(&mut params, &resolved_params)?;
 
         let ports = oracle.ports_for(&ir_node.node_type, &DSLParams::new(&params))?;
 
@@ -564,20 +568,45 @@ pub fn resolve_plan(
         }
     }
 
-    // Exterior signature. Outputs are the sink node's output ports.
+    // Exterior signature. Outputs are the sink node's output ports, then one per `out`.
     let sink = decl_idx_of[&ir_macro.sink];
     let sink_out = &node_ports[sink.0].audio_out;
 
+    let mut output_slots: Vec<ValueSlot> =
+        (0..sink_out.len()).map(|p| value_slot(sink, p)).collect();
+    let mut output_names: Vec<String> = sink_out.iter().map(|p| p.name.to_string()).collect();
+
+    for (name, (node_id, selector, port)) in &ir_macro.virtual_output_map {
+        if *selector != NodeSelector::Single {
+            return Err(unsupported("node selectors inside kernels".to_string()));
+        }
+        if output_names.contains(name) {
+            return Err(ValidationError::DuplicateOutput(format!(
+                "kernel '{}' declares `out {name}`, but its sink already has an output '{name}'",
+                ir_macro.name
+            )));
+        }
+
+        let decl = decl_idx_of[node_id];
+        let alias = &plan_nodes[decl.0].alias;
+        let [port] = resolve_port(port, &node_ports[decl.0].audio_out, alias)?[..] else {
+            return Err(unsupported(format!(
+                "kernel '{}' feeds `out {name}` from several ports of '{alias}'; pick one",
+                ir_macro.name
+            )));
+        };
+        output_slots.push(value_slot(decl, port));
+        output_names.push(name.clone());
+    }
+
     let n_exterior_in = ir_macro.virtual_input_map.len();
-    if n_exterior_in > MAX_FRAME_PORTS || sink_out.len() > MAX_FRAME_PORTS {
+    if n_exterior_in > MAX_FRAME_PORTS || output_slots.len() > MAX_FRAME_PORTS {
         return Err(unsupported(format!(
             "kernel '{}' exceeds {MAX_FRAME_PORTS} exterior ports",
             ir_macro.name
         )));
     }
 
-    let output_slots: Vec<ValueSlot> = (0..sink_out.len()).map(|p| value_slot(sink, p)).collect();
-    let output_names: Vec<String> = sink_out.iter().map(|p| p.name.to_string()).collect();
     let input_names: Vec<String> = virtual_input_names(&ir_macro.virtual_input_map);
 
     // Finally permute the node list into execution order. Slot bases stay put,
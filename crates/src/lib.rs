@@ -1,17 +1,33 @@
 #![feature(portable_simd)]
 
-use std::{collections::HashMap, fmt::Debug, path::Path};
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 use crate::{
-    builder::ValidationError,
+    builder::{ResourceBuilderView, ValidationError},
     config::Config,
-    executor::OutputView,
+    dsl::ir::DSLParams,
+    edit::{Edit, GraphMirror},
+    executor::{BufferStats, OutputView},
+    graph::{Connection, ConnectionEntry, GraphError},
     msg::{LegatoMsg, NodeMessage},
+    node::LegatoNode,
+    profile::{Profile, ProfileReader},
+    reload::{DEFAULT_RELOAD_CROSSFADE_BLOCKS, PreparedReload, ReloadContext},
     resources::{
+        ResourceBuilder,
         buffer::AudioSampleError,
         params::{ParamError, ParamKey},
     },
-    runtime::{NodeKey, Runtime, RuntimeFrontend},
+    runtime::{NodeKey, RetiredGraph, Runtime, RuntimeFrontend},
+    tap::{Tap, TapSender},
 };
 
 pub mod builder;
@@ -19,6 +35,7 @@ pub mod config;
 pub mod connection;
 pub mod context;
 pub mod dsl;
+pub mod edit;
 pub mod executor;
 pub mod graph;
 pub mod harness;
@@ -34,17 +51,24 @@ pub mod msg;
 pub mod node;
 pub mod out;
 pub mod persample;
+pub mod pipes;
+pub mod pool;
 pub mod ports;
+pub mod profile;
 pub mod registry;
+pub mod reload;
 pub mod resources;
 pub mod ring;
 pub mod runtime;
 pub mod simd;
 pub mod spec;
+pub mod tap;
 pub mod window;
 
 #[cfg(feature = "docs")]
 pub mod docs;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod nodes;
 
 #[derive(Debug, PartialEq, Clone)]
@@ -94,6 +118,29 @@ impl LegatoApp {
     pub fn node_kinds(&self) -> Vec<&str> {
         self.runtime.node_kinds()
     }
+
+    /// The built graph as Graphviz DOT, see [`graph::AudioGraph::to_dot`].
+    pub fn to_dot(&self) -> String {
+        self.runtime.get_executor().graph.to_dot()
+    }
+
+    /// One of the graph's named outputs from the last [`Self::next_block`], e.g. a cue bus
+    /// declared as `{ mix, cue: cue_bus }`. The sink is `main`, the same as `next_block` returns.
+    ///
+    /// During a hot reload crossfade, `main` here is the incoming graph's alone.
+    pub fn output(&self, name: &str) -> Option<OutputView<'_>> {
+        self.runtime.output(name)
+    }
+
+    /// The name of every output, starting with `main`.
+    pub fn output_names(&self) -> Vec<&str> {
+        self.runtime.output_names()
+    }
+
+    /// How much memory the executor saves by reusing output buffers once they have been read.
+    pub fn buffer_stats(&self) -> BufferStats {
+        self.runtime.buffer_stats()
+    }
 }
 
 impl Debug for LegatoApp {
@@ -104,14 +151,37 @@ impl Debug for LegatoApp {
     }
 }
 
+#[derive(Debug, PartialEq, Clone)]
 pub enum FrontendError {
     NodeNotFound(),
+    /// The new graph failed to build, so the running one was left alone.
+    InvalidGraph(ValidationError),
+    /// The message queue to the runtime is full.
+    QueueFull(),
+    /// A structural edit does not fit the running graph, so it was not sent.
+    InvalidEdit(GraphError),
+    /// The sink and named outputs are what the runtime plays, so they cannot be removed.
+    CannotRemoveSink(),
+    /// The node would need a delay line, buffer or param that the running graph does not have.
+    /// Nodes like these can only be added with [`LegatoFrontend::reload_dsl`].
+    ClaimsResources(),
+    /// The node has no audio output with that index.
+    PortNotFound(),
+    /// The frontend was made without what it needs to change the running graph, see
+    /// [`LegatoFrontend::new`].
+    NotEditable(),
 }
 
 pub struct LegatoFrontend {
     runtime_frontend: RuntimeFrontend,
     producer: rtrb::Producer<LegatoMsg>,
     node_registry: HashMap<String, NodeKey>,
+    reload_context: Option<ReloadContext>,
+    mirror: Option<GraphMirror>,
+    retired_graphs: Option<rtrb::Consumer<RetiredGraph>>,
+    leaked_graphs: Arc<AtomicUsize>,
+    reload_crossfade_blocks: usize,
+    profile: Option<ProfileReader>,
 }
 
 impl LegatoFrontend {
@@ -124,9 +194,320 @@ impl LegatoFrontend {
             runtime_frontend,
             producer,
             node_registry,
+            reload_context: None,
+            mirror: None,
+            retired_graphs: None,
+            leaked_graphs: Arc::default(),
+            reload_crossfade_blocks: DEFAULT_RELOAD_CROSSFADE_BLOCKS,
+            profile: None,
+        }
+    }
+
+    pub(crate) fn with_reload(
+        mut self,
+        reload_context: ReloadContext,
+        mirror: GraphMirror,
+        retired_graphs: rtrb::Consumer<RetiredGraph>,
+        leaked_graphs: Arc<AtomicUsize>,
+    ) -> Self {
+        self.reload_context = Some(reload_context);
+        self.mirror = Some(mirror);
+        self.retired_graphs = Some(retired_graphs);
+        self.leaked_graphs = leaked_graphs;
+        self
+    }
+
+    pub(crate) fn with_profiler(mut self, profile: Option<ProfileReader>) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// The timings from the last complete profiling window, or `None` if the app was not
+    /// built with [`builder::LegatoBuilder::enable_profiling`].
+    ///
+    /// Nodes are keyed like the registry, see [`Self::clone_registry`].
+    pub fn profile(&mut self) -> Option<&Profile> {
+        self.profile.as_mut().map(ProfileReader::read)
+    }
+
+    /// How many blocks [`Self::reload_dsl`] crossfades the old graph out over. `0` cuts
+    /// straight over, which is only click-free when every node carries its state.
+    pub fn set_reload_crossfade(&mut self, blocks: usize) {
+        self.reload_crossfade_blocks = blocks;
+    }
+
+    /// Replace the running graph with one built from `graph`, without stopping audio.
+    ///
+    /// The graph is built and prepared here, then swapped in by the runtime at the start of its
+    /// next block, running alongside the old graph for the crossfade window. Nodes whose fully qualified alias (e.g. `voice.0.adsr`) and kind are unchanged
+    /// keep their state, as do delay lines, loaded samples, audio inputs and param values with
+    /// the same names.
+    ///
+    /// Node keys from before the reload are stale afterwards, so look them up again.
+    pub fn reload_dsl(&mut self, graph: &str) -> Result<(), FrontendError> {
+        self.drain_garbage();
+
+        let context = self
+            .reload_context
+            .as_ref()
+            .ok_or(FrontendError::NotEditable())?;
+
+        let prepared = PreparedReload::build(
+            graph,
+            context,
+            &self.node_registry,
+            self.reload_crossfade_blocks,
+        )
+        .map_err(FrontendError::InvalidGraph)?;
+
+        // Only adopt the new graph's frontend state once the runtime is guaranteed to see it
+        if self.producer.is_full() {
+            return Err(FrontendError::QueueFull());
+        }
+
+        prepared
+            .resources_frontend
+            .carry_params(self.runtime_frontend.resource_frontend());
+
+        let mirror = GraphMirror::new(&prepared.swap.executor, context.config.block_size);
+
+        let _ = self
+            .producer
+            .push(LegatoMsg::SwapGraph(Box::new(prepared.swap)));
+
+        self.runtime_frontend = RuntimeFrontend::new(prepared.resources_frontend);
+        self.node_registry = prepared.node_registry;
+        self.reload_context = Some(prepared.reload_context);
+        self.mirror = Some(mirror);
+
+        Ok(())
+    }
+
+    /// Add a node from one of the app's namespaces to the running graph, unconnected.
+    ///
+    /// The node can read delay lines and samples the running graph already has, but cannot
+    /// claim new ones, see [`FrontendError::ClaimsResources`].
+    pub fn add_node(
+        &mut self,
+        namespace: &str,
+        node_kind: &str,
+        alias: &str,
+        params: &DSLParams,
+    ) -> Result<NodeKey, FrontendError> {
+        self.check_alias_free(alias)?;
+
+        let context = self
+            .reload_context
+            .as_ref()
+            .ok_or(FrontendError::NotEditable())?;
+
+        let ns = context.namespaces.get(namespace).ok_or_else(|| {
+            FrontendError::InvalidGraph(ValidationError::NamespaceNotFound(namespace.into()))
+        })?;
+
+        let mut resource_builder = ResourceBuilder::default();
+        let mut external_buffer_keys = context.external_buffer_to_key.clone();
+        let mut delay_keys = context.delay_name_to_key.clone();
+
+        let mut resource_builder_view = ResourceBuilderView {
+            config: &context.config,
+            resource_builder: &mut resource_builder,
+            external_buffer_keys: &mut external_buffer_keys,
+            delay_keys: &mut delay_keys,
+            instance_alias: alias,
+        };
+
+        let node = ns
+            .get_node(&mut resource_builder_view, &node_kind.into(), params)
+            .map_err(FrontendError::InvalidGraph)?;
+
+        if resource_builder.has_claims() {
+            return Err(FrontendError::ClaimsResources());
+        }
+
+        self.add_node_raw(LegatoNode::new(alias.into(), node_kind.into(), node), alias)
+    }
+
+    /// Add an already constructed node to the running graph, unconnected.
+    pub fn add_node_raw(
+        &mut self,
+        node: LegatoNode,
+        alias: &str,
+    ) -> Result<NodeKey, FrontendError> {
+        self.check_alias_free(alias)?;
+
+        let key = self
+            .edit(Edit::AddNode(node))?
+            .expect("Added node has a key");
+        self.node_registry.insert(alias.into(), key);
+
+        Ok(key)
+    }
+
+    /// Remove a node and every edge to and from it from the running graph.
+    pub fn remove_node(&mut self, alias: &str) -> Result<(), FrontendError> {
+        let key = self.lookup(alias)?;
+        self.edit(Edit::RemoveNode(key))?;
+        self.node_registry.remove(alias);
+
+        Ok(())
+    }
+
+    /// Swap a running node for `node`, keeping its edges. The new node starts from scratch.
+    pub fn replace_node(&mut self, alias: &str, node: LegatoNode) -> Result<(), FrontendError> {
+        let key = self.lookup(alias)?;
+        self.edit(Edit::ReplaceNode(key, node))?;
+
+        Ok(())
+    }
+
+    /// Connect an audio output of one running node to an audio input of another.
+    pub fn connect(
+        &mut self,
+        source: &str,
+        source_port: usize,
+        sink: &str,
+        sink_port: usize,
+    ) -> Result<(), FrontendError> {
+        let connection = self.connection(source, source_port, sink, sink_port)?;
+        self.edit(Edit::AddEdge(connection))?;
+
+        Ok(())
+    }
+
+    /// Remove an edge added by the graph or [`Self::connect`].
+    pub fn disconnect(
+        &mut self,
+        source: &str,
+        source_port: usize,
+        sink: &str,
+        sink_port: usize,
+    ) -> Result<(), FrontendError> {
+        let connection = self.connection(source, source_port, sink, sink_port)?;
+        self.edit(Edit::RemoveEdge(connection))?;
+
+        Ok(())
+    }
+
+    /// Pass a running node's inputs straight through to its outputs, channel by channel,
+    /// fading over [`node::BYPASS_RAMP`] samples. Outputs with no matching input go silent.
+    pub fn set_bypass(&mut self, alias: &str, bypassed: bool) -> Result<(), FrontendError> {
+        self.push_node_msg(alias, NodeMessage::SetBypass(bypassed))
+    }
+
+    /// Fade a running node's outputs to silence, or back in. A muted node stays silent
+    /// whether or not it is bypassed.
+    pub fn set_mute(&mut self, alias: &str, muted: bool) -> Result<(), FrontendError> {
+        self.push_node_msg(alias, NodeMessage::SetMute(muted))
+    }
+
+    fn push_node_msg(&mut self, alias: &str, msg: NodeMessage) -> Result<(), FrontendError> {
+        let key = self.lookup(alias)?;
+        self.producer
+            .push(LegatoMsg::NodeMessage(key, msg))
+            .map_err(|_| FrontendError::QueueFull())
+    }
+
+    /// Receive one of a running node's outputs every block, as samples and as levels.
+    ///
+    /// The first tap on a node takes an edit, which keeps its outputs in slots of their own
+    /// so they can be read once the block is done. Taps end when the node is removed or
+    /// replaced with fewer outputs, or the graph is reloaded.
+    pub fn tap(&mut self, alias: &str, port: usize) -> Result<Tap, FrontendError> {
+        let key = self.lookup(alias)?;
+
+        let mirror = self.mirror.as_ref().ok_or(FrontendError::NotEditable())?;
+
+        if mirror.outputs(key).is_none_or(|outputs| port >= outputs) {
+            return Err(FrontendError::PortNotFound());
+        }
+
+        let block_size = mirror.block_size();
+        if !mirror.is_pinned(key) {
+            self.edit(Edit::PinOutputs(key))?;
+        }
+
+        let (sender, tap) = TapSender::new(key, port, block_size);
+        self.producer
+            .push(LegatoMsg::AddTap(sender))
+            .map_err(|_| FrontendError::QueueFull())?;
+
+        Ok(tap)
+    }
+
+    /// Prepare `edit` against the mirrored graph and send it to the runtime.
+    ///
+    /// The mirror only moves forward once the edit is in the queue, so a rejected edit
+    /// leaves both sides as they were.
+    fn edit(&mut self, edit: Edit) -> Result<Option<NodeKey>, FrontendError> {
+        self.drain_garbage();
+
+        let mirror = self.mirror.as_ref().ok_or(FrontendError::NotEditable())?;
+
+        let (mirror, edit, key) = mirror.prepare(edit)?;
+
+        if self.producer.is_full() {
+            return Err(FrontendError::QueueFull());
+        }
+
+        let _ = self.producer.push(LegatoMsg::EditGraph(edit));
+        self.mirror = Some(mirror);
+
+        Ok(key)
+    }
+
+    fn check_alias_free(&self, alias: &str) -> Result<(), FrontendError> {
+        match self.node_registry.contains_key(alias) {
+            true => Err(FrontendError::InvalidGraph(
+                ValidationError::DuplicateAlias(alias.into()),
+            )),
+            false => Ok(()),
         }
     }
 
+    fn lookup(&self, alias: &str) -> Result<NodeKey, FrontendError> {
+        self.node_registry
+            .get(alias)
+            .copied()
+            .ok_or(FrontendError::NodeNotFound())
+    }
+
+    fn connection(
+        &self,
+        source: &str,
+        source_port: usize,
+        sink: &str,
+        sink_port: usize,
+    ) -> Result<Connection, FrontendError> {
+        Ok(Connection {
+            source: ConnectionEntry {
+                node_key: self.lookup(source)?,
+                port_index: source_port,
+            },
+            sink: ConnectionEntry {
+                node_key: self.lookup(sink)?,
+                port_index: sink_port,
+            },
+        })
+    }
+
+    /// Drop graphs the runtime has swapped out, off of the audio thread.
+    pub fn drain_garbage(&mut self) {
+        if let Some(retired) = &mut self.retired_graphs {
+            while let Ok(graph) = retired.pop() {
+                drop(graph);
+            }
+        }
+        self.runtime_frontend.drain_garbage();
+    }
+
+    /// How many swapped out graphs the runtime had to leak, because it replaced them faster
+    /// than [`Self::drain_garbage`] took them back. Anything but `0` means reloads or edits
+    /// are being sent in a burst without the frontend keeping up.
+    pub fn leaked_graphs(&self) -> usize {
+        self.leaked_graphs.load(Ordering::Relaxed)
+    }
+
     pub fn load_sample(
         &mut self,
         buffer_name: &str,
@@ -0,0 +1,675 @@
+//! What the language server knows about one document: its diagnostics, and what to offer
+//! for completion, hover and go-to-definition at a given offset.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    panic::{AssertUnwindSafe, catch_unwind},
+    path::{Path, PathBuf},
+};
+
+use chumsky::Parser;
+use lsp_types::{CompletionItem, CompletionItemKind, Documentation, MarkupContent, MarkupKind};
+
+use crate::{
+    builder::{LegatoBuilder, ResourceBuilderView, Unconfigured, ValidationError},
+    config::Config,
+    dsl::{
+        ir::{DSLParams, IRNodeKind, Object, Span},
+        module::{FileLoader, ModuleLoader, resolve_imports},
+        parse::legato_parser_inner,
+        pipeline::Pipeline,
+    },
+    lsp::outline::{DeclOutline, MacroOutline, Outline},
+    ports::{PortBuilder, Ports},
+    registry::NodeRegistry,
+    resources::ResourceBuilder,
+    spec::NodeSpec,
+};
+
+const KEYWORDS: &[&str] = &["import", "use", "patch", "kernel", "in", "out", "as"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub message: String,
+    /// Worth a look, but the graph still builds.
+    pub warning: bool,
+}
+
+impl Diagnostic {
+    fn error(span: Span, message: String) -> Self {
+        Self {
+            span,
+            message,
+            warning: false,
+        }
+    }
+}
+
+/// A file brought in by `import`, kept for hover and go-to-definition.
+#[derive(Clone, Debug)]
+pub struct Imported {
+    pub path: PathBuf,
+    pub src: String,
+    pub outline: Outline,
+}
+
+/// Where a definition is: in the document itself if `file` is `None`.
+#[derive(Clone, Debug)]
+pub struct Target<'a> {
+    pub file: Option<&'a Imported>,
+    pub span: Span,
+}
+
+pub struct Analysis<'a> {
+    namespaces: &'a HashMap<&'static str, NodeRegistry>,
+    pub outline: Outline,
+    pub imports: HashMap<String, Imported>,
+    pub diagnostics: Vec<Diagnostic>,
+    // The ports of every node the pipeline resolved, by the alias written in the source
+    ports: HashMap<String, Ports>,
+}
+
+impl<'a> Analysis<'a> {
+    /// Analyse `src`, resolving its imports relative to `dir`.
+    pub fn new(src: &str, dir: &Path, namespaces: &'a HashMap<&'static str, NodeRegistry>) -> Self {
+        let loader = FileLoader::new(dir);
+        let outline = Outline::new(src);
+
+        let imports = outline
+            .imports
+            .iter()
+            .filter_map(|import| {
+                let path = PathBuf::from(loader.resolve(&import.path, None));
+                let src = loader.load(&path.to_string_lossy()).ok()?;
+                let outline = Outline::new(&src);
+                Some((import.namespace.clone(), Imported { path, src, outline }))
+            })
+            .collect();
+
+        let mut analysis = Self {
+            namespaces,
+            outline,
+            imports,
+            diagnostics: vec![],
+            ports: HashMap::new(),
+        };
+
+        let (ast, errs) = legato_parser_inner().parse(src).into_output_errors();
+        analysis.diagnostics = errs
+            .into_iter()
+            .map(|e| Diagnostic::error(e.span().into_range(), e.to_string()))
+            .collect();
+        analysis.check_declarations();
+
+        let Some(ast) = ast else {
+            return analysis;
+        };
+        if analysis.diagnostics.iter().any(|d| !d.warning) {
+            return analysis;
+        }
+
+        // The pipeline alone gives the resolved params each node is built with
+        let ir = catch_unwind(AssertUnwindSafe(|| {
+            Pipeline::default().run_from_ast(resolve_imports(ast, &loader)?)
+        }));
+        match ir {
+            Ok(Ok(ir)) => {
+                for node in ir.nodes().filter(|n| n.kind == IRNodeKind::Leaf) {
+                    let spec = namespaces
+                        .get(node.namespace.as_str())
+                        .and_then(|r| r.spec(&node.node_type));
+                    if let Some(ports) =
+                        spec.and_then(|s| probe_ports(s, &node.alias, &node.params))
+                    {
+                        // `osc.0` for spawned nodes, `lead.osc` inside patches
+                        for name in node
+                            .alias
+                            .split('.')
+                            .filter(|s| s.parse::<usize>().is_err())
+                        {
+                            analysis.ports.entry(name.into()).or_insert(ports.clone());
+                        }
+                    }
+                }
+            }
+            Ok(Err(err)) => {
+                analysis.report(err);
+                return analysis;
+            }
+            Err(panic) => {
+                analysis.report_panic(panic);
+                return analysis;
+            }
+        }
+
+        // Building catches what only the node factories check
+        let built = catch_unwind(AssertUnwindSafe(|| {
+            let config = Config {
+                sample_rate: 48_000,
+                block_size: 256,
+                channels: 2,
+                rt_capacity: 0,
+            };
+            let ports = PortBuilder::default().audio_out(2).build();
+            namespaces
+                .iter()
+                .fold(
+                    LegatoBuilder::<Unconfigured>::new(config, ports),
+                    |builder, (name, registry)| builder.add_node_registry(name, registry.clone()),
+                )
+                .set_module_loader(FileLoader::new(dir))
+                .build_dsl(src)
+                .map(|_| ())
+        }));
+        match built {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => analysis.report(err),
+            Err(panic) => analysis.report_panic(panic),
+        }
+
+        analysis
+    }
+
+    fn report(&mut self, err: ValidationError) {
+        let message = err.to_string();
+        let span = err.span().unwrap_or_else(|| self.locate(&message));
+        self.diagnostics.push(Diagnostic::error(span, message));
+    }
+
+    fn report_panic(&mut self, panic: Box<dyn std::any::Any + Send>) {
+        let message = panic
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+            .unwrap_or_else(|| "the graph could not be built".into());
+        self.diagnostics
+            .push(Diagnostic::error(self.locate(&message), message));
+    }
+
+    /// The first node an error message names, for build errors that carry no span.
+    fn locate(&self, message: &str) -> Span {
+        let words = message
+            .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':'))
+            .map(|w| w.trim_matches(':'))
+            .filter(|w| !w.is_empty());
+
+        for word in words {
+            let decl = self
+                .outline
+                .scopes
+                .iter()
+                .flat_map(|s| &s.declarations)
+                .find(|d| d.alias() == word || d.node_type == word);
+            if let Some(decl) = decl {
+                return decl
+                    .alias
+                    .as_ref()
+                    .map_or(decl.type_span.clone(), |(_, s)| s.clone());
+            }
+            if let Some(r) = self.outline.references.iter().find(|r| r.node == word) {
+                return r.span.clone();
+            }
+        }
+        0..0
+    }
+
+    /// Unknown namespaces, node types and params, which the parser cannot know about.
+    fn check_declarations(&mut self) {
+        let mut diagnostics = vec![];
+
+        for scope in &self.outline.scopes {
+            let registry = self.namespaces.get(scope.namespace.as_str());
+            let mut reported_namespace = false;
+
+            for decl in &scope.declarations {
+                if self.is_macro(&decl.node_type) {
+                    continue;
+                }
+                let Some(registry) = registry else {
+                    // A namespace of patches only, like `patches`, is fine
+                    if !reported_namespace {
+                        diagnostics.push(Diagnostic::error(
+                            scope.namespace_span.clone(),
+                            format!("unknown namespace `{}`", scope.namespace),
+                        ));
+                        reported_namespace = true;
+                    }
+                    continue;
+                };
+                let Some(spec) = registry.spec(&decl.node_type) else {
+                    diagnostics.push(Diagnostic::error(
+                        decl.type_span.clone(),
+                        format!("unknown node `{}` in `{}`", decl.node_type, scope.namespace),
+                    ));
+                    continue;
+                };
+
+                for (key, span) in &decl.params {
+                    if !spec.required_params.contains(key) && !spec.optional_params.contains(key) {
+                        diagnostics.push(Diagnostic::error(
+                            span.clone(),
+                            format!("`{}` has no param `{key}`", spec.name),
+                        ));
+                    }
+                }
+
+                let missing: Vec<_> = spec
+                    .required_params
+                    .iter()
+                    .filter(|p| !decl.params.iter().any(|(k, _)| k == *p))
+                    .map(|p| format!("`{p}`"))
+                    .collect();
+                if !missing.is_empty() {
+                    diagnostics.push(Diagnostic {
+                        span: decl.type_span.clone(),
+                        message: format!("`{}` expects {}", spec.name, missing.join(", ")),
+                        warning: true,
+                    });
+                }
+            }
+        }
+
+        self.diagnostics.extend(diagnostics);
+    }
+
+    /// Whether `node_type` names a patch or kernel, including ones that are imported.
+    fn is_macro(&self, node_type: &str) -> bool {
+        // `ns::name` is checked by the import resolver, which knows nested imports too
+        node_type.contains("::")
+            || self.outline.find_macro(node_type).is_some()
+            || self.outline.uses.iter().any(|u| u.alias == node_type)
+    }
+
+    /// The patch or kernel `node_type` names, and the file it is defined in.
+    pub fn find_macro(&self, node_type: &str) -> Option<(Option<&Imported>, &MacroOutline)> {
+        if let Some(local) = self.outline.find_macro(node_type) {
+            return Some((None, local));
+        }
+
+        let path = match self.outline.uses.iter().find(|u| u.alias == node_type) {
+            Some(use_) => use_.path.clone(),
+            None => node_type.split("::").map(String::from).collect(),
+        };
+        match path.as_slice() {
+            [namespace, name] => {
+                let imported = self.imports.get(namespace)?;
+                Some((Some(imported), imported.outline.find_macro(name)?))
+            }
+            _ => None,
+        }
+    }
+
+    fn spec(&self, namespace: &str, node_type: &str) -> Option<&NodeSpec> {
+        self.namespaces.get(namespace)?.spec(node_type)
+    }
+
+    /// The declaration `alias` names, from inside the patch `parent` or the top level.
+    fn declaration(&self, alias: &str, parent: Option<usize>) -> Option<(&str, &DeclOutline)> {
+        self.outline
+            .scopes
+            .iter()
+            .filter(|s| s.parent == parent)
+            .flat_map(|s| {
+                s.declarations
+                    .iter()
+                    .map(move |d| (s.namespace.as_str(), d))
+            })
+            .find(|(_, d)| d.alias() == alias)
+    }
+
+    pub fn completions(&self, offset: usize) -> Vec<CompletionItem> {
+        // Ports after `alias.`
+        let reference = self.outline.references.iter().find(|r| {
+            r.port
+                .as_ref()
+                .is_some_and(|(_, span)| span.start <= offset && offset <= span.end)
+        });
+        if let Some(reference) = reference {
+            return self.port_completions(&reference.node, reference.parent);
+        }
+
+        // Params inside a declaration's braces
+        for scope in &self.outline.scopes {
+            for decl in &scope.declarations {
+                let Some(body) = &decl.params_body else {
+                    continue;
+                };
+                if body.start < offset && offset < body.end.max(body.start + 2) {
+                    return self.param_completions(&scope.namespace, decl);
+                }
+            }
+        }
+
+        // Node types inside a scope
+        if let Some(scope) = self
+            .outline
+            .scopes
+            .iter()
+            .find(|s| s.body.start < offset && offset < s.body.end.max(s.body.start + 2))
+        {
+            return self.node_completions(&scope.namespace);
+        }
+
+        let mut items: Vec<CompletionItem> = KEYWORDS
+            .iter()
+            .map(|k| item(k, CompletionItemKind::KEYWORD, None, None))
+            .collect();
+        let mut namespaces: Vec<_> = self.namespaces.keys().collect();
+        namespaces.sort();
+        items.extend(
+            namespaces
+                .into_iter()
+                .chain(&["patches"])
+                .map(|n| item(n, CompletionItemKind::MODULE, None, None)),
+        );
+        items
+    }
+
+    fn port_completions(&self, alias: &str, parent: Option<usize>) -> Vec<CompletionItem> {
+        let port_item = |name: &str, detail: &str| {
+            item(name, CompletionItemKind::FIELD, Some(detail.into()), None)
+        };
+
+        let Some((namespace, decl)) = self.declaration(alias, parent) else {
+            // A port of the patch itself, from inside its body
+            return vec![];
+        };
+        if let Some((_, found)) = self.find_macro(&decl.node_type) {
+            let inputs = found.ports.iter().map(|p| port_item(p, "patch input"));
+            let outputs = found.outputs.iter().map(|p| port_item(p, "patch output"));
+            return inputs.chain(outputs).collect();
+        }
+
+        let ports = self.ports.get(alias).cloned().or_else(|| {
+            let spec = self.spec(namespace, &decl.node_type)?;
+            probe_ports(spec, alias, &Object::new())
+        });
+        let Some(ports) = ports else {
+            return vec![];
+        };
+
+        let mut items: Vec<CompletionItem> = vec![];
+        for (meta, detail) in ports
+            .audio_in
+            .iter()
+            .map(|m| (m, "input"))
+            .chain(ports.audio_out.iter().map(|m| (m, "output")))
+        {
+            match items.iter_mut().find(|i| i.label == meta.name) {
+                Some(existing) => existing.detail = Some("input and output".into()),
+                None => items.push(port_item(meta.name, detail)),
+            }
+        }
+        items
+    }
+
+    fn param_completions(&self, namespace: &str, decl: &DeclOutline) -> Vec<CompletionItem> {
+        let unwritten = |p: &&String| !decl.params.iter().any(|(k, _)| k == *p);
+
+        if let Some((_, found)) = self.find_macro(&decl.node_type) {
+            return found
+                .params
+                .iter()
+                .filter(unwritten)
+                .map(|p| item(p, CompletionItemKind::PROPERTY, Some("param".into()), None))
+                .collect();
+        }
+
+        let Some(spec) = self.spec(namespace, &decl.node_type) else {
+            return vec![];
+        };
+        let required = spec
+            .required_params
+            .iter()
+            .filter(unwritten)
+            .map(|p| (p, "required"));
+        let optional = spec
+            .optional_params
+            .iter()
+            .filter(unwritten)
+            .map(|p| (p, "optional"));
+
+        required
+            .chain(optional)
+            .map(|(p, detail)| {
+                let mut item = item(p, CompletionItemKind::PROPERTY, Some(detail.into()), None);
+                item.insert_text = Some(format!("{p}: "));
+                item
+            })
+            .collect()
+    }
+
+    fn node_completions(&self, namespace: &str) -> Vec<CompletionItem> {
+        let mut items: Vec<CompletionItem> = vec![];
+
+        if let Some(registry) = self.namespaces.get(namespace) {
+            let mut specs: Vec<_> = registry.specs().collect();
+            specs.sort_by(|a, b| a.name.cmp(&b.name));
+            items.extend(specs.into_iter().map(|spec| {
+                item(
+                    &spec.name,
+                    CompletionItemKind::CLASS,
+                    Some(params_summary(spec)),
+                    Some(spec.description.into()),
+                )
+            }));
+        }
+
+        let macros = self
+            .outline
+            .macros
+            .iter()
+            .map(|m| (m.name.clone(), m))
+            .chain(self.imports.iter().flat_map(|(namespace, imported)| {
+                imported
+                    .outline
+                    .macros
+                    .iter()
+                    .map(move |m| (format!("{namespace}::{}", m.name), m))
+            }));
+        items
+            .extend(macros.map(|(name, m)| {
+                item(&name, CompletionItemKind::MODULE, Some(signature(m)), None)
+            }));
+        items.extend(self.outline.uses.iter().filter_map(|u| {
+            let (_, m) = self.find_macro(&u.alias)?;
+            Some(item(
+                &u.alias,
+                CompletionItemKind::MODULE,
+                Some(signature(m)),
+                None,
+            ))
+        }));
+
+        items
+    }
+
+    /// Markdown describing the node or patch at `offset`, and the span it describes.
+    pub fn hover(&self, offset: usize) -> Option<(String, Span)> {
+        let within = |span: &Span| span.start <= offset && offset <= span.end;
+
+        for scope in &self.outline.scopes {
+            for decl in &scope.declarations {
+                let on_alias = decl.alias.as_ref().filter(|(_, s)| within(s));
+                if within(&decl.type_span) || on_alias.is_some() {
+                    let span = on_alias.map_or(decl.type_span.clone(), |(_, s)| s.clone());
+                    return Some((self.describe(&scope.namespace, decl)?, span));
+                }
+            }
+        }
+
+        let reference = self.outline.references.iter().find(|r| within(&r.span))?;
+        let (namespace, decl) = self.declaration(&reference.node, reference.parent)?;
+        Some((self.describe(namespace, decl)?, reference.span.clone()))
+    }
+
+    fn describe(&self, namespace: &str, decl: &DeclOutline) -> Option<String> {
+        if let Some((_, found)) = self.find_macro(&decl.node_type) {
+            let mut ports = String::new();
+            for (label, names) in [("Inputs", &found.ports), ("Outputs", &found.outputs)] {
+                if !names.is_empty() {
+                    write!(ports, "\n\n{label}: {}", names.join(", ")).unwrap();
+                }
+            }
+            return Some(format!("```legato\n{}\n```{ports}", signature(found)));
+        }
+
+        let spec = self.spec(namespace, &decl.node_type)?;
+        Some(format!(
+            "```legato\n{namespace}::{}\n```\n\n{}\n\n{}",
+            spec.name,
+            spec.description,
+            params_summary(spec)
+        ))
+    }
+
+    /// Where the patch, kernel or node named at `offset` is defined.
+    pub fn definition(&self, offset: usize) -> Option<Target<'_>> {
+        let within = |span: &Span| span.start <= offset && offset <= span.end;
+
+        let decl = self
+            .outline
+            .scopes
+            .iter()
+            .flat_map(|s| &s.declarations)
+            .find(|d| within(&d.type_span));
+        if let Some(decl) = decl {
+            let (file, found) = self.find_macro(&decl.node_type)?;
+            return Some(Target {
+                file,
+                span: found.name_span.clone(),
+            });
+        }
+
+        let reference = self.outline.references.iter().find(|r| within(&r.span))?;
+        let (_, decl) = self.declaration(&reference.node, reference.parent)?;
+        Some(Target {
+            file: None,
+            span: decl
+                .alias
+                .as_ref()
+                .map_or(decl.type_span.clone(), |(_, s)| s.clone()),
+        })
+    }
+}
+
+/// The ports a node would have, by building one. Nodes that need resources, such as a
+/// sampler's buffer, may not build outside a graph.
+fn probe_ports(spec: &NodeSpec, alias: &str, params: &Object) -> Option<Ports> {
+    let config = Config {
+        sample_rate: 48_000,
+        block_size: 256,
+        channels: 2,
+        rt_capacity: 0,
+    };
+    let mut resource_builder = ResourceBuilder::default();
+    let (mut external_buffer_keys, mut delay_keys) = (HashMap::new(), HashMap::new());
+    let alias = alias.to_string();
+
+    catch_unwind(AssertUnwindSafe(|| {
+        let mut view = ResourceBuilderView {
+            config: &config,
+            resource_builder: &mut resource_builder,
+            external_buffer_keys: &mut external_buffer_keys,
+            delay_keys: &mut delay_keys,
+            instance_alias: &alias,
+        };
+        (spec.build)(&mut view, &DSLParams::new(params)).map(|node| node.ports().clone())
+    }))
+    .ok()?
+    .ok()
+}
+
+fn params_summary(spec: &NodeSpec) -> String {
+    let list = |params: &std::collections::BTreeSet<String>| match params.len() {
+        0 => "none".to_string(),
+        _ => params.iter().cloned().collect::<Vec<_>>().join(", "),
+    };
+    format!(
+        "Required: {}. Optional: {}.",
+        list(&spec.required_params),
+        list(&spec.optional_params)
+    )
+}
+
+fn signature(m: &MacroOutline) -> String {
+    format!("{} {}({})", m.keyword, m.name, m.params.join(", "))
+}
+
+fn item(
+    label: &str,
+    kind: CompletionItemKind,
+    detail: Option<String>,
+    docs: Option<String>,
+) -> CompletionItem {
+    CompletionItem {
+        label: label.into(),
+        kind: Some(kind),
+        detail,
+        documentation: docs.map(|value| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            })
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::audio_registry_factory;
+
+    fn namespaces() -> HashMap<&'static str, NodeRegistry> {
+        HashMap::from([("audio", audio_registry_factory())])
+    }
+
+    fn labels(items: Vec<CompletionItem>) -> Vec<String> {
+        items.into_iter().map(|i| i.label).collect()
+    }
+
+    #[test]
+    fn registry_mistakes_point_at_the_declaration() {
+        let namespaces = namespaces();
+        let src = "audio { sine { freq: 1.0, nope: 2.0 }, wobble } { sine }";
+        let analysis = Analysis::new(src, Path::new("."), &namespaces);
+
+        let errors: Vec<_> = analysis
+            .diagnostics
+            .iter()
+            .filter(|d| !d.warning)
+            .map(|d| &src[d.span.clone()])
+            .collect();
+        assert_eq!(errors, ["nope", "wobble"]);
+    }
+
+    #[test]
+    fn build_errors_are_located_by_name() {
+        let namespaces = namespaces();
+        let src = "audio { sine { freq: 1.0 } } sine >> missing { sine }";
+        let analysis = Analysis::new(src, Path::new("."), &namespaces);
+
+        assert_eq!(analysis.diagnostics.len(), 1, "{:?}", analysis.diagnostics);
+        assert_eq!(&src[analysis.diagnostics[0].span.clone()], "missing");
+    }
+
+    #[test]
+    fn completes_what_the_cursor_is_in() {
+        let namespaces = namespaces();
+        let src = "audio { svf { cutoff: 100.0, } } svf. { svf }";
+        let analysis = Analysis::new(src, Path::new("."), &namespaces);
+
+        let params = labels(analysis.completions(src.find(", }").unwrap() + 2));
+        assert!(params.contains(&"q".to_string()));
+        assert!(!params.contains(&"cutoff".to_string()));
+
+        let nodes = labels(analysis.completions(src.find("svf").unwrap()));
+        assert!(nodes.contains(&"sine".to_string()));
+
+        let ports = labels(analysis.completions(src.find("svf.").unwrap() + 4));
+        assert!(ports.contains(&"cutoff".to_string()), "{ports:?}");
+    }
+}
@@ -0,0 +1,382 @@
+//! A language server for `.legato` files, behind the `lsp` feature.
+//!
+//! It reuses the parser, the [`Pipeline`](crate::dsl::pipeline::Pipeline) and the node
+//! registries, so what it reports is what [`LegatoBuilder::build_dsl`] would. It offers:
+//!
+//! - diagnostics, from parse errors, unknown nodes and params, and failed builds
+//! - completion of node types, their params, and the named ports of declared nodes
+//! - hover docs from each node's `DESCRIPTION`
+//! - go-to-definition for patches and kernels, including imported ones
+//!
+//! The `legato-lsp` binary runs it over stdio. Projects with their own nodes can run their
+//! own binary, adding their registries with [`LanguageServer::add_node_registry`].
+//!
+//! [`LegatoBuilder::build_dsl`]: crate::builder::LegatoBuilder::build_dsl
+
+use std::{collections::HashMap, error::Error, path::PathBuf, str::FromStr};
+
+use lsp_server::{Connection, Message, Notification, Request, Response};
+use lsp_types::{
+    CompletionOptions, CompletionParams, CompletionResponse, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InitializeParams, Location, MarkupContent, MarkupKind, OneOf,
+    Position, PublishDiagnosticsParams, Range, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Uri,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{Completion, GotoDefinition, HoverRequest, Request as _},
+};
+
+use crate::{
+    dsl::ir::Span,
+    lsp::analysis::{Analysis, Target},
+    registry::{
+        NodeRegistry, audio_registry_factory, control_registry_factory, midi_registry_factory,
+    },
+};
+
+pub mod analysis;
+pub mod outline;
+
+type ServerResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Serves completion, hover, go-to-definition and diagnostics for open `.legato` files.
+pub struct LanguageServer {
+    namespaces: HashMap<&'static str, NodeRegistry>,
+    documents: HashMap<Uri, Document>,
+}
+
+struct Document {
+    text: String,
+    lines: LineIndex,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    /// A server knowing the same namespaces as a new [`LegatoBuilder`](crate::builder::LegatoBuilder).
+    pub fn new() -> Self {
+        let namespaces = HashMap::from([
+            ("audio", audio_registry_factory()),
+            ("control", control_registry_factory()),
+            ("midi", midi_registry_factory()),
+            ("user", NodeRegistry::new()),
+        ]);
+
+        Self {
+            namespaces,
+            documents: HashMap::new(),
+        }
+    }
+
+    /// Add a registry, as with [`LegatoBuilder::add_node_registry`](crate::builder::LegatoBuilder::add_node_registry).
+    pub fn add_node_registry(mut self, name: &'static str, registry: NodeRegistry) -> Self {
+        self.namespaces.insert(name, registry);
+        self
+    }
+
+    /// Serve a client over stdin and stdout until it exits.
+    pub fn run_stdio(self) -> ServerResult<()> {
+        let (connection, io_threads) = Connection::stdio();
+        self.run(connection)?;
+        io_threads.join()?;
+        Ok(())
+    }
+
+    /// Serve a client over `connection` until it exits.
+    pub fn run(mut self, connection: Connection) -> ServerResult<()> {
+        let capabilities = ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec![".".into(), "{".into(), ",".into()]),
+                ..Default::default()
+            }),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            definition_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        };
+        let params = connection.initialize(serde_json::to_value(capabilities)?)?;
+        let _: InitializeParams = serde_json::from_value(params)?;
+
+        for msg in &connection.receiver {
+            match msg {
+                Message::Request(req) => {
+                    if connection.handle_shutdown(&req)? {
+                        return Ok(());
+                    }
+                    let response = self.request(req)?;
+                    connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(not) => {
+                    if let Some(uri) = self.notification(not)? {
+                        let diagnostics = self.diagnostics(&uri);
+                        connection.sender.send(Message::Notification(diagnostics))?;
+                    }
+                }
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Track document changes, returning the document to publish diagnostics for.
+    fn notification(&mut self, not: Notification) -> ServerResult<Option<Uri>> {
+        Ok(match not.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), Document::new(params.text_document.text));
+                Some(uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                // Full sync, so the last change is the whole text
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(None);
+                };
+                self.documents
+                    .insert(uri.clone(), Document::new(change.text));
+                Some(uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                self.documents.remove(&params.text_document.uri);
+                None
+            }
+            _ => None,
+        })
+    }
+
+    fn request(&self, req: Request) -> ServerResult<Response> {
+        let id = req.id.clone();
+        let result = match req.method.as_str() {
+            Completion::METHOD => {
+                let params: CompletionParams = serde_json::from_value(req.params)?;
+                let at = params.text_document_position;
+                let items = self.analyse(&at.text_document.uri, at.position, |analysis, offset| {
+                    analysis.completions(offset)
+                });
+                serde_json::to_value(items.map(CompletionResponse::Array))?
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(req.params)?;
+                let at = params.text_document_position_params;
+                let uri = &at.text_document.uri;
+                let hover = self
+                    .analyse(uri, at.position, |analysis, offset| analysis.hover(offset))
+                    .flatten()
+                    .map(|(value, span)| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value,
+                        }),
+                        range: Some(self.documents[uri].range(&span)),
+                    });
+                serde_json::to_value(hover)?
+            }
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(req.params)?;
+                let at = params.text_document_position_params;
+                let uri = &at.text_document.uri;
+                let location = self
+                    .analyse(uri, at.position, |analysis, offset| {
+                        let target = analysis.definition(offset)?;
+                        self.location(uri, target)
+                    })
+                    .flatten();
+                serde_json::to_value(location.map(GotoDefinitionResponse::Scalar))?
+            }
+            method => {
+                return Ok(Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("unsupported request {method}"),
+                ));
+            }
+        };
+
+        Ok(Response {
+            id,
+            result: Some(result),
+            error: None,
+        })
+    }
+
+    /// Analyse the document `uri`, and call `f` with the byte offset of `position` in it.
+    fn analyse<T>(
+        &self,
+        uri: &Uri,
+        position: Position,
+        f: impl FnOnce(&Analysis, usize) -> T,
+    ) -> Option<T> {
+        let document = self.documents.get(uri)?;
+        let analysis = Analysis::new(&document.text, &directory(uri), &self.namespaces);
+        Some(f(
+            &analysis,
+            document.lines.offset(&document.text, position),
+        ))
+    }
+
+    fn diagnostics(&self, uri: &Uri) -> Notification {
+        let diagnostics = match self.documents.get(uri) {
+            Some(document) => Analysis::new(&document.text, &directory(uri), &self.namespaces)
+                .diagnostics
+                .into_iter()
+                .map(|d| lsp_types::Diagnostic {
+                    range: document.range(&d.span),
+                    severity: Some(match d.warning {
+                        true => DiagnosticSeverity::WARNING,
+                        false => DiagnosticSeverity::ERROR,
+                    }),
+                    source: Some("legato".into()),
+                    message: d.message,
+                    ..Default::default()
+                })
+                .collect(),
+            None => vec![],
+        };
+
+        Notification::new(
+            PublishDiagnostics::METHOD.into(),
+            PublishDiagnosticsParams {
+                uri: uri.clone(),
+                diagnostics,
+                version: None,
+            },
+        )
+    }
+
+    fn location(&self, uri: &Uri, target: Target) -> Option<Location> {
+        let Some(imported) = target.file else {
+            return Some(Location {
+                uri: uri.clone(),
+                range: self.documents.get(uri)?.range(&target.span),
+            });
+        };
+
+        let lines = LineIndex::new(&imported.src);
+        Some(Location {
+            uri: file_uri(&imported.path)?,
+            range: Range {
+                start: lines.position(&imported.src, target.span.start),
+                end: lines.position(&imported.src, target.span.end),
+            },
+        })
+    }
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        Self {
+            lines: LineIndex::new(&text),
+            text,
+        }
+    }
+
+    fn range(&self, span: &Span) -> Range {
+        Range {
+            start: self.lines.position(&self.text, span.start),
+            end: self.lines.position(&self.text, span.end),
+        }
+    }
+}
+
+/// Converts byte offsets to the UTF-16 line and column positions LSP uses, and back.
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { starts }
+    }
+
+    fn position(&self, text: &str, offset: usize) -> Position {
+        let mut offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        let line = self.starts.partition_point(|&s| s <= offset) - 1;
+        let character = text[self.starts[line]..offset].encode_utf16().count();
+
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, text: &str, position: Position) -> usize {
+        let Some(&start) = self.starts.get(position.line as usize) else {
+            return text.len();
+        };
+
+        let mut units = 0;
+        for (i, c) in text[start..].char_indices() {
+            if units >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        text.len()
+    }
+}
+
+/// The directory imports in `uri` resolve against, or the working directory for unsaved files.
+fn directory(uri: &Uri) -> PathBuf {
+    let path = match uri.scheme().map(|s| s.as_str()) {
+        Some("file") => PathBuf::from(uri.path().as_estr().decode().into_string_lossy().as_ref()),
+        _ => return PathBuf::from("."),
+    };
+    path.parent()
+        .map_or(PathBuf::from("."), |p| p.to_path_buf())
+}
+
+fn file_uri(path: &std::path::Path) -> Option<Uri> {
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    Uri::from_str(&format!("file://{encoded}")).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "// é😀\naudio { sine }";
+        let lines = LineIndex::new(text);
+
+        let sine = text.find("sine").unwrap();
+        assert_eq!(lines.position(text, sine), Position::new(1, 8));
+        assert_eq!(lines.offset(text, Position::new(1, 8)), sine);
+
+        // After the emoji, which is two UTF-16 units
+        let end = text.find('\n').unwrap();
+        assert_eq!(lines.position(text, end), Position::new(0, 6));
+        assert_eq!(lines.offset(text, Position::new(0, 6)), end);
+    }
+
+    #[test]
+    fn file_uris_round_trip() {
+        let uri = file_uri(std::path::Path::new("/tmp/my patches/lead.legato")).unwrap();
+        assert_eq!(uri.as_str(), "file:///tmp/my%20patches/lead.legato");
+        assert_eq!(directory(&uri), PathBuf::from("/tmp/my patches"));
+    }
+}
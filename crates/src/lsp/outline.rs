@@ -0,0 +1,787 @@
+//! A forgiving outline of a Legato file: where its patches, scopes, declarations and
+//! connections are.
+//!
+//! The parser gives up at the first mistake, and a file being edited is rarely free of them.
+//! This only looks at tokens and braces, so it still finds what is around the cursor while
+//! the rest of the file is half typed.
+
+use crate::dsl::ir::Span;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    /// A number or a `$template`.
+    Literal,
+    Punct(&'static str),
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    tok: Tok,
+    span: Span,
+    line: usize,
+}
+
+fn lex(src: &str) -> Vec<Token> {
+    let bytes = src.as_bytes();
+    let mut tokens = vec![];
+    let (mut i, mut line) = (0, 0);
+
+    while i < bytes.len() {
+        let start = i;
+        let c = bytes[i];
+
+        if c == b'\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_ascii_whitespace() {
+            i += 1;
+        } else if src[i..].starts_with("//") {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+        } else if src[i..].starts_with("/*") {
+            let end = src[i + 2..]
+                .find("*/")
+                .map_or(bytes.len(), |e| i + 2 + e + 2);
+            line += src[i..end].matches('\n').count();
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            i += 1;
+            loop {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                // A hole is part of the name, e.g. `t{i}` in a block, but params are not
+                if c == b'$' || bytes.get(i) != Some(&b'{') {
+                    break;
+                }
+                match src[i + 1..].find(['}', '{', ':', ',', '\n']) {
+                    Some(end) if end > 0 && src[i + 1 + end..].starts_with('}') => i += end + 2,
+                    _ => break,
+                }
+            }
+            let tok = match c {
+                b'$' => Tok::Literal,
+                _ => Tok::Ident(src[start..i].to_string()),
+            };
+            tokens.push(Token {
+                tok,
+                span: start..i,
+                line,
+            });
+        } else if c.is_ascii_digit() {
+            while i < bytes.len()
+                && (bytes[i].is_ascii_digit()
+                    || (bytes[i] == b'.' && bytes.get(i + 1) != Some(&b'.')))
+            {
+                i += 1;
+            }
+            tokens.push(Token {
+                tok: Tok::Literal,
+                span: start..i,
+                line,
+            });
+        } else if c == b'"' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+            let text = src[start + 1..i.saturating_sub(1).max(start + 1)].to_string();
+            tokens.push(Token {
+                tok: Tok::Str(text),
+                span: start..i,
+                line,
+            });
+        } else {
+            let punct = [">>", "::", "..", "~>"]
+                .into_iter()
+                .find(|p| src[i..].starts_with(p))
+                .unwrap_or(match c {
+                    b'{' => "{",
+                    b'}' => "}",
+                    b'(' => "(",
+                    b')' => ")",
+                    b'[' => "[",
+                    b']' => "]",
+                    b':' => ":",
+                    b',' => ",",
+                    b'.' => ".",
+                    b'=' => "=",
+                    b'*' => "*",
+                    b'|' => "|",
+                    b'@' => "@",
+                    _ => "?",
+                });
+            // Step over a whole character, so spans stay on char boundaries
+            i += match punct {
+                "?" => src[i..].chars().next().map_or(1, char::len_utf8),
+                p => p.len(),
+            };
+            tokens.push(Token {
+                tok: Tok::Punct(punct),
+                span: start..i,
+                line,
+            });
+        }
+    }
+
+    tokens
+}
+
+/// `import "path" as namespace`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportOutline {
+    pub path: String,
+    pub span: Span,
+    pub namespace: String,
+}
+
+/// `use a::b as alias`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UseOutline {
+    pub path: Vec<String>,
+    pub span: Span,
+    pub alias: String,
+}
+
+/// A `patch` or `kernel` definition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MacroOutline {
+    pub keyword: String,
+    pub name: String,
+    pub name_span: Span,
+    pub params: Vec<String>,
+    pub ports: Vec<String>,
+    pub outputs: Vec<String>,
+    pub body: Span,
+}
+
+/// `audio { .. }` and the like, at the top level or in a patch, given by index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScopeOutline {
+    pub namespace: String,
+    pub namespace_span: Span,
+    pub body: Span,
+    pub parent: Option<usize>,
+    pub declarations: Vec<DeclOutline>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeclOutline {
+    pub node_type: String,
+    pub type_span: Span,
+    pub alias: Option<(String, Span)>,
+    /// The param names written so far, and their spans.
+    pub params: Vec<(String, Span)>,
+    /// Between the braces of the params, if there are any.
+    pub params_body: Option<Span>,
+}
+
+impl DeclOutline {
+    /// The name connections use for this node.
+    pub fn alias(&self) -> &str {
+        match &self.alias {
+            Some((alias, _)) => alias,
+            None => self.node_type.rsplit("::").next().unwrap_or_default(),
+        }
+    }
+}
+
+/// A node named in a connection or the outputs, e.g. `osc` and `freq` in `osc.freq`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reference {
+    pub node: String,
+    pub span: Span,
+    pub port: Option<(String, Span)>,
+    pub parent: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Outline {
+    pub imports: Vec<ImportOutline>,
+    pub uses: Vec<UseOutline>,
+    pub macros: Vec<MacroOutline>,
+    pub scopes: Vec<ScopeOutline>,
+    pub references: Vec<Reference>,
+}
+
+impl Outline {
+    pub fn new(src: &str) -> Self {
+        let mut scanner = Scanner {
+            tokens: lex(src),
+            pos: 0,
+            len: src.len(),
+            outline: Outline::default(),
+        };
+        scanner.block(None);
+        scanner.outline
+    }
+
+    /// The declarations at the top level, or in the patch `parent`.
+    pub fn declarations_in(&self, parent: Option<usize>) -> impl Iterator<Item = &DeclOutline> {
+        self.scopes
+            .iter()
+            .filter(move |s| s.parent == parent)
+            .flat_map(|s| &s.declarations)
+    }
+
+    pub fn find_macro(&self, name: &str) -> Option<&MacroOutline> {
+        self.macros.iter().find(|m| m.name == name)
+    }
+}
+
+struct Scanner {
+    tokens: Vec<Token>,
+    pos: usize,
+    len: usize,
+    outline: Outline,
+}
+
+impl Scanner {
+    fn peek(&self, ahead: usize) -> Option<&Tok> {
+        self.tokens.get(self.pos + ahead).map(|t| &t.tok)
+    }
+
+    fn is_punct(&self, ahead: usize, punct: &str) -> bool {
+        matches!(self.peek(ahead), Some(Tok::Punct(p)) if *p == punct)
+    }
+
+    fn ident(&self, ahead: usize) -> Option<String> {
+        match self.peek(ahead) {
+            Some(Tok::Ident(name)) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    fn span(&self, ahead: usize) -> Span {
+        self.tokens
+            .get(self.pos + ahead)
+            .map_or(self.len..self.len, |t| t.span.clone())
+    }
+
+    /// The end of the brace that closes the one just stepped over, stepping past it.
+    fn skip_group(&mut self, open: &str, close: &str) -> usize {
+        let mut depth = 1;
+        while let Some(tok) = self.peek(0).cloned() {
+            let end = self.span(0).end;
+            self.pos += 1;
+            match tok {
+                Tok::Punct(p) if p == open => depth += 1,
+                Tok::Punct(p) if p == close => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return end;
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.len
+    }
+
+    /// `a::b::c`, returning the segments and their span.
+    fn path(&mut self) -> (Vec<String>, Span) {
+        let start = self.span(0).start;
+        let mut end = start;
+        let mut path = vec![];
+        while let Some(name) = self.ident(0) {
+            end = self.span(0).end;
+            path.push(name);
+            self.pos += 1;
+            if self.is_punct(0, "::") && self.ident(1).is_some() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+        (path, start..end)
+    }
+
+    /// `as name`, if it is there.
+    fn rename(&mut self) -> Option<String> {
+        if self.ident(0).as_deref() == Some("as") {
+            let name = self.ident(1);
+            self.pos += 1 + name.is_some() as usize;
+            return name;
+        }
+        None
+    }
+
+    /// Items until the `}` closing the patch `parent`, or the end of the file.
+    fn block(&mut self, parent: Option<usize>) -> usize {
+        while let Some(tok) = self.peek(0).cloned() {
+            if tok == Tok::Punct("}") && parent.is_some() {
+                let end = self.span(0).end;
+                self.pos += 1;
+                return end;
+            }
+            self.item(tok, parent);
+        }
+        self.len
+    }
+
+    fn item(&mut self, tok: Tok, parent: Option<usize>) {
+        match tok {
+            Tok::Punct("{") => self.outputs(parent),
+            Tok::Ident(word) => match word.as_str() {
+                "import" => self.import(),
+                "use" => self.use_(),
+                "patch" | "kernel" if self.ident(1).is_some() => self.definition(word),
+                "in" | "out" if parent.is_some() && self.ident(1).is_some() => {
+                    self.ports(&word, parent)
+                }
+                "for" if self.ident(2).as_deref() == Some("in") => self.generator(parent),
+                "if" if matches!(self.peek(1), Some(Tok::Literal | Tok::Ident(_))) => {
+                    self.generator(parent)
+                }
+                "else" if self.is_punct(1, "{") => self.generator(parent),
+                "oversample" if self.is_punct(1, "(") => self.generator(parent),
+                _ if self.is_punct(1, "{") => self.scope(word, parent),
+                _ => self.reference(parent),
+            },
+            _ => self.pos += 1,
+        }
+    }
+
+    /// A `for`, `if`, `else` or `oversample` block, whose body belongs to the patch `parent` as if it
+    /// were written there.
+    fn generator(&mut self, parent: Option<usize>) {
+        // The header, up to the opening brace
+        while let Some(tok) = self.peek(0).cloned() {
+            self.pos += 1;
+            if tok == Tok::Punct("{") {
+                break;
+            }
+        }
+        while let Some(tok) = self.peek(0).cloned() {
+            if tok == Tok::Punct("}") {
+                self.pos += 1;
+                return;
+            }
+            self.item(tok, parent);
+        }
+    }
+
+    fn import(&mut self) {
+        let start = self.span(0).start;
+        self.pos += 1;
+        let Some(Tok::Str(path)) = self.peek(0).cloned() else {
+            return;
+        };
+        self.pos += 1;
+
+        let namespace = self.rename().unwrap_or_else(|| {
+            std::path::Path::new(&path)
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        let end = self.tokens[self.pos - 1].span.end;
+
+        self.outline.imports.push(ImportOutline {
+            path,
+            span: start..end,
+            namespace,
+        });
+    }
+
+    fn use_(&mut self) {
+        let start = self.span(0).start;
+        self.pos += 1;
+        let (path, span) = self.path();
+        let alias = self
+            .rename()
+            .or_else(|| path.last().cloned())
+            .unwrap_or_default();
+        let end = self.tokens[self.pos - 1].span.end.max(span.end);
+
+        self.outline.uses.push(UseOutline {
+            path,
+            span: start..end,
+            alias,
+        });
+    }
+
+    fn definition(&mut self, keyword: String) {
+        let name = self.ident(1).unwrap_or_default();
+        let name_span = self.span(1);
+        self.pos += 2;
+
+        // `(freq = 440.0, gain = 0.5)`, keeping the names
+        let mut params = vec![];
+        if self.is_punct(0, "(") {
+            self.pos += 1;
+            let mut depth = 1;
+            while let Some(tok) = self.peek(0).cloned() {
+                match tok {
+                    Tok::Punct("(" | "[" | "{") => depth += 1,
+                    Tok::Punct(")" | "]" | "}") => depth -= 1,
+                    Tok::Ident(param) if depth == 1 && self.is_punct(1, "=") => params.push(param),
+                    _ => {}
+                }
+                self.pos += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        }
+
+        // `@ /2` or `@ x2`
+        if self.is_punct(0, "@") {
+            self.pos += 1;
+            while self.peek(0).is_some() && !self.is_punct(0, "{") && !self.is_punct(0, "}") {
+                self.pos += 1;
+            }
+        }
+
+        if !self.is_punct(0, "{") {
+            return;
+        }
+        let start = self.span(0).start;
+        self.pos += 1;
+
+        let index = self.outline.macros.len();
+        self.outline.macros.push(MacroOutline {
+            keyword,
+            name,
+            name_span,
+            params,
+            ports: vec![],
+            outputs: vec![],
+            body: start..self.len,
+        });
+
+        let end = self.block(Some(index));
+        self.outline.macros[index].body.end = end;
+    }
+
+    /// `in gate freq` or `out dry wet`, up to the end of the line.
+    fn ports(&mut self, keyword: &str, parent: Option<usize>) {
+        let line = self.tokens[self.pos].line;
+        self.pos += 1;
+        while let Some(port) = self.ident(0) {
+            if self.tokens[self.pos].line != line {
+                break;
+            }
+            if let Some(index) = parent {
+                let found = &mut self.outline.macros[index];
+                match keyword {
+                    "in" => found.ports.push(port),
+                    _ => found.outputs.push(port),
+                }
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// `{ sink }` or `{ main, cue: bus }`.
+    fn outputs(&mut self, parent: Option<usize>) {
+        self.pos += 1;
+        while let Some(tok) = self.peek(0).cloned() {
+            match tok {
+                Tok::Punct("}") => {
+                    self.pos += 1;
+                    return;
+                }
+                // Output names are not nodes
+                Tok::Ident(_) if self.is_punct(1, ":") => self.pos += 2,
+                Tok::Ident(_) => self.reference(parent),
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// A node in a connection, with its port if it has one.
+    fn reference(&mut self, parent: Option<usize>) {
+        let node = self.ident(0).unwrap_or_default();
+        let span = self.span(0);
+        self.pos += 1;
+
+        // A selector, e.g. `voice(0)` or `voice(*)`
+        if self.is_punct(0, "(") {
+            self.pos += 1;
+            self.skip_group("(", ")");
+        }
+        // A port index, e.g. `fb[0]` or `fb[{i}]`
+        if self.is_punct(0, "[") {
+            self.pos += 1;
+            self.skip_group("[", "]");
+        }
+
+        let port = match (self.is_punct(0, "."), self.ident(1)) {
+            (true, Some(port)) => {
+                let port_span = self.span(1);
+                self.pos += 2;
+                Some((port, port_span))
+            }
+            // `osc.` while typing the port
+            (true, None) => {
+                let at = self.span(0).end;
+                self.pos += 1;
+                Some((String::new(), at..at))
+            }
+            _ => None,
+        };
+
+        self.outline.references.push(Reference {
+            node,
+            span,
+            port,
+            parent,
+        });
+    }
+
+    fn scope(&mut self, namespace: String, parent: Option<usize>) {
+        let namespace_span = self.span(0);
+        let start = self.span(1).start;
+        self.pos += 2;
+
+        let mut declarations = vec![];
+        let mut end = self.len;
+        while let Some(tok) = self.peek(0).cloned() {
+            match tok {
+                Tok::Punct("}") => {
+                    end = self.span(0).end;
+                    self.pos += 1;
+                    break;
+                }
+                Tok::Ident(_) => declarations.push(self.declaration()),
+                _ => self.pos += 1,
+            }
+        }
+
+        self.outline.scopes.push(ScopeOutline {
+            namespace,
+            namespace_span,
+            body: start..end,
+            parent,
+            declarations,
+        });
+    }
+
+    /// `type: alias * count { params } | pipe`, everything after the type being optional.
+    fn declaration(&mut self) -> DeclOutline {
+        let (path, type_span) = self.path();
+
+        let alias = match (self.is_punct(0, ":"), self.ident(1)) {
+            (true, Some(alias)) => {
+                let span = self.span(1);
+                self.pos += 2;
+                Some((alias, span))
+            }
+            _ => None,
+        };
+
+        // `* 4`, or an expression such as `* ($n + 1)`, up to the params
+        if self.is_punct(0, "*") {
+            let line = self.tokens[self.pos].line;
+            self.pos += 1;
+            let mut depth = 0usize;
+            while let Some(token) = self.tokens.get(self.pos).filter(|t| t.line == line) {
+                match token.tok {
+                    Tok::Punct("(") => depth += 1,
+                    Tok::Punct(")") => depth = depth.saturating_sub(1),
+                    Tok::Punct("{" | "}" | "|" | ",") if depth == 0 => break,
+                    _ => {}
+                }
+                self.pos += 1;
+            }
+        }
+
+        let mut params = vec![];
+        let mut params_body = None;
+        if self.is_punct(0, "{") {
+            let start = self.span(0).start;
+            self.pos += 1;
+            let mut depth = 1;
+            while let Some(tok) = self.peek(0).cloned() {
+                match tok {
+                    Tok::Punct("{" | "[" | "(") => depth += 1,
+                    Tok::Punct("}" | "]" | ")") => depth -= 1,
+                    Tok::Ident(key) if depth == 1 && self.is_punct(1, ":") => {
+                        params.push((key, self.span(0)))
+                    }
+                    _ => {}
+                }
+                if depth == 0 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            params_body = Some(start..self.span(0).end);
+            self.pos += 1;
+        }
+
+        // `| spread(0.8)`, which are not declarations of their own
+        while self.is_punct(0, "|") {
+            self.pos += 1;
+            if self.ident(0).is_some() {
+                self.pos += 1;
+            }
+            if self.is_punct(0, "(") {
+                while !matches!(self.peek(0), None | Some(Tok::Punct(")" | "}"))) {
+                    self.pos += 1;
+                }
+                if self.is_punct(0, ")") {
+                    self.pos += 1;
+                }
+            }
+        }
+
+        DeclOutline {
+            node_type: path.join("::"),
+            type_span,
+            alias,
+            params,
+            params_body,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outlines_a_patch_and_its_scopes() {
+        let src = r#"
+            import "verbs.legato" as v
+            use v::plate
+
+            patch voice(freq = 440.0) {
+                in gate
+                audio { sine: osc { freq: $freq }, adsr { attack: 1.0, } }
+                gate >> adsr.gate
+                { adsr }
+            }
+
+            audio { svf * 2 { cutoff: 100.0, q: } }
+            patches { voice: lead {} }
+
+            lead >> svf.
+        "#;
+        let outline = Outline::new(src);
+
+        assert_eq!(outline.imports[0].namespace, "v");
+        assert_eq!(outline.uses[0].alias, "plate");
+
+        let voice = outline.find_macro("voice").unwrap();
+        assert_eq!(voice.params, ["freq"]);
+        assert_eq!(voice.ports, ["gate"]);
+        assert_eq!(&src[voice.name_span.clone()], "voice");
+
+        let inner: Vec<_> = outline
+            .declarations_in(Some(0))
+            .map(|d| d.alias())
+            .collect();
+        assert_eq!(inner, ["osc", "adsr"]);
+        let top: Vec<_> = outline.declarations_in(None).map(|d| d.alias()).collect();
+        assert_eq!(top, ["svf", "lead"]);
+
+        let svf = outline.declarations_in(None).next().unwrap();
+        let keys: Vec<_> = svf.params.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["cutoff", "q"]);
+
+        // The half typed port at the end
+        let last = outline.references.last().unwrap();
+        assert_eq!(last.node, "svf");
+        assert_eq!(last.port.as_ref().unwrap().0, "");
+    }
+
+    #[test]
+    fn unclosed_scopes_run_to_the_end() {
+        let src = "audio { sine { fr";
+        let outline = Outline::new(src);
+
+        let decl = &outline.scopes[0].declarations[0];
+        assert_eq!(decl.node_type, "sine");
+        assert_eq!(decl.params_body, Some(13..src.len()));
+        assert_eq!(outline.scopes[0].body.end, src.len());
+    }
+
+    #[test]
+    fn looks_inside_blocks() {
+        let src = r#"
+            patch bank(n = 2) {
+                audio { add: mix }
+                for i in 0..$n {
+                    audio { sine: t{i} }
+                    if $i % 2 { t{i} >> mix[{i}] } else { mix >> t{i} }
+                }
+                oversample(2) { audio { svf } mix >> svf }
+                { mix }
+            }
+        "#;
+        let outline = Outline::new(src);
+
+        let aliases: Vec<_> = outline
+            .declarations_in(Some(0))
+            .map(|d| d.alias())
+            .collect();
+        assert_eq!(aliases, ["mix", "t{i}", "svf"]);
+
+        let nodes: Vec<_> = outline.references.iter().map(|r| r.node.as_str()).collect();
+        assert_eq!(nodes, ["t{i}", "mix", "mix", "t{i}", "mix", "svf", "mix"]);
+    }
+
+    #[test]
+    fn pipes_are_not_declarations() {
+        let src = "audio { saw * 2 { chans: 1 } | spread(0.5) | stereo, sine }";
+        let outline = Outline::new(src);
+
+        let types: Vec<_> = outline
+            .declarations_in(None)
+            .map(|d| d.node_type.as_str())
+            .collect();
+        assert_eq!(types, ["saw", "sine"]);
+    }
+
+    #[test]
+    fn counts_may_be_expressions() {
+        let src = "audio { saw * ($n + 1) { chans: 1 }, sine * $n, noise }";
+        let outline = Outline::new(src);
+
+        let types: Vec<_> = outline
+            .declarations_in(None)
+            .map(|d| d.node_type.as_str())
+            .collect();
+        assert_eq!(types, ["saw", "sine", "noise"]);
+        let saw = outline.declarations_in(None).next().unwrap();
+        assert_eq!(saw.params.len(), 1);
+    }
+
+    #[test]
+    fn feedback_links_are_references() {
+        let src = "audio { delay, add: mix }\nmix >> delay ~> mix[1]\n{ mix }";
+        let outline = Outline::new(src);
+
+        let nodes: Vec<_> = outline.references.iter().map(|r| r.node.as_str()).collect();
+        assert_eq!(nodes, ["mix", "delay", "mix", "mix"]);
+    }
+
+    #[test]
+    fn patch_outputs_are_listed() {
+        let src = "patch p() {\n    in x\n    out dry wet\n    audio { sine }\n    { sine }\n}";
+        let outline = Outline::new(src);
+
+        assert_eq!(outline.macros[0].ports, ["x"]);
+        assert_eq!(outline.macros[0].outputs, ["dry", "wet"]);
+    }
+
+    #[test]
+    fn rates_are_skipped() {
+        for rate in ["/2", "x4"] {
+            let src = format!(
+                "patch lofi(bits = 8) @ {rate} {{\n    audio {{ sine }}\n    {{ sine }}\n}}"
+            );
+            let outline = Outline::new(&src);
+
+            assert_eq!(outline.macros[0].params, ["bits"]);
+            assert_eq!(outline.macros[0].body, 26..src.len());
+            assert_eq!(outline.declarations_in(Some(0)).count(), 1);
+        }
+    }
+}
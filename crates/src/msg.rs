@@ -1,4 +1,4 @@
-use crate::runtime::NodeKey;
+use crate::{edit::GraphEdit, reload::GraphSwap, runtime::NodeKey, tap::TapSender};
 
 /// A subset of the Values used in the AST that are realtime safe
 #[derive(Clone, Debug, PartialEq)]
@@ -16,9 +16,15 @@ pub struct ParamPayload {
     pub value: RtValue,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug)]
 pub enum LegatoMsg {
     NodeMessage(NodeKey, NodeMessage),
+    /// Replace the running graph with one prepared off of the audio thread.
+    SwapGraph(Box<GraphSwap>),
+    /// Add or remove a node or edge, with the edited graph prepared off of the audio thread.
+    EditGraph(Box<GraphEdit>),
+    /// Start feeding a node's output to the frontend. The node must already be pinned.
+    AddTap(TapSender),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -35,5 +41,9 @@ pub struct StepPayload {
 pub enum NodeMessage {
     SetParam(ParamPayload),
     SetStep(StepPayload),
+    /// Pass the node's inputs straight through to its outputs. Handled by the executor.
+    SetBypass(bool),
+    /// Silence the node's outputs. Handled by the executor, and wins over bypass.
+    SetMute(bool),
     Dummy(),
 }
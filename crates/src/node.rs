@@ -1,6 +1,10 @@
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug};
 
-use crate::{context::AudioContext, msg::NodeMessage, ports::Ports};
+use crate::{
+    context::{AudioContext, Rate},
+    msg::NodeMessage,
+    ports::Ports,
+};
 
 pub type Inputs<'a> = [Option<&'a [f32]>];
 
@@ -22,6 +26,44 @@ pub trait Node {
     fn handle_msg(&mut self, _msg: NodeMessage) {}
     // Get the port information for your node. This should not change after contruction.
     fn ports(&self) -> &Ports;
+    /// Adopt the running state (phase, envelope stage, filter memory...) of the node this one
+    /// replaces on a hot reload. `previous` had the same alias and node kind, but may have been
+    /// built with different params or channel counts, so only copy what still fits.
+    ///
+    /// This runs on the audio thread and must not allocate.
+    fn carry_state(&mut self, _previous: &dyn Any) {}
+    /// Whether `process` may change state other nodes can see through the context, like delay
+    /// lines, audio inputs, system midi or the context's config.
+    ///
+    /// A parallel executor never runs these alongside another node, and keeps them in serial
+    /// order. Nodes that only read the context, like oscillators and filters, return `false` so
+    /// they can run alongside each other; they must then never write through it.
+    fn shares_context(&self) -> bool {
+        true
+    }
+    /// How an audio-rate node reads this node's outputs while it runs at control rate, see
+    /// [`crate::executor::Executor::set_control_rate`].
+    fn control_interpolation(&self) -> Interpolation {
+        Interpolation::Linear
+    }
+    /// Whether this node converts between rates, reading its inputs at the rate of the nodes
+    /// feeding it, and writing its outputs at its own [`LegatoNode::rate`]. Its inputs and
+    /// outputs then differ in length.
+    ///
+    /// Every other node reads and writes at its own rate.
+    fn resamples(&self) -> bool {
+        false
+    }
+}
+
+/// How a control-rate output is spread back over the block for an audio-rate reader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Ramp from each value to the next, for smooth modulation like an LFO's.
+    #[default]
+    Linear,
+    /// Hold each value until the next, for gates, steps and wrapping ramps.
+    Step,
 }
 
 // This ceremony with NodeClone and DynNode is needed so that we can "clone" nodes by cloning the interior and boxing the result,
@@ -31,8 +73,19 @@ pub trait NodeClone {
     fn clone_box(&self) -> Box<dyn DynNode>;
 }
 
-pub trait DynNode: Node + NodeClone + Send {}
-impl<T> DynNode for T where T: Node + NodeClone + Send {}
+/// Lets a type erased node be downcast again, e.g. to carry state across a hot reload.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<T: Node + 'static> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+pub trait DynNode: Node + NodeClone + AsAny + Send {}
+impl<T> DynNode for T where T: Node + NodeClone + AsAny + Send {}
 
 impl<T> NodeClone for T
 where
@@ -48,6 +101,8 @@ pub struct LegatoNode {
     pub name: String,
     pub node_kind: String,
     node: Box<dyn DynNode>,
+    pub(crate) bypass: Bypass,
+    rate: Rate,
 }
 
 impl LegatoNode {
@@ -56,9 +111,25 @@ impl LegatoNode {
             name,
             node_kind,
             node,
+            bypass: Bypass::default(),
+            rate: Rate::GRAPH,
         }
     }
 
+    /// Run the node at `rate` rather than the graph's, e.g. [`Rate::times`] 4 to oversample it.
+    ///
+    /// The builder puts a resampler on every connection between nodes at different rates, and
+    /// the node sees its own rate through [`AudioContext::get_config`].
+    pub fn with_rate(mut self, rate: Rate) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// The rate the node runs at, relative to the graph's.
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
     #[inline(always)]
     pub fn get_node(&self) -> &dyn DynNode {
         &*self.node
@@ -68,10 +139,29 @@ impl LegatoNode {
     pub fn get_node_mut(&mut self) -> &mut Box<dyn DynNode> {
         &mut self.node
     }
+    /// Whether the node is set to pass its inputs straight through.
+    pub fn is_bypassed(&self) -> bool {
+        self.bypass.bypassed
+    }
+
+    /// Whether the node is set to output silence.
+    pub fn is_muted(&self) -> bool {
+        self.bypass.muted
+    }
+
+    #[inline(always)]
+    pub(crate) fn split_mut(&mut self) -> (&mut dyn DynNode, &mut Bypass) {
+        (&mut *self.node, &mut self.bypass)
+    }
+
     /// A meta wrapper that handles messages. This is used because we may need more messages in the future than just params
     #[inline(always)]
     pub fn handle_msg(&mut self, msg: NodeMessage) {
-        self.get_node_mut().as_mut().handle_msg(msg);
+        match msg {
+            NodeMessage::SetBypass(bypassed) => self.bypass.bypassed = bypassed,
+            NodeMessage::SetMute(muted) => self.bypass.muted = muted,
+            msg => self.get_node_mut().as_mut().handle_msg(msg),
+        }
     }
 }
 
@@ -81,6 +171,8 @@ impl Clone for LegatoNode {
             name: self.name.clone(),
             node_kind: self.node_kind.clone(),
             node: self.node.clone_box(),
+            bypass: self.bypass.clone(),
+            rate: self.rate,
         }
     }
 }
@@ -93,3 +185,85 @@ impl Debug for LegatoNode {
             .finish()
     }
 }
+
+/// How many samples a node takes to fade between running, bypassed and muted.
+pub const BYPASS_RAMP: usize = 256;
+
+/// The bypass and mute flags on a node, and how far it has faded towards them.
+///
+/// The executor mixes the node's own outputs with its inputs, channel by channel, so any node
+/// can be bypassed. Generators have nothing to pass through, so bypassing one silences it.
+#[derive(Clone, Debug)]
+pub(crate) struct Bypass {
+    bypassed: bool,
+    muted: bool,
+    // Gain on the node's outputs, and on the inputs passed through in their place
+    wet: f32,
+    dry: f32,
+}
+
+impl Default for Bypass {
+    fn default() -> Self {
+        Self {
+            bypassed: false,
+            muted: false,
+            wet: 1.0,
+            dry: 0.0,
+        }
+    }
+}
+
+impl Bypass {
+    fn targets(&self) -> (f32, f32) {
+        match (self.muted, self.bypassed) {
+            (true, _) => (0.0, 0.0),
+            (false, true) => (0.0, 1.0),
+            (false, false) => (1.0, 0.0),
+        }
+    }
+
+    /// Running as normal, with nothing left to fade.
+    #[inline(always)]
+    pub(crate) fn is_idle(&self) -> bool {
+        self.targets() == (1.0, 0.0) && self.wet == 1.0 && self.dry == 0.0
+    }
+
+    /// Whether the node's own outputs are heard at all, this block or once the fade ends.
+    #[inline(always)]
+    pub(crate) fn runs_node(&self) -> bool {
+        self.wet > 0.0 || self.targets().0 > 0.0
+    }
+
+    /// Mix the node's outputs with its inputs. If the node did not run, its outputs are
+    /// overwritten rather than read.
+    pub(crate) fn apply(&mut self, inputs: &Inputs, outputs: &mut [&mut [f32]], ran: bool) {
+        let (wet_to, dry_to) = self.targets();
+        let step = 1.0 / BYPASS_RAMP as f32;
+
+        for (chan, out) in outputs.iter_mut().enumerate() {
+            let input = inputs.get(chan).copied().flatten();
+            let (mut wet, mut dry) = (self.wet, self.dry);
+
+            for (s, y) in out.iter_mut().enumerate() {
+                wet = approach(wet, wet_to, step);
+                dry = approach(dry, dry_to, step);
+
+                let wet_part = if ran { *y * wet } else { 0.0 };
+                *y = wet_part + input.map_or(0.0, |x| x[s] * dry);
+            }
+        }
+
+        let block = outputs.first().map_or(0, |o| o.len());
+        self.wet = approach(self.wet, wet_to, step * block as f32);
+        self.dry = approach(self.dry, dry_to, step * block as f32);
+    }
+}
+
+#[inline(always)]
+fn approach(from: f32, to: f32, step: f32) -> f32 {
+    if from < to {
+        (from + step).min(to)
+    } else {
+        (from - step).max(to)
+    }
+}
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use crate::{
     math::lerp,
     msg::{NodeMessage, RtValue},
@@ -165,6 +167,18 @@ impl Node for Adsr {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.state = prev.state.clone();
+            self.state_delta_t = prev.state_delta_t;
+            self.attack_starting_level = prev.attack_starting_level;
+            self.release_starting_level = prev.release_starting_level;
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -185,11 +199,11 @@ impl NodeDefinition for Adsr {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let attack = p.get_f32("attack").expect("Must provide attack to ADSR");
-        let decay = p.get_f32("decay").expect("Must provide decay to ADSR");
-        let sustain = p.get_f32("sustain").expect("Must provide sustain to ADSR");
-        let release = p.get_f32("release").expect("Must provide release to ADSR");
-        let chans = p.get_usize("chans").expect("Must provide chans to ADSR");
+        let attack = p.require("attack", DSLParams::get_f32)?;
+        let decay = p.require("decay", DSLParams::get_f32)?;
+        let sustain = p.require("sustain", DSLParams::get_f32)?;
+        let release = p.require("release", DSLParams::get_f32)?;
+        let chans = p.require("chans", DSLParams::get_usize)?;
         Ok(Box::new(Self::new(chans, attack, decay, sustain, release)))
     }
 }
@@ -0,0 +1,88 @@
+use crate::{
+    builder::{ResourceBuilderView, ValidationError},
+    context::AudioContext,
+    dsl::ir::DSLParams,
+    msg::{NodeMessage, RtValue},
+    node::{DynNode, Inputs, Node},
+    persample::PerSampleNode,
+    ports::{PortBuilder, Ports},
+    spec::NodeDefinition,
+};
+
+/// Outputs `val` on every channel, e.g. a DC rail into a frequency port.
+///
+/// The DSL creates these for numbers in connections, as in `440.0 >> osc.freq`.
+#[derive(Clone)]
+pub struct Constant {
+    val: f32,
+    ports: Ports,
+}
+
+impl Constant {
+    pub fn new(val: f32, chans: usize) -> Self {
+        Self {
+            val,
+            ports: PortBuilder::default().audio_out(chans).build(),
+        }
+    }
+
+    pub fn from_params(p: &DSLParams) -> Result<Self, ValidationError> {
+        Ok(Self::new(
+            p.get_f32("val")?.unwrap_or(0.0),
+            p.get_usize("chans")?.unwrap_or(1),
+        ))
+    }
+}
+
+impl Node for Constant {
+    /// Set the output value, i.e. the `val` param. Unknown names are ignored.
+    fn handle_msg(&mut self, msg: NodeMessage) {
+        if let NodeMessage::SetParam(payload) = msg
+            && let ("val", RtValue::F32(value)) = (payload.param_name, payload.value)
+        {
+            self.val = value;
+        }
+    }
+
+    fn process(&mut self, _: &mut AudioContext, _: &Inputs, outputs: &mut [&mut [f32]]) {
+        for channel in outputs.iter_mut() {
+            channel.fill(self.val);
+        }
+    }
+
+    fn ports(&self) -> &Ports {
+        &self.ports
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
+}
+
+impl PerSampleNode for Constant {
+    fn ports(&self) -> &Ports {
+        &self.ports
+    }
+
+    fn tick(&mut self, _in_frame: &[Option<f32>], out_frame: &mut [f32]) {
+        out_frame.fill(self.val);
+    }
+
+    fn handle_msg(&mut self, msg: NodeMessage) {
+        Node::handle_msg(self, msg);
+    }
+}
+
+impl NodeDefinition for Constant {
+    const NAME: &'static str = "const";
+    const DESCRIPTION: &'static str = "Outputs a constant value on every channel";
+    const REQUIRED_PARAMS: &'static [&'static str] = &["val"];
+    const OPTIONAL_PARAMS: &'static [&'static str] = &["chans"];
+
+    fn create(
+        _rb: &mut ResourceBuilderView,
+        p: &DSLParams,
+    ) -> Result<Box<dyn DynNode>, ValidationError> {
+        Ok(Box::new(Self::from_params(p)?))
+    }
+}
@@ -68,6 +68,10 @@ impl Node for FirFilter {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
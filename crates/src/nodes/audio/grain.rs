@@ -264,6 +264,10 @@ impl Node for Granular {
             self.sample_pos = (self.sample_pos + self.scan).rem_euclid(region_len);
         }
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl NodeDefinition for Granular {
@@ -276,20 +280,18 @@ impl NodeDefinition for Granular {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let name = p
-            .get_str("sampler_name")
-            .expect("Could not find required parameter sampler_name");
+        let name = p.require("sampler_name", DSLParams::get_str)?;
 
-        let chans = p.get_usize("chans").unwrap_or(2);
+        let chans = p.get_usize("chans")?.unwrap_or(2);
 
         let grain_size = p
-            .get_duration_ms("size")
+            .get_duration_ms("size")?
             .unwrap_or(Duration::from_millis(200))
             .clamp(Duration::from_millis(5), Duration::from_secs(3));
 
-        let shape = p.get_f32("shape").unwrap_or(0.3);
+        let shape = p.get_f32("shape")?.unwrap_or(0.3);
 
-        let scan = p.get_f32("scan").unwrap_or(1.0);
+        let scan = p.get_f32("scan")?.unwrap_or(1.0);
 
         let key = rb.add_external_buffer_key(&name);
 
@@ -76,6 +76,10 @@ impl Node for HadamardMixer {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl PerSampleNode for HadamardMixer {
@@ -106,9 +110,12 @@ impl HadamardMixer {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let chans = p
-            .get_usize("chans")
-            .expect("Must provide chans to hadamard");
+        let chans = p.require("chans", DSLParams::get_usize)?;
+        if !chans.is_power_of_two() {
+            return Err(ValidationError::InvalidParameter(format!(
+                "hadamard `chans` should be a power of two, found {chans}"
+            )));
+        }
         Ok(Self::new(chans))
     }
 }
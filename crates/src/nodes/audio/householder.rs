@@ -44,6 +44,10 @@ impl Node for HouseholderMixer {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl PerSampleNode for HouseholderMixer {
@@ -73,9 +77,7 @@ impl HouseholderMixer {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let chans = p
-            .get_usize("chans")
-            .expect("Must provide chans to householder");
+        let chans = p.require("chans", DSLParams::get_usize)?;
         Ok(Self::new(chans))
     }
 }
@@ -78,6 +78,10 @@ impl Node for TrackMixer {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 /// A mono -> N mixer with unity gain
@@ -113,6 +117,10 @@ impl Node for MonoFanOut {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -133,15 +141,17 @@ impl NodeDefinition for TrackMixer {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let chans_per_track = p
-            .get_usize("chans_per_track")
-            .expect("Could not find required parameter chans_per_track for track mixer!");
-        let tracks = p
-            .get_usize("tracks")
-            .expect("Could not find required parameter tracks for track mixer!");
+        let chans_per_track = p.require("chans_per_track", DSLParams::get_usize)?;
+        let tracks = p.require("tracks", DSLParams::get_usize)?;
         let gain = p
-            .get_array_f32("gain")
+            .get_array_f32("gain")?
             .unwrap_or(vec![(1.0 / f32::sqrt(tracks as f32))]);
+        if gain.len() != 1 && gain.len() != tracks {
+            return Err(ValidationError::InvalidParameter(format!(
+                "track_mixer gain has {} values, but should have 1 or one per track ({tracks})",
+                gain.len()
+            )));
+        }
         Ok(Box::new(Self::new(chans_per_track, tracks, gain)))
     }
 }
@@ -195,7 +205,7 @@ impl NodeDefinition for MonoFanOut {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let chans = p.get_usize("chans").unwrap_or(2);
+        let chans = p.get_usize("chans")?.unwrap_or(2);
         Ok(Box::new(Self::new(chans)))
     }
 }
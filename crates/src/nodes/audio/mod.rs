@@ -1,5 +1,6 @@
 pub mod adsr;
 pub mod allpass;
+pub mod constant;
 pub mod delay;
 pub mod external;
 pub mod fir;
@@ -13,9 +14,11 @@ pub mod ops;
 pub mod oversample;
 pub mod pan;
 pub mod plate;
+pub mod resample;
 pub mod sampler;
 pub mod saw;
 pub mod sine;
+pub mod stereo;
 pub mod svf;
 pub mod sweep;
 pub mod tap;
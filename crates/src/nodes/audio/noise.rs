@@ -66,6 +66,10 @@ impl Node for Noise {
             out.iter_mut().for_each(|x| *x = self.white())
         }
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl PerSampleNode for Noise {
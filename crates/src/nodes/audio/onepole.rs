@@ -1,4 +1,4 @@
-use std::f32::consts::TAU;
+use std::{any::Any, f32::consts::TAU};
 
 use crate::{
     context::AudioContext,
@@ -65,6 +65,19 @@ impl Node for OnePole {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.state
+                .iter_mut()
+                .zip(prev.state.iter())
+                .for_each(|(dst, src)| *dst = *src);
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl PerSampleNode for OnePole {
@@ -85,6 +98,10 @@ impl PerSampleNode for OnePole {
     fn handle_msg(&mut self, msg: NodeMessage) {
         Node::handle_msg(self, msg);
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        Node::carry_state(self, previous);
+    }
 }
 
 fn get_a_from_cutoff(sr: f32, fc: f32) -> f32 {
@@ -105,15 +122,13 @@ impl OnePole {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let chans = p.get_usize("chans").unwrap_or(2);
+        let chans = p.get_usize("chans")?.unwrap_or(2);
         // Either a cutoff in Hz, or the pole coefficient `a` directly (handy
         // when porting designs specified as raw one-pole coefficients).
-        if let Some(a) = p.get_f32("a") {
+        if let Some(a) = p.get_f32("a")? {
             Ok(Self::from_coefficient(a, chans))
         } else {
-            let cutoff = p
-                .get_f32("cutoff")
-                .expect("onepole requires either `cutoff` (Hz) or `a` (pole coefficient)");
+            let cutoff = p.require("cutoff", DSLParams::get_f32)?;
             let sr = rb.get_config().sample_rate;
             Ok(Self::new(cutoff, chans, sr))
         }
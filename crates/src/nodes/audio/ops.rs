@@ -120,6 +120,10 @@ impl Node for ApplyOp {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 fn add(a: Vf32, b: Vf32) -> Vf32 {
@@ -190,7 +194,7 @@ impl NodeDefinition for MultDef {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let val = p.get_f32("val").unwrap_or(1.0);
+        let val = p.get_f32("val")?.unwrap_or(1.0);
         Ok(Box::new(mult_node_factory(val, 1, ApplyOpKind::Mult)))
     }
 }
@@ -205,7 +209,7 @@ impl NodeDefinition for AddDef {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let val = p.get_f32("val").unwrap_or(0.0);
+        let val = p.get_f32("val")?.unwrap_or(0.0);
         Ok(Box::new(mult_node_factory(val, 1, ApplyOpKind::Add)))
     }
 }
@@ -220,7 +224,7 @@ impl NodeDefinition for SubDef {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let val = p.get_f32("val").unwrap_or(0.0);
+        let val = p.get_f32("val")?.unwrap_or(0.0);
         Ok(Box::new(mult_node_factory(val, 1, ApplyOpKind::Subtract)))
     }
 }
@@ -235,7 +239,7 @@ impl NodeDefinition for DivDef {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let val = p.get_f32("val").unwrap_or(0.0);
+        let val = p.get_f32("val")?.unwrap_or(0.0);
         Ok(Box::new(mult_node_factory(val, 1, ApplyOpKind::Div)))
     }
 }
@@ -251,8 +255,8 @@ impl NodeDefinition for GainDef {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let chans = p.get_usize("chans").unwrap_or(2);
-        let val = p.get_f32("val").unwrap_or(1.0);
+        let chans = p.get_usize("chans")?.unwrap_or(2);
+        let val = p.get_f32("val")?.unwrap_or(1.0);
         Ok(Box::new(mult_node_factory(val, chans, ApplyOpKind::Gain)))
     }
 }
@@ -1,173 +1,158 @@
-use std::cmp::max;
-
-use halfband::fir::{Downsampler16, Upsampler16};
+use std::{any::Any, cmp::max};
 
 use crate::{
-    context::AudioContext,
+    context::{AudioContext, Rate, at_rate},
     executor::MAX_ARITY,
+    msg::NodeMessage,
     node::{Inputs, LegatoNode, Node},
+    nodes::audio::resample::Resample,
     ports::Ports,
 };
 
 const OVERSAMPLE_K: usize = 2;
 
-#[derive(Debug)]
+/// Runs a node at twice the rate of the graph, resampling on the way in and out.
+///
+/// Kept for code that built these by hand. Give the node a rate with
+/// [`LegatoNode::with_rate`], or put it in an `oversample(n)` block, and the builder places
+/// the [`Resample`] nodes this wraps on its connections instead.
+#[deprecated(note = "use `LegatoNode::with_rate` or an `oversample(n)` block instead")]
+#[derive(Debug, Clone)]
 pub struct Oversampler2X {
     node: LegatoNode,
-    upsamplers: Box<[Upsampler16]>,
-    downsamplers: Box<[Downsampler16]>,
+    upsample: Resample,
+    downsample: Resample,
     // Flat work buffer, so buffer_size * upsample * chans
     upsampled: Box<[f32]>,
     node_outputs: Box<[f32]>,
     chans: usize,
 }
 
+#[allow(deprecated)]
 impl Oversampler2X {
     pub fn new(node: LegatoNode, buffer_size: usize) -> Self {
         let ports = node.get_node().ports();
 
         let chans = max(ports.audio_in.len(), ports.audio_out.len());
-
-        let upsamplers = (0..chans)
-            .map(|_| Upsampler16::default())
-            .collect::<Vec<Upsampler16>>()
-            .into();
-
-        let downsamplers = (0..chans)
-            .map(|_| Downsampler16::default())
-            .collect::<Vec<Downsampler16>>()
-            .into();
+        let high_len = buffer_size * OVERSAMPLE_K;
 
         Self {
             node,
-            upsamplers,
-            downsamplers,
-            upsampled: vec![0.0; buffer_size * OVERSAMPLE_K * chans].into(),
-            node_outputs: vec![0.0; buffer_size * OVERSAMPLE_K * chans].into(),
+            upsample: Resample::new(chans, Rate::times(OVERSAMPLE_K), buffer_size).unwrap(),
+            downsample: Resample::new(chans, Rate::divided(OVERSAMPLE_K), high_len).unwrap(),
+            upsampled: vec![0.0; high_len * chans].into(),
+            node_outputs: vec![0.0; high_len * chans].into(),
             chans,
         }
     }
 }
 
-/// Upsampler and Downsampler are not clone.
-///
-/// So, we lose state, but likely that is not what we want anyways.
-impl Clone for Oversampler2X {
-    fn clone(&self) -> Self {
-        let upsamplers = (0..self.chans)
-            .map(|_| Upsampler16::default())
-            .collect::<Vec<Upsampler16>>()
-            .into();
-
-        let downsamplers = (0..self.chans)
-            .map(|_| Downsampler16::default())
-            .collect::<Vec<Downsampler16>>()
-            .into();
-
-        Self {
-            node: self.node.clone(),
-            upsamplers,
-            downsamplers,
-            upsampled: self.upsampled.clone(),
-            node_outputs: self.node_outputs.clone(),
-            chans: self.chans,
-        }
-    }
-}
-
+#[allow(deprecated)]
 impl Node for Oversampler2X {
     fn process(&mut self, ctx: &mut AudioContext, inputs: &Inputs, outputs: &mut [&mut [f32]]) {
-        let cfg = ctx.get_config();
+        let block_size = ctx.get_config().block_size;
+        let high_len = block_size * OVERSAMPLE_K;
 
-        let block_size = cfg.block_size;
-        let sample_rate = cfg.sample_rate;
-
-        assert!(self.upsampled.len() == self.chans * block_size * OVERSAMPLE_K);
-
-        // Used to construct slices for oversampling
-        let mut node_inputs: [Option<&[f32]>; MAX_ARITY] = [None; MAX_ARITY];
-        let mut has_inputs: [bool; MAX_ARITY] = [false; MAX_ARITY];
+        assert!(self.upsampled.len() == self.chans * high_len);
 
         // Upsample audio into flat buffer slices per chan
-        for (c, chan_in_outer) in inputs.iter().enumerate() {
-            if let Some(chan_in) = chan_in_outer {
-                has_inputs[c] = true;
-                let start = block_size * OVERSAMPLE_K * c;
-                let end = start + block_size * OVERSAMPLE_K;
-
-                let chan_mut = &mut self.upsampled[start..end];
-
-                self.upsamplers[c].process_block(chan_in, chan_mut);
-            }
-        }
+        let mut upsampled = slice_node_ports_mut(&mut self.upsampled, high_len, self.chans);
+        self.upsample
+            .process(ctx, inputs, &mut upsampled[..inputs.len()]);
 
         // Construct optional slices for oversampler inputs
-        for (c, (input_chan, has_input_chan)) in node_inputs
-            .iter_mut()
-            .zip(has_inputs.iter())
-            .take(self.chans)
-            .enumerate()
-        {
-            if *has_input_chan {
-                let start = block_size * OVERSAMPLE_K * c;
-                let end = start + block_size * OVERSAMPLE_K;
-
-                let chan_in = &self.upsampled[start..end];
-
-                *input_chan = Some(chan_in);
+        let mut node_inputs: [Option<&[f32]>; MAX_ARITY] = [None; MAX_ARITY];
+        for (c, input_chan) in node_inputs.iter_mut().enumerate().take(inputs.len()) {
+            if inputs[c].is_some() {
+                let start = high_len * c;
+                *input_chan = Some(&self.upsampled[start..start + high_len]);
             }
         }
 
         // Reset outputs
         self.node_outputs.fill(0.0);
 
-        let mut node_outputs_raw = slice_node_ports_mut(
-            &mut self.node_outputs,
-            0,
-            block_size * OVERSAMPLE_K,
-            self.chans,
-        );
-
-        let outputs_for_node = &mut node_outputs_raw[..self.chans];
-
-        // TODO: This is stupid, find a different pattern
-
-        ctx.set_block_size(block_size * OVERSAMPLE_K);
-        ctx.set_sample_rate(sample_rate * OVERSAMPLE_K);
-
-        self.node
-            .get_node_mut()
-            .process(ctx, inputs, outputs_for_node);
-
-        // Drop the context back to original state
+        let mut node_outputs_raw =
+            slice_node_ports_mut(&mut self.node_outputs, high_len, self.chans);
+        let outputs_for_node = &mut node_outputs_raw[..outputs.len()];
+
+        // The inner node sees twice the rate of this one through the context
+        let graph_rate = ctx.graph_config().sample_rate;
+        let rate = Rate::new(ctx.get_config().sample_rate, graph_rate) * Rate::times(OVERSAMPLE_K);
+        at_rate(rate, || {
+            self.node
+                .get_node_mut()
+                .process(ctx, &node_inputs[..inputs.len()], outputs_for_node)
+        });
+
+        let mut node_outputs: [Option<&[f32]>; MAX_ARITY] = [None; MAX_ARITY];
+        for (output, chan) in node_outputs.iter_mut().zip(outputs_for_node.iter()) {
+            *output = Some(&**chan);
+        }
+        self.downsample
+            .process(ctx, &node_outputs[..outputs.len()], outputs);
+    }
 
-        ctx.set_block_size(block_size);
-        ctx.set_sample_rate(sample_rate);
+    fn handle_msg(&mut self, msg: NodeMessage) {
+        self.node.get_node_mut().handle_msg(msg);
+    }
 
-        for c in 0..self.chans {
-            let downsampler = &mut self.downsamplers[c];
-            let chan_out = &mut outputs[c];
+    fn ports(&self) -> &Ports {
+        self.node.get_node().ports()
+    }
 
-            downsampler.process_block(outputs_for_node[c], chan_out);
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.upsample.carry_state(&prev.upsample);
+            self.downsample.carry_state(&prev.downsample);
+            self.node
+                .get_node_mut()
+                .carry_state(prev.node.get_node().as_any());
         }
     }
 
-    fn ports(&self) -> &Ports {
-        self.node.get_node().ports()
+    fn shares_context(&self) -> bool {
+        self.node.get_node().shares_context()
     }
 }
 
 #[inline(always)]
 fn slice_node_ports_mut(
     buffer: &mut [f32],
-    offset: usize,
     block_size: usize,
     chans: usize,
 ) -> [&mut [f32]; MAX_ARITY] {
-    let end = (block_size * chans) + offset;
-    let node_buffer = &mut buffer[offset..end];
-
-    let mut chunks = node_buffer.chunks_exact_mut(block_size);
+    let mut chunks = buffer[..block_size * chans].chunks_exact_mut(block_size);
 
     std::array::from_fn(|_| chunks.next().unwrap_or_default())
 }
+
+#[cfg(test)]
+#[allow(deprecated)]
+mod test {
+    use super::*;
+    use crate::{
+        config::{BlockSize, Config},
+        harness::build_placeholder_context,
+        nodes::audio::constant::Constant,
+    };
+
+    /// The wrapped node's output comes back down at the graph's rate, at full level.
+    #[test]
+    fn wraps_a_node_at_twice_the_rate() {
+        let mut ctx = build_placeholder_context(Config::new(48_000, BlockSize::Block64, 1, 0));
+        let node = LegatoNode::new(
+            "dc".into(),
+            "constant".into(),
+            Box::new(Constant::new(1.0, 1)),
+        );
+        let mut oversampler = Oversampler2X::new(node, 64);
+
+        let mut out = [0.0; 64];
+        for _ in 0..4 {
+            oversampler.process(&mut ctx, &[], &mut [&mut out[..]]);
+        }
+
+        assert!(out.iter().all(|x| (x - 1.0).abs() < 1e-2), "{out:?}");
+    }
+}
@@ -58,6 +58,10 @@ impl Node for Pan {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl PerSampleNode for Pan {
@@ -84,7 +88,7 @@ impl Pan {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let pan = p.get_f32("pan").unwrap_or(0.5);
+        let pan = p.get_f32("pan")?.unwrap_or(0.5);
         Ok(Self::new(pan))
     }
 }
@@ -0,0 +1,247 @@
+use std::any::Any;
+
+use halfband::fir::{Downsampler16, Upsampler16};
+
+use crate::{
+    context::{AudioContext, Rate},
+    node::{Inputs, Node},
+    ports::{PortBuilder, Ports},
+};
+
+/// Converts a signal between two rates a power of two apart, with a cascade of halfband FIR
+/// filters: one 2x stage for each doubling or halving.
+///
+/// The builder puts one of these on every connection between nodes at different rates, see
+/// [`crate::node::LegatoNode::with_rate`]. It runs at the rate it converts to, and reads its
+/// inputs at the rate of its source.
+#[derive(Debug)]
+pub struct Resample {
+    factor: Rate,
+    // `stages` filters per channel, one channel after another
+    filters: Box<[Stage]>,
+    stages: usize,
+    // Two halves, each as long as the longer of a channel's input and output
+    work: Box<[f32]>,
+    ports: Ports,
+}
+
+/// How many of its latest inputs a stage keeps. The filters hold the last 32 samples, or
+/// the last 32 pairs when halving, so replaying these rebuilds them exactly.
+const HISTORY: usize = 64;
+
+#[derive(Debug)]
+struct Stage {
+    filter: Filter,
+    // The latest inputs, oldest first
+    recent: [f32; HISTORY],
+}
+
+#[derive(Debug)]
+enum Filter {
+    Up(Upsampler16),
+    Down(Downsampler16),
+}
+
+impl Stage {
+    fn new(up: bool) -> Self {
+        let filter = match up {
+            true => Filter::Up(Upsampler16::default()),
+            false => Filter::Down(Downsampler16::default()),
+        };
+        Self {
+            filter,
+            recent: [0.0; HISTORY],
+        }
+    }
+
+    /// Filter `input` into `output`, returning how many samples it wrote.
+    fn process(&mut self, input: &[f32], output: &mut [f32]) -> usize {
+        let kept = input.len().min(HISTORY);
+        self.recent.copy_within(kept.., 0);
+        self.recent[HISTORY - kept..].copy_from_slice(&input[input.len() - kept..]);
+
+        match &mut self.filter {
+            Filter::Up(up) => {
+                let len = input.len() * 2;
+                up.process_block(input, &mut output[..len]);
+                len
+            }
+            Filter::Down(down) => {
+                let len = input.len() / 2;
+                down.process_block(input, &mut output[..len]);
+                len
+            }
+        }
+    }
+
+    /// Pick up where `previous` left off, by running its latest inputs through this
+    /// stage's filter.
+    fn resume(&mut self, previous: &Stage) {
+        self.recent = previous.recent;
+        match &mut self.filter {
+            Filter::Up(up) => {
+                up.clear();
+                self.recent.iter().for_each(|&x| {
+                    up.process(x);
+                });
+            }
+            Filter::Down(down) => {
+                down.clear();
+                self.recent.chunks_exact(2).for_each(|pair| {
+                    down.process(pair[0], pair[1]);
+                });
+            }
+        }
+    }
+}
+
+impl Resample {
+    /// Resample `chans` channels by `factor`, read in blocks of `input_len`. `None` unless
+    /// `factor` is a power of two, or one over a power of two.
+    pub fn new(chans: usize, factor: Rate, input_len: usize) -> Option<Self> {
+        let (up, down) = (factor.up(), factor.down());
+        if !(up.is_power_of_two() && down.is_power_of_two()) || (up > 1 && down > 1) {
+            return None;
+        }
+        let stages = (up * down).trailing_zeros() as usize;
+
+        let filters = (0..chans * stages).map(|_| Stage::new(up > 1)).collect();
+        let half = input_len.max(factor.scale(input_len));
+
+        Some(Self {
+            factor,
+            filters,
+            stages,
+            work: vec![0.0; 2 * half].into(),
+            ports: PortBuilder::default()
+                .audio_in(chans)
+                .audio_out(chans)
+                .build(),
+        })
+    }
+
+    pub fn factor(&self) -> Rate {
+        self.factor
+    }
+}
+
+/// The filters are not `Clone`, so a clone builds new ones and replays into them.
+impl Clone for Resample {
+    fn clone(&self) -> Self {
+        let up = self.factor.up() > 1;
+        let mut clone = Self {
+            factor: self.factor,
+            filters: (0..self.filters.len()).map(|_| Stage::new(up)).collect(),
+            stages: self.stages,
+            work: self.work.clone(),
+            ports: self.ports.clone(),
+        };
+        clone.carry_state(self);
+        clone
+    }
+}
+
+impl Node for Resample {
+    fn process(&mut self, _: &mut AudioContext, inputs: &Inputs, outputs: &mut [&mut [f32]]) {
+        let half = self.work.len() / 2;
+
+        for (c, output) in outputs.iter_mut().enumerate() {
+            let Some(input) = inputs.get(c).copied().flatten() else {
+                output.fill(0.0);
+                continue;
+            };
+
+            let (mut src, mut dst) = self.work.split_at_mut(half);
+            src[..input.len()].copy_from_slice(input);
+
+            let mut len = input.len();
+            for stage in &mut self.filters[c * self.stages..(c + 1) * self.stages] {
+                len = stage.process(&src[..len], dst);
+                std::mem::swap(&mut src, &mut dst);
+            }
+            output.copy_from_slice(&src[..len]);
+        }
+    }
+
+    fn ports(&self) -> &Ports {
+        &self.ports
+    }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>()
+            && prev.factor == self.factor
+        {
+            self.filters
+                .iter_mut()
+                .zip(prev.filters.iter())
+                .for_each(|(stage, prev)| stage.resume(prev));
+        }
+    }
+
+    fn resamples(&self) -> bool {
+        true
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::{BlockSize, Config},
+        harness::build_placeholder_context,
+    };
+
+    fn run(resample: &mut Resample, input: &[f32], out_len: usize) -> Vec<f32> {
+        let mut ctx = build_placeholder_context(Config::new(48_000, BlockSize::Block64, 1, 0));
+        let mut out = vec![0.0; out_len];
+        resample.process(&mut ctx, &[Some(input)], &mut [&mut out[..]]);
+        out
+    }
+
+    /// Up and back down again, a constant comes out as it went in, once the filters fill.
+    #[test]
+    fn cascades_keep_a_constant() {
+        let mut up = Resample::new(1, Rate::times(4), 64).unwrap();
+        let mut down = Resample::new(1, Rate::divided(4), 256).unwrap();
+
+        let mut last = vec![];
+        for _ in 0..8 {
+            let high = run(&mut up, &[1.0; 64], 256);
+            last = run(&mut down, &high, 64);
+        }
+
+        assert!(last.iter().all(|x| (x - 1.0).abs() < 1e-2), "{last:?}");
+    }
+
+    /// A clone, or a new resampler carrying an old one's state, carries on where it left off.
+    #[test]
+    fn clones_keep_their_filters() {
+        let input: Vec<f32> = (0..64).map(|i| (i as f32 * 0.3).sin()).collect();
+        for factor in [Rate::times(4), Rate::divided(4)] {
+            let mut first = Resample::new(2, factor, 64).unwrap();
+            let out_len = factor.scale(64);
+            run(&mut first, &input, out_len);
+
+            let mut cloned = first.clone();
+            let mut carried = Resample::new(2, factor, 64).unwrap();
+            carried.carry_state(&first);
+
+            let expected = run(&mut first, &input, out_len);
+            assert_eq!(run(&mut cloned, &input, out_len), expected);
+            assert_eq!(run(&mut carried, &input, out_len), expected);
+        }
+    }
+
+    /// Each stage doubles or halves, so other factors are refused.
+    #[test]
+    fn only_powers_of_two() {
+        assert!(Resample::new(1, Rate::times(8), 64).is_some());
+        assert!(Resample::new(1, Rate::divided(2), 64).is_some());
+        assert!(Resample::new(1, Rate::times(3), 64).is_none());
+        assert!(Resample::new(1, Rate::new(2, 3), 64).is_none());
+    }
+}
@@ -73,6 +73,10 @@ impl Node for Sampler {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -92,10 +96,8 @@ impl NodeDefinition for Sampler {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let name = p
-            .get_str("sampler_name")
-            .expect("Could not find required parameter sampler_name");
-        let chans = p.get_usize("chans").unwrap_or(2);
+        let name = p.require("sampler_name", DSLParams::get_str)?;
+        let chans = p.get_usize("chans")?.unwrap_or(2);
         let key = rb.add_external_buffer_key(&name);
         Ok(Box::new(Self::new(key, chans)))
     }
@@ -1,4 +1,7 @@
-use std::simd::{Select, Simd, StdFloat, cmp::SimdPartialOrd};
+use std::{
+    any::Any,
+    simd::{Select, Simd, StdFloat, cmp::SimdPartialOrd},
+};
 
 use crate::{
     builder::{ResourceBuilderView, ValidationError},
@@ -132,6 +135,10 @@ impl PerSampleNode for Saw {
     fn handle_msg(&mut self, msg: NodeMessage) {
         Node::handle_msg(self, msg);
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        Node::carry_state(self, previous);
+    }
 }
 
 impl Node for Saw {
@@ -155,6 +162,16 @@ impl Node for Saw {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.phase = prev.phase;
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 #[inline(always)]
@@ -186,11 +203,9 @@ impl Saw {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let chans = p
-            .get_usize("chans")
-            .expect("Must provide chans to audio_input");
+        let chans = p.require("chans", DSLParams::get_usize)?;
 
-        let freq = p.get_f32("freq").unwrap_or(440.0);
+        let freq = p.get_f32("freq")?.unwrap_or(440.0);
 
         let sr = rb.get_config().sample_rate as f32;
 
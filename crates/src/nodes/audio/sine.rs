@@ -32,7 +32,10 @@
 // OR TORT (INCLUDING NEGLIGENCE OR OTHERWISE) ARISING IN ANY WAY OUT OF THE USE
 // OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY OF SUCH DAMAGE.
 
-use std::simd::{Simd, StdFloat};
+use std::{
+    any::Any,
+    simd::{Simd, StdFloat},
+};
 
 use crate::{
     context::AudioContext,
@@ -199,6 +202,10 @@ impl PerSampleNode for Sine {
     fn handle_msg(&mut self, msg: NodeMessage) {
         Node::handle_msg(self, msg);
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        Node::carry_state(self, previous);
+    }
 }
 
 impl Node for Sine {
@@ -226,6 +233,16 @@ impl Node for Sine {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.phase = prev.phase;
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -240,9 +257,9 @@ impl Sine {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let freq = p.get_f32("freq").unwrap_or(440.0);
+        let freq = p.get_f32("freq")?.unwrap_or(440.0);
         let quality = p
-            .get_str("quality")
+            .get_str("quality")?
             .map(|s| {
                 Quality::from_str(&s).ok_or_else(|| {
                     ValidationError::InvalidParameter(
@@ -253,7 +270,7 @@ impl Sine {
             .transpose()?
             .unwrap_or_default();
         let sr = rb.get_config().sample_rate as f32;
-        let phase = p.get_f32("phase").unwrap_or(0.0);
+        let phase = p.get_f32("phase")?.unwrap_or(0.0);
         Ok(Self::with_quality(freq, sr, quality).with_start_phase(phase))
     }
 }
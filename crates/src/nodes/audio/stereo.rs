@@ -0,0 +1,66 @@
+use std::any::Any;
+
+use crate::{
+    context::AudioContext,
+    msg::NodeMessage,
+    node::{Inputs, LegatoNode, Node},
+    ports::{PortBuilder, Ports},
+};
+
+/// Runs a mono node and writes its output to both sides of a stereo pair, each side with its
+/// own gain.
+///
+/// The `stereo` and `spread` pipes swap a node for one of these, so it keeps the inputs of
+/// the node it wraps.
+#[derive(Clone, Debug)]
+pub struct Stereo {
+    node: LegatoNode,
+    gains: [f32; 2],
+    ports: Ports,
+}
+
+impl Stereo {
+    pub fn new(node: LegatoNode, gains: [f32; 2]) -> Self {
+        let ports = Ports {
+            audio_in: node.get_node().ports().audio_in.clone(),
+            audio_out: PortBuilder::default().audio_out(2).build().audio_out,
+        };
+        Self { node, gains, ports }
+    }
+}
+
+impl Node for Stereo {
+    fn process(&mut self, ctx: &mut AudioContext, inputs: &Inputs, outputs: &mut [&mut [f32]]) {
+        // The wrapped node writes into the left side, which is then copied right
+        self.node
+            .get_node_mut()
+            .process(ctx, inputs, &mut outputs[..1]);
+
+        let (left, right) = outputs.split_at_mut(1);
+        let [l, r] = self.gains;
+        for (left, right) in left[0].iter_mut().zip(right[0].iter_mut()) {
+            *right = *left * r;
+            *left *= l;
+        }
+    }
+
+    fn handle_msg(&mut self, msg: NodeMessage) {
+        self.node.get_node_mut().handle_msg(msg);
+    }
+
+    fn ports(&self) -> &Ports {
+        &self.ports
+    }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(previous) = previous.downcast_ref::<Self>() {
+            self.node
+                .get_node_mut()
+                .carry_state(previous.node.get_node().as_any());
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        self.node.get_node().shares_context()
+    }
+}
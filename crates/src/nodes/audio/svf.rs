@@ -1,4 +1,4 @@
-use std::f32::consts::PI;
+use std::{any::Any, f32::consts::PI};
 
 use crate::{
     context::AudioContext,
@@ -309,6 +309,10 @@ impl PerSampleNode for Svf {
             }
         }
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        Node::carry_state(self, previous);
+    }
 }
 
 impl Node for Svf {
@@ -330,6 +334,18 @@ impl Node for Svf {
     fn handle_msg(&mut self, _msg: crate::msg::NodeMessage) {
         todo!()
     }
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.filter_state
+                .iter_mut()
+                .zip(prev.filter_state.iter())
+                .for_each(|(dst, src)| *dst = *src);
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -344,25 +360,27 @@ impl Svf {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let cutoff = p.get_f32("cutoff").unwrap_or(7500.0);
-        let chans = p.get_usize("chans").unwrap_or(2);
-        let gain = p.get_f32("gain").unwrap_or(1.0);
-        let q = p.get_f32("q").unwrap_or(0.4);
-
-        let filter_type = p
-            .get_str("type")
-            .map_or(FilterType::LowPass, |f| match f.as_str() {
-                "lowpass" => FilterType::LowPass,
-                "highpass" => FilterType::HighPass,
-                "allpass" => FilterType::AllPass,
-                "bandpass" => FilterType::BandPass,
-                "bell" => FilterType::Bell,
-                "highshelf" => FilterType::HighShelf,
-                "lowshelf" => FilterType::LowShelf,
-                "notch" => FilterType::Notch,
-                "peak" => FilterType::Peak,
-                _ => panic!("Could not find filter type!"),
-            });
+        let cutoff = p.get_f32("cutoff")?.unwrap_or(7500.0);
+        let chans = p.get_usize("chans")?.unwrap_or(2);
+        let gain = p.get_f32("gain")?.unwrap_or(1.0);
+        let q = p.get_f32("q")?.unwrap_or(0.4);
+
+        let filter_type = match p.get_str("type")?.as_deref() {
+            None | Some("lowpass") => FilterType::LowPass,
+            Some("highpass") => FilterType::HighPass,
+            Some("allpass") => FilterType::AllPass,
+            Some("bandpass") => FilterType::BandPass,
+            Some("bell") => FilterType::Bell,
+            Some("highshelf") => FilterType::HighShelf,
+            Some("lowshelf") => FilterType::LowShelf,
+            Some("notch") => FilterType::Notch,
+            Some("peak") => FilterType::Peak,
+            Some(other) => {
+                return Err(ValidationError::InvalidParameter(format!(
+                    "Unknown svf type '{other}'"
+                )));
+            }
+        };
 
         let sr = rb.get_config().sample_rate as f32;
         Ok(Self::new(sr, filter_type, cutoff, gain, q, chans))
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use crate::{
     context::AudioContext,
     msg::{NodeMessage, RtValue},
@@ -77,6 +79,10 @@ impl PerSampleNode for DelayTap {
     fn handle_msg(&mut self, msg: NodeMessage) {
         Node::handle_msg(self, msg);
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        Node::carry_state(self, previous);
+    }
 }
 
 impl Node for DelayTap {
@@ -120,6 +126,18 @@ impl Node for DelayTap {
             }
         }
     }
+    fn carry_state(&mut self, previous: &dyn Any) {
+        // Cursors index into a power of two window, so the lines only line up
+        // when the capacity did not change.
+        if let Some(prev) = previous.downcast_ref::<Self>()
+            && prev.cap == self.cap
+        {
+            let chans = self.chans.min(prev.chans);
+            let len = chans * self.cap;
+            self.data[..len].copy_from_slice(&prev.data[..len]);
+            self.delays[..chans].clone_from_slice(&prev.delays[..chans]);
+        }
+    }
 }
 
 use crate::{
@@ -137,12 +155,12 @@ impl DelayTap {
         use std::time::Duration;
         let config = rb.get_config();
         let sr = config.sample_rate;
-        let chans = p.get_usize("chans").unwrap_or(2);
+        let chans = p.get_usize("chans")?.unwrap_or(2);
         let delay_length = p
-            .get_duration_ms("delay_length")
+            .get_duration_ms("delay_length")?
             .unwrap_or(Duration::from_millis(50));
         let delay_length_samples = sr as f32 * delay_length.as_secs_f32();
-        let mut capacity = p.get_usize("capacity").unwrap_or(sr);
+        let mut capacity = p.get_usize("capacity")?.unwrap_or(sr);
         if capacity < (delay_length_samples as usize) {
             capacity = (delay_length_samples as usize) * 2;
         }
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use crate::{
     builder::{ResourceBuilderView, ValidationError},
     context::AudioContext,
@@ -47,6 +49,16 @@ impl Node for Lfo {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            Node::carry_state(&mut self.inner, &prev.inner);
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl NodeDefinition for Lfo {
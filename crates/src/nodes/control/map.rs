@@ -62,6 +62,10 @@ impl Node for Map {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 impl PerSampleNode for Map {
@@ -104,19 +108,13 @@ impl Map {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Self, ValidationError> {
-        let range = p
-            .get_array_f32("range")
-            .expect("Must pass original range to map");
-        let new_range = p
-            .get_array_f32("new_range")
-            .expect("Must pass new_range to map");
-        assert!(range.len() == 2);
-        assert!(new_range.len() == 2);
-        let mut r_0 = [0.0; 2];
-        let mut r_1 = [0.0; 2];
-        r_0.copy_from_slice(&range[..2]);
-        r_1.copy_from_slice(&new_range[..2]);
-        Ok(Self::new(r_0, r_1))
+        let pair = |key| match p.require(key, DSLParams::get_array_f32)?[..] {
+            [lo, hi] => Ok([lo, hi]),
+            ref other => Err(ValidationError::InvalidParameter(format!(
+                "`{key}` should be [min, max], found {other:?}"
+            ))),
+        };
+        Ok(Self::new(pair("range")?, pair("new_range")?))
     }
 }
 
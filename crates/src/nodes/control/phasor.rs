@@ -1,7 +1,9 @@
+use std::any::Any;
+
 use crate::{
     context::AudioContext,
     msg::{self, RtValue},
-    node::{Inputs, Node},
+    node::{Inputs, Interpolation, Node},
     ports::{PortBuilder, Ports},
 };
 
@@ -48,6 +50,10 @@ impl Node for Phasor {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+    // A ramp would sweep back down through the wrap
+    fn control_interpolation(&self) -> Interpolation {
+        Interpolation::Step
+    }
 
     fn handle_msg(&mut self, msg: crate::msg::NodeMessage) {
         if let msg::NodeMessage::SetParam(inner) = msg {
@@ -58,6 +64,16 @@ impl Node for Phasor {
             }
         }
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.phase = prev.phase;
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -77,7 +93,7 @@ impl NodeDefinition for Phasor {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let freq = p.get_f32("freq").expect("Must pass frequency to phasor");
+        let freq = p.require("freq", DSLParams::get_f32)?;
         Ok(Box::new(Self::new(freq)))
     }
 }
@@ -97,11 +113,9 @@ impl NodeDefinition for ClockDef {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let bpm = p.get_usize("bpm").expect("Must pass bpm to clock");
-        let division = p
-            .get_usize("division")
-            .expect("Must pass division to clock");
-        let steps = p.get_usize("steps").expect("Must pass steps to clock");
+        let bpm = p.require("bpm", DSLParams::get_usize)?;
+        let division = p.require("division", DSLParams::get_usize)?;
+        let steps = p.require("steps", DSLParams::get_usize)?;
         let freq = (bpm * division) as f32 / (60.0 * steps as f32);
         Ok(Box::new(Phasor::new(freq)))
     }
@@ -1,7 +1,7 @@
 use crate::{
     context::AudioContext,
     msg::{NodeMessage, RtValue},
-    node::{Inputs, Node},
+    node::{Inputs, Interpolation, Node},
     ports::{PortBuilder, Ports},
 };
 
@@ -125,6 +125,14 @@ impl Node for StepSequencer {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+    // Steps and gates should stay steps when read at audio rate
+    fn control_interpolation(&self) -> Interpolation {
+        Interpolation::Step
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -145,9 +153,7 @@ impl NodeDefinition for StepSequencer {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let num_steps = p
-            .get_usize("num_steps")
-            .expect("Must pass num_steps to sequencer");
+        let num_steps = p.require("num_steps", DSLParams::get_usize)?;
         Ok(Box::new(Self::new(num_steps)))
     }
 }
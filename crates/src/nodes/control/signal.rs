@@ -26,8 +26,8 @@ impl Signal {
 
 impl Node for Signal {
     fn process(&mut self, ctx: &mut AudioContext, _: &Inputs, outputs: &mut [&mut [f32]]) {
-        // Param set on each block, then smoothed with a one pole filter
-        // Maybe we do this per control sample as well in the future with less smoothing, provided the benchmark is decent
+        // Param set on each block, then smoothed with a one pole filter on every value. At control
+        // rate there are fewer values per block, so the same smoothing settles over more samples
         if let Ok(target) = ctx.get_param(&self.key) {
             for channel in outputs.iter_mut() {
                 for sample in channel.iter_mut() {
@@ -40,6 +40,10 @@ impl Node for Signal {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -60,13 +64,11 @@ impl NodeDefinition for Signal {
         rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let name = p.get_str("name").expect("Must pass name to signal!");
-        let min = p.get_f32("min").expect("Must provide min to signal!");
-        let max = p.get_f32("max").expect("Must provide max to signal!");
-        let default = p
-            .get_f32("default")
-            .expect("Must provide default(f32) to signal!");
-        let smoothing = p.get_f32("smoothing").unwrap_or(0.5).clamp(0.0, 1.0);
+        let name = p.require("name", DSLParams::get_str)?;
+        let min = p.require("min", DSLParams::get_f32)?;
+        let max = p.require("max", DSLParams::get_f32)?;
+        let default = p.require("default", DSLParams::get_f32)?;
+        let smoothing = p.get_f32("smoothing")?.unwrap_or(0.5).clamp(0.0, 1.0);
         let meta = ParamMeta {
             name: name.clone(),
             min,
@@ -203,18 +203,17 @@ impl NodeDefinition for MidiSequencer {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let midi_chan = p
-            .get_usize("midi_chan")
-            .expect("Must pass midi_chan to MidiSequencer!");
-
-        let num_steps = p
-            .get_usize("num_steps")
-            .expect("Must pass num_steps to sequencer");
-        Ok(Box::new(Self::new(
-            midi_chan
-                .try_into()
-                .expect("Could not cast midi channel to u8!"),
-            num_steps,
-        )))
+        let midi_chan = p.require("midi_chan", DSLParams::get_usize)?;
+        let midi_chan = u8::try_from(midi_chan)
+            .ok()
+            .filter(|chan| *chan <= 15)
+            .ok_or_else(|| {
+                ValidationError::InvalidParameter(format!(
+                    "`midi_chan` should be a MIDI channel (0-15), found {midi_chan}"
+                ))
+            })?;
+
+        let num_steps = p.require("num_steps", DSLParams::get_usize)?;
+        Ok(Box::new(Self::new(midi_chan, num_steps)))
     }
 }
@@ -1,7 +1,7 @@
 use crate::{
     context::AudioContext,
     midi::MidiMessageKind,
-    node::{Inputs, Node},
+    node::{Inputs, Interpolation, Node},
     ports::{PortBuilder, Ports},
 };
 
@@ -85,6 +85,14 @@ impl Node for Voice {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+    // Gates and note changes land on one sample, not across a ramp
+    fn control_interpolation(&self) -> Interpolation {
+        Interpolation::Step
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 #[inline(always)]
@@ -312,6 +320,14 @@ impl Node for PolyVoice {
     fn ports(&self) -> &Ports {
         &self.ports
     }
+    // Gates and note changes land on one sample, not across a ramp
+    fn control_interpolation(&self) -> Interpolation {
+        Interpolation::Step
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 use crate::{
@@ -332,10 +348,7 @@ impl NodeDefinition for Voice {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let channel = p
-            .get_usize("chan")
-            .expect("Must provide midi channel (chan) (0-15) to voice!");
-        assert!(channel <= 15);
+        let channel = midi_channel(p)?;
         Ok(Box::new(Self::new(channel)))
     }
 }
@@ -351,17 +364,23 @@ impl NodeDefinition for PolyVoice {
         _rb: &mut ResourceBuilderView,
         p: &DSLParams,
     ) -> Result<Box<dyn DynNode>, ValidationError> {
-        let channel = p
-            .get_usize("chan")
-            .expect("Must provide midi channel (chan) (0-15) to voice!");
-        let voices = p
-            .get_usize("voices")
-            .expect("Must provide number of voices to poly voice!");
-        assert!(channel <= 15);
-        assert!(
-            voices < 10,
-            "Currently, a maximum of 32 tracks is supported."
-        );
+        let channel = midi_channel(p)?;
+        let voices = p.require("voices", DSLParams::get_usize)?;
+        if voices >= 10 {
+            return Err(ValidationError::InvalidParameter(format!(
+                "poly_voice supports at most 9 voices, not {voices}"
+            )));
+        }
         Ok(Box::new(Self::new(voices, channel)))
     }
 }
+
+/// The `chan` param, which must be a MIDI channel (0-15).
+fn midi_channel(p: &DSLParams) -> Result<usize, ValidationError> {
+    match p.require("chan", DSLParams::get_usize)? {
+        chan @ 0..=15 => Ok(chan),
+        chan => Err(ValidationError::InvalidParameter(format!(
+            "`chan` should be a MIDI channel (0-15), found {chan}"
+        ))),
+    }
+}
@@ -1,39 +1,90 @@
-use std::{path::Path, time::Duration};
+use std::{fs::File, io::BufWriter, path::Path, time::Duration};
 
 use hound::{WavSpec, WavWriter};
 
-use crate::LegatoApp;
+use crate::{LegatoApp, config::Config, executor::MAIN_OUTPUT};
 
 /// Just render out to a .wav file, more used for testing for the time being.
 ///
 /// TODO: In the future, we will have a dedicated writer thread
-pub fn render(mut app: LegatoApp, path: &Path, time: Duration) -> Result<(), hound::Error> {
+pub fn render(app: LegatoApp, path: &Path, time: Duration) -> Result<(), hound::Error> {
+    render_outputs(app, &[(MAIN_OUTPUT, path)], time)
+}
+
+/// Render several of the graph's named outputs at once, each to its own .wav file, e.g.
+/// `[("main", mix), ("cue", cue)]`.
+///
+/// `main` is what [`LegatoApp::next_block`] returns, so a reload's crossfade is in it, and
+/// has `config.channels` channels, as [`render`] always has. Every other file has as many
+/// channels as its output.
+pub fn render_outputs(
+    mut app: LegatoApp,
+    outputs: &[(&str, &Path)],
+    time: Duration,
+) -> Result<(), hound::Error> {
     let config = app.get_config();
     let dur_in_samples = (time.as_secs_f32() * config.sample_rate as f32) as usize;
 
-    let spec = WavSpec {
-        channels: config.channels as u16,
-        sample_rate: config.sample_rate as u32,
-        bits_per_sample: 32,
-        sample_format: hound::SampleFormat::Float,
-    };
-
-    let mut writer = WavWriter::create(path, spec)?;
-    let block_size = config.block_size;
+    // Created on the first block, once the outputs' channel counts are known
+    let mut writers: Vec<Option<WavWriter<BufWriter<File>>>> =
+        outputs.iter().map(|_| None).collect();
     let mut count = 0_usize;
 
     while count < dur_in_samples {
-        let block_view = app.next_block();
-        let block = &block_view.channels[0..block_view.chans];
+        let main = app.next_block();
+        for (writer, (_, path)) in writers
+            .iter_mut()
+            .zip(outputs)
+            .filter(|(_, (name, _))| *name == MAIN_OUTPUT)
+        {
+            let block = &main.channels[0..main.chans];
+            write_block(writer, path, &config, config.channels, block)?;
+        }
 
-        for n in 0..block_size {
-            for block_chan in block {
-                writer.write_sample(block_chan[n])?;
-            }
+        for (writer, (name, path)) in writers
+            .iter_mut()
+            .zip(outputs)
+            .filter(|(_, (name, _))| *name != MAIN_OUTPUT)
+        {
+            let block_view = app.output(name).ok_or(hound::Error::Unsupported)?;
+            let block = &block_view.channels[0..block_view.chans];
+            write_block(writer, path, &config, block_view.chans, block)?;
         }
-        count += block_size;
+        count += config.block_size;
+    }
+
+    for writer in writers.into_iter().flatten() {
+        writer.finalize()?;
     }
+    Ok(())
+}
 
-    writer.finalize()?;
+/// Interleave `block` into `writer` as `channels` channels, creating it at `path` first if
+/// needed. Channels the block is missing are written as silence.
+fn write_block(
+    writer: &mut Option<WavWriter<BufWriter<File>>>,
+    path: &Path,
+    config: &Config,
+    channels: usize,
+    block: &[&[f32]],
+) -> Result<(), hound::Error> {
+    let writer = match writer {
+        Some(writer) => writer,
+        None => writer.insert(WavWriter::create(
+            path,
+            WavSpec {
+                channels: channels as u16,
+                sample_rate: config.sample_rate as u32,
+                bits_per_sample: 32,
+                sample_format: hound::SampleFormat::Float,
+            },
+        )?),
+    };
+
+    for n in 0..config.block_size {
+        for c in 0..channels {
+            writer.write_sample(block.get(c).map_or(0.0, |chan| chan[n]))?;
+        }
+    }
     Ok(())
 }
@@ -1,3 +1,5 @@
+use std::any::Any;
+
 use crate::{context::AudioContext, msg::NodeMessage, node::Node, ports::Ports};
 
 pub const MAX_FRAME_PORTS: usize = 64;
@@ -18,6 +20,8 @@ pub trait PerSampleNode: Send {
     /// nodes keep their "fall back to the internal param" behavior.
     fn tick(&mut self, in_frame: &[Option<f32>], out_frame: &mut [f32]);
     fn handle_msg(&mut self, _msg: NodeMessage) {}
+    /// The per-sample counterpart of [`Node::carry_state`].
+    fn carry_state(&mut self, _previous: &dyn Any) {}
 }
 
 impl<T: PerSampleNode + ?Sized> PerSampleNode for Box<T> {
@@ -30,6 +34,9 @@ impl<T: PerSampleNode + ?Sized> PerSampleNode for Box<T> {
     fn handle_msg(&mut self, msg: NodeMessage) {
         (**self).handle_msg(msg)
     }
+    fn carry_state(&mut self, previous: &dyn Any) {
+        (**self).carry_state(previous)
+    }
 }
 
 /// Drives a [`SampleNode`] as a block-rate [`Node`], owning the reusable frame
@@ -110,6 +117,16 @@ impl<T: PerSampleNode + Clone + 'static> Node for PerSample<T> {
     fn handle_msg(&mut self, msg: NodeMessage) {
         self.inner.handle_msg(msg);
     }
+
+    fn carry_state(&mut self, previous: &dyn Any) {
+        if let Some(prev) = previous.downcast_ref::<Self>() {
+            self.inner.carry_state(&prev.inner);
+        }
+    }
+
+    fn shares_context(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,181 @@
+//! Pipes transform the nodes a declaration built, before anything is connected to them.
+//!
+//! They are written after a declaration, e.g. `saw * 4 { freq: 110.0 } | spread(0.8)`, and
+//! run in order, each one handed the selection the last one left behind.
+
+use std::{collections::HashMap, f32::consts::FRAC_PI_2, sync::Arc};
+
+use crate::{
+    builder::{SelectionView, ValidationError},
+    context::MAX_OVERSAMPLE,
+    dsl::ir::Value,
+    node::{DynNode, LegatoNode},
+    nodes::audio::stereo::Stereo,
+    runtime::NodeKey,
+};
+
+/// A builder-time transform over the instances of one declaration.
+///
+/// Pipes run once every node in the graph is built, but before any connection is made, so
+/// they are free to clone a node, or replace it with one that has different ports.
+/// `params` is the value written between the parentheses, if any.
+///
+/// Any `Fn(&mut SelectionView, Option<&Value>) -> Result<(), ValidationError>` is a pipe.
+pub trait Pipe: Send + Sync {
+    fn pipe(&self, view: &mut SelectionView, params: Option<&Value>)
+    -> Result<(), ValidationError>;
+}
+
+impl<F> Pipe for F
+where
+    F: Fn(&mut SelectionView, Option<&Value>) -> Result<(), ValidationError> + Send + Sync,
+{
+    fn pipe(
+        &self,
+        view: &mut SelectionView,
+        params: Option<&Value>,
+    ) -> Result<(), ValidationError> {
+        self(view, params)
+    }
+}
+
+/// Pipe registries map the name a pipe is written with to its implementation, like
+/// [`crate::registry::NodeRegistry`] does for nodes.
+#[derive(Clone, Default)]
+pub struct PipeRegistry {
+    data: HashMap<String, Arc<dyn Pipe>>,
+}
+
+impl PipeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn get_pipe(&self, name: &str) -> Result<&dyn Pipe, ValidationError> {
+        self.data
+            .get(name)
+            .map(|pipe| &**pipe)
+            .ok_or_else(|| ValidationError::PipeNotFound(format!("Could not find pipe {name}")))
+    }
+    /// Every registered pipe name, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(String::as_str)
+    }
+    /// Register `pipe` as `name`, replacing any pipe already registered with that name.
+    pub fn declare_pipe(&mut self, name: &str, pipe: impl Pipe + 'static) {
+        self.data.insert(name.into(), Arc::new(pipe));
+    }
+}
+
+pub fn pipe_registry_factory() -> PipeRegistry {
+    let mut registry = PipeRegistry::new();
+    registry.declare_pipe("stereo", MakeStereo);
+    registry.declare_pipe("spread", Spread);
+    registry
+}
+
+/// `| oversample(n)` runs each node at n times its rate, for n a power of two up to
+/// [`MAX_OVERSAMPLE`], 2 by default.
+///
+/// It is not a [`Pipe`]: the builder reads it before building the nodes, so they are built
+/// for the faster rate and filtered on the way in and out, as in an `oversample(n) { }` block.
+pub const OVERSAMPLE: &str = "oversample";
+
+/// The factor written in `| oversample(n)`.
+pub(crate) fn oversample_factor(params: Option<&Value>) -> Result<usize, ValidationError> {
+    let factor = number(OVERSAMPLE, params, 2.0)?;
+    let n = factor as usize;
+    if n as f32 != factor || !n.is_power_of_two() || n > MAX_OVERSAMPLE {
+        return Err(ValidationError::InvalidParameter(format!(
+            "`oversample` takes a power of two up to {MAX_OVERSAMPLE}, found {factor}"
+        )));
+    }
+    Ok(n)
+}
+
+/// `| stereo` copies a mono node's output to both channels. Stereo nodes are left alone.
+pub struct MakeStereo;
+
+impl Pipe for MakeStereo {
+    fn pipe(
+        &self,
+        view: &mut SelectionView,
+        _params: Option<&Value>,
+    ) -> Result<(), ValidationError> {
+        for key in view.selection().keys().to_vec() {
+            let node = node(view, key)?;
+            match node.get_node().ports().audio_out.len() {
+                1 => {
+                    let stereo = Stereo::new(node.clone(), [1.0, 1.0]);
+                    view.replace(key, wrap(node, stereo));
+                }
+                2 => {}
+                n => return Err(not_mono("stereo", &node, n)),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `| spread(width)` pans the instances of a mono node evenly across the stereo field, from
+/// all centered at `0.0` to hard left and right at `1.0`, the default.
+pub struct Spread;
+
+impl Pipe for Spread {
+    fn pipe(
+        &self,
+        view: &mut SelectionView,
+        params: Option<&Value>,
+    ) -> Result<(), ValidationError> {
+        let width = number("spread", params, 1.0)?.clamp(0.0, 1.0);
+
+        let keys = view.selection().keys().to_vec();
+        let n = keys.len();
+        for (i, key) in keys.into_iter().enumerate() {
+            let node = node(view, key)?;
+            let outputs = node.get_node().ports().audio_out.len();
+            if outputs != 1 {
+                return Err(not_mono("spread", &node, outputs));
+            }
+
+            // A lone instance sits in the middle
+            let pan = match n {
+                1 => 0.5,
+                n => 0.5 + width * (i as f32 / (n - 1) as f32 - 0.5),
+            };
+            let angle = pan * FRAC_PI_2;
+            let stereo = Stereo::new(node.clone(), [angle.cos(), angle.sin()]);
+            view.replace(key, wrap(node, stereo));
+        }
+        Ok(())
+    }
+}
+
+fn node(view: &mut SelectionView, key: NodeKey) -> Result<LegatoNode, ValidationError> {
+    view.clone_node(key)
+        .ok_or_else(|| ValidationError::NodeNotFound(format!("Could not find node {key:?}")))
+}
+
+/// `wrapper` in place of `node`, under the same name and kind.
+fn wrap(node: LegatoNode, wrapper: impl DynNode + 'static) -> LegatoNode {
+    let rate = node.rate();
+    LegatoNode::new(node.name, node.node_kind, Box::new(wrapper)).with_rate(rate)
+}
+
+fn number(pipe: &str, params: Option<&Value>, default: f32) -> Result<f32, ValidationError> {
+    match params {
+        None => Ok(default),
+        Some(Value::F32(x)) => Ok(*x),
+        Some(Value::I32(x)) => Ok(*x as f32),
+        Some(Value::U32(x)) => Ok(*x as f32),
+        Some(other) => Err(ValidationError::InvalidParameter(format!(
+            "`{pipe}` takes a number, found {other:?}"
+        ))),
+    }
+}
+
+fn not_mono(pipe: &str, node: &LegatoNode, outputs: usize) -> ValidationError {
+    ValidationError::SelectionArity(format!(
+        "`{pipe}` needs a mono node, but '{}' ({}) has {outputs} outputs",
+        node.name, node.node_kind
+    ))
+}
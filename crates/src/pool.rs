@@ -0,0 +1,193 @@
+use std::{
+    fmt::Debug,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize, Ordering},
+    },
+    thread::{self, JoinHandle, Thread},
+    time::Duration,
+};
+
+use assert_no_alloc::assert_no_alloc;
+use crossbeam::utils::Backoff;
+
+/// How long an idle worker sleeps before checking for work on its own.
+/// The audio thread wakes sleeping workers whenever it hands out a batch.
+const PARK_TIMEOUT: Duration = Duration::from_millis(5);
+
+/// A batch of tasks handed to the pool. `run` is called once for every task below the batch's count.
+pub(crate) trait Job: Sync {
+    fn run(&self, task: usize, worker: usize);
+}
+
+// A thin pointer to the current job, so it fits in an atomic
+struct JobRef<'a>(&'a dyn Job);
+
+struct Shared {
+    // The task count in the high half, and the next unclaimed task in the low half.
+    // Packing both means a claim can only ever succeed against the batch that is running.
+    claim: AtomicU64,
+    job: AtomicPtr<JobRef<'static>>,
+    done: AtomicUsize,
+    busy: AtomicBool,
+    sleeping: AtomicUsize,
+    shutdown: AtomicBool,
+}
+
+impl Shared {
+    fn try_claim(&self) -> Option<usize> {
+        let mut claim = self.claim.load(Ordering::SeqCst);
+        loop {
+            let (count, next) = (claim >> 32, claim & u32::MAX as u64);
+            if next >= count {
+                return None;
+            }
+            match self.claim.compare_exchange_weak(
+                claim,
+                claim + 1,
+                Ordering::AcqRel,
+                Ordering::SeqCst,
+            ) {
+                Ok(_) => return Some(next as usize),
+                Err(current) => claim = current,
+            }
+        }
+    }
+
+    /// Claim and run tasks until none are left.
+    ///
+    /// A claimed task holds the batch open, so the job it was claimed from is still alive.
+    fn drain(&self, worker: usize) -> bool {
+        let mut ran = false;
+        while let Some(task) = self.try_claim() {
+            let job = unsafe { &*self.job.load(Ordering::Acquire) };
+            job.0.run(task, worker);
+            self.done.fetch_add(1, Ordering::Release);
+            ran = true;
+        }
+        ran
+    }
+}
+
+/// A set of pre-spawned threads that the parallel executor spreads independent nodes over.
+///
+/// Handing out and waiting on a batch is lock free and allocation free. Idle workers spin for a
+/// short while, then park until the next batch. A pool serves one audio thread at a time; if a
+/// second one runs a batch meanwhile, it simply runs every task itself.
+pub struct WorkerPool {
+    shared: Arc<Shared>,
+    workers: Vec<Thread>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Spawn `threads` workers. The thread that runs a batch always works on it too.
+    pub fn new(threads: usize) -> Self {
+        let shared = Arc::new(Shared {
+            claim: AtomicU64::new(0),
+            job: AtomicPtr::new(std::ptr::null_mut()),
+            done: AtomicUsize::new(0),
+            busy: AtomicBool::new(false),
+            sleeping: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+        });
+
+        let handles: Vec<JoinHandle<()>> = (1..=threads)
+            .map(|worker| {
+                let shared = shared.clone();
+                thread::Builder::new()
+                    .name(format!("legato-worker-{worker}"))
+                    .spawn(move || work(&shared, worker))
+                    .expect("Could not spawn worker thread")
+            })
+            .collect();
+
+        let workers = handles.iter().map(|h| h.thread().clone()).collect();
+
+        Self {
+            shared,
+            workers,
+            handles,
+        }
+    }
+
+    /// The number of worker threads, not counting the thread that runs a batch.
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Run `count` tasks of `job` across the pool and the calling thread, returning once all are done.
+    ///
+    /// The calling thread is worker `0`, and the pool's threads are `1..=threads()`.
+    pub(crate) fn run(&self, count: usize, job: &dyn Job) {
+        let shared = &*self.shared;
+
+        if count == 0 {
+            return;
+        }
+        if shared.busy.swap(true, Ordering::Acquire) {
+            (0..count).for_each(|task| job.run(task, 0));
+            return;
+        }
+
+        let job = JobRef(job);
+
+        shared.done.store(0, Ordering::Relaxed);
+        shared
+            .job
+            .store((&job as *const JobRef).cast_mut().cast(), Ordering::Relaxed);
+        shared.claim.store((count as u64) << 32, Ordering::SeqCst);
+
+        if shared.sleeping.load(Ordering::SeqCst) > 0 {
+            self.workers.iter().for_each(Thread::unpark);
+        }
+
+        shared.drain(0);
+
+        let backoff = Backoff::new();
+        while shared.done.load(Ordering::Acquire) < count {
+            backoff.snooze();
+        }
+
+        shared.busy.store(false, Ordering::Release);
+    }
+}
+
+fn work(shared: &Shared, worker: usize) {
+    let backoff = Backoff::new();
+
+    while !shared.shutdown.load(Ordering::Acquire) {
+        if assert_no_alloc(|| shared.drain(worker)) {
+            backoff.reset();
+        } else if backoff.is_completed() {
+            shared.sleeping.fetch_add(1, Ordering::SeqCst);
+            // A batch may have been handed out before this worker said it was going to sleep
+            let claim = shared.claim.load(Ordering::SeqCst);
+            if claim & u32::MAX as u64 >= claim >> 32 {
+                thread::park_timeout(PARK_TIMEOUT);
+            }
+            shared.sleeping.fetch_sub(1, Ordering::SeqCst);
+            backoff.reset();
+        } else {
+            backoff.snooze();
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        self.workers.iter().for_each(Thread::unpark);
+        for handle in self.handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Debug for WorkerPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WorkerPool")
+            .field("threads", &self.threads())
+            .finish()
+    }
+}
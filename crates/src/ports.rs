@@ -1,4 +1,6 @@
-/// Signal vs control value; independent of rate, it only governs how portless endpoints auto-map.
+/// Signal vs control value. It governs how portless endpoints auto-map, and a node whose
+/// outputs are all control-kind may run at control rate, see
+/// [`crate::executor::Executor::set_control_rate`].
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PortKind {
     Audio,
@@ -0,0 +1,183 @@
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{config::Config, executor::Executor, runtime::NodeKey};
+
+/// Room for a few windows of events, in case the frontend falls behind.
+pub(crate) const PROFILE_QUEUE_CAPACITY: usize = 4096;
+
+/// How long something took over a profiling window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimingStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// How many times it ran in the window.
+    pub count: usize,
+}
+
+/// The time spent running the whole graph, against the time there is to run it in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockProfile {
+    pub time: TimingStats,
+    /// `block_size / sample_rate`, the time until the next block is due.
+    pub deadline: Duration,
+    /// Blocks in the window that took longer than the deadline.
+    pub overruns: usize,
+}
+
+impl BlockProfile {
+    /// The average share of the deadline spent running the graph.
+    pub fn load(&self) -> f32 {
+        self.time.avg.as_secs_f32() / self.deadline.as_secs_f32()
+    }
+}
+
+/// Sent from the runtime once per window: one `Node` per node that ran, then the `Block`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProfileEvent {
+    Node(NodeKey, TimingStats),
+    Block(BlockProfile),
+}
+
+/// A complete profiling window, as collected by the frontend.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile {
+    pub block: Option<BlockProfile>,
+    pub nodes: HashMap<NodeKey, TimingStats>,
+}
+
+/// Running min, max and total of a duration, in nanoseconds.
+///
+/// Atomic so that worker threads can record the nodes they run. Each node only runs once a
+/// block, so there is never any contention.
+#[derive(Debug)]
+pub(crate) struct Timing {
+    min: AtomicU64,
+    max: AtomicU64,
+    total: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Clone for Timing {
+    fn clone(&self) -> Self {
+        let load = |a: &AtomicU64| AtomicU64::new(a.load(Ordering::Relaxed));
+        Self {
+            min: load(&self.min),
+            max: load(&self.max),
+            total: load(&self.total),
+            count: load(&self.count),
+        }
+    }
+}
+
+impl Timing {
+    #[inline(always)]
+    pub(crate) fn record(&self, elapsed: Duration) {
+        let nanos = elapsed.as_nanos() as u64;
+        self.min.fetch_min(nanos, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+        self.total.fetch_add(nanos, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count.load(Ordering::Relaxed) as usize
+    }
+
+    /// The stats since the last take, if anything was recorded, starting a new window.
+    pub(crate) fn take(&self) -> Option<TimingStats> {
+        let count = self.count.swap(0, Ordering::Relaxed);
+        let min = self.min.swap(u64::MAX, Ordering::Relaxed);
+        let max = self.max.swap(0, Ordering::Relaxed);
+        let total = self.total.swap(0, Ordering::Relaxed);
+
+        (count > 0).then(|| TimingStats {
+            min: Duration::from_nanos(min),
+            avg: Duration::from_nanos(total / count),
+            max: Duration::from_nanos(max),
+            count: count as usize,
+        })
+    }
+}
+
+/// The runtime's end of the profiling channel.
+pub(crate) struct Profiler {
+    sender: rtrb::Producer<ProfileEvent>,
+    // Blocks per window
+    window: usize,
+    deadline: Duration,
+}
+
+impl Profiler {
+    pub(crate) fn new(sender: rtrb::Producer<ProfileEvent>, window: usize, config: Config) -> Self {
+        Self {
+            sender,
+            window: window.max(1),
+            deadline: Duration::from_secs_f64(config.block_size as f64 / config.sample_rate as f64),
+        }
+    }
+
+    /// Send the executor's timings once a full window has run, and start the next one.
+    ///
+    /// Events that do not fit in the queue are dropped rather than blocking the audio thread.
+    pub(crate) fn publish(&mut self, executor: &mut Executor) {
+        if executor.profiled_blocks() < self.window {
+            return;
+        }
+
+        executor.take_profile(self.deadline, |event| {
+            let _ = self.sender.push(event);
+        });
+    }
+}
+
+/// The frontend's end of the profiling channel.
+pub(crate) struct ProfileReader {
+    receiver: rtrb::Consumer<ProfileEvent>,
+    // Node timings for a window whose block timing has not arrived yet
+    pending: HashMap<NodeKey, TimingStats>,
+    latest: Profile,
+}
+
+impl ProfileReader {
+    pub(crate) fn new(receiver: rtrb::Consumer<ProfileEvent>) -> Self {
+        Self {
+            receiver,
+            pending: HashMap::new(),
+            latest: Profile::default(),
+        }
+    }
+
+    /// Read everything the runtime has sent, and return the last complete window.
+    pub(crate) fn read(&mut self) -> &Profile {
+        while let Ok(event) = self.receiver.pop() {
+            match event {
+                ProfileEvent::Node(key, stats) => {
+                    self.pending.insert(key, stats);
+                }
+                ProfileEvent::Block(block) => {
+                    self.latest = Profile {
+                        block: Some(block),
+                        nodes: std::mem::take(&mut self.pending),
+                    };
+                }
+            }
+        }
+        &self.latest
+    }
+}
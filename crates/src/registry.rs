@@ -10,6 +10,7 @@ use crate::{
         audio::{
             adsr::Adsr,
             allpass::Allpass,
+            constant::Constant,
             delay::{DelayRead, DelayWrite},
             external::ExternalInput,
             grain::Granular,
@@ -47,6 +48,7 @@ use crate::{
 /// corresponding NodeSpec.
 ///
 /// This lets Legato users add additional nodes to a "namespace" of nodes.
+#[derive(Clone)]
 pub struct NodeRegistry {
     data: HashMap<String, NodeSpec>,
 }
@@ -70,7 +72,7 @@ impl NodeRegistry {
     ) -> Result<Box<dyn DynNode>, ValidationError> {
         let node = match self.data.get(node_name) {
             Some(spec) => {
-                spec.check_for_bad_params(params);
+                spec.check_for_bad_params(params)?;
                 (spec.build)(resource_builder, params)
             }
             None => Err(ValidationError::NodeNotFound(format!(
@@ -80,6 +82,14 @@ impl NodeRegistry {
         }?;
         Ok(node)
     }
+    /// The spec registered as `node_name`, if any.
+    pub fn spec(&self, node_name: &str) -> Option<&NodeSpec> {
+        self.data.get(node_name)
+    }
+    /// Every registered spec, in no particular order.
+    pub fn specs(&self) -> impl Iterator<Item = &NodeSpec> {
+        self.data.values()
+    }
     pub fn declare_node(&mut self, spec: NodeSpec) {
         // `HashMap::insert` returns the *previous* value (None for a new key), so
         // `.expect()` here panicked on every first registration. A redefinition
@@ -109,6 +119,7 @@ pub fn audio_registry_factory() -> NodeRegistry {
     registry.register_node::<SubDef>();
     registry.register_node::<DivDef>();
     registry.register_node::<GainDef>();
+    registry.register_node::<Constant>();
     registry.register_node::<Granular>();
     registry.register_node::<Adsr>();
     registry.register_node::<Svf>();
@@ -0,0 +1,209 @@
+use std::{collections::HashMap, fmt::Debug, path::PathBuf, sync::Arc};
+
+use crate::{
+    builder::{LegatoBuilder, Unconfigured, ValidationError},
+    config::Config,
+    dsl::module::ModuleLoader,
+    executor::{Executor, MAX_ARITY},
+    pool::WorkerPool,
+    ports::Ports,
+    registry::NodeRegistry,
+    resources::{AudioInputKey, DelayLineKey, ExternalBufferKey, ResourceFrontend, Resources},
+    runtime::NodeKey,
+};
+
+/// How many blocks a hot reload crossfades over unless the frontend says otherwise.
+pub const DEFAULT_RELOAD_CROSSFADE_BLOCKS: usize = 4;
+
+/// A graph built and prepared off of the audio thread, waiting to replace the running one.
+///
+/// The runtime swaps its executor and resources with the ones in here at a block boundary,
+/// then sends the box back holding the outgoing graph, so nothing is dropped on the audio thread.
+/// With a crossfade, the box is held onto until the outgoing graph has faded out.
+pub struct GraphSwap {
+    pub(crate) executor: Executor,
+    pub(crate) resources: Resources,
+    pub(crate) crossfade_blocks: usize,
+    /// Where the blended sink output is written while crossfading, `MAX_ARITY * block_size` long.
+    pub(crate) crossfade_buffer: Box<[f32]>,
+    // (incoming, outgoing) pairs of everything that should survive the swap
+    nodes: Vec<(NodeKey, NodeKey)>,
+    delay_lines: Vec<(DelayLineKey, DelayLineKey)>,
+    external_buffers: Vec<(ExternalBufferKey, ExternalBufferKey)>,
+    audio_inputs: Vec<(AudioInputKey, AudioInputKey)>,
+}
+
+impl GraphSwap {
+    pub fn new(executor: Executor, resources: Resources) -> Self {
+        Self {
+            executor,
+            resources,
+            crossfade_blocks: 0,
+            crossfade_buffer: Box::default(),
+            nodes: Vec::new(),
+            delay_lines: Vec::new(),
+            external_buffers: Vec::new(),
+            audio_inputs: Vec::new(),
+        }
+    }
+
+    /// Blend from the running graph into this one over `blocks` blocks, rather than cutting over.
+    pub fn with_crossfade(mut self, blocks: usize, block_size: usize) -> Self {
+        self.crossfade_blocks = blocks;
+        self.crossfade_buffer = if blocks > 0 {
+            vec![0.0; MAX_ARITY * block_size].into()
+        } else {
+            Box::default()
+        };
+        self
+    }
+
+    /// Pair up everything the incoming graph shares by name with the running one.
+    ///
+    /// Nodes pair by fully qualified alias here; their kinds are compared on the audio
+    /// thread, where the outgoing graph lives.
+    pub(crate) fn pair_with(
+        &mut self,
+        incoming: &ReloadContext,
+        incoming_nodes: &HashMap<String, NodeKey>,
+        outgoing: &ReloadContext,
+        outgoing_nodes: &HashMap<String, NodeKey>,
+    ) {
+        self.nodes = pair_by_name(incoming_nodes, outgoing_nodes);
+
+        self.delay_lines = incoming
+            .delay_name_to_key
+            .iter()
+            .filter_map(|(name, keys)| {
+                outgoing
+                    .delay_name_to_key
+                    .get(name)
+                    .map(|prev| keys.iter().copied().zip(prev.iter().copied()))
+            })
+            .flatten()
+            .collect();
+
+        self.external_buffers = pair_by_name(
+            &incoming.external_buffer_to_key,
+            &outgoing.external_buffer_to_key,
+        );
+
+        self.audio_inputs = incoming
+            .audio_inputs
+            .iter()
+            .filter_map(|input| {
+                outgoing
+                    .audio_inputs
+                    .iter()
+                    .find(|prev| prev.name == input.name)
+                    .map(|prev| (input.key, prev.key))
+            })
+            .collect();
+    }
+
+    /// Carry state over from the running graph into this one. Called on the audio thread.
+    pub(crate) fn carry_from(&mut self, executor: &Executor, resources: &mut Resources) {
+        for (key, prev_key) in &self.nodes {
+            if let (Some(node), Some(prev)) = (
+                self.executor.graph.get_node_mut(*key),
+                executor.graph.get_node(*prev_key),
+            ) && node.node_kind == prev.node_kind
+            {
+                node.get_node_mut().carry_state(prev.get_node().as_any());
+                node.bypass = prev.bypass.clone();
+            }
+        }
+
+        for (key, prev_key) in &self.delay_lines {
+            self.resources.carry_delay_line(*key, resources, *prev_key);
+        }
+
+        for (key, prev_key) in &self.external_buffers {
+            self.resources
+                .carry_external_buffer(*key, resources, *prev_key);
+        }
+
+        for (key, prev_key) in &self.audio_inputs {
+            self.resources.carry_audio_input(*key, resources, *prev_key);
+        }
+    }
+}
+
+impl Debug for GraphSwap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GraphSwap")
+            .field("graph", &self.executor.graph)
+            .field("carried_nodes", &self.nodes.len())
+            .finish()
+    }
+}
+
+fn pair_by_name<K: Copy>(
+    incoming: &HashMap<String, K>,
+    outgoing: &HashMap<String, K>,
+) -> Vec<(K, K)> {
+    incoming
+        .iter()
+        .filter_map(|(name, key)| outgoing.get(name).map(|prev| (*key, *prev)))
+        .collect()
+}
+
+/// The shape of an audio input registered on the original builder.
+#[derive(Clone, Debug)]
+pub(crate) struct AudioInputLayout {
+    pub name: String,
+    pub key: AudioInputKey,
+    pub chans: usize,
+    pub block_size: usize,
+}
+
+/// What the frontend keeps from the last build in order to build the next graph the same way,
+/// and to match that graph's resources against the running one.
+#[derive(Clone)]
+pub(crate) struct ReloadContext {
+    pub config: Config,
+    pub ports: Ports,
+    pub namespaces: HashMap<String, NodeRegistry>,
+    pub delay_name_to_key: HashMap<String, Vec<DelayLineKey>>,
+    pub external_buffer_to_key: HashMap<String, ExternalBufferKey>,
+    pub audio_inputs: Vec<AudioInputLayout>,
+    pub worker_pool: Option<Arc<WorkerPool>>,
+    pub control_rate: usize,
+    pub module_loader: Arc<dyn ModuleLoader>,
+    pub pipeline_dump: Option<PathBuf>,
+}
+
+/// A reloaded graph, and the frontend half that talks to it once it is live.
+pub(crate) struct PreparedReload {
+    pub swap: GraphSwap,
+    pub resources_frontend: ResourceFrontend,
+    pub node_registry: HashMap<String, NodeKey>,
+    pub reload_context: ReloadContext,
+}
+
+impl PreparedReload {
+    /// Build `graph` off of the audio thread against the app described by `context`.
+    pub(crate) fn build(
+        graph: &str,
+        context: &ReloadContext,
+        node_registry: &HashMap<String, NodeKey>,
+        crossfade_blocks: usize,
+    ) -> Result<Self, ValidationError> {
+        let mut prepared =
+            LegatoBuilder::<Unconfigured>::new(context.config, context.ports.clone())
+                .build_dsl_reload(graph, context)?;
+
+        prepared.swap = prepared
+            .swap
+            .with_crossfade(crossfade_blocks, context.config.block_size);
+
+        prepared.swap.pair_with(
+            &prepared.reload_context,
+            &prepared.node_registry,
+            context,
+            node_registry,
+        );
+
+        Ok(prepared)
+    }
+}
@@ -50,6 +50,14 @@ impl AudioInput {
         }
     }
 
+    pub fn chans(&self) -> usize {
+        self.chans
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
     /// The full flat non-interleaved buffer, note: this is not a per channel abstraction.
     #[inline]
     pub fn as_slice(&self) -> &[f32] {
@@ -142,6 +142,61 @@ impl Resources {
             .channel(chan)
     }
 
+    /// Replay the most recent contents of `previous`'s delay line into ours, oldest first,
+    /// so a reloaded graph keeps its echoes. Lines of different capacity keep the overlap.
+    pub fn carry_delay_line(
+        &mut self,
+        key: DelayLineKey,
+        previous: &Resources,
+        previous_key: DelayLineKey,
+    ) {
+        let (Some(delay), Some(prev)) = (
+            self.delay_lines.get_mut(key),
+            previous.delay_lines.get(previous_key),
+        ) else {
+            return;
+        };
+
+        let data = self.arena.slice_mut(delay.get_window());
+        let prev_data = previous.arena.slice(prev.get_window());
+
+        let n = data.len().min(prev_data.len());
+        for d in (0..n).rev() {
+            delay.push(data, prev.get_offset(prev_data, d));
+        }
+    }
+
+    /// Share a loaded sample from `previous`'s slot. Only the `Arc` is cloned, so both
+    /// graphs can keep playing it while they crossfade.
+    pub fn carry_external_buffer(
+        &mut self,
+        key: ExternalBufferKey,
+        previous: &Resources,
+        previous_key: ExternalBufferKey,
+    ) {
+        if let (Some(slot), Some(prev_slot)) = (
+            self.external_buffers.get_mut(key),
+            previous.external_buffers.get(previous_key),
+        ) {
+            slot.clone_from(prev_slot);
+        }
+    }
+
+    /// Trade audio inputs with `previous`, handing us its live consumer.
+    pub fn carry_audio_input(
+        &mut self,
+        key: AudioInputKey,
+        previous: &mut Resources,
+        previous_key: AudioInputKey,
+    ) {
+        if let (Some(input), Some(prev_input)) = (
+            self.audio_inputs.get_mut(key),
+            previous.audio_inputs.get_mut(previous_key),
+        ) {
+            std::mem::swap(input, prev_input);
+        }
+    }
+
     pub fn drain(&mut self) {
         // Drain external buffers
         while let Ok(incoming) = self.external_buffer_update_receiver.pop() {
@@ -234,6 +289,28 @@ impl ResourceBuilder {
         self.audio_input_key_lookup.get(name).copied()
     }
 
+    /// The name, key, channel count and block size of every registered audio input.
+    pub fn audio_input_layouts(&self) -> Vec<(String, AudioInputKey, usize, usize)> {
+        self.audio_input_key_lookup
+            .iter()
+            .filter_map(|(name, key)| {
+                self.audio_inputs
+                    .get(*key)
+                    .map(|input| (name.clone(), *key, input.chans(), input.block_size()))
+            })
+            .collect()
+    }
+
+    /// Whether any delay line, buffer or param has been reserved on this builder.
+    ///
+    /// Audio inputs are not counted, as they are registered up front rather than claimed by nodes.
+    pub fn has_claims(&self) -> bool {
+        !self.delay_lines.is_empty()
+            || !self.internal_buffers.is_empty()
+            || !self.external_buffers.is_empty()
+            || !self.param_builder.is_empty()
+    }
+
     pub fn build(
         self,
         rt_capacity: usize,
@@ -338,4 +415,9 @@ impl ResourceFrontend {
     pub fn get_all(&self) -> Vec<f32> {
         self.param_front_end.get_all()
     }
+
+    /// Copy the current value of every param `previous` shares by name with this store.
+    pub fn carry_params(&self, previous: &ResourceFrontend) {
+        self.param_front_end.carry_values(&previous.param_front_end);
+    }
 }
@@ -110,6 +110,18 @@ impl ParamStoreFrontend {
         }
     }
 
+    /// Copy the current value of every param `previous` shares by name with this store.
+    pub fn carry_values(&self, previous: &ParamStoreFrontend) {
+        for (name, prev_key) in &previous.param_lookup {
+            if let (Some(key), Ok(val)) = (
+                self.param_lookup.get(name),
+                previous.get_param(prev_key.clone()),
+            ) {
+                let _ = self.set_param(key.clone(), val);
+            }
+        }
+    }
+
     #[inline(always)]
     pub fn get_key(&self, name: &'static str) -> Result<ParamKey, ParamError> {
         match self.param_lookup.get(name) {
@@ -145,6 +157,10 @@ impl ParamStoreBuilder {
         key
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.meta.is_empty()
+    }
+
     pub fn build(self) -> (ParamStoreFrontend, ParamStore) {
         let data_vec = self
             .meta
@@ -1,16 +1,25 @@
 use crate::builder::ValidationError;
 use crate::config::Config;
 use crate::context::AudioContext;
-use crate::executor::{Executor, OutputView};
+use crate::dsl::ir::SourceSpan;
+use crate::edit::GraphEdit;
+use crate::executor::{BufferStats, Executor, MAIN_OUTPUT, MAX_ARITY, OutputView};
 use crate::graph::{Connection, GraphError};
 use crate::msg::LegatoMsg;
 use crate::node::LegatoNode;
+use crate::pool::WorkerPool;
 use crate::ports::Ports;
+use crate::profile::Profiler;
+use crate::reload::GraphSwap;
 use crate::resources::buffer::{AudioSampleError, decode_with_ffmpeg};
 use crate::resources::params::{ParamError, ParamKey};
 use crate::resources::{ResourceFrontend, Resources};
+use crate::tap::{MAX_TAPS, TapSender};
 use slotmap::new_key_type;
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 new_key_type! {
     /// A slotmap key corresponding to a particular node.
@@ -21,6 +30,32 @@ pub struct Runtime {
     context: AudioContext,
     executor: Executor,
     ports: Ports,
+    // Graphs replaced by a hot reload or edit, sent back to be dropped off of the audio thread
+    retired_graphs: Option<rtrb::Producer<RetiredGraph>>,
+    // Graphs leaked because that queue was full or missing, see `LegatoFrontend::leaked_graphs`
+    leaked_graphs: Arc<AtomicUsize>,
+    // The replaced graph, while it is still being faded out
+    crossfade: Option<Crossfade>,
+    // Set when the app was built with profiling
+    profiler: Option<Profiler>,
+    // Node outputs being sent to the frontend, see `LegatoFrontend::tap`
+    taps: Vec<TapSender>,
+}
+
+/// A graph the runtime is done with, in the box it arrived in.
+#[derive(Debug)]
+pub enum RetiredGraph {
+    Swap(Box<GraphSwap>),
+    Edit(Box<GraphEdit>),
+    /// A tap whose node is gone, or whose receiver was dropped.
+    Tap(TapSender),
+}
+
+/// An outgoing graph that keeps running until the incoming one has faded in over it.
+struct Crossfade {
+    outgoing: Box<GraphSwap>,
+    block: usize,
+    blocks: usize,
 }
 impl Runtime {
     pub fn new(context: AudioContext, ports: Ports) -> Self {
@@ -30,8 +65,28 @@ impl Runtime {
             context,
             executor,
             ports,
+            retired_graphs: None,
+            leaked_graphs: Arc::default(),
+            crossfade: None,
+            profiler: None,
+            taps: Vec::with_capacity(MAX_TAPS),
         }
     }
+    /// Split the runtime into its prepared executor and resources, e.g. to ship them to a running app.
+    pub fn into_parts(self) -> (Executor, Resources) {
+        (self.executor, self.context.into_resources())
+    }
+    pub fn set_retired_graph_sender(&mut self, sender: rtrb::Producer<RetiredGraph>) {
+        self.retired_graphs = Some(sender);
+    }
+    /// Counts the retired graphs that had to be leaked because the queue back was full or missing.
+    pub fn leaked_graph_counter(&self) -> Arc<AtomicUsize> {
+        self.leaked_graphs.clone()
+    }
+    pub(crate) fn set_profiler(&mut self, profiler: Profiler) {
+        self.executor.set_profiling(true);
+        self.profiler = Some(profiler);
+    }
     pub fn add_node(&mut self, node: LegatoNode) -> NodeKey {
         self.executor.graph.add_node(node)
     }
@@ -49,12 +104,60 @@ impl Runtime {
     pub fn remove_edge(&mut self, connection: Connection) -> Result<(), GraphError> {
         self.executor.graph.remove_edge(connection)
     }
+    /// Add an edge that reads the previous block of its source, so it may close a cycle.
+    pub fn add_feedback_edge(&mut self, connection: Connection) -> Result<Connection, GraphError> {
+        self.executor.graph.add_feedback_edge(connection)
+    }
+    pub fn remove_feedback_edge(&mut self, connection: Connection) -> Result<(), GraphError> {
+        self.executor.graph.remove_feedback_edge(connection)
+    }
+    pub fn set_worker_pool(&mut self, pool: Option<Arc<WorkerPool>>) {
+        self.executor.set_worker_pool(pool);
+    }
+    pub fn set_control_rate(&mut self, decimation: usize) {
+        self.executor.set_control_rate(decimation);
+    }
     pub fn set_sink_key(&mut self, key: NodeKey) -> Result<(), GraphError> {
         self.executor.set_sink(key)
     }
+    pub fn add_output(&mut self, name: &str, key: NodeKey) -> Result<(), GraphError> {
+        self.executor.add_output(name, key)
+    }
+    /// A named output from the last block, or the sink's as `main`. Only the sink is
+    /// crossfaded on a hot reload, other outputs cut straight over.
+    pub fn output(&self, name: &str) -> Option<OutputView<'_>> {
+        let block_size = self.context.get_config().block_size;
+        match name {
+            MAIN_OUTPUT => Some(self.executor.output(block_size)),
+            name => self.executor.named_output(name, block_size),
+        }
+    }
+    /// The name of every output, starting with the sink's.
+    pub fn output_names(&self) -> Vec<&str> {
+        std::iter::once(MAIN_OUTPUT)
+            .chain(
+                self.executor
+                    .outputs()
+                    .iter()
+                    .map(|(name, _)| name.as_str()),
+            )
+            .collect()
+    }
     pub fn validate_arity(&self) -> Result<(), ValidationError> {
         self.executor.validate_arity()
     }
+    pub fn validate_control_rate(&self) -> Result<(), ValidationError> {
+        let block_size = self.context.get_config().block_size;
+        self.executor.validate_control_rate(block_size)
+    }
+    /// See [`Executor::validate_rates`]. Errors point at the node's entry in `spans`, if any.
+    pub fn validate_rates(
+        &self,
+        spans: &HashMap<NodeKey, SourceSpan>,
+    ) -> Result<(), ValidationError> {
+        let block_size = self.context.get_config().block_size;
+        self.executor.validate_rates(block_size, spans)
+    }
     pub fn set_resources(&mut self, resources: Resources) {
         self.context.set_resources(resources);
     }
@@ -67,6 +170,12 @@ impl Runtime {
     pub fn get_config(&self) -> Config {
         self.context.get_config()
     }
+    pub fn get_executor(&self) -> &Executor {
+        &self.executor
+    }
+    pub fn get_ports(&self) -> &Ports {
+        &self.ports
+    }
     /// Prepare and allocate all of the information needed for the audio execution plan
     pub fn prepare(&mut self) {
         let block_size = self.context.get_config().block_size;
@@ -82,9 +191,80 @@ impl Runtime {
                     node.handle_msg(param_msg);
                 }
             }
+            LegatoMsg::SwapGraph(swap) => self.swap_graph(swap),
+            LegatoMsg::EditGraph(edit) => self.edit_graph(edit),
+            LegatoMsg::AddTap(tap) => {
+                if self.taps.len() < MAX_TAPS {
+                    self.taps.push(tap);
+                } else {
+                    self.retire_graph(RetiredGraph::Tap(tap));
+                }
+            }
+        }
+    }
+
+    /// Swap in a graph prepared off of the audio thread, at the block boundary this is called on.
+    ///
+    /// Matching nodes, delay lines, loaded samples and audio inputs are carried over from the
+    /// outgoing graph first. That graph then leaves in the same box the new one arrived in,
+    /// straight away or once it has been crossfaded out.
+    fn swap_graph(&mut self, mut swap: Box<GraphSwap>) {
+        // A reload landing mid fade cuts the older graph off, and fades from the current one
+        if let Some(fade) = self.crossfade.take() {
+            self.retire_graph(RetiredGraph::Swap(fade.outgoing));
+        }
+
+        swap.carry_from(&self.executor, self.context.get_resources_mut());
+
+        // Node keys mean nothing in the new graph
+        while let Some(tap) = self.taps.pop() {
+            self.retire_graph(RetiredGraph::Tap(tap));
+        }
+
+        std::mem::swap(&mut self.executor, &mut swap.executor);
+        std::mem::swap(self.context.get_resources_mut(), &mut swap.resources);
+        self.executor.set_profiling(self.profiler.is_some());
+        swap.executor.set_profiling(false);
+
+        // `swap` now holds the outgoing graph, which is only worth fading from if it ever ran
+        let blocks = swap.crossfade_blocks;
+        if blocks > 0 && swap.executor.sink().is_some() {
+            self.crossfade = Some(Crossfade {
+                outgoing: swap,
+                block: 0,
+                blocks,
+            });
+        } else {
+            self.retire_graph(RetiredGraph::Swap(swap));
         }
     }
 
+    /// Swap in an edited copy of the running graph, moving the running nodes across so
+    /// that only the edit itself is heard.
+    fn edit_graph(&mut self, mut edit: Box<GraphEdit>) {
+        edit.adopt_nodes(&mut self.executor);
+
+        std::mem::swap(&mut self.executor, &mut edit.executor);
+        self.executor.set_profiling(self.profiler.is_some());
+
+        self.retire_graph(RetiredGraph::Edit(edit));
+    }
+
+    /// Send `graph` back to be dropped off of the audio thread. If there is no queue back, or
+    /// the frontend has fallen so far behind that it is full, the graph is leaked rather than
+    /// freed here.
+    fn retire_graph(&mut self, graph: RetiredGraph) {
+        let graph = match &mut self.retired_graphs {
+            Some(sender) => match sender.push(graph) {
+                Ok(()) => return,
+                Err(rtrb::PushError::Full(graph)) => graph,
+            },
+            None => graph,
+        };
+        std::mem::forget(graph);
+        self.leaked_graphs.fetch_add(1, Ordering::Relaxed);
+    }
+
     // F32 is a bit weird here, but we cast so frequently why not
     pub fn get_sample_rate(&self) -> usize {
         self.context.get_config().sample_rate
@@ -104,6 +284,10 @@ impl Runtime {
     pub fn get_node_mut(&mut self, key: &NodeKey) -> Option<&mut LegatoNode> {
         self.executor.graph.get_node_mut(*key)
     }
+    /// The size of the executor's data buffer, with and without output slots being reused.
+    pub fn buffer_stats(&self) -> BufferStats {
+        self.executor.buffer_stats()
+    }
     /// The kind of every node in the built graph, for introspecting graph shape.
     pub fn node_kinds(&self) -> Vec<&str> {
         self.executor
@@ -121,7 +305,98 @@ impl Runtime {
 
     // Execute the audio plan and return the next block
     pub fn next_block(&mut self) -> OutputView<'_> {
-        self.executor.process(&mut self.context)
+        if let Some(profiler) = &mut self.profiler {
+            profiler.publish(&mut self.executor);
+        }
+
+        if let Some(fade) = &self.crossfade
+            && fade.block >= fade.blocks
+        {
+            let fade = self.crossfade.take().unwrap();
+            self.retire_graph(RetiredGraph::Swap(fade.outgoing));
+        }
+
+        match self.crossfade {
+            Some(_) => self.next_block_crossfade(),
+            None => {
+                self.executor.run(&mut self.context);
+                self.feed_taps();
+                self.executor.output(self.context.get_config().block_size)
+            }
+        }
+    }
+
+    /// Send this block's tapped outputs to the frontend, retiring taps that are no longer read
+    /// or no longer fit the graph.
+    fn feed_taps(&mut self) {
+        let block_size = self.context.get_config().block_size;
+
+        let mut i = 0;
+        while i < self.taps.len() {
+            let tap = &mut self.taps[i];
+            match self.executor.node_output(tap.key, tap.port, block_size) {
+                Some(block) if !tap.is_abandoned() => {
+                    tap.feed(block);
+                    i += 1;
+                }
+                _ => {
+                    let tap = self.taps.swap_remove(i);
+                    self.retire_graph(RetiredGraph::Tap(tap));
+                }
+            }
+        }
+    }
+
+    /// Run both the outgoing and incoming graph, and blend their sink outputs with an equal-power curve.
+    fn next_block_crossfade(&mut self) -> OutputView<'_> {
+        let block_size = self.context.get_config().block_size;
+        let fade = self.crossfade.as_mut().unwrap();
+        let outgoing = &mut *fade.outgoing;
+
+        // The outgoing graph still reads and writes its own resources
+        std::mem::swap(self.context.get_resources_mut(), &mut outgoing.resources);
+        let old = outgoing.executor.process(&mut self.context);
+
+        let buffer = &mut outgoing.crossfade_buffer;
+        for (c, chan) in buffer.chunks_exact_mut(block_size).enumerate() {
+            match old.channels.get(c).filter(|_| c < old.chans) {
+                Some(samples) => chan.copy_from_slice(samples),
+                None => chan.fill(0.0),
+            }
+        }
+        std::mem::swap(self.context.get_resources_mut(), &mut outgoing.resources);
+
+        self.executor.run(&mut self.context);
+        self.feed_taps();
+
+        let fade = self.crossfade.as_mut().unwrap();
+        let buffer = &mut fade.outgoing.crossfade_buffer;
+        let new = self.executor.output(block_size);
+
+        let total = (fade.blocks * block_size) as f32;
+        let start = fade.block * block_size;
+
+        for (c, chan) in buffer
+            .chunks_exact_mut(block_size)
+            .enumerate()
+            .take(new.chans)
+        {
+            for (n, (out, incoming)) in chan.iter_mut().zip(new.channels[c]).enumerate() {
+                let t = (start + n + 1) as f32 / total * std::f32::consts::FRAC_PI_2;
+                *out = *out * t.cos() + incoming * t.sin();
+            }
+        }
+
+        fade.block += 1;
+
+        let mut chunks = fade.outgoing.crossfade_buffer.chunks_exact(block_size);
+        let channels: [&[f32]; MAX_ARITY] =
+            std::array::from_fn(|_| chunks.next().unwrap_or_default());
+
+        OutputView {
+            channels,
+            chans: new.chans,
+        }
     }
 }
 
@@ -181,4 +456,57 @@ impl RuntimeFrontend {
     pub fn get_param_key(&self, param_name: &'static str) -> Result<ParamKey, ParamError> {
         self.resource_frontend.get_param_key(param_name)
     }
+
+    pub fn resource_frontend(&self) -> &ResourceFrontend {
+        &self.resource_frontend
+    }
+
+    /// Drop external buffers the runtime has replaced.
+    pub fn drain_garbage(&mut self) {
+        self.resource_frontend.drain_garbage();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{harness::build_placeholder_context, ports::PortBuilder};
+
+    fn runtime() -> Runtime {
+        let config = Config {
+            sample_rate: 48_000,
+            block_size: 64,
+            channels: 1,
+            rt_capacity: 0,
+        };
+        let ports = PortBuilder::default().audio_out(1).build();
+        Runtime::new(build_placeholder_context(config), ports)
+    }
+
+    /// A full queue back to the frontend leaks the graph and counts it, rather than panicking
+    /// on the audio thread.
+    #[test]
+    fn full_retired_queue_leaks() {
+        let mut runtime = runtime();
+        let (sender, _receiver) = rtrb::RingBuffer::new(1);
+        runtime.set_retired_graph_sender(sender);
+
+        for _ in 0..3 {
+            let (tap, _) = TapSender::new(NodeKey::default(), 0, 64);
+            runtime.retire_graph(RetiredGraph::Tap(tap));
+        }
+
+        assert_eq!(runtime.leaked_graph_counter().load(Ordering::Relaxed), 2);
+    }
+
+    /// Without a queue back at all, graphs are leaked and counted too, never freed here.
+    #[test]
+    fn missing_retired_queue_leaks() {
+        let mut runtime = runtime();
+
+        let (tap, _) = TapSender::new(NodeKey::default(), 0, 64);
+        runtime.retire_graph(RetiredGraph::Tap(tap));
+
+        assert_eq!(runtime.leaked_graph_counter().load(Ordering::Relaxed), 1);
+    }
 }
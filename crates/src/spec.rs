@@ -4,7 +4,7 @@ use std::collections::BTreeSet;
 
 use crate::{
     builder::{ResourceBuilderView, ValidationError},
-    dsl::ir::DSLParams,
+    dsl::ir::{DSLParams, Value},
     node::DynNode,
 };
 
@@ -14,7 +14,7 @@ pub type NodeFactory =
 
 /// This struct defines the node display/debug name, required and optional params,
 /// as well as a node factory for a node definition.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct NodeSpec {
     pub name: String,
     pub description: &'static str,
@@ -26,12 +26,23 @@ pub struct NodeSpec {
 impl NodeSpec {
     /// A quick pass to simply see if there are any keys
     /// we do not need in this context.
-    pub fn check_for_bad_params(&self, params: &DSLParams) {
+    pub fn check_for_bad_params(&self, params: &DSLParams) -> Result<(), ValidationError> {
         for k in params.0.keys() {
             if !self.required_params.contains(k) && !self.optional_params.contains(k) {
-                panic!("Invalid params {} found on node {}", k, self.name);
+                return Err(ValidationError::InvalidParameter(format!(
+                    "Invalid params {} found on node {}",
+                    k, self.name
+                )));
             }
         }
+        // A channel width may come from a patch param, so it is only known to be sane here
+        if matches!(params.0.get("chans"), Some(Value::U32(0))) {
+            return Err(ValidationError::InvalidParameter(format!(
+                "`chans` on node {} must be at least 1",
+                self.name
+            )));
+        }
+        Ok(())
     }
 }
 
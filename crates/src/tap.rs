@@ -0,0 +1,104 @@
+use std::fmt::Debug;
+
+use crate::runtime::NodeKey;
+
+/// How many blocks of samples a tap holds before it starts dropping them.
+pub const TAP_BLOCKS: usize = 8;
+
+/// How many blocks of levels a tap holds before it starts dropping them.
+pub const TAP_LEVELS: usize = 64;
+
+/// How many taps the runtime feeds at once. Taps past this are dropped straight away.
+pub const MAX_TAPS: usize = 64;
+
+/// The peak and RMS of one block of a tapped output.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TapLevel {
+    pub peak: f32,
+    pub rms: f32,
+}
+
+impl TapLevel {
+    pub fn of(block: &[f32]) -> Self {
+        let (peak, sum_sq) = block.iter().fold((0.0f32, 0.0f32), |(peak, sum_sq), x| {
+            (peak.max(x.abs()), sum_sq + x * x)
+        });
+
+        Self {
+            peak,
+            rms: (sum_sq / block.len().max(1) as f32).sqrt(),
+        }
+    }
+}
+
+/// The receiving end of a node output tap, for scopes and meters.
+///
+/// Every block, the runtime pushes the tapped samples into `samples` and their level into
+/// `levels`. Whatever does not fit is dropped, so read from whichever is needed and ignore the
+/// other. The runtime stops feeding the tap once this is dropped, or the node is removed or
+/// reloaded away, see [`rtrb::Consumer::is_abandoned`].
+pub struct Tap {
+    pub samples: rtrb::Consumer<f32>,
+    pub levels: rtrb::Consumer<TapLevel>,
+}
+
+impl Debug for Tap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tap")
+            .field("samples", &self.samples.slots())
+            .field("levels", &self.levels.slots())
+            .finish()
+    }
+}
+
+/// The runtime's end of a [`Tap`].
+pub struct TapSender {
+    pub(crate) key: NodeKey,
+    pub(crate) port: usize,
+    samples: rtrb::Producer<f32>,
+    levels: rtrb::Producer<TapLevel>,
+}
+
+impl TapSender {
+    pub(crate) fn new(key: NodeKey, port: usize, block_size: usize) -> (Self, Tap) {
+        let (samples, samples_rx) = rtrb::RingBuffer::new(block_size * TAP_BLOCKS);
+        let (levels, levels_rx) = rtrb::RingBuffer::new(TAP_LEVELS);
+
+        let sender = Self {
+            key,
+            port,
+            samples,
+            levels,
+        };
+        let tap = Tap {
+            samples: samples_rx,
+            levels: levels_rx,
+        };
+
+        (sender, tap)
+    }
+
+    /// Nobody is listening any more, so the tap can be retired.
+    pub(crate) fn is_abandoned(&self) -> bool {
+        self.samples.is_abandoned()
+    }
+
+    /// Push as much of `block` as fits, and its level.
+    pub(crate) fn feed(&mut self, block: &[f32]) {
+        let n = block.len().min(self.samples.slots());
+        if let Ok(chunk) = self.samples.write_chunk_uninit(n) {
+            chunk.fill_from_iter(block.iter().copied());
+        }
+
+        let _ = self.levels.push(TapLevel::of(block));
+    }
+}
+
+impl Debug for TapSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TapSender")
+            .field("key", &self.key)
+            .field("port", &self.port)
+            .finish()
+    }
+}
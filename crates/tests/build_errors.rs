@@ -0,0 +1,196 @@
+//! `build_dsl` on sources that are wrong in ways only the builder can tell: bad params,
+//! missing ports, cycles. Every such mistake must come back as a [`ValidationError`]
+//! pointing at the declaration or connection that made it, never as a panic.
+
+use legato::{
+    builder::ValidationError,
+    registry::{audio_registry_factory, control_registry_factory, midi_registry_factory},
+};
+use proptest::prelude::*;
+
+mod common;
+use common::{blamed, stereo_builder};
+
+fn build(src: &str) -> Result<(), ValidationError> {
+    stereo_builder().build_dsl(src).map(|_| ())
+}
+
+/// Every node type with the params it declares, so generated programs reach the factories.
+fn node_types() -> Vec<(&'static str, String, Vec<String>)> {
+    [
+        ("audio", audio_registry_factory()),
+        ("control", control_registry_factory()),
+        ("midi", midi_registry_factory()),
+    ]
+    .into_iter()
+    .flat_map(|(namespace, registry)| {
+        registry
+            .specs()
+            .map(|spec| {
+                let params = spec
+                    .required_params
+                    .iter()
+                    .chain(&spec.optional_params)
+                    .cloned()
+                    .collect();
+                (namespace, spec.name.clone(), params)
+            })
+            .collect::<Vec<_>>()
+    })
+    .collect()
+}
+
+/// Small values of every type, so each param sees both what it wants and what it doesn't,
+/// and expressions, including ones that overflow or divide by zero.
+const VALUES: [&str; 17] = [
+    "0",
+    "1",
+    "3",
+    "-1",
+    "0.5",
+    "200.0",
+    "\"x\"",
+    "true",
+    "[]",
+    "[1.0]",
+    "[0.5, 2.0]",
+    "2 * 3 - 1",
+    "0.5 + 1",
+    "-(1 - 3)",
+    "65536 * 65536 * 65536 * 65536",
+    "7 % 0",
+    "clamp(4, 0, 2)",
+];
+const PORTS: [&str; 7] = ["", "[0]", "[3]", "[0..2]", "[1:5:2]", ".freq", ".audio_in"];
+const SELECTORS: [&str; 4] = ["", "(0)", "(*)", "(1..3)"];
+
+/// `(node type, count, (param, value) picks)` for each declaration.
+type Decl = (usize, u32, Vec<(usize, usize)>);
+/// `(source, selector, port, sink, selector, port)` by position.
+type Wire = (usize, usize, usize, usize, usize, usize);
+
+fn program() -> impl Strategy<Value = (Vec<Decl>, Vec<Wire>, usize)> {
+    let decl = (
+        any::<usize>(),
+        1u32..=3,
+        prop::collection::vec((any::<usize>(), 0..VALUES.len()), 0..4),
+    );
+    let wire = (
+        0..4usize,
+        0..SELECTORS.len(),
+        0..PORTS.len(),
+        0..4usize,
+        0..SELECTORS.len(),
+        0..PORTS.len(),
+    );
+    (
+        prop::collection::vec(decl, 1..4),
+        prop::collection::vec(wire, 0..4),
+        0..4usize,
+    )
+}
+
+fn render(types: &[(&str, String, Vec<String>)], program: (Vec<Decl>, Vec<Wire>, usize)) -> String {
+    let (decls, wires, sink) = program;
+    let alias = |i: usize| format!("n{}", i % decls.len());
+
+    let mut src = String::new();
+    for (i, (ty, count, params)) in decls.iter().enumerate() {
+        let (namespace, name, known) = &types[ty % types.len()];
+        let params: Vec<String> = params
+            .iter()
+            .filter(|_| !known.is_empty())
+            .map(|(param, value)| format!("{}: {}", known[param % known.len()], VALUES[*value]))
+            .collect();
+        src += &format!(
+            "{namespace} {{ {name}: n{i} * {count} {{ {} }} }}\n",
+            params.join(", ")
+        );
+    }
+    for (a, a_sel, a_port, b, b_sel, b_port) in wires {
+        src += &format!(
+            "{}{}{} >> {}{}{}\n",
+            alias(a),
+            SELECTORS[a_sel],
+            PORTS[a_port],
+            alias(b),
+            SELECTORS[b_sel],
+            PORTS[b_port]
+        );
+    }
+    src + &format!("{{ {} }}", alias(sink))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    /// Real node types with params of the wrong type, range or name, wired through ports
+    /// they may not have, build or fail without panicking.
+    #[test]
+    fn generated_programs_never_panic(program in program()) {
+        let src = render(&node_types(), program);
+        let _ = build(&src);
+    }
+
+    /// Text that barely parses, or doesn't, is just as safe.
+    #[test]
+    fn arbitrary_text_never_panics(src in "(audio \\{ sine \\{ freq: [0-9]{1,3} \\} \\}|[ -~\n]{0,40})*") {
+        let _ = build(&src);
+    }
+}
+
+/// An unknown filter type is blamed on the filter's declaration.
+#[test]
+fn bad_param_points_at_declaration() {
+    let src = "audio {\n    sine,\n    svf { type: \"wobble\" }\n}\nsine >> svf\n{ svf }";
+    let err = build(src).unwrap_err();
+    assert!(
+        matches!(err.unspanned(), ValidationError::InvalidParameter(_)),
+        "{err:?}"
+    );
+    assert_eq!(blamed(src, &err), "svf { type: \"wobble\" }");
+}
+
+/// A param the node does not declare is an error, not a panic.
+#[test]
+fn unknown_param_points_at_declaration() {
+    let src = "audio { sine { freq: 440.0 }, saw { frequency: 2.0 } }\n{ sine }";
+    let err = build(src).unwrap_err();
+    assert_eq!(blamed(src, &err), "saw { frequency: 2.0 }");
+}
+
+/// Connecting a port the node does not have is blamed on the connection.
+#[test]
+fn missing_port_points_at_connection() {
+    let src = "audio { sine, svf }\nsine >> svf.wobble\n{ svf }";
+    let err = build(src).unwrap_err();
+    assert!(
+        matches!(err.unspanned(), ValidationError::NodeNotFound(_)),
+        "{err:?}"
+    );
+    assert_eq!(blamed(src, &err), "sine >> svf.wobble");
+}
+
+/// So is connecting a node that was never declared.
+#[test]
+fn undeclared_node_points_at_connection() {
+    let src = "audio { sine }\nsine >> filter\n{ sine }";
+    let err = build(src).unwrap_err();
+    assert_eq!(blamed(src, &err), "sine >> filter");
+}
+
+/// Feedback outside a kernel names the connection that closes the loop.
+#[test]
+fn cycle_points_at_closing_connection() {
+    let src =
+        "audio { sine, svf, gain { val: 0.5 } }\nsine >> svf\nsvf >> gain\ngain >> svf\n{ svf }";
+    let err = build(src).unwrap_err();
+    assert!(
+        matches!(err.unspanned(), ValidationError::Cycle(_)),
+        "{err:?}"
+    );
+    assert!(
+        ["svf >> gain", "gain >> svf"].contains(&blamed(src, &err)),
+        "{err:?}"
+    );
+}
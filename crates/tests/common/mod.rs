@@ -0,0 +1,74 @@
+//! Fixtures shared by the integration tests: graphs at 48 kHz with `BLOCK`-sample blocks,
+//! built from DSL and rendered a block at a time.
+//!
+//! Each test file is its own crate and only uses some of these.
+#![allow(dead_code)]
+
+use legato::{
+    LegatoApp, LegatoFrontend,
+    builder::{Configured, LegatoBuilder, Unconfigured, ValidationError},
+    config::Config,
+    ports::PortBuilder,
+};
+
+pub const BLOCK: usize = 256;
+
+/// A config with `channels` outputs.
+pub fn config(channels: usize) -> Config {
+    Config {
+        sample_rate: 48_000,
+        block_size: BLOCK,
+        channels,
+        rt_capacity: 0,
+    }
+}
+
+/// A builder for a mono graph.
+pub fn builder() -> LegatoBuilder<Configured> {
+    builder_with_channels(1)
+}
+
+/// A builder for a graph with a left and a right output.
+pub fn stereo_builder() -> LegatoBuilder<Configured> {
+    builder_with_channels(2)
+}
+
+fn builder_with_channels(channels: usize) -> LegatoBuilder<Configured> {
+    let ports = PortBuilder::default().audio_out(channels).build();
+    LegatoBuilder::<Unconfigured>::new(config(channels), ports)
+}
+
+/// A mono graph, or why it did not build.
+pub fn build(src: &str) -> Result<LegatoApp, ValidationError> {
+    builder().build_dsl(src).map(|(app, _)| app)
+}
+
+/// A mono graph and the frontend to change it with, for sources that must build.
+pub fn build_live(src: &str) -> (LegatoApp, LegatoFrontend) {
+    builder().build_dsl(src).expect("graph should build")
+}
+
+/// The first channel of the next `blocks` blocks.
+pub fn render(app: &mut LegatoApp, blocks: usize) -> Vec<f32> {
+    let mut out = Vec::with_capacity(BLOCK * blocks);
+    for _ in 0..blocks {
+        out.extend_from_slice(app.next_block().channels[0]);
+    }
+    out
+}
+
+/// The source text the error points at.
+pub fn blamed<'a>(src: &'a str, err: &ValidationError) -> &'a str {
+    let span = err
+        .span()
+        .unwrap_or_else(|| panic!("{err:?} should have a span"));
+    &src[span]
+}
+
+/// How many times `signal` crosses zero on the way up, to compare pitches by.
+pub fn rising_crossings(signal: &[f32]) -> usize {
+    signal
+        .windows(2)
+        .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+        .count()
+}
@@ -0,0 +1,160 @@
+//! Constants and arithmetic at the head of a connection, against the nodes they stand for.
+
+mod common;
+use common::{build, render};
+
+fn assert_renders_same(sugar: &str, nodes: &str) {
+    let want = render(&mut build(nodes).expect("graph should build"), 4);
+    assert!(
+        want.iter().any(|x| x.abs() > 1e-3),
+        "patch rendered silence"
+    );
+    assert_eq!(
+        render(&mut build(sugar).expect("graph should build"), 4),
+        want
+    );
+}
+
+#[test]
+fn constant_into_a_port() {
+    let sugar = r#"
+        audio {
+            saw { chans: 1, freq: 110.0 }
+        }
+
+        220.0 >> saw.freq
+
+        { saw }
+    "#;
+    let nodes = r#"
+        audio {
+            const { val: 220.0 },
+            saw { chans: 1, freq: 110.0 }
+        }
+
+        const >> saw.freq
+
+        { saw }
+    "#;
+
+    assert_renders_same(sugar, nodes);
+}
+
+/// Constants become the op's `val`; `1.0 - x` needs a rail since `sub` takes `x` first.
+#[test]
+fn scaling_and_offsetting_a_signal() {
+    let sugar = r#"
+        audio {
+            sine: lfo { freq: 3.0 },
+            mult: out { val: 1.0 }
+        }
+
+        1.0 - lfo * 0.5 + 0.25 >> out[0]
+
+        { out }
+    "#;
+    let nodes = r#"
+        audio {
+            sine: lfo { freq: 3.0 },
+            mult: scale { val: 0.5 },
+            const: one { val: 1.0 },
+            sub: invert { val: 0.0 },
+            add: offset { val: 0.25 },
+            mult: out { val: 1.0 }
+        }
+
+        lfo >> scale[0]
+        one >> invert[0]
+        scale >> invert.val
+        invert >> offset[0]
+        offset >> out[0]
+
+        { out }
+    "#;
+
+    assert_renders_same(sugar, nodes);
+}
+
+#[test]
+fn combining_two_signals() {
+    let sugar = r#"
+        audio {
+            sine: a { freq: 220.0 },
+            saw: b { chans: 1, freq: 110.0 },
+            mult: out { val: 1.0 }
+        }
+
+        (a + b) * -a >> out[0]
+
+        { out }
+    "#;
+    let nodes = r#"
+        audio {
+            sine: a { freq: 220.0 },
+            saw: b { chans: 1, freq: 110.0 },
+            add: sum { val: 0.0 },
+            mult: neg { val: -1.0 },
+            mult: product { val: 1.0 },
+            mult: out { val: 1.0 }
+        }
+
+        a >> sum[0]
+        b >> sum.val
+        a >> neg[0]
+        sum >> product[0]
+        neg >> product.val
+        product >> out[0]
+
+        { out }
+    "#;
+
+    assert_renders_same(sugar, nodes);
+}
+
+/// Virtual ports and patch params work as operands inside patches and kernels.
+#[test]
+fn patch_and_kernel_bodies() {
+    let nodes = r#"
+        audio {
+            sine { freq: 220.0 },
+            mult { val: 0.25 },
+            add { val: 0.125 }
+        }
+
+        sine >> mult[0] >> add[0]
+
+        { add }
+    "#;
+
+    for keyword in ["patch", "kernel"] {
+        let sugar = format!(
+            r#"
+            {keyword} shaped(depth = 0.5) {{
+                in x
+
+                audio {{
+                    mult: out {{ val: 1.0 }}
+                }}
+
+                x * $depth * 0.5 + $depth / 4.0 >> out[0]
+
+                {{ out }}
+            }}
+
+            patches {{
+                shaped: s {{}}
+            }}
+
+            audio {{
+                sine {{ freq: 220.0 }}
+            }}
+
+            sine >> s.x
+
+            {{ s }}
+        "#
+        );
+
+        assert_renders_same(&sugar, nodes);
+    }
+}
@@ -0,0 +1,109 @@
+//! Control-kind nodes running at a decimated rate, see `LegatoBuilder::set_control_rate`.
+
+use std::sync::Arc;
+
+use legato::{LegatoApp, builder::ValidationError, pool::WorkerPool};
+
+mod common;
+use common::{BLOCK, builder, render};
+
+const RATE: usize = 16;
+
+fn build(src: &str, control_rate: usize) -> Result<LegatoApp, ValidationError> {
+    builder()
+        .set_control_rate(control_rate)
+        .build_dsl(src)
+        .map(|(app, _)| app)
+}
+
+/// An LFO mapped to a range, read by an audio-rate node.
+const MODULATED: &str = r#"
+    control {
+        lfo { freq: 3.0 },
+        map { range: [-1.0, 1.0], new_range: [0.0, 1.0] }
+    }
+    audio { add: o { val: 0.0 } }
+    lfo >> map >> o[0]
+    { o }
+"#;
+
+/// Read at audio rate, a control-rate LFO ramps between its values and stays close to the
+/// LFO run at audio rate.
+#[test]
+fn audio_rate_readers_interpolate() {
+    let full = render(&mut build(MODULATED, 1).unwrap(), 8);
+    let decimated = render(&mut build(MODULATED, RATE).unwrap(), 8);
+
+    assert!(full.iter().any(|x| *x > 0.6));
+    let worst = full
+        .iter()
+        .zip(&decimated)
+        .map(|(a, b)| (a - b).abs())
+        .fold(0.0, f32::max);
+    assert!(worst < 0.01, "{worst}");
+
+    // Linear ramps, so no two neighbouring samples are far apart either
+    assert!(decimated.windows(2).all(|w| (w[1] - w[0]).abs() < 1e-3));
+}
+
+/// A phasor is held between values rather than ramped, so it never sweeps back through its wrap.
+#[test]
+fn steps_are_held() {
+    let src = r#"
+        control { phasor { freq: 200.0 } }
+        audio { add: o { val: 0.0 } }
+        phasor >> o[0]
+        { o }
+    "#;
+    let out = render(&mut build(src, RATE).unwrap(), 2);
+
+    for chunk in out.chunks_exact(RATE) {
+        assert!(chunk.iter().all(|x| *x == chunk[0]), "{chunk:?}");
+    }
+    assert!(out.chunks_exact(RATE).any(|c| c[0] != out[0]));
+}
+
+/// The sink is read after the block, so a control node there keeps running at audio rate.
+#[test]
+fn sinks_stay_at_audio_rate() {
+    let src = "control { lfo { freq: 3.0 } }\n{ lfo }";
+    let full = render(&mut build(src, 1).unwrap(), 4);
+    let decimated = render(&mut build(src, RATE).unwrap(), 4);
+
+    assert_eq!(full, decimated);
+}
+
+/// Control-rate outputs take a fraction of the buffer an audio-rate output does.
+#[test]
+fn control_outputs_are_short() {
+    let full = build(MODULATED, 1).unwrap().buffer_stats();
+    let decimated = build(MODULATED, RATE).unwrap().buffer_stats();
+
+    // One audio-rate output, and two control-rate ones
+    assert_eq!(full.unshared_len, 3 * BLOCK);
+    assert_eq!(decimated.unshared_len, BLOCK + 2 * BLOCK / RATE);
+}
+
+/// On a pool, control-rate nodes run alongside the rest, with the same result.
+#[test]
+fn matches_on_a_pool() {
+    let serial = render(&mut build(MODULATED, RATE).unwrap(), 4);
+    let (mut pooled, _) = builder()
+        .set_control_rate(RATE)
+        .set_worker_pool(Arc::new(WorkerPool::new(2)))
+        .build_dsl(MODULATED)
+        .unwrap();
+
+    assert_eq!(render(&mut pooled, 4), serial);
+}
+
+/// A rate that does not leave whole SIMD vectors per block is rejected.
+#[test]
+fn rates_must_split_the_block() {
+    let err = build(MODULATED, 3).unwrap_err();
+
+    assert!(
+        matches!(err, ValidationError::InvalidParameter(ref msg) if msg.contains("control rate")),
+        "{err:?}"
+    );
+}
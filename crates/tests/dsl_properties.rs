@@ -9,6 +9,7 @@ use legato::builder::ValidationError;
 use legato::dsl::ir::*;
 use legato::dsl::parse::legato_parser;
 use legato::dsl::pipeline::Pipeline;
+use legato::dsl::print::print;
 use proptest::prelude::*;
 use std::collections::BTreeSet;
 
@@ -47,7 +48,7 @@ fn broadcastable(src: usize, snk: usize) -> bool {
 fn clamp(sel: &mut NodeSelector, count: u32) {
     let count = count as usize;
     match sel {
-        NodeSelector::Single | NodeSelector::All => {}
+        NodeSelector::Single | NodeSelector::All | NodeSelector::Template(_) => {}
         NodeSelector::Index(i) => *i %= count,
         NodeSelector::Range(a, b) => {
             *a %= count;
@@ -70,6 +71,10 @@ fn build_decls(specs: Vec<DeclSpec>, macros: &[AstMacro], prefix: &str) -> Vec<N
                 alias: Some(format!("{prefix}{i}")),
                 params: Some(params),
                 count,
+                count_expr: None,
+                pipes: vec![],
+                oversample: 1,
+                span: Default::default(),
             },
         )
         .collect();
@@ -120,6 +125,8 @@ fn build_conns(specs: Vec<WireSpec>, decls: &[NodeDeclaration]) -> Vec<Connectio
                     node_selector: snk_sel,
                     port: snk_port,
                 },
+                feedback: false,
+                span: Default::default(),
             })
         })
         .collect()
@@ -131,6 +138,10 @@ fn leaf(alias: &str, count: u32) -> NodeDeclaration {
         alias: Some(alias.to_string()),
         params: Some(Object::new()),
         count,
+        count_expr: None,
+        pipes: vec![],
+        oversample: 1,
+        span: Default::default(),
     }
 }
 
@@ -153,6 +164,8 @@ fn wire(src: &str, src_port: Port, snk: &str, snk_port: Port) -> Connection {
     Connection {
         source: endpoint(src, src_port),
         sink: endpoint(snk, snk_port),
+        feedback: false,
+        span: Default::default(),
     }
 }
 
@@ -189,12 +202,16 @@ fn build_macro(idx: usize, spec: MacroSpec, earlier: &[AstMacro]) -> AstMacro {
                 .collect(),
         ),
         virtual_ports_in: (0..vports).map(|i| format!("v{i}")).collect(),
+        virtual_ports_out: Default::default(),
+        rate: None,
         sink: alias_of(&body[sink]).to_string(),
         declarations: vec![DeclarationScope {
             namespace: NAMESPACE.to_string(),
             declarations: body,
         }],
         connections,
+        expr_connections: vec![],
+        blocks: vec![],
     }
 }
 
@@ -342,8 +359,12 @@ fn build_transparency(
         kind: MacroKind::Patch,
         default_params: Some(defaults),
         virtual_ports_in: vports.iter().map(|v| v.name.clone()).collect(),
+        virtual_ports_out: Default::default(),
+        rate: None,
         declarations: vec![scope(body)],
         connections: patch_conns,
+        expr_connections: vec![],
+        blocks: vec![],
         sink: sink_alias.clone(),
     });
 
@@ -360,6 +381,10 @@ fn build_transparency(
         alias: Some(INSTANCE.to_string()),
         params: Some(overrides),
         count: 1,
+        count_expr: None,
+        pipes: vec![],
+        oversample: 1,
+        span: Default::default(),
     });
     let mut patched_conns: Vec<Connection> = vports
         .iter()
@@ -403,20 +428,30 @@ fn build_transparency(
 
     Transparency {
         patched: Ast {
+            imports: vec![],
+            uses: vec![],
             declarations: vec![scope(patched_decls)],
             connections: patched_conns,
+            expr_connections: vec![],
+            blocks: vec![],
             macros: patched_macros,
             sink: top_sink.clone(),
+            outputs: vec![],
             source: None,
         },
         inlined: Ast {
+            imports: vec![],
+            uses: vec![],
             declarations: vec![scope(inline_decls)],
             connections: inline_conns,
+            expr_connections: vec![],
+            blocks: vec![],
             macros,
             sink: match downstream {
                 true => top_sink,
                 false => sink_alias,
             },
+            outputs: vec![],
             source: None,
         },
     }
@@ -442,6 +477,7 @@ fn selected(sel: &NodeSelector, n: u32) -> Vec<usize> {
         NodeSelector::Single | NodeSelector::All => (0..n as usize).collect(),
         NodeSelector::Index(i) => vec![*i],
         NodeSelector::Range(a, b) => (*a..*b).collect(),
+        NodeSelector::Template(_) => vec![],
     }
 }
 
@@ -477,6 +513,10 @@ fn build_multiplicity(
         alias: Some(alias.to_string()),
         params: Some(params.clone()),
         count,
+        count_expr: None,
+        pipes: vec![],
+        oversample: 1,
+        span: Default::default(),
     };
 
     let clamped = |sel: Option<NodeSelector>| {
@@ -497,6 +537,8 @@ fn build_multiplicity(
         spawn_conns.push(Connection {
             source: at(SRC, NodeSelector::Single),
             sink: at(SUBJECT, sel.clone()),
+            feedback: false,
+            span: Default::default(),
         });
         declare_conns.extend(
             selected(sel, n)
@@ -514,6 +556,8 @@ fn build_multiplicity(
         spawn_conns.push(Connection {
             source: at(SUBJECT, sel.clone()),
             sink: at(SNK, NodeSelector::Single),
+            feedback: false,
+            span: Default::default(),
         });
         declare_conns.extend(
             selected(sel, n)
@@ -530,17 +574,27 @@ fn build_multiplicity(
 
     Multiplicity {
         spawned: Ast {
+            imports: vec![],
+            uses: vec![],
             declarations: vec![scope(spawn_decls)],
             connections: spawn_conns,
+            expr_connections: vec![],
+            blocks: vec![],
             macros: macros.clone(),
             sink: spawn_sink,
+            outputs: vec![],
             source: None,
         },
         declared: Ast {
+            imports: vec![],
+            uses: vec![],
             declarations: vec![scope(declare_decls)],
             connections: declare_conns,
+            expr_connections: vec![],
+            blocks: vec![],
             macros,
             sink: declare_sink,
+            outputs: vec![],
             source: None,
         },
     }
@@ -612,6 +666,29 @@ fn render_value(val: &Value) -> String {
         Value::Template(x) => x.clone(),
         Value::Array(xs) => format!("[{}]", list(xs)),
         Value::Object(o) => format!("{{ {} }}", render_params(o)),
+        Value::Expr(e) => render_expr(e),
+    }
+}
+
+fn render_expr(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Number(x) => render_value(x),
+        ExprKind::Template(x) => x.clone(),
+        ExprKind::Neg(x) => format!("-({})", render_expr(x)),
+        ExprKind::Binary(op, a, b) => {
+            let op = match op {
+                BinaryOp::Add => "+",
+                BinaryOp::Sub => "-",
+                BinaryOp::Mul => "*",
+                BinaryOp::Div => "/",
+                BinaryOp::Rem => "%",
+            };
+            format!("({} {op} {})", render_expr(a), render_expr(b))
+        }
+        ExprKind::Call(name, args) => {
+            let args = args.iter().map(render_expr).collect::<Vec<_>>();
+            format!("{name}({})", args.join(", "))
+        }
     }
 }
 
@@ -629,6 +706,7 @@ fn render_selector(sel: &NodeSelector) -> String {
         NodeSelector::All => "(*)".to_string(),
         NodeSelector::Index(i) => format!("({i})"),
         NodeSelector::Range(a, b) => format!("({a}..{b})"),
+        NodeSelector::Template(t) => format!("({t})"),
     }
 }
 
@@ -639,6 +717,7 @@ fn render_port(port: &Port) -> String {
         Port::Index(i) => format!("[{i}]"),
         Port::Slice(a, b) => format!("[{a}..{b}]"),
         Port::Stride { start, end, stride } => format!("[{start}:{end}:{stride}]"),
+        Port::Template(t) => format!("[{t}]"),
     }
 }
 
@@ -834,12 +913,17 @@ fn ast() -> impl Strategy<Value = Ast> {
             let decls = build_decls(decl_specs, &macros, "a");
             let connections = build_conns(wire_specs, &decls);
             Ast {
+                imports: vec![],
+                uses: vec![],
                 sink: alias_of(&decls[sink % decls.len()]).to_string(),
+                outputs: vec![],
                 declarations: vec![DeclarationScope {
                     namespace: NAMESPACE.to_string(),
                     declarations: decls,
                 }],
                 connections,
+                expr_connections: vec![],
+                blocks: vec![],
                 macros,
                 source: None,
             }
@@ -963,6 +1047,17 @@ proptest! {
             declare_src
         );
     }
+
+    /// P7: the canonical printer is lossless, `parse(print(ast)) == ast`.
+    #[test]
+    fn printed_source_parses_to_the_same_ast(ast in ast(), case in transparency()) {
+        for ast in [ast, case.patched, case.inlined] {
+            let src = print(&ast);
+            let reparsed = legato_parser(&src).expect("printed source must parse");
+            prop_assert_eq!(&reparsed, &ast, "in:\n{}", src);
+            prop_assert_eq!(print(&reparsed), src);
+        }
+    }
 }
 
 /// Writing the same node type twice without aliases is the natural way to hit this.
@@ -970,7 +1065,10 @@ proptest! {
 fn repeated_node_type_without_alias_is_rejected() {
     let ast = legato_parser("audio { sine { }, sine { } }\n{ sine }").expect("parses");
     let err = Pipeline::default().run_from_ast(ast).unwrap_err();
-    assert!(matches!(err, ValidationError::DuplicateAlias(_)), "{err:?}");
+    assert!(
+        matches!(err.unspanned(), ValidationError::DuplicateAlias(_)),
+        "{err:?}"
+    );
 }
 
 /// A sink-side selector must mean the same thing for a patch as for a leaf.
@@ -1004,6 +1102,7 @@ fn patch_instance_arity_matches_leaf() {
         Pipeline::default()
             .run_from_ast(ast)
             .map(|g| g.edge_count())
+            .map_err(|e| e.unspanned().clone())
     };
 
     // Both directions matter: a patch source splits its outgoing edges, a patch
@@ -1079,7 +1178,10 @@ fn duplicate_alias_inside_patch_is_rejected() {
     "#;
     let ast = legato_parser(src).expect("parses");
     let err = Pipeline::default().run_from_ast(ast).unwrap_err();
-    assert!(matches!(err, ValidationError::DuplicateAlias(_)), "{err:?}");
+    assert!(
+        matches!(err.unspanned(), ValidationError::DuplicateAlias(_)),
+        "{err:?}"
+    );
 }
 
 /// Resolved edges keyed by endpoint alias and port, order-independent.
@@ -1172,7 +1274,7 @@ proptest! {
             .run_from_ast(legato_parser(&src).expect("parses"))
             .unwrap_err();
         prop_assert!(
-            matches!(err, ValidationError::SelectionArity(_)),
+            matches!(err.unspanned(), ValidationError::SelectionArity(_)),
             "expected SelectionArity for {n}x{sp} into width {width}, got {:?}\n{}",
             err,
             src
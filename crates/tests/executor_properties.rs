@@ -7,11 +7,15 @@ use legato::{
     graph::{Connection, ConnectionEntry},
     harness::build_placeholder_context,
     node::{Inputs, LegatoNode, Node},
+    pool::WorkerPool,
     ports::{PortBuilder, Ports},
     runtime::NodeKey,
 };
 use proptest::prelude::*;
-use std::ops::{Range, RangeInclusive};
+use std::{
+    ops::{Range, RangeInclusive},
+    sync::{Arc, LazyLock},
+};
 
 const SR: usize = 48_000;
 const BLOCK: usize = 64;
@@ -34,6 +38,9 @@ const ARITY_OVERSHOOT: usize = 8;
 /// Node count for the arity property.
 const ARITY_NODES: Range<usize> = 1..5;
 
+/// Shared by every case, rather than spawning threads per graph.
+static POOL: LazyLock<Arc<WorkerPool>> = LazyLock::new(|| Arc::new(WorkerPool::new(3)));
+
 /// Stateless node: `out[p][s] = sum(inputs at s) * gain + bias * (p + 1)`.
 #[derive(Clone)]
 struct Affine {
@@ -223,6 +230,45 @@ fn close(got: f32, want: f32) -> bool {
     (got - want).abs() <= TOLERANCE * want.abs().max(1.0)
 }
 
+/// A chain only ever has a node's input and output live at once, however long it is.
+#[test]
+fn chains_reuse_two_slots() {
+    let mut executor = Executor::default();
+
+    let keys: Vec<NodeKey> = (0..16)
+        .map(|i| {
+            executor.graph.add_node(LegatoNode::new(
+                format!("n{i}"),
+                "Affine".into(),
+                Box::new(Affine::new(1, 1, 0.5, 0.25)),
+            ))
+        })
+        .collect();
+
+    for pair in keys.windows(2) {
+        executor
+            .graph
+            .add_edge(Connection {
+                source: ConnectionEntry {
+                    node_key: pair[0],
+                    port_index: 0,
+                },
+                sink: ConnectionEntry {
+                    node_key: pair[1],
+                    port_index: 0,
+                },
+            })
+            .unwrap();
+    }
+
+    executor.set_sink(*keys.last().unwrap()).unwrap();
+    executor.prepare(BLOCK);
+
+    let stats = executor.buffer_stats();
+    assert_eq!(stats.unshared_len, 16 * BLOCK);
+    assert_eq!(stats.shared_len, 2 * BLOCK);
+}
+
 proptest! {
     /// P1: covers buffer offset arithmetic, fan-in accumulation and topo order.
     #[test]
@@ -237,6 +283,9 @@ proptest! {
             executor.set_sink(*key).unwrap();
             executor.prepare(BLOCK);
 
+            let stats = executor.buffer_stats();
+            prop_assert!(stats.shared_len <= stats.unshared_len);
+
             let view = executor.process(&mut ctx);
 
             prop_assert_eq!(
@@ -339,4 +388,37 @@ proptest! {
             prop_assert_eq!(view.chans, ports.last().unwrap().1);
         }
     }
+
+    /// P3: spreading levels over the worker pool must not change a single bit of output.
+    #[test]
+    fn parallel_executor_matches_serial(spec in graph_spec()) {
+        let (mut serial, keys) = build(&spec);
+        let (mut parallel, _) = build(&spec);
+        parallel.set_worker_pool(Some(POOL.clone()));
+
+        let mut ctx = context();
+
+        for key in &keys {
+            for executor in [&mut serial, &mut parallel] {
+                executor.set_sink(*key).unwrap();
+                executor.prepare(BLOCK);
+            }
+
+            let want: Vec<Vec<u32>> = {
+                let view = serial.process(&mut ctx);
+                view.channels[..view.chans]
+                    .iter()
+                    .map(|chan| chan.iter().map(|x| x.to_bits()).collect())
+                    .collect()
+            };
+
+            let view = parallel.process(&mut ctx);
+            let got: Vec<Vec<u32>> = view.channels[..view.chans]
+                .iter()
+                .map(|chan| chan.iter().map(|x| x.to_bits()).collect())
+                .collect();
+
+            prop_assert_eq!(got, want);
+        }
+    }
 }
@@ -0,0 +1,120 @@
+//! `~>` connections, which read their source a block late so they may close a cycle.
+
+use std::sync::Arc;
+
+use legato::{LegatoApp, builder::ValidationError, pool::WorkerPool};
+
+mod common;
+use common::{build, builder};
+
+/// The first sample of each of `blocks` blocks.
+fn firsts(app: &mut LegatoApp, blocks: usize) -> Vec<f32> {
+    (0..blocks)
+        .map(|_| {
+            let block = app.next_block().channels[0];
+            assert!(block.iter().all(|x| *x == block[0]), "{block:?}");
+            block[0]
+        })
+        .collect()
+}
+
+/// An accumulator: each block adds one to what it put out the block before.
+const COUNTER: &str = r#"
+    audio { add: acc { val: 1.0 } }
+    acc ~> acc[0]
+    { acc }
+"#;
+
+/// A node feeding back into itself reads its own previous block, starting from silence.
+#[test]
+fn self_loop_reads_previous_block() {
+    let mut app = build(COUNTER).unwrap();
+
+    assert_eq!(firsts(&mut app, 4), [1.0, 2.0, 3.0, 4.0]);
+}
+
+/// The same loop closed with `>>` is still a cycle, and the error suggests `~>`.
+#[test]
+fn plain_cycles_are_still_rejected() {
+    let src = COUNTER.replace("~>", ">>");
+    let err = build(&src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::Cycle(msg) if msg.contains("~>")),
+        "{err:?}"
+    );
+    assert_eq!(&src[err.span().unwrap()], "acc >> acc[0]");
+}
+
+/// A delayed source sums with the other sources on the same port.
+#[test]
+fn feedback_sums_with_other_inputs() {
+    let src = r#"
+        audio { const { val: 0.5 }, add: acc { val: 1.0 } }
+        const >> acc[0]
+        acc ~> acc[0]
+        { acc }
+    "#;
+    let mut app = build(src).unwrap();
+
+    assert_eq!(firsts(&mut app, 4), [1.5, 3.0, 4.5, 6.0]);
+}
+
+/// A loop through several nodes only needs one `~>` to break it, and runs the same on a pool.
+#[test]
+fn longer_loops_match_on_a_pool() {
+    let src = r#"
+        audio { add: a { val: 1.0 }, mult: b { val: 0.5 } }
+        a >> b[0] ~> a[0]
+        { a }
+    "#;
+    let mut serial = build(src).unwrap();
+    let (mut pooled, _) = builder()
+        .set_worker_pool(Arc::new(WorkerPool::new(2)))
+        .build_dsl(src)
+        .unwrap();
+
+    let want = [1.0, 1.5, 1.75, 1.875];
+    assert_eq!(firsts(&mut serial, 4), want);
+    assert_eq!(firsts(&mut pooled, 4), want);
+}
+
+/// `~>` into a patch delays every edge it becomes inside the patch.
+#[test]
+fn feedback_into_patches() {
+    let src = r#"
+        patch step() {
+            in audio_in
+            audio { add: inc { val: 1.0 } }
+            audio_in >> inc[0]
+            { inc }
+        }
+        patches { step }
+        step ~> step
+        { step }
+    "#;
+    let mut app = build(src).unwrap();
+
+    assert_eq!(firsts(&mut app, 3), [1.0, 2.0, 3.0]);
+}
+
+/// Kernels already feed back within the block, so `~>` has no place in one.
+#[test]
+fn kernels_reject_feedback() {
+    let src = r#"
+        kernel k() {
+            audio { add: acc { val: 1.0 } }
+            acc ~> acc[0]
+            { acc }
+        }
+        patches { k }
+        { k }
+    "#;
+    let err = build(src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::UnsupportedInKernel(_)),
+        "{err:?}"
+    );
+    assert_eq!(&src[err.span().unwrap()], "acc ~> acc[0]");
+}
@@ -0,0 +1,438 @@
+//! `for` and `if` blocks, and counts written with params, filled in for each instance's
+//! params before patches are inlined.
+
+use legato::{
+    builder::ValidationError,
+    dsl::{ir::IRGraph, parse::legato_parser, pipeline::Pipeline},
+};
+
+mod common;
+use common::{blamed, build, render};
+
+fn lower(src: &str) -> IRGraph {
+    Pipeline::default()
+        .run_from_ast(legato_parser(src).unwrap())
+        .unwrap()
+}
+
+/// The aliases of the nodes declared with `node_type`, sorted.
+fn aliases(graph: &IRGraph, node_type: &str) -> Vec<String> {
+    let mut aliases: Vec<String> = graph
+        .nodes()
+        .filter(|n| n.node_type == node_type)
+        .map(|n| n.alias.clone())
+        .collect();
+    aliases.sort();
+    aliases
+}
+
+const UNROLLED: &str = r#"
+    patch bank() {
+        audio {
+            add: mix { val: 0.0 },
+            sine: osc0 { freq: 110.0 },
+            sine: osc1 { freq: 220.0 },
+            sine: osc2 { freq: 330.0 },
+            gain: fb { val: 0.2, chans: 3 }
+        }
+
+        osc0 >> fb[0]
+        osc1 >> fb[1]
+        osc2 >> fb[2]
+        fb[0] >> mix[0]
+        fb[1] >> mix[0]
+        fb[2] >> mix[0]
+
+        { mix }
+    }
+
+    patches { bank: b }
+
+    { b }
+"#;
+
+/// A loop over a patch param wires the same graph as writing each line by hand.
+#[test]
+fn loops_match_the_unrolled_patch() {
+    let src = r#"
+        patch bank(taps = 3) {
+            audio {
+                add: mix { val: 0.0 },
+                gain: fb { val: 0.2, chans: $taps }
+            }
+
+            for i in 0..$taps {
+                audio { sine: osc{i} { freq: 110.0 * ($i + 1) } }
+
+                osc{i} >> fb[{i}]
+                fb[{i}] >> mix[0]
+            }
+
+            { mix }
+        }
+
+        patches { bank: b }
+
+        { b }
+    "#;
+
+    let mut want = build(UNROLLED).unwrap();
+    let mut got = build(src).unwrap();
+    assert_eq!(render(&mut got, 4), render(&mut want, 4));
+}
+
+/// Each instance is filled in for its own params.
+#[test]
+fn bounds_come_from_each_instance() {
+    let src = r#"
+        patch bank(taps = 1) {
+            audio { add: mix { val: 0.0 } }
+            for i in 0..$taps {
+                audio { sine: osc{i} }
+                osc{i} >> mix[0]
+            }
+            { mix }
+        }
+
+        patches {
+            bank: small { taps: 2 },
+            bank: large { taps: 2 * 2 },
+            bank: default
+        }
+
+        small >> large[0]
+        default >> large[0]
+
+        { large }
+    "#;
+
+    assert_eq!(
+        aliases(&lower(src), "sine"),
+        [
+            "default.osc0",
+            "large.osc0",
+            "large.osc1",
+            "large.osc2",
+            "large.osc3",
+            "small.osc0",
+            "small.osc1"
+        ]
+    );
+}
+
+/// `if` takes its body for `true` or a non-zero number, and its `else` otherwise.
+#[test]
+fn if_picks_a_body() {
+    let src = r#"
+        patch voice(bright = true) {
+            if $bright {
+                audio { saw: osc }
+            } else {
+                audio { sine: osc }
+            }
+            { osc }
+        }
+
+        patches {
+            voice: a,
+            voice: b { bright: false },
+            voice: c { bright: 1 - 1 }
+        }
+
+        a >> b
+        c >> b
+
+        { b }
+    "#;
+    let graph = lower(src);
+
+    assert_eq!(aliases(&graph, "saw"), ["a.osc"]);
+    assert_eq!(aliases(&graph, "sine"), ["b.osc", "c.osc"]);
+}
+
+/// Blocks nest, see the variables of the blocks around them, and work at the top level
+/// with literal bounds.
+#[test]
+fn blocks_nest_at_the_top_level() {
+    let src = r#"
+        audio { add: mix { val: 0.0 } }
+
+        for row in 0..2 {
+            for col in 0..3 {
+                if ($row + $col) % 2 {
+                    audio { sine: s{row}_{col} { freq: 100.0 * $col } }
+                    s{row}_{col} >> mix[0]
+                }
+            }
+        }
+
+        { mix }
+    "#;
+
+    assert_eq!(aliases(&lower(src), "sine"), ["s0_1", "s1_0", "s1_2"]);
+}
+
+/// A patch that instantiates a template passes its own params down to it.
+#[test]
+fn templates_nest() {
+    let src = r#"
+        patch taps(n = 1) {
+            audio { add: mix { val: 0.0 } }
+            for i in 0..$n {
+                audio { sine: t{i} }
+                t{i} >> mix[0]
+            }
+            { mix }
+        }
+
+        patch stereo(n = 1) {
+            patches { taps: l { n: $n }, taps: r { n: $n + 1 } }
+            audio { gain: out { val: 1.0, chans: 2 } }
+            l >> out[0]
+            r >> out[1]
+            { out }
+        }
+
+        patches { stereo: s { n: 2 } }
+
+        { s }
+    "#;
+
+    assert_eq!(
+        aliases(&lower(src), "sine"),
+        ["s.l.t0", "s.l.t1", "s.r.t0", "s.r.t1", "s.r.t2"]
+    );
+}
+
+/// Holes work in selectors and port ranges as well as in indices.
+#[test]
+fn holes_fill_selectors_and_ranges() {
+    let src = r#"
+        audio {
+            sine: osc * 4,
+            gain: g { val: 0.5, chans: 4 },
+            gain: mix { val: 1.0, chans: 2 }
+        }
+
+        for i in 0..2 {
+            osc({2 * i}..{2 * i + 2}) >> g[{2 * i}..{2 * i + 2}]
+            g[{2 * i}:{2 * i + 2}:1] >> mix[{i}]
+        }
+
+        { mix }
+    "#;
+
+    assert!(build(src).is_ok());
+}
+
+/// Kernels can use blocks too.
+#[test]
+fn kernels_loop_over_their_params() {
+    let src = r#"
+        kernel chorus(voices = 3) {
+            audio { add: mix { val: 0.0 } }
+            for i in 0..$voices {
+                audio { sine: v{i} { freq: 100.0 + $i } }
+                v{i} >> mix[0]
+            }
+            { mix }
+        }
+
+        patches { chorus: c { voices: 2 } }
+
+        { c }
+    "#;
+    let unrolled = r#"
+        kernel chorus() {
+            audio {
+                add: mix { val: 0.0 },
+                sine: v0 { freq: 100.0 },
+                sine: v1 { freq: 101.0 }
+            }
+            v0 >> mix[0]
+            v1 >> mix[0]
+            { mix }
+        }
+
+        patches { chorus: c }
+
+        { c }
+    "#;
+
+    let mut want = build(unrolled).unwrap();
+    let mut got = build(src).unwrap();
+    assert_eq!(render(&mut got, 4), render(&mut want, 4));
+}
+
+/// A bound that is not a whole number is blamed on its block.
+#[test]
+fn fractional_bound_points_at_block() {
+    let src = "patch p(n = 2.5) {\n    audio { add: mix }\n    for i in 0..$n { audio { sine: s{i} } }\n    { mix }\n}\npatches { p }\n{ p }";
+    let err = build(src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::InvalidParameter(_)),
+        "{err:?}"
+    );
+    assert_eq!(blamed(src, &err), "for i in 0..$n { audio { sine: s{i} } }");
+}
+
+/// A hole naming something that is not in scope is blamed on the line it is in.
+#[test]
+fn unknown_hole_points_at_connection() {
+    let src = "audio { add: mix, sine: s0 }\nfor i in 0..1 {\n    s{j} >> mix\n}\n{ mix }";
+    let err = build(src).unwrap_err();
+
+    assert!(err.to_string().contains("j"), "{err}");
+    assert_eq!(blamed(src, &err), "s{j} >> mix");
+}
+
+/// Forgetting the hole gives every iteration the same alias.
+#[test]
+fn repeated_alias_points_at_declaration() {
+    let src = "audio { add: mix }\nfor i in 0..2 {\n    audio { sine: osc }\n}\n{ mix }";
+    let err = build(src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::DuplicateAlias(_)),
+        "{err:?}"
+    );
+    assert_eq!(blamed(src, &err), "sine: osc");
+}
+
+/// A runaway bound is an error rather than a graph of millions of nodes.
+#[test]
+fn runaway_loops_are_rejected() {
+    let src = "audio { add: mix }\nfor i in 0..48000 { audio { sine: s{i} } }\n{ mix }";
+    let err = build(src).unwrap_err();
+
+    assert!(err.to_string().contains("more than"), "{err}");
+}
+
+/// A spawn count can come from a patch's params, so each instance has its own topology.
+#[test]
+fn counts_come_from_params() {
+    let src = r#"
+        patch synth(voices = 2) {
+            audio {
+                add: mix { val: 0.0 },
+                sine: osc * $voices { freq: 110.0 }
+            }
+            osc >> mix[0]
+            { mix }
+        }
+
+        patches { synth: a { voices: 3 }, synth: b }
+
+        a >> b[0]
+
+        { b }
+    "#;
+
+    assert_eq!(
+        aliases(&lower(src), "sine"),
+        ["a.osc.0", "a.osc.1", "a.osc.2", "b.osc.0", "b.osc.1"]
+    );
+}
+
+/// A count and a channel width driven by the same param wire like the written out patch.
+#[test]
+fn counts_and_widths_share_a_param() {
+    let written = r#"
+        patch bank() {
+            audio {
+                sine: osc * 3 { freq: 110.0 },
+                gain: g { val: 0.5, chans: 3 },
+                add: mix { val: 0.0 }
+            }
+            osc(*) >> g[0..3]
+            g[0] >> mix[0]
+            g[1] >> mix[0]
+            g[2] >> mix[0]
+            { mix }
+        }
+        patches { bank: b }
+        { b }
+    "#;
+    let src = r#"
+        patch bank(n = 1) {
+            audio {
+                sine: osc * ($n + 1) { freq: 110.0 },
+                gain: g { val: 0.5, chans: $n + 1 },
+                add: mix { val: 0.0 }
+            }
+            osc(*) >> g[0..3]
+            for i in 0..$n + 1 {
+                g[{i}] >> mix[0]
+            }
+            { mix }
+        }
+        patches { bank: b { n: 2 } }
+        { b }
+    "#;
+
+    let mut want = build(written).unwrap();
+    let mut got = build(src).unwrap();
+    assert_eq!(render(&mut got, 4), render(&mut want, 4));
+}
+
+/// Counts at the top level have no params, but can still be worked out from numbers.
+#[test]
+fn top_level_counts_are_evaluated() {
+    let src = "audio { add: mix { val: 0.0 }, sine * (2 * 2) }\nsine >> mix[0]\n{ mix }";
+
+    assert_eq!(
+        aliases(&lower(src), "sine"),
+        ["sine.0", "sine.1", "sine.2", "sine.3"]
+    );
+}
+
+/// A count that is not a positive whole number is blamed on its declaration.
+#[test]
+fn bad_counts_point_at_declaration() {
+    for count in ["0", "1.5", "-1"] {
+        let src = format!(
+            "patch p(n = 1) {{\n    audio {{ sine * $n }}\n    {{ sine }}\n}}\npatches {{ p {{ n: {count} }} }}\n{{ p }}"
+        );
+        let err = build(&src).unwrap_err();
+
+        assert!(
+            matches!(err.unspanned(), ValidationError::InvalidParameter(_)),
+            "{count}: {err:?}"
+        );
+        assert_eq!(blamed(&src, &err), "sine * $n", "{count}");
+    }
+}
+
+/// Written out counts are held to the same limits, and blamed the same way.
+#[test]
+fn literal_counts_are_checked_too() {
+    for (count, message) in [("0", "positive"), ("100000000", "more than")] {
+        let src = format!("audio {{ sine * {count} }}\n{{ sine }}");
+        let err = build(&src).unwrap_err();
+
+        assert!(
+            matches!(err.unspanned(), ValidationError::InvalidParameter(_)),
+            "{count}: {err:?}"
+        );
+        assert!(err.to_string().contains(message), "{err}");
+        assert_eq!(blamed(&src, &err), format!("sine * {count}"));
+    }
+}
+
+/// A param used as a count must be one the patch has.
+#[test]
+fn unknown_count_param_is_an_error() {
+    let err = build("audio { sine * $voices }\n{ sine }").unwrap_err();
+
+    assert!(err.to_string().contains("$voices"), "{err}");
+}
+
+/// A width of zero is rejected, rather than building a node with no channels.
+#[test]
+fn zero_widths_are_rejected() {
+    let src = "patch p(n = 1) {\n    audio { gain: g { val: 1.0, chans: $n } }\n    { g }\n}\npatches { p { n: 0 } }\n{ p }";
+    let err = build(src).unwrap_err();
+
+    assert!(err.to_string().contains("chans"), "{err}");
+}
@@ -0,0 +1,223 @@
+//! Structural edits to a running graph through the `LegatoFrontend`.
+
+use legato::{
+    FrontendError, LegatoFrontend,
+    builder::ValidationError,
+    dsl::ir::{DSLParams, Object, Value},
+    graph::GraphError,
+};
+
+mod common;
+use common::{BLOCK, build_live, builder, render};
+
+fn params(pairs: &[(&str, Value)]) -> Object {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.clone()))
+        .collect()
+}
+
+const CHAIN: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: out { cutoff: 18000.0, chans: 1 }
+    }
+
+    osc >> out[0]
+
+    { out }
+"#;
+
+const FILTERED: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: lp { cutoff: 800.0, chans: 1 },
+        onepole: out { cutoff: 18000.0, chans: 1 }
+    }
+
+    osc >> lp[0]
+    lp >> out[0]
+
+    { out }
+"#;
+
+fn add_filter(frontend: &mut LegatoFrontend) {
+    let obj = params(&[("cutoff", Value::F32(800.0)), ("chans", Value::U32(1))]);
+    frontend
+        .add_node("audio", "onepole", "lp", &DSLParams::new(&obj))
+        .expect("node should be added");
+}
+
+/// Splicing an effect into the chain sounds exactly like building it in from the start.
+#[test]
+fn an_effect_can_be_spliced_into_a_running_chain() {
+    let (mut reference, _) = build_live(FILTERED);
+    let (mut app, mut frontend) = build_live(CHAIN);
+
+    add_filter(&mut frontend);
+    frontend.disconnect("osc", 0, "out", 0).unwrap();
+    frontend.connect("osc", 0, "lp", 0).unwrap();
+    frontend.connect("lp", 0, "out", 0).unwrap();
+
+    let expected = render(&mut reference, 4);
+    assert!(expected.iter().any(|x| *x != 0.0), "patch rendered silence");
+    assert_eq!(render(&mut app, 4), expected);
+    assert!(frontend.clone_registry().contains_key("lp"));
+}
+
+/// Nodes the edit does not touch keep running through it.
+#[test]
+fn untouched_nodes_keep_their_state() {
+    let (mut reference, _) = build_live(CHAIN);
+    let (mut app, mut frontend) = build_live(CHAIN);
+
+    let expected = render(&mut reference, 8);
+
+    let mut out = render(&mut app, 4);
+    add_filter(&mut frontend);
+    out.extend(render(&mut app, 2));
+    frontend.remove_node("lp").unwrap();
+    out.extend(render(&mut app, 2));
+
+    assert_eq!(out, expected);
+    assert!(!frontend.clone_registry().contains_key("lp"));
+}
+
+/// Rejected edits are never sent, and the running graph carries on.
+#[test]
+fn invalid_edits_are_rejected() {
+    let (mut reference, _) = build_live(CHAIN);
+    let (mut app, mut frontend) = build_live(CHAIN);
+
+    assert_eq!(
+        frontend.remove_node("out"),
+        Err(FrontendError::CannotRemoveSink())
+    );
+    assert_eq!(
+        frontend.remove_node("missing"),
+        Err(FrontendError::NodeNotFound())
+    );
+    assert_eq!(
+        frontend.connect("osc", 1, "out", 0),
+        Err(FrontendError::InvalidEdit(GraphError::BadConnection))
+    );
+    assert_eq!(
+        frontend.connect("osc", 0, "out", 2),
+        Err(FrontendError::InvalidEdit(GraphError::BadConnection))
+    );
+    assert_eq!(
+        frontend.disconnect("out", 0, "osc", 0),
+        Err(FrontendError::InvalidEdit(GraphError::BadConnection))
+    );
+
+    add_filter(&mut frontend);
+    frontend.connect("out", 0, "lp", 0).unwrap();
+    assert_eq!(
+        frontend.connect("lp", 0, "out", 0),
+        Err(FrontendError::InvalidEdit(GraphError::CycleDetected))
+    );
+
+    assert!(matches!(
+        frontend.add_node("audio", "onepole", "lp", &DSLParams::new(&Object::new())),
+        Err(FrontendError::InvalidGraph(
+            ValidationError::DuplicateAlias(_)
+        ))
+    ));
+
+    assert_eq!(render(&mut app, 2), render(&mut reference, 2));
+}
+
+/// A new delay line only exists once the graph is rebuilt, so that takes a reload.
+#[test]
+fn nodes_claiming_resources_need_a_reload() {
+    let (_, mut frontend) = build_live(CHAIN);
+
+    let obj = params(&[
+        ("delay_name", Value::String("echo".into())),
+        ("delay_length", Value::F32(100.0)),
+        ("chans", Value::U32(1)),
+    ]);
+
+    assert_eq!(
+        frontend.add_node("audio", "delay_write", "dw", &DSLParams::new(&obj)),
+        Err(FrontendError::ClaimsResources())
+    );
+    assert!(!frontend.clone_registry().contains_key("dw"));
+}
+
+/// Edits and reloads share the queue, and each sees the graph the last one left behind.
+#[test]
+fn edits_apply_on_top_of_a_reload() {
+    let (mut reference, _) = build_live(FILTERED);
+    let (mut app, mut frontend) = build_live(FILTERED);
+    frontend.set_reload_crossfade(0);
+
+    frontend.reload_dsl(CHAIN).unwrap();
+    add_filter(&mut frontend);
+    frontend.disconnect("osc", 0, "out", 0).unwrap();
+    frontend.connect("osc", 0, "lp", 0).unwrap();
+    frontend.connect("lp", 0, "out", 0).unwrap();
+
+    assert_eq!(render(&mut app, 4), render(&mut reference, 4));
+}
+
+/// An edit keeps the control rate the graph was built with.
+#[test]
+fn edits_keep_the_control_rate() {
+    let src = r#"
+        control { lfo { freq: 3.0 } }
+        audio { add: o { val: 0.0 } }
+        lfo >> o[0]
+        { o }
+    "#;
+    let (mut app, mut frontend) = builder().set_control_rate(4).build_dsl(src).unwrap();
+    let decimated = app.buffer_stats().unshared_len;
+    assert_eq!(decimated, BLOCK + BLOCK / 4);
+
+    add_filter(&mut frontend);
+    render(&mut app, 1);
+
+    // The new filter's output, and nothing else grows
+    assert_eq!(app.buffer_stats().unshared_len, decimated + BLOCK);
+}
+
+/// An edit elsewhere in the graph is not heard in a `~>` loop or a control-rate LFO: what they
+/// kept from the block before carries over, so the output matches a graph left alone.
+#[test]
+fn edits_keep_feedback_and_control_history() {
+    let src = r#"
+        control { lfo { freq: 3.0 } }
+        audio {
+            add: acc { val: 0.01 },
+            add: o { val: 0.0 }
+        }
+        acc ~> acc[0]
+        acc >> o[0]
+        lfo >> o[0]
+        { o }
+    "#;
+    let (mut reference, _) = builder().set_control_rate(4).build_dsl(src).unwrap();
+    let (mut app, mut frontend) = builder().set_control_rate(4).build_dsl(src).unwrap();
+    let expected = render(&mut reference, 8);
+
+    let mut out = render(&mut app, 4);
+    add_filter(&mut frontend);
+    out.extend(render(&mut app, 4));
+
+    assert_eq!(out, expected);
+}
+
+/// Replaced graphs come back to the frontend to be dropped, like reloaded ones.
+#[test]
+fn retired_edits_are_drained_by_the_frontend() {
+    let (mut app, mut frontend) = build_live(CHAIN);
+
+    for _ in 0..600 {
+        add_filter(&mut frontend);
+        frontend.remove_node("lp").unwrap();
+        render(&mut app, 1);
+    }
+
+    frontend.drain_garbage();
+    assert_eq!(app.node_kinds().len(), 2);
+}
@@ -0,0 +1,210 @@
+//! Hot reloading a running graph with `LegatoFrontend::reload_dsl`.
+
+use legato::{FrontendError, builder::ValidationError};
+
+mod common;
+use common::{BLOCK, build_live, render};
+
+/// Oscillator phase, delay line contents and filter memory all live across blocks.
+const ECHO: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        delay_write { delay_name: "echo", delay_length: 100.0, chans: 1 },
+        delay_read { delay_name: "echo", delay_length: 20.0, chans: 1 },
+        onepole { cutoff: 2000.0, chans: 1 }
+    }
+
+    osc >> delay_write
+    delay_read >> onepole
+
+    { onepole }
+"#;
+
+const KERNEL: &str = r#"
+    kernel voice() {
+        audio {
+            saw { chans: 1, freq: 110.0 },
+            svf { cutoff: 900.0, q: 2.0, chans: 1 },
+        }
+
+        saw >> svf[0]
+
+        { svf }
+    }
+
+    patches {
+        voice: v {}
+    }
+
+    { v }
+"#;
+
+/// The resamplers in and out of the block carry their filter memory too.
+const OVERSAMPLED: &str = r#"
+    audio { saw: osc { freq: 220.0, chans: 1 } }
+    oversample(4) {
+        audio { onepole { cutoff: 2000.0, chans: 1 } }
+    }
+
+    osc >> onepole
+
+    { onepole }
+"#;
+
+/// Reloading the source that is already running must not be audible at all,
+/// even when cutting straight over.
+#[test]
+fn reloading_the_same_graph_is_seamless() {
+    for src in [ECHO, KERNEL, OVERSAMPLED] {
+        let (mut reference, _) = build_live(src);
+        let (mut app, mut frontend) = build_live(src);
+        frontend.set_reload_crossfade(0);
+
+        let expected = render(&mut reference, 8);
+
+        let mut out = render(&mut app, 4);
+        frontend.reload_dsl(src).expect("reload should build");
+        out.extend(render(&mut app, 4));
+
+        assert!(expected.iter().any(|x| *x != 0.0), "patch rendered silence");
+        assert_eq!(out, expected, "reload was audible in:\n{src}");
+    }
+}
+
+/// A changed param takes effect, while the echo already in the delay line keeps playing.
+#[test]
+fn changed_params_apply_and_delay_contents_survive() {
+    let (mut app, mut frontend) = build_live(ECHO);
+    render(&mut app, 8);
+
+    let retuned = ECHO.replace("freq: 220.0", "freq: 330.0");
+    frontend.reload_dsl(&retuned).expect("reload should build");
+    let reloaded = render(&mut app, 1);
+
+    let (mut fresh, _) = build_live(&retuned);
+    let fresh = render(&mut fresh, 1);
+
+    // A fresh graph has an empty delay line, so its first block is silent.
+    assert!(fresh.iter().all(|x| *x == 0.0));
+    assert!(
+        reloaded.iter().any(|x| *x != 0.0),
+        "delay contents were lost"
+    );
+}
+
+/// A node whose alias changed is a different node, so it starts from scratch.
+#[test]
+fn renamed_nodes_start_fresh() {
+    let src = r#"
+        audio { saw: osc { freq: 220.0, chans: 1 } }
+        { osc }
+    "#;
+    let renamed = src.replace("osc", "lead");
+
+    let (mut app, mut frontend) = build_live(src);
+    frontend.set_reload_crossfade(0);
+    render(&mut app, 3);
+
+    frontend.reload_dsl(&renamed).expect("reload should build");
+    let reloaded = render(&mut app, 2);
+
+    let (mut fresh, _) = build_live(&renamed);
+    assert_eq!(reloaded, render(&mut fresh, 2));
+
+    let registry = frontend.clone_registry();
+    assert!(registry.contains_key("lead") && !registry.contains_key("osc"));
+}
+
+/// DC rails make the fade curve itself the output.
+const ON: &str = r#"
+    audio { sine: on { freq: 0.0, phase: 0.25 } }
+    { on }
+"#;
+
+const OFF: &str = r#"
+    audio { sine: off { freq: 0.0 } }
+    { off }
+"#;
+
+/// The outgoing graph fades out along a quarter cosine while the incoming one fades
+/// in along a quarter sine, then the old graph is gone.
+#[test]
+fn reload_crossfades_with_equal_power() {
+    const FADE: usize = 3;
+
+    for (from, to) in [(ON, OFF), (OFF, ON)] {
+        let (mut app, mut frontend) = build_live(from);
+        frontend.set_reload_crossfade(FADE);
+        render(&mut app, 2);
+
+        frontend.reload_dsl(to).expect("reload should build");
+        let faded = render(&mut app, FADE);
+        let after = render(&mut app, 2);
+
+        let total = (FADE * BLOCK) as f32;
+        for (n, x) in faded.iter().enumerate() {
+            let t = (n + 1) as f32 / total * std::f32::consts::FRAC_PI_2;
+            let expected = if from == ON { t.cos() } else { t.sin() };
+            assert!(
+                (x - expected).abs() < 1e-5,
+                "sample {n} of the fade was {x}, expected {expected}"
+            );
+        }
+
+        let settled = if to == ON { 1.0 } else { 0.0 };
+        assert!(after.iter().all(|x| (x - settled).abs() < 1e-5));
+    }
+}
+
+/// A second reload mid fade drops the oldest graph and fades from the current one.
+#[test]
+fn reload_during_a_crossfade_restarts_it() {
+    let (mut app, mut frontend) = build_live(ON);
+    frontend.set_reload_crossfade(4);
+
+    frontend.reload_dsl(OFF).expect("reload should build");
+    render(&mut app, 2);
+    frontend.reload_dsl(ON).expect("reload should build");
+    let restarted = render(&mut app, 4);
+
+    // Fading from OFF to ON, whatever ON was doing before.
+    assert!(restarted[0].abs() < 0.01);
+    assert!((restarted[restarted.len() - 1] - 1.0).abs() < 1e-5);
+    assert!(restarted.windows(2).all(|w| w[1] >= w[0]));
+}
+
+/// A graph that fails to build is reported, and the running one carries on.
+#[test]
+fn failed_reload_keeps_the_running_graph() {
+    let (mut reference, _) = build_live(ECHO);
+    let (mut app, mut frontend) = build_live(ECHO);
+
+    let expected = render(&mut reference, 4);
+
+    let mut out = render(&mut app, 2);
+    let err = frontend.reload_dsl("audio { saw: osc { freq: 220.0 } ");
+    assert!(matches!(
+        err,
+        Err(FrontendError::InvalidGraph(ValidationError::ParseError(_)))
+    ));
+    out.extend(render(&mut app, 2));
+
+    assert_eq!(out, expected);
+}
+
+/// The outgoing graph comes back to the frontend to be dropped. Reloading more
+/// often than the retired queue holds only works if it is drained along the way.
+#[test]
+fn retired_graphs_are_drained_by_the_frontend() {
+    let (mut app, mut frontend) = build_live(ECHO);
+    let kinds: Vec<String> = app.node_kinds().iter().map(|k| k.to_string()).collect();
+
+    for _ in 0..600 {
+        frontend.reload_dsl(ECHO).expect("reload should build");
+        render(&mut app, 1);
+    }
+
+    frontend.drain_garbage();
+    assert_eq!(app.node_kinds(), kinds);
+    assert_eq!(frontend.leaked_graphs(), 0);
+}
@@ -0,0 +1,217 @@
+//! Patches and kernels imported from other `.legato` files.
+
+use legato::{
+    LegatoApp, LegatoFrontend,
+    builder::ValidationError,
+    dsl::module::{FileLoader, MemoryLoader, ModuleLoader},
+};
+
+mod common;
+use common::{builder, render};
+
+fn build_with(
+    src: &str,
+    loader: impl ModuleLoader + 'static,
+) -> Result<(LegatoApp, LegatoFrontend), ValidationError> {
+    builder().set_module_loader(loader).build_dsl(src)
+}
+
+fn build(src: &str) -> LegatoApp {
+    build_with(src, MemoryLoader::new())
+        .expect("graph should build")
+        .0
+}
+
+const COMB: &str = r#"
+    kernel comb(fb = 0.6) {
+        in audio_in
+
+        audio {
+            add { val: 0.0 },
+            tap { delay_length: 5, chans: 1 },
+            mult { val: $fb }
+        }
+
+        audio_in >> add[0]
+        add >> tap
+        tap >> mult[0]
+        mult >> add[1]
+
+        { add }
+    }
+"#;
+
+fn voice(freq: f32) -> String {
+    format!(
+        r#"
+        patch voice(gain = 0.5) {{
+            audio {{
+                sine {{ freq: {freq:.1} }},
+                mult {{ val: $gain }}
+            }}
+
+            sine >> mult[0]
+
+            {{ mult }}
+        }}
+        "#
+    )
+}
+
+/// An imported kernel renders exactly like the same kernel pasted into the file.
+#[test]
+fn imported_kernels_match_pasted_ones() {
+    let graph = r#"
+        patches {
+            comb: c { fb: 0.4 }
+        }
+
+        audio {
+            sine { freq: 330.0 }
+        }
+
+        sine >> c.audio_in
+
+        { c }
+    "#;
+
+    let (mut imported, _) = build_with(
+        &format!("import \"combs.legato\"\nuse combs::comb\n{graph}"),
+        MemoryLoader::new().with_file("combs.legato", COMB),
+    )
+    .expect("graph should build");
+    let mut pasted = build(&format!("{COMB}\n{graph}"));
+
+    let want = render(&mut pasted, 4);
+    assert!(
+        want.iter().any(|x| x.abs() > 1e-3),
+        "patch rendered silence"
+    );
+    assert_eq!(render(&mut imported, 4), want);
+}
+
+/// Two libraries can each define a `voice`, and the file importing them can too.
+#[test]
+fn libraries_do_not_clash() {
+    let loader = MemoryLoader::new()
+        .with_file("low.legato", voice(110.0))
+        .with_file("high.legato", voice(440.0));
+
+    let (mut imported, _) = build_with(
+        &format!(
+            r#"
+            import "low.legato"
+            import "high.legato" as hi
+
+            {}
+
+            patches {{
+                low::voice: a {{ gain: 0.25 }},
+                hi::voice: b {{}},
+                voice: c {{ gain: 0.125 }}
+            }}
+
+            audio {{ add {{ val: 0.0 }} }}
+
+            a >> add[0]
+            b >> add[0]
+            c >> add[0]
+
+            {{ add }}
+            "#,
+            voice(220.0)
+        ),
+        loader,
+    )
+    .expect("graph should build");
+
+    let mut inlined = build(
+        r#"
+        audio {
+            sine: a { freq: 110.0 },
+            sine: b { freq: 440.0 },
+            sine: c { freq: 220.0 },
+            mult: a_gain { val: 0.25 },
+            mult: b_gain { val: 0.5 },
+            mult: c_gain { val: 0.125 },
+            add { val: 0.0 }
+        }
+
+        a >> a_gain[0]
+        b >> b_gain[0]
+        c >> c_gain[0]
+        a_gain >> add[0]
+        b_gain >> add[0]
+        c_gain >> add[0]
+
+        { add }
+        "#,
+    );
+
+    assert_eq!(render(&mut imported, 4), render(&mut inlined, 4));
+}
+
+/// Files on disk import relative to themselves, and reloads find them the same way.
+#[test]
+fn files_import_relative_to_each_other() {
+    let dir = std::env::temp_dir().join(format!("legato-imports-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    std::fs::write(dir.join("lib/voices.legato"), voice(220.0)).unwrap();
+    std::fs::write(
+        dir.join("lib/synth.legato"),
+        r#"
+        import "voices.legato"
+
+        patch synth() {
+            patches { voices::voice { gain: 0.25 } }
+            { voice }
+        }
+        "#,
+    )
+    .unwrap();
+
+    let src = r#"
+        import "lib/synth.legato"
+
+        patches { synth::synth: s {} }
+
+        { s }
+    "#;
+    let (mut app, mut frontend) =
+        build_with(src, FileLoader::new(&dir)).expect("graph should build");
+    let mut inlined = build(
+        r#"
+        audio { sine { freq: 220.0 }, mult { val: 0.25 } }
+        sine >> mult[0]
+        { mult }
+        "#,
+    );
+
+    let want = render(&mut inlined, 4);
+    assert_eq!(render(&mut app, 4), want);
+
+    frontend.set_reload_crossfade(0);
+    frontend
+        .reload_dsl(src)
+        .expect("reload should find the imports");
+    assert_eq!(render(&mut app, 4), render(&mut inlined, 4));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_and_cyclic_imports_are_errors() {
+    let graph = "audio { sine } { sine }";
+
+    let missing = build_with(
+        &format!("import \"nope.legato\" {graph}"),
+        MemoryLoader::new(),
+    );
+    assert!(matches!(missing, Err(ValidationError::Import(_))));
+
+    let cyclic = MemoryLoader::new()
+        .with_file("a.legato", "import \"b.legato\"")
+        .with_file("b.legato", "import \"a.legato\"");
+    let cycle = build_with(&format!("import \"a.legato\" {graph}"), cyclic);
+    assert!(matches!(cycle, Err(ValidationError::Import(msg)) if msg.contains("cycle")));
+}
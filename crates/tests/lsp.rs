@@ -0,0 +1,216 @@
+//! The `legato-lsp` binary, driven over stdio by a scripted client.
+#![cfg(feature = "lsp")]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{Value, json};
+
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_legato-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("server should start");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let mut client = Self {
+            child,
+            stdin,
+            stdout,
+            next_id: 0,
+        };
+        client.request("initialize", json!({ "capabilities": {} }));
+        client.notify("initialized", json!({}));
+        client
+    }
+
+    fn send(&mut self, msg: Value) {
+        let body = msg.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut len = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            match line.trim_end() {
+                "" => break,
+                header => {
+                    if let Some(n) = header.strip_prefix("Content-Length: ") {
+                        len = n.parse().unwrap();
+                    }
+                }
+            }
+        }
+        let mut body = vec![0; len];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let msg = self.receive();
+            if msg["id"] == id {
+                return msg["result"].clone();
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Open `text`, returning the diagnostics published for it.
+    fn open(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "legato", "version": 1, "text": text }
+            }),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn change(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": text }]
+            }),
+        );
+        self.diagnostics(uri)
+    }
+
+    fn diagnostics(&mut self, uri: &str) -> Vec<Value> {
+        loop {
+            let msg = self.receive();
+            if msg["method"] == "textDocument/publishDiagnostics" && msg["params"]["uri"] == uri {
+                return msg["params"]["diagnostics"].as_array().unwrap().clone();
+            }
+        }
+    }
+
+    fn at(&mut self, method: &str, uri: &str, line: u32, character: u32) -> Value {
+        self.request(
+            method,
+            json!({
+                "textDocument": { "uri": uri },
+                "position": { "line": line, "character": character }
+            }),
+        )
+    }
+
+    fn shutdown(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn labels(completions: &Value) -> Vec<&str> {
+    completions
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect()
+}
+
+const URI: &str = "file:///tmp/legato-lsp-test/main.legato";
+
+/// A mistake is reported where it was made, and goes away once it is fixed.
+#[test]
+fn diagnostics_follow_edits() {
+    let mut client = Client::start();
+
+    let diagnostics = client.open(
+        URI,
+        "audio {\n    sine { freq: 440.0, detune: 2.0 }\n}\n{ sine }",
+    );
+    assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": { "line": 1, "character": 24 }, "end": { "line": 1, "character": 30 } })
+    );
+
+    let diagnostics = client.change(URI, "audio {\n    sine { freq: 440.0 }\n}\n{ sine }");
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+    let diagnostics = client.change(URI, "audio { sine }\nsine >>");
+    assert!(!diagnostics.is_empty(), "parse errors should be reported");
+
+    client.shutdown();
+}
+
+/// Node types, params and ports complete where they can be written.
+#[test]
+fn completion() {
+    let mut client = Client::start();
+    client.open(
+        URI,
+        "audio {\n    svf { cutoff: 100.0,  },\n    sine\n}\nsine >> svf.\n{ svf }",
+    );
+
+    let nodes = client.at("textDocument/completion", URI, 2, 8);
+    assert!(labels(&nodes).contains(&"sine"));
+    assert!(labels(&nodes).contains(&"adsr"));
+
+    let params = client.at("textDocument/completion", URI, 1, 25);
+    assert!(labels(&params).contains(&"q"));
+    assert!(!labels(&params).contains(&"cutoff"));
+
+    let ports = client.at("textDocument/completion", URI, 4, 12);
+    assert!(labels(&ports).contains(&"cutoff"), "{ports}");
+
+    client.shutdown();
+}
+
+/// Hovering a node shows its description, and patches jump to their definition.
+#[test]
+fn hover_and_definition() {
+    let mut client = Client::start();
+    let diagnostics = client.open(
+        URI,
+        "patch voice(freq = 220.0) {\n    audio { sine { freq: $freq } }\n    { sine }\n}\n\
+         patches { voice: lead {} }\n{ lead }",
+    );
+    assert!(diagnostics.is_empty(), "{diagnostics:?}");
+
+    let hover = client.at("textDocument/hover", URI, 1, 14);
+    let docs = hover["contents"]["value"].as_str().unwrap();
+    assert!(docs.contains("Required:"), "{docs}");
+
+    let definition = client.at("textDocument/definition", URI, 4, 12);
+    assert_eq!(definition["uri"], URI);
+    assert_eq!(
+        definition["range"],
+        json!({ "start": { "line": 0, "character": 6 }, "end": { "line": 0, "character": 11 } })
+    );
+
+    let definition = client.at("textDocument/definition", URI, 5, 3);
+    assert_eq!(
+        definition["range"]["start"],
+        json!({ "line": 4, "character": 17 })
+    );
+
+    client.shutdown();
+}
@@ -1378,7 +1378,6 @@ mod build_dsl_delay {
     /// graph reads `[2..4]`). Previously this slipped through to the audio thread
     /// and panicked mid-process.
     #[test]
-    #[should_panic(expected = "out of range")]
     fn test_out_of_range_port_is_rejected_at_build() {
         let config = Config {
             sample_rate: 48_000,
@@ -1399,9 +1398,11 @@ mod build_dsl_delay {
             { feedback }
         "#;
 
-        let _ = LegatoBuilder::<Unconfigured>::new(config, ports)
+        let err = LegatoBuilder::<Unconfigured>::new(config, ports)
             .build_dsl(graph)
-            .expect("graph should build");
+            .map(|_| ())
+            .unwrap_err();
+        assert!(err.to_string().contains("out of range"), "{err:?}");
     }
 
     #[test]
@@ -0,0 +1,186 @@
+//! Graphs with auxiliary outputs besides the sink, e.g. a cue bus and a dry send.
+
+use legato::{
+    FrontendError, LegatoApp, LegatoFrontend, builder::ValidationError, out::render_outputs,
+};
+use std::time::Duration;
+
+mod common;
+use common::{BLOCK, builder};
+
+fn build(src: &str) -> Result<(LegatoApp, LegatoFrontend), ValidationError> {
+    builder().build_dsl(src)
+}
+
+fn render(app: &mut LegatoApp, name: &str, blocks: usize) -> Vec<f32> {
+    let mut out = Vec::with_capacity(BLOCK * blocks);
+    for _ in 0..blocks {
+        app.next_block();
+        out.extend_from_slice(app.output(name).unwrap().channels[0]);
+    }
+    out
+}
+
+/// The dry send and cue bus are read off of nodes upstream of the mix, whose slots would
+/// otherwise be reused by the time the block is done.
+const STEMS: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: cue_bus { cutoff: 2000.0, chans: 1 },
+        onepole: lp { cutoff: 800.0, chans: 1 },
+        onepole: mix { cutoff: 18000.0, chans: 1 }
+    }
+
+    osc >> cue_bus[0]
+    osc >> lp[0]
+    lp >> mix[0]
+
+    { mix, cue: cue_bus, dry: osc }
+"#;
+
+const CUE_ONLY: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: cue_bus { cutoff: 2000.0, chans: 1 }
+    }
+
+    osc >> cue_bus[0]
+
+    { cue_bus }
+"#;
+
+const OSC_ONLY: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 }
+    }
+
+    { osc }
+"#;
+
+/// Each output carries exactly what its node would as the sink of its own graph.
+#[test]
+fn every_output_renders_its_node() {
+    let (mut app, _) = build(STEMS).unwrap();
+    assert_eq!(app.output_names(), ["main", "cue", "dry"]);
+
+    let (mut cue, _) = build(CUE_ONLY).unwrap();
+    let (mut dry, _) = build(OSC_ONLY).unwrap();
+    let (mut main, _) = build(STEMS).unwrap();
+
+    let mut got = (vec![], vec![], vec![]);
+    for _ in 0..4 {
+        let block = app.next_block().channels[0].to_vec();
+        assert_eq!(app.output("main").unwrap().channels[0], block);
+        got.0.extend(block);
+        got.1
+            .extend_from_slice(app.output("cue").unwrap().channels[0]);
+        got.2
+            .extend_from_slice(app.output("dry").unwrap().channels[0]);
+    }
+
+    assert_eq!(got.0, render(&mut main, "main", 4));
+    assert_eq!(got.1, render(&mut cue, "main", 4));
+    assert_eq!(got.2, render(&mut dry, "main", 4));
+    assert!(app.output("missing").is_none());
+}
+
+#[test]
+fn output_names_must_be_unique() {
+    let dup = STEMS.replace("dry: osc", "cue: osc");
+    assert!(matches!(
+        build(&dup),
+        Err(ValidationError::DuplicateOutput(name)) if name == "cue"
+    ));
+
+    let main = STEMS.replace("dry: osc", "main: osc");
+    assert!(matches!(
+        build(&main),
+        Err(ValidationError::DuplicateOutput(name)) if name == "main"
+    ));
+
+    let missing = STEMS.replace("dry: osc", "dry: nowhere");
+    assert!(matches!(
+        build(&missing),
+        Err(ValidationError::NodeNotFound(_))
+    ));
+}
+
+/// Structural edits keep the outputs, and cannot remove them.
+#[test]
+fn outputs_survive_edits() {
+    let (mut app, mut frontend) = build(STEMS).unwrap();
+    let (mut cue, _) = build(CUE_ONLY).unwrap();
+
+    assert_eq!(
+        frontend.remove_node("cue_bus"),
+        Err(FrontendError::CannotRemoveSink())
+    );
+
+    frontend.disconnect("lp", 0, "mix", 0).unwrap();
+    assert_eq!(render(&mut app, "cue", 4), render(&mut cue, "main", 4));
+}
+
+/// Each output is written to its own file, as wide as the output.
+#[test]
+fn outputs_render_to_their_own_files() {
+    let (app, _) = build(STEMS).unwrap();
+
+    let dir = std::env::temp_dir().join(format!("legato-named-outputs-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (main_path, cue_path) = (dir.join("main.wav"), dir.join("cue.wav"));
+
+    render_outputs(
+        app,
+        &[("main", &main_path), ("cue", &cue_path)],
+        Duration::from_millis(100),
+    )
+    .unwrap();
+
+    let (mut cue, _) = build(CUE_ONLY).unwrap();
+    let blocks = (4_800usize).div_ceil(BLOCK);
+    let expected = render(&mut cue, "main", blocks);
+
+    let reader = hound::WavReader::open(&cue_path).unwrap();
+    assert_eq!(reader.spec().channels, 1);
+    let samples: Vec<f32> = reader.into_samples().map(Result::unwrap).collect();
+    assert_eq!(samples, expected);
+
+    assert_eq!(
+        hound::WavReader::open(&main_path).unwrap().len() as usize,
+        blocks * BLOCK
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// `main` is written as the app plays it, so a reload's crossfade is in the file, and has
+/// the config's channel count.
+#[test]
+fn main_renders_reload_crossfades() {
+    let (mut reference, mut reference_frontend) = build(OSC_ONLY).unwrap();
+    let (app, mut frontend) = build(OSC_ONLY).unwrap();
+    for frontend in [&mut reference_frontend, &mut frontend] {
+        frontend.set_reload_crossfade(4);
+        frontend.reload_dsl(CUE_ONLY).unwrap();
+    }
+
+    let dir = std::env::temp_dir().join(format!("legato-main-output-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.wav");
+    render_outputs(app, &[("main", &path)], Duration::from_millis(100)).unwrap();
+
+    let blocks = (4_800usize).div_ceil(BLOCK);
+    let expected: Vec<f32> = (0..blocks)
+        .flat_map(|_| reference.next_block().channels[0].to_vec())
+        .collect();
+
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(
+        reader.spec().channels as usize,
+        reference.get_config().channels
+    );
+    let samples: Vec<f32> = reader.into_samples().map(Result::unwrap).collect();
+    assert_eq!(samples, expected);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
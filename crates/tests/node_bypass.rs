@@ -0,0 +1,98 @@
+//! Bypassing and muting running nodes through the `LegatoFrontend`.
+
+use legato::{FrontendError, node::BYPASS_RAMP};
+
+mod common;
+use common::{BLOCK, build_live, render};
+
+const OSC: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 }
+    }
+
+    { osc }
+"#;
+
+const FILTERED: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: lp { cutoff: 400.0, chans: 1 }
+    }
+
+    osc >> lp[0]
+
+    { lp }
+"#;
+
+/// Once the ramp is over, a bypassed filter hands its input on untouched.
+#[test]
+fn a_bypassed_effect_passes_its_input_through() {
+    let (mut reference, _) = build_live(OSC);
+    let (mut filtered, _) = build_live(FILTERED);
+    let (mut app, mut frontend) = build_live(FILTERED);
+
+    frontend.set_bypass("lp", true).unwrap();
+
+    let ramp_blocks = BYPASS_RAMP.div_ceil(BLOCK);
+    let dry = render(&mut reference, ramp_blocks + 4);
+    let wet = render(&mut filtered, ramp_blocks);
+    let got = render(&mut app, ramp_blocks + 4);
+
+    assert_eq!(got[BYPASS_RAMP..], dry[BYPASS_RAMP..]);
+
+    // The fade starts from the filtered signal, and never strays outside of the two
+    for s in 0..BYPASS_RAMP {
+        let (lo, hi) = (wet[s].min(dry[s]), wet[s].max(dry[s]));
+        assert!(
+            got[s] >= lo - 1e-6 && got[s] <= hi + 1e-6,
+            "sample {s}: {} is not between {} and {}",
+            got[s],
+            wet[s],
+            dry[s]
+        );
+    }
+    assert!((got[0] - wet[0]).abs() < (got[0] - dry[0]).abs() + 1e-6);
+}
+
+/// Muting fades to silence and back, and a generator has nothing to pass through when bypassed.
+#[test]
+fn muted_and_bypassed_generators_go_silent() {
+    let (mut app, mut frontend) = build_live(OSC);
+    let ramp_blocks = BYPASS_RAMP.div_ceil(BLOCK);
+
+    render(&mut app, 2);
+    frontend.set_mute("osc", true).unwrap();
+    let out = render(&mut app, ramp_blocks + 2);
+    assert!(out[..BYPASS_RAMP].iter().any(|x| *x != 0.0));
+    assert!(out[BYPASS_RAMP..].iter().all(|x| *x == 0.0));
+
+    frontend.set_mute("osc", false).unwrap();
+    assert!(render(&mut app, 1).iter().any(|x| *x != 0.0));
+
+    frontend.set_bypass("osc", true).unwrap();
+    let out = render(&mut app, ramp_blocks + 2);
+    assert!(out[BYPASS_RAMP..].iter().all(|x| *x == 0.0));
+}
+
+/// Mute wins over bypass, and clearing both brings back the unaltered node.
+#[test]
+fn mute_wins_over_bypass() {
+    let (mut reference, _) = build_live(FILTERED);
+    let (mut app, mut frontend) = build_live(FILTERED);
+
+    frontend.set_bypass("lp", true).unwrap();
+    frontend.set_mute("lp", true).unwrap();
+    let out = render(&mut app, BYPASS_RAMP.div_ceil(BLOCK) + 2);
+    assert!(out[BYPASS_RAMP..].iter().all(|x| *x == 0.0));
+
+    assert_eq!(
+        frontend.set_bypass("missing", true),
+        Err(FrontendError::NodeNotFound())
+    );
+
+    // A node that never faded out keeps producing exactly what it would have
+    let (mut untouched, mut frontend) = build_live(FILTERED);
+    frontend.set_bypass("lp", false).unwrap();
+    frontend.set_mute("lp", false).unwrap();
+    assert_eq!(render(&mut untouched, 4), render(&mut reference, 4));
+}
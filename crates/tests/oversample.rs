@@ -0,0 +1,158 @@
+//! `oversample(n)` blocks, which run the nodes inside at n times the graph's sample rate
+//! with resamplers on every connection in and out.
+
+use std::sync::Arc;
+
+use legato::{builder::ValidationError, pool::WorkerPool};
+
+mod common;
+use common::{BLOCK, build, builder, render, rising_crossings};
+
+const SINE: &str = "audio { sine { freq: 1000.0 } }\n{ sine }";
+
+/// A sine inside the block is built for the faster rate, so it keeps its pitch and level
+/// once it is brought back down.
+#[test]
+fn nodes_see_the_oversampled_rate() {
+    let plain = render(&mut build(SINE).unwrap(), 8);
+    let oversampled = render(
+        &mut build("oversample(4) {\n audio { sine { freq: 1000.0 } }\n}\n{ sine }").unwrap(),
+        8,
+    );
+
+    // Skip the first block while the filters fill
+    let (plain, oversampled) = (&plain[BLOCK..], &oversampled[BLOCK..]);
+    assert!(rising_crossings(plain).abs_diff(rising_crossings(oversampled)) <= 1);
+    let peak = oversampled.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    assert!((peak - 1.0).abs() < 0.05, "{peak}");
+}
+
+/// The oversampled output takes four blocks' worth of buffer, and the resampler back down one.
+#[test]
+fn buffers_run_at_the_oversampled_length() {
+    let app = build("oversample(4) {\n audio { sine }\n}\n{ sine }").unwrap();
+    let dot = app.to_dot();
+
+    assert_eq!(app.buffer_stats().unshared_len, 4 * BLOCK + BLOCK);
+    assert!(dot.contains("sine @ x4"), "{dot}");
+    assert!(dot.contains("resample"), "{dot}");
+}
+
+/// A constant goes up into the block and back down again unchanged, once the filters fill.
+#[test]
+fn signals_cross_in_and_out() {
+    let src = r#"
+        audio { const { val: 0.5 } }
+        oversample(2) {
+            audio { add: inner { val: 0.0 } }
+        }
+        const >> inner[0]
+        { inner }
+    "#;
+    let out = render(&mut build(src).unwrap(), 4);
+
+    assert!(
+        out[BLOCK..].iter().all(|x| (x - 0.5).abs() < 1e-2),
+        "{out:?}"
+    );
+}
+
+/// Blocks nest and reach into the patches inside them, multiplying as they go.
+#[test]
+fn factors_multiply() {
+    let src = r#"
+        patch osc() {
+            audio { add: pre { val: 0.0 } }
+            oversample(2) {
+                audio { sine }
+            }
+            pre >> sine
+            { sine }
+        }
+        oversample(2) {
+            patches { osc: o }
+        }
+        { o }
+    "#;
+    let dot = build(src).unwrap().to_dot();
+
+    assert!(dot.contains("sine @ x4"), "{dot}");
+    assert!(dot.contains("add @ x2"), "{dot}");
+}
+
+/// The filters only double or halve, so other factors are rejected.
+#[test]
+fn factors_are_powers_of_two() {
+    let err = build("oversample(3) {\n audio { sine }\n}\n{ sine }").unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::InvalidParameter(msg) if msg.contains("oversample(3)")),
+        "{err:?}"
+    );
+}
+
+/// Nodes at different rates share the pool like any others, with the same result.
+#[test]
+fn matches_on_a_pool() {
+    let src = r#"
+        audio {
+            saw { freq: 110.0, chans: 1 },
+            add: mix { val: 0.0 }
+        }
+        oversample(4) {
+            audio { svf { cutoff: 2000.0, chans: 1 } }
+        }
+        oversample(2) {
+            audio { sine { freq: 220.0 } }
+        }
+        saw >> svf
+        svf >> mix[0]
+        sine >> mix[0]
+        { mix }
+    "#;
+    let serial = render(&mut build(src).unwrap(), 4);
+    let (mut pooled, _) = builder()
+        .set_worker_pool(Arc::new(WorkerPool::new(2)))
+        .build_dsl(src)
+        .unwrap();
+
+    assert_eq!(render(&mut pooled, 4), serial);
+}
+
+/// `| oversample(n)` builds a single node for the faster rate the same way, so a filter
+/// piped up sounds just like one in a block.
+#[test]
+fn pipes_match_blocks() {
+    let piped = r#"
+        audio {
+            saw { freq: 110.0, chans: 1 },
+            svf { cutoff: 500.0, chans: 1 } | oversample(4)
+        }
+        saw >> svf
+        { svf }
+    "#;
+    let block = r#"
+        audio { saw { freq: 110.0, chans: 1 } }
+        oversample(4) {
+            audio { svf { cutoff: 500.0, chans: 1 } }
+        }
+        saw >> svf
+        { svf }
+    "#;
+    let mut app = build(piped).unwrap();
+    let dot = app.to_dot();
+
+    assert!(dot.contains("svf @ x4"), "{dot}");
+    assert_eq!(render(&mut app, 8), render(&mut build(block).unwrap(), 8));
+}
+
+/// The pipe's factor is checked like the block's.
+#[test]
+fn pipe_factors_are_checked() {
+    let err = build("audio { sine | oversample(3) }\n{ sine }").unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::InvalidParameter(msg) if msg.contains("found 3")),
+        "{err:?}"
+    );
+}
@@ -0,0 +1,85 @@
+//! Running a patch on a `WorkerPool`, against the same patch on the audio thread alone.
+
+use std::sync::Arc;
+
+use assert_no_alloc::{AllocDisabler, assert_no_alloc};
+use legato::{LegatoApp, LegatoFrontend, pool::WorkerPool};
+
+mod common;
+use common::{BLOCK, builder};
+
+#[global_allocator]
+static A: AllocDisabler = AllocDisabler;
+
+fn build(src: &str, pool: Option<Arc<WorkerPool>>) -> (LegatoApp, LegatoFrontend) {
+    let builder = match pool {
+        Some(pool) => builder().set_worker_pool(pool),
+        None => builder(),
+    };
+    builder.build_dsl(src).expect("graph should build")
+}
+
+/// Render with allocation on the audio thread, and on every worker, turned into an abort.
+fn render(app: &mut LegatoApp, blocks: usize) -> Vec<f32> {
+    let mut out = vec![0.0; BLOCK * blocks];
+    for chunk in out.chunks_exact_mut(BLOCK) {
+        assert_no_alloc(|| chunk.copy_from_slice(app.next_block().channels[0]));
+    }
+    out
+}
+
+/// Three independent voices, plus a delay line whose writer and reader share no edge.
+const BRANCHES: &str = r#"
+    audio {
+        saw: a { freq: 110.0, chans: 1 },
+        saw: b { freq: 165.0, chans: 1 },
+        saw: c { freq: 220.0, chans: 1 },
+        svf: fa { cutoff: 900.0, q: 2.0, chans: 1 },
+        svf: fb { cutoff: 1200.0, q: 1.0, chans: 1 },
+        svf: fc { cutoff: 400.0, q: 4.0, chans: 1 },
+        delay_write { delay_name: "echo", delay_length: 100.0, chans: 1 },
+        delay_read { delay_name: "echo", delay_length: 2.0, chans: 1 },
+        onepole: out { cutoff: 18000.0, chans: 1 }
+    }
+
+    a >> fa[0]
+    b >> fb[0]
+    c >> fc[0]
+    c >> delay_write
+    fa >> out[0]
+    fb >> out[0]
+    fc >> out[0]
+    delay_read >> out[0]
+
+    { out }
+"#;
+
+#[test]
+fn worker_pool_output_is_bit_identical() {
+    let (mut serial, _) = build(BRANCHES, None);
+    let (mut parallel, _) = build(BRANCHES, Some(Arc::new(WorkerPool::new(3))));
+
+    let want = render(&mut serial, 32);
+    let got = render(&mut parallel, 32);
+
+    assert!(want.iter().any(|x| *x != 0.0), "patch rendered silence");
+    assert!(
+        want.iter()
+            .zip(&got)
+            .all(|(w, g)| w.to_bits() == g.to_bits()),
+        "parallel output differs from serial"
+    );
+}
+
+/// Hot reloads build their graph on the same pool.
+#[test]
+fn reloaded_graphs_keep_the_worker_pool() {
+    let (mut serial, _) = build(BRANCHES, None);
+    let (mut parallel, mut frontend) = build(BRANCHES, Some(Arc::new(WorkerPool::new(2))));
+    frontend.set_reload_crossfade(0);
+
+    frontend.reload_dsl(BRANCHES).expect("reload should build");
+    let got = render(&mut parallel, 8);
+
+    assert_eq!(got, render(&mut serial, 8));
+}
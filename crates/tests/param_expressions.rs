@@ -0,0 +1,113 @@
+//! Arithmetic in param values, evaluated against patch and kernel params at expansion time.
+
+use legato::builder::ValidationError;
+
+mod common;
+use common::{build, render};
+
+const LITERAL: &str = r#"
+    audio {
+        sine { freq: 220.0 },
+        mult { val: 0.25 }
+    }
+
+    sine >> mult[0]
+
+    { mult }
+"#;
+
+/// Expressions over patch params, and over literals at the instantiation site.
+#[test]
+fn patch_params_drive_expressions() {
+    let src = r#"
+        patch voice(base = 110.0, gain = 0.5) {
+            audio {
+                sine { freq: $base * 2.0 },
+                mult { val: min($gain, 1.0) / 2 }
+            }
+
+            sine >> mult[0]
+
+            { mult }
+        }
+
+        patches {
+            voice: v { base: 55.0 + 55.0 }
+        }
+
+        { v }
+    "#;
+
+    let mut want = build(LITERAL).unwrap();
+    let mut got = build(src).unwrap();
+
+    let want = render(&mut want, 4);
+    assert_eq!(render(&mut got, 4), want);
+    assert!(want.iter().any(|x| x.abs() > 1e-3));
+}
+
+#[test]
+fn kernel_params_drive_expressions() {
+    let src = r#"
+        kernel scaled(amount = 0.5) {
+            in audio_in
+
+            audio {
+                mult { val: $amount * $amount }
+            }
+
+            audio_in >> mult[0]
+
+            { mult }
+        }
+
+        patches {
+            scaled: s {}
+        }
+
+        audio {
+            sine { freq: pow(2, 3) * 27.5 }
+        }
+
+        sine >> s.audio_in
+
+        { s }
+    "#;
+
+    let mut want = build(LITERAL).unwrap();
+    let mut got = build(src).unwrap();
+
+    assert_eq!(render(&mut got, 4), render(&mut want, 4));
+}
+
+/// The error points at the offending part of the source.
+#[test]
+fn bad_expressions_are_spanned_errors() {
+    let span_of = |src: &str| match build(src) {
+        Err(ValidationError::InvalidExpression(_, span)) => src[span].to_string(),
+        other => panic!("expected an expression error, got {other:?}"),
+    };
+
+    let unknown = LITERAL.replace("220.0", "$freq * 2.0");
+    assert_eq!(span_of(&unknown), "$freq");
+
+    let mismatch = r#"
+        patch voice(name = "lead") {
+            audio {
+                sine { freq: 220.0 + $name }
+            }
+
+            { sine }
+        }
+
+        patches {
+            voice: v {}
+        }
+
+        { v }
+    "#;
+    assert_eq!(span_of(mismatch), "$name");
+
+    let function = LITERAL.replace("220.0", "sqr(2.0)");
+    assert_eq!(span_of(&function), "sqr(2.0)");
+}
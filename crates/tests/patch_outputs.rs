@@ -0,0 +1,86 @@
+//! `out` declarations on patches and kernels, read from outside as `voice.wet`.
+
+use legato::builder::ValidationError;
+
+mod common;
+use common::{build, render};
+
+fn close(a: &[f32], b: &[f32]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4)
+}
+
+/// A sine as the sink, and the same sine at half level as `half`.
+fn source(keyword: &str, spawn: &str, read: &str) -> String {
+    format!(
+        r#"
+        {keyword} p() {{
+            out half
+            audio {{ sine {{ freq: 440.0 }}, mult: h {{ val: 0.5 }} }}
+            sine >> h[0]
+            h >> half
+            {{ sine }}
+        }}
+        patches {{ p{spawn} }}
+        audio {{ add: o {{ val: 0.0 }} }}
+        {read} >> o[0]
+        {{ o }}
+        "#
+    )
+}
+
+/// A named output reads the node behind it, while the bare patch still reads its sink.
+#[test]
+fn named_outputs_read_their_node() {
+    let full = render(&mut build(&source("patch", "", "p")).unwrap(), 4);
+    let half = render(&mut build(&source("patch", "", "p.half")).unwrap(), 4);
+
+    assert!(full.iter().any(|x| x.abs() > 0.1));
+    let want: Vec<f32> = full.iter().map(|x| x * 0.5).collect();
+    assert!(close(&half, &want));
+}
+
+/// Every selected instance contributes its own output.
+#[test]
+fn spawned_patches_sum_their_outputs() {
+    let one = render(&mut build(&source("patch", "", "p")).unwrap(), 4);
+    let two = render(
+        &mut build(&source("patch", " * 2", "p(*).half")).unwrap(),
+        4,
+    );
+
+    assert!(close(&two, &one));
+}
+
+/// Kernels expose their `out`s as ports after the sink's.
+#[test]
+fn kernels_have_named_outputs() {
+    let patch = render(&mut build(&source("patch", "", "p.half")).unwrap(), 4);
+    let kernel = render(&mut build(&source("kernel", "", "p.half")).unwrap(), 4);
+
+    assert!(close(&kernel, &patch));
+}
+
+/// An output nothing feeds is an error, rather than silence.
+#[test]
+fn outputs_must_be_connected() {
+    let src = source("patch", "", "p.half").replace("h >> half", "");
+    let err = build(&src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::Expansion(msg) if msg.contains("half")),
+        "{err:?}"
+    );
+}
+
+/// An output is fed by one connection, and the second is blamed.
+#[test]
+fn outputs_are_fed_once() {
+    let src = source("patch", "", "p.half").replace("h >> half", "h >> half\nsine >> half");
+    let err = build(&src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::Expansion(_)),
+        "{err:?}"
+    );
+    assert_eq!(&src[err.span().unwrap()], "sine >> half");
+}
@@ -0,0 +1,173 @@
+//! Pipes after a declaration, e.g. `saw * 4 | spread`, run over its built instances before
+//! anything is connected.
+
+use legato::{
+    LegatoApp,
+    builder::{SelectionView, ValidationError},
+    dsl::ir::Value,
+    pipes::Pipe,
+};
+use std::sync::{Arc, Mutex};
+
+mod common;
+use common::stereo_builder;
+
+fn build(src: &str) -> Result<LegatoApp, ValidationError> {
+    stereo_builder().build_dsl(src).map(|(app, _)| app)
+}
+
+/// `[left, right]`, each `blocks` long.
+fn render(app: &mut LegatoApp, blocks: usize) -> [Vec<f32>; 2] {
+    let mut out = [vec![], vec![]];
+    for _ in 0..blocks {
+        let block = app.next_block();
+        out[0].extend_from_slice(block.channels[0]);
+        out[1].extend_from_slice(block.channels[1]);
+    }
+    out
+}
+
+fn close(a: &[f32], b: &[f32]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6)
+}
+
+/// How many nodes each run of a pipe saw, and its params.
+type Seen = Arc<Mutex<Vec<(usize, Option<Value>)>>>;
+
+/// A pipe that records what it was given, and leaves the nodes alone.
+fn probe() -> (impl Pipe, Seen) {
+    let seen = Arc::new(Mutex::new(vec![]));
+    let log = seen.clone();
+    let pipe = move |view: &mut SelectionView, params: Option<&Value>| {
+        log.lock()
+            .unwrap()
+            .push((view.selection().keys().len(), params.cloned()));
+        Ok(())
+    };
+    (pipe, seen)
+}
+
+/// `stereo` puts a mono node on both sides, where it would otherwise only reach the left.
+#[test]
+fn stereo_copies_mono_to_both_sides() {
+    let mut mono = build("audio { sine { freq: 440.0 } }\n{ sine }").unwrap();
+    let mut piped = build("audio { sine { freq: 440.0 } | stereo }\n{ sine }").unwrap();
+
+    let [want, _] = render(&mut mono, 4);
+    let [left, right] = render(&mut piped, 4);
+    assert!(close(&left, &want));
+    assert!(close(&right, &want));
+}
+
+/// `spread` pans the first instance hard left and the last hard right.
+#[test]
+fn spread_pans_across_instances() {
+    let mut mono = build("audio { sine { freq: 440.0 } }\n{ sine }").unwrap();
+    // The sink of a spawned node is its last instance
+    let mut piped = build("audio { sine * 3 { freq: 440.0 } | spread }\n{ sine }").unwrap();
+
+    let [want, _] = render(&mut mono, 4);
+    let [left, right] = render(&mut piped, 4);
+    assert!(left.iter().all(|x| x.abs() < 1e-6));
+    assert!(close(&right, &want));
+}
+
+/// `oversample` keeps a node's ports, so it wires like any other.
+#[test]
+fn oversample_keeps_ports() {
+    let src = r#"
+        audio {
+            saw { freq: 110.0, chans: 1 },
+            svf { cutoff: 2000.0, chans: 1 } | oversample
+        }
+        saw >> svf
+        { svf }
+    "#;
+    let mut app = build(src).unwrap();
+
+    let [left, _] = render(&mut app, 8);
+    let peak = left.iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    assert!(left.iter().all(|x| x.is_finite()));
+    assert!(peak > 0.1, "{peak}");
+}
+
+/// Pipes run in order over every instance at once, and see their params after evaluation.
+#[test]
+fn chains_see_every_instance() {
+    let (pipe, seen) = probe();
+    let src = "audio { saw * 4 { freq: 110.0, chans: 1 } | detune(0.1 * 2) | stereo }\n{ saw }";
+    stereo_builder()
+        .register_pipe("detune", pipe)
+        .build_dsl(src)
+        .unwrap();
+
+    assert_eq!(*seen.lock().unwrap(), [(4, Some(Value::F32(0.2)))]);
+}
+
+/// Inside a patch, pipe params can use the patch's params and a block's variables.
+#[test]
+fn pipe_params_are_filled_per_instance() {
+    let (pipe, seen) = probe();
+    let src = r#"
+        patch p(depth = 1) {
+            audio { add: mix { val: 0.0 } }
+            for i in 0..2 {
+                audio { sine: s{i} | probe($depth + $i) }
+                s{i} >> mix[0]
+            }
+            { mix }
+        }
+        patches { p: a, p: b { depth: 10 } }
+        a >> b
+        { b }
+    "#;
+    stereo_builder()
+        .register_pipe("probe", pipe)
+        .build_dsl(src)
+        .unwrap();
+
+    let mut seen: Vec<u32> = seen
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, params)| match params {
+            Some(Value::U32(x)) => *x,
+            other => panic!("{other:?}"),
+        })
+        .collect();
+    seen.sort();
+    assert_eq!(seen, [1, 2, 10, 11]);
+}
+
+/// An unknown pipe is blamed on the declaration it follows.
+#[test]
+fn unknown_pipe_points_at_declaration() {
+    let src = "audio {\n    sine | wobble\n}\n{ sine }";
+    let err = build(src).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::PipeNotFound(_)),
+        "{err:?}"
+    );
+    assert_eq!(&src[err.span().unwrap()], "sine | wobble");
+}
+
+/// `spread` needs something to pan.
+#[test]
+fn spread_rejects_stereo_nodes() {
+    let err = build("audio { pan * 2 | spread }\n{ pan }").unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::SelectionArity(_)),
+        "{err:?}"
+    );
+}
+
+/// A patch has no single node to pipe.
+#[test]
+fn patches_cannot_be_piped() {
+    let src = "patch p() { audio { sine } { sine } }\npatches { p | stereo }\n{ p }";
+    let err = build(src).unwrap_err();
+
+    assert_eq!(&src[err.span().unwrap()], "p | stereo");
+}
@@ -0,0 +1,110 @@
+//! Per-node and per-block timings sent from the runtime to the `LegatoFrontend`.
+
+use std::{sync::Arc, time::Duration};
+
+use legato::{LegatoApp, LegatoFrontend, pool::WorkerPool};
+
+mod common;
+use common::{BLOCK, builder};
+
+const WINDOW: usize = 4;
+
+fn build(src: &str, pool: Option<Arc<WorkerPool>>) -> (LegatoApp, LegatoFrontend) {
+    let builder = builder().enable_profiling(WINDOW);
+    let builder = match pool {
+        Some(pool) => builder.set_worker_pool(pool),
+        None => builder,
+    };
+    builder.build_dsl(src).expect("graph should build")
+}
+
+fn render(app: &mut LegatoApp, blocks: usize) {
+    for _ in 0..blocks {
+        app.next_block();
+    }
+}
+
+const VOICES: &str = r#"
+    audio {
+        saw: a { freq: 110.0, chans: 1 },
+        saw: b { freq: 165.0, chans: 1 },
+        svf: fa { cutoff: 900.0, q: 2.0, chans: 1 },
+        svf: fb { cutoff: 1200.0, q: 1.0, chans: 1 },
+        onepole: out { cutoff: 18000.0, chans: 1 }
+    }
+
+    a >> fa[0]
+    b >> fb[0]
+    fa >> out[0]
+    fb >> out[0]
+
+    { out }
+"#;
+
+/// Every node is timed once a block, whichever thread runs it.
+#[test]
+fn every_node_is_timed_each_window() {
+    for pool in [None, Some(Arc::new(WorkerPool::new(2)))] {
+        let (mut app, mut frontend) = build(VOICES, pool);
+        let registry = frontend.clone_registry();
+
+        // A window is sent at the start of the block after it fills up
+        render(&mut app, WINDOW + 1);
+
+        let profile = frontend.profile().expect("profiling is enabled");
+        let block = profile.block.expect("a full window has run");
+
+        assert_eq!(block.time.count, WINDOW);
+        assert_eq!(
+            block.deadline,
+            Duration::from_secs_f64(BLOCK as f64 / 48_000.0)
+        );
+        assert!(block.overruns <= WINDOW);
+        assert!(block.load() >= 0.0);
+
+        assert_eq!(profile.nodes.len(), registry.len());
+        for (alias, key) in &registry {
+            let stats = profile.nodes[key];
+            assert_eq!(
+                stats.count, WINDOW,
+                "{alias} was timed the wrong number of times"
+            );
+            assert!(stats.min <= stats.avg && stats.avg <= stats.max);
+            assert!(stats.max <= block.time.max);
+        }
+    }
+}
+
+/// The frontend only ever shows a complete window.
+#[test]
+fn partial_windows_are_not_reported() {
+    let (mut app, mut frontend) = build(VOICES, None);
+
+    render(&mut app, WINDOW);
+    assert_eq!(frontend.profile().unwrap().block, None);
+
+    render(&mut app, 1);
+    assert!(frontend.profile().unwrap().block.is_some());
+}
+
+/// A reloaded graph is timed too, under its new keys.
+#[test]
+fn reloaded_graphs_keep_profiling() {
+    let (mut app, mut frontend) = build(VOICES, None);
+    frontend.set_reload_crossfade(0);
+
+    frontend.reload_dsl(VOICES).unwrap();
+    render(&mut app, WINDOW + 1);
+
+    let registry = frontend.clone_registry();
+    let profile = frontend.profile().unwrap();
+    assert!(registry.values().all(|key| profile.nodes.contains_key(key)));
+}
+
+#[test]
+fn profiling_is_opt_in() {
+    let (mut app, mut frontend) = builder().build_dsl(VOICES).unwrap();
+
+    render(&mut app, WINDOW + 1);
+    assert!(frontend.profile().is_none());
+}
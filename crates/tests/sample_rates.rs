@@ -0,0 +1,145 @@
+//! Patches and kernels with a rate of their own, e.g. `patch lofi() @ /2 { .. }`,
+//! resampled to and from the graph's rate where they are wired in.
+
+use legato::builder::ValidationError;
+
+mod common;
+use common::{BLOCK, blamed, build, render, rising_crossings};
+
+/// A sine in a patch at `rate`, e.g. `/2`, played at the graph's rate.
+fn sine_at(rate: &str) -> String {
+    format!(
+        r#"
+            patch osc() @ {rate} {{
+                audio {{ sine {{ freq: 1000.0 }} }}
+                {{ sine }}
+            }}
+            patches {{ osc }}
+            {{ osc }}
+        "#
+    )
+}
+
+/// A sine at half the graph's rate keeps its pitch, and takes half the buffer of one at
+/// the graph's rate, with a full block for the resampler back up.
+#[test]
+fn lower_rates_keep_their_pitch() {
+    let plain = render(
+        &mut build("audio { sine { freq: 1000.0 } }\n{ sine }").unwrap(),
+        8,
+    );
+    let mut app = build(&sine_at("/2")).unwrap();
+    let dot = app.to_dot();
+    let halved = render(&mut app, 8);
+
+    assert!(dot.contains("sine @ /2"), "{dot}");
+    assert_eq!(app.buffer_stats().unshared_len, BLOCK / 2 + BLOCK);
+    assert!(rising_crossings(&plain[BLOCK..]).abs_diff(rising_crossings(&halved[BLOCK..])) <= 1);
+}
+
+/// A sine at twice the graph's rate comes back down at full level.
+#[test]
+fn higher_rates_come_back_down() {
+    let mut app = build(&sine_at("x2")).unwrap();
+    let dot = app.to_dot();
+    let out = render(&mut app, 8);
+
+    assert!(dot.contains("sine @ x2"), "{dot}");
+    let peak = out[BLOCK..].iter().fold(0.0f32, |m, x| m.max(x.abs()));
+    assert!((peak - 1.0).abs() < 0.05, "{peak}");
+}
+
+/// The innermost patch with a rate sets it, whatever it is instanced inside. Blocks inside
+/// that patch oversample from its rate.
+#[test]
+fn the_innermost_rate_wins() {
+    let src = r#"
+        patch lofi() @ /2 {
+            audio { add: pre { val: 0.0 } }
+            oversample(4) {
+                audio { sine }
+            }
+            pre >> sine
+            { sine }
+        }
+        patch core() @ x2 {
+            audio { add: mix { val: 0.0 } }
+            oversample(2) {
+                patches { lofi }
+            }
+            lofi >> mix[0]
+            { mix }
+        }
+        patches { core }
+        { core }
+    "#;
+    let dot = build(src).unwrap().to_dot();
+
+    assert!(dot.contains(r"core.mix\nadd @ x2"), "{dot}");
+    assert!(dot.contains(r"core.lofi.pre\nadd @ /2"), "{dot}");
+    assert!(dot.contains(r"core.lofi.sine\nsine @ x2"), "{dot}");
+}
+
+/// A kernel runs at its own rate as one node.
+#[test]
+fn kernels_take_a_rate_too() {
+    let src = r#"
+        kernel voice() @ x2 {
+            audio { saw { chans: 1, freq: 110.0 } }
+            { saw }
+        }
+        patches { voice: v {} }
+        { v }
+    "#;
+    let dot = build(src).unwrap().to_dot();
+
+    assert!(dot.contains(r"v\nvoice @ x2"), "{dot}");
+}
+
+/// The resamplers only double or halve, so rates are a power of two from the graph's.
+#[test]
+fn rates_are_a_power_of_two_apart() {
+    let err = build(&sine_at("/3")).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::ParseError(_)),
+        "{err:?}"
+    );
+}
+
+/// Rates in Hz only line up with some device rates, so they are refused with a pointer to
+/// the relative form.
+#[test]
+fn rates_in_hz_are_refused() {
+    let err = build(&sine_at("24000")).unwrap_err();
+
+    assert!(
+        matches!(err.unspanned(), ValidationError::ParseError(_)),
+        "{err:?}"
+    );
+}
+
+/// A patch's rate counts against the same x8 limit as `oversample(n)`, alone or together,
+/// and the error points at the node that would go over it.
+#[test]
+fn rates_share_the_oversampling_limit() {
+    let together = r#"
+        patch osc() @ x2 {
+            oversample(8) {
+                audio { sine }
+            }
+            { sine }
+        }
+        patches { osc }
+        { osc }
+    "#;
+    for src in [sine_at("x32"), together.to_string()] {
+        let err = build(&src).unwrap_err();
+
+        assert!(
+            matches!(err.unspanned(), ValidationError::InvalidParameter(msg) if msg.contains("at most x8")),
+            "{err:?}"
+        );
+        assert!(blamed(&src, &err).starts_with("sine"), "{err:?}");
+    }
+}
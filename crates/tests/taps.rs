@@ -0,0 +1,129 @@
+//! Tapping running nodes' outputs through the `LegatoFrontend`.
+
+use std::sync::Arc;
+
+use legato::{
+    FrontendError, LegatoApp, LegatoFrontend,
+    pool::WorkerPool,
+    tap::{Tap, TapLevel},
+};
+
+mod common;
+use common::{BLOCK, builder, render};
+
+fn build(src: &str, pool: Option<Arc<WorkerPool>>) -> (LegatoApp, LegatoFrontend) {
+    let builder = match pool {
+        Some(pool) => builder().set_worker_pool(pool),
+        None => builder(),
+    };
+    builder.build_dsl(src).expect("graph should build")
+}
+
+fn drain(tap: &mut Tap) -> (Vec<f32>, Vec<TapLevel>) {
+    let samples = std::iter::from_fn(|| tap.samples.pop().ok()).collect();
+    let levels = std::iter::from_fn(|| tap.levels.pop().ok()).collect();
+    (samples, levels)
+}
+
+/// A chain long enough that `lp`'s slot would be reused by `out` if it were not pinned.
+const FILTERED: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: lp { cutoff: 800.0, chans: 1 },
+        onepole: hp { cutoff: 4000.0, chans: 1 },
+        onepole: out { cutoff: 18000.0, chans: 1 }
+    }
+
+    osc >> lp[0]
+    lp >> hp[0]
+    hp >> out[0]
+
+    { out }
+"#;
+
+const UP_TO_LP: &str = r#"
+    audio {
+        saw: osc { freq: 220.0, chans: 1 },
+        onepole: lp { cutoff: 800.0, chans: 1 }
+    }
+
+    osc >> lp[0]
+
+    { lp }
+"#;
+
+/// A tap sees exactly what the node wrote, and the sink is unaffected by it.
+#[test]
+fn a_tap_receives_the_nodes_output() {
+    for pool in [None, Some(Arc::new(WorkerPool::new(2)))] {
+        let (mut reference, _) = build(UP_TO_LP, None);
+        let (mut untapped, _) = build(FILTERED, None);
+        let (mut app, mut frontend) = build(FILTERED, pool);
+
+        let mut tap = frontend.tap("lp", 0).unwrap();
+        let out = render(&mut app, 4);
+
+        let (samples, levels) = drain(&mut tap);
+        let expected = render(&mut reference, 4);
+        assert!(expected.iter().any(|x| *x != 0.0), "patch rendered silence");
+        assert_eq!(samples, expected);
+        assert_eq!(out, render(&mut untapped, 4));
+
+        assert_eq!(levels.len(), 4);
+        for (level, block) in levels.iter().zip(expected.chunks_exact(BLOCK)) {
+            assert_eq!(*level, TapLevel::of(block));
+            assert_eq!(level.peak, block.iter().fold(0.0f32, |m, x| m.max(x.abs())));
+        }
+    }
+}
+
+/// Tapping the same node twice, or the sink, needs no further edits.
+#[test]
+fn nodes_can_be_tapped_more_than_once() {
+    let (mut app, mut frontend) = build(FILTERED, None);
+
+    let mut first = frontend.tap("hp", 0).unwrap();
+    let mut second = frontend.tap("hp", 0).unwrap();
+    let mut sink = frontend.tap("out", 0).unwrap();
+    let out = render(&mut app, 2);
+
+    assert_eq!(drain(&mut first).0, drain(&mut second).0);
+    assert_eq!(drain(&mut sink).0, out);
+}
+
+#[test]
+fn taps_on_missing_ports_are_rejected() {
+    let (_, mut frontend) = build(FILTERED, None);
+
+    assert!(matches!(
+        frontend.tap("lp", 1),
+        Err(FrontendError::PortNotFound())
+    ));
+    assert!(matches!(
+        frontend.tap("missing", 0),
+        Err(FrontendError::NodeNotFound())
+    ));
+}
+
+/// Removing a tapped node, or reloading the graph, hangs up on the tap.
+#[test]
+fn taps_end_with_their_node() {
+    let (mut app, mut frontend) = build(FILTERED, None);
+    frontend.set_reload_crossfade(0);
+
+    let removed = frontend.tap("osc", 0).unwrap();
+    let reloaded = frontend.tap("hp", 0).unwrap();
+    render(&mut app, 1);
+    assert!(!removed.samples.is_abandoned());
+
+    frontend.remove_node("osc").unwrap();
+    render(&mut app, 1);
+    frontend.drain_garbage();
+    assert!(removed.samples.is_abandoned());
+    assert!(!reloaded.samples.is_abandoned());
+
+    frontend.reload_dsl(FILTERED).unwrap();
+    render(&mut app, 1);
+    frontend.drain_garbage();
+    assert!(reloaded.samples.is_abandoned());
+}